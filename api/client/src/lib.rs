@@ -0,0 +1,346 @@
+//! An async client for the linkman API, with the JSON it speaks as Rust types.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), linkman_client::Error> {
+//! use linkman_client::{Client, Filter, NewBookmark};
+//!
+//! let client = Client::new("http://localhost:3000", "API_KEY")?;
+//! let saved = client
+//!     .save_bookmark(&NewBookmark { url: "https://example.com".into(), ..Default::default() })
+//!     .await?;
+//! println!("{} ({})", saved.bookmark.url, if saved.created { "new" } else { "updated" });
+//!
+//! let filter = Filter { tag: Some("dev/rust".into()), unread: Some(true), ..Default::default() };
+//! let page = client.list_bookmarks(&filter, None, None).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! It covers bookmarks, tags, collections, saved searches, stats, digests and collection
+//! suggestions. Admin, webhooks, API keys, imports and backups are left to plain HTTP for now.
+
+use std::{fmt, time::Duration};
+
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use url::{form_urlencoded, Url};
+use uuid::Uuid;
+
+mod types;
+
+pub use types::*;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub enum Error {
+    /// The base URL isn't one requests can be made to
+    Url(String),
+    /// No answer, or not the JSON that was expected
+    Http(reqwest::Error),
+    /// The server turned the request down
+    Status(StatusCode),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Url(url) => write!(f, "{:?} is not an http(s) URL", url),
+            Error::Http(e) => write!(f, "{}", e),
+            Error::Status(status) => write!(f, "the server answered {}", status),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// What `save_bookmark` did.
+#[derive(Debug, Clone)]
+pub struct Saved {
+    pub bookmark: Bookmark,
+    /// `false` when the URL was already saved and has been updated instead
+    pub created: bool,
+}
+
+/// `filter` as a query string, leaving out what isn't set.
+pub fn query_string(filter: &Filter) -> String {
+    let mut query = form_urlencoded::Serializer::new(String::new());
+    let Ok(Value::Object(fields)) = serde_json::to_value(filter) else {
+        unreachable!("a struct serializes to an object")
+    };
+    for (name, value) in fields {
+        match value {
+            Value::Null => {}
+            Value::String(value) => {
+                query.append_pair(&name, &value);
+            }
+            value => {
+                query.append_pair(&name, &value.to_string());
+            }
+        }
+    }
+    query.finish()
+}
+
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base: Url,
+    token: String,
+}
+
+impl Client {
+    /// A client for the server at `base_url`, authenticating with an API key.
+    pub fn new(base_url: &str, token: &str) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent(format!("linkman-client/{}", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Self::with_http_client(http, base_url, token)
+    }
+
+    /// The same with a `reqwest::Client` of your own, for other timeouts or a proxy.
+    pub fn with_http_client(http: reqwest::Client, base_url: &str, token: &str) -> Result<Self> {
+        let base = Url::parse(base_url).map_err(|_| Error::Url(base_url.to_string()))?;
+        if !matches!(base.scheme(), "http" | "https") || base.cannot_be_a_base() {
+            return Err(Error::Url(base_url.to_string()));
+        }
+        Ok(Client { http, base, token: token.to_string() })
+    }
+
+    /// The address of `segments` under the base URL, each one escaped.
+    fn url(&self, segments: &[&str]) -> Url {
+        let mut url = self.base.clone();
+        url.path_segments_mut().expect("checked in with_http_client").pop_if_empty().extend(segments);
+        url
+    }
+
+    fn request(&self, method: Method, url: Url) -> RequestBuilder {
+        self.http.request(method, url).bearer_auth(&self.token)
+    }
+
+    async fn send(request: RequestBuilder) -> Result<Response> {
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(Error::Status(response.status()));
+        }
+        Ok(response)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, url: Url) -> Result<T> {
+        Ok(Self::send(self.request(Method::GET, url)).await?.json().await?)
+    }
+
+    async fn send_json<T: DeserializeOwned>(&self, method: Method, url: Url, body: &impl Serialize) -> Result<T> {
+        Ok(Self::send(self.request(method, url).json(body)).await?.json().await?)
+    }
+
+    async fn post_empty<T: DeserializeOwned>(&self, url: Url) -> Result<T> {
+        Ok(Self::send(self.request(Method::POST, url)).await?.json().await?)
+    }
+
+    /// For the endpoints that answer `204 No Content`.
+    async fn send_no_content(&self, request: RequestBuilder) -> Result<()> {
+        Self::send(request).await?;
+        Ok(())
+    }
+
+    /// `GET /bookmarks`: one page of what `filter` matches, `limit` long (50 when `None`, at
+    /// most 200), starting at a `next_cursor` from the page before.
+    pub async fn list_bookmarks(&self, filter: &Filter, limit: Option<u32>, cursor: Option<&str>) -> Result<BookmarkPage> {
+        let mut url = self.url(&["bookmarks"]);
+        url.set_query(Some(&query_string(filter)).filter(|query| !query.is_empty()).map(String::as_str));
+        if let Some(limit) = limit {
+            url.query_pairs_mut().append_pair("limit", &limit.to_string());
+        }
+        if let Some(cursor) = cursor {
+            url.query_pairs_mut().append_pair("cursor", cursor);
+        }
+        self.get(url).await
+    }
+
+    /// `GET /bookmarks/search`: everything `filter` matches at once, newest first, or the 50
+    /// closest with `semantic` set.
+    pub async fn search(&self, filter: &Filter) -> Result<Vec<Bookmark>> {
+        let mut url = self.url(&["bookmarks", "search"]);
+        url.set_query(Some(&query_string(filter)));
+        self.get(url).await
+    }
+
+    pub async fn get_bookmark(&self, id: Uuid) -> Result<Bookmark> {
+        self.get(self.url(&["bookmarks", &id.to_string()])).await
+    }
+
+    /// `POST /bookmarks/sync`: saves a bookmark, or updates the one already saved at its URL.
+    pub async fn save_bookmark(&self, bookmark: &NewBookmark) -> Result<Saved> {
+        let response = Self::send(self.request(Method::POST, self.url(&["bookmarks", "sync"])).json(bookmark)).await?;
+        let created = response.status() == StatusCode::CREATED;
+        Ok(Saved { bookmark: response.json().await?, created })
+    }
+
+    pub async fn update_bookmark(&self, id: Uuid, update: &BookmarkUpdate) -> Result<Bookmark> {
+        self.send_json(Method::PATCH, self.url(&["bookmarks", &id.to_string()]), update).await
+    }
+
+    /// Moves a bookmark to the trash.
+    pub async fn delete_bookmark(&self, id: Uuid) -> Result<()> {
+        self.send_no_content(self.request(Method::DELETE, self.url(&["bookmarks", &id.to_string()]))).await
+    }
+
+    pub async fn trash(&self) -> Result<Vec<Bookmark>> {
+        self.get(self.url(&["bookmarks", "trash"])).await
+    }
+
+    /// Takes a bookmark back out of the trash.
+    pub async fn restore_bookmark(&self, id: Uuid) -> Result<Bookmark> {
+        self.post_empty(self.url(&["bookmarks", &id.to_string(), "restore"])).await
+    }
+
+    /// Flips a bookmark between read and unread.
+    pub async fn toggle_read(&self, id: Uuid) -> Result<Bookmark> {
+        self.post_empty(self.url(&["bookmarks", &id.to_string(), "read"])).await
+    }
+
+    pub async fn toggle_favorite(&self, id: Uuid) -> Result<Bookmark> {
+        self.post_empty(self.url(&["bookmarks", &id.to_string(), "favorite"])).await
+    }
+
+    /// The page as Markdown; `Error::Status(NOT_FOUND)` until it has been fetched.
+    pub async fn bookmark_content(&self, id: Uuid) -> Result<Content> {
+        self.get(self.url(&["bookmarks", &id.to_string(), "content"])).await
+    }
+
+    /// The user's tags by how often they're used, those starting with `prefix` if it's given.
+    pub async fn tags(&self, prefix: Option<&str>, limit: Option<u32>) -> Result<Vec<TagCount>> {
+        let mut url = self.url(&["tags"]);
+        if let Some(prefix) = prefix {
+            url.query_pairs_mut().append_pair("prefix", prefix);
+        }
+        if let Some(limit) = limit {
+            url.query_pairs_mut().append_pair("limit", &limit.to_string());
+        }
+        self.get(url).await
+    }
+
+    /// Renames a tag and the ones nested under it.
+    pub async fn rename_tag(&self, from: &str, to: &str) -> Result<()> {
+        let request = self.request(Method::POST, self.url(&["tags", "rename"])).json(&json!({ "from": from, "to": to }));
+        self.send_no_content(request).await
+    }
+
+    /// Retags everything tagged with any of `from` with `into`, removing `from`.
+    pub async fn merge_tags(&self, from: &[String], into: &str) -> Result<()> {
+        let request = self.request(Method::POST, self.url(&["tags", "merge"])).json(&json!({ "from": from, "into": into }));
+        self.send_no_content(request).await
+    }
+
+    /// Takes a tag off every bookmark; tags nested under it stay.
+    pub async fn delete_tag(&self, tag: &str) -> Result<()> {
+        let mut segments = vec!["tags"];
+        segments.extend(tag.split('/'));
+        self.send_no_content(self.request(Method::DELETE, self.url(&segments))).await
+    }
+
+    pub async fn collections(&self) -> Result<Vec<Collection>> {
+        self.get(self.url(&["collections"])).await
+    }
+
+    pub async fn create_collection(&self, collection: &NewCollection) -> Result<Collection> {
+        self.send_json(Method::POST, self.url(&["collections"]), collection).await
+    }
+
+    /// Deletes a collection; its bookmarks are kept.
+    pub async fn delete_collection(&self, id: Uuid) -> Result<()> {
+        self.send_no_content(self.request(Method::DELETE, self.url(&["collections", &id.to_string()]))).await
+    }
+
+    pub async fn saved_searches(&self) -> Result<Vec<SavedSearch>> {
+        self.get(self.url(&["searches"])).await
+    }
+
+    pub async fn save_search(&self, name: &str, params: &Filter) -> Result<SavedSearch> {
+        self.send_json(Method::POST, self.url(&["searches"]), &json!({ "name": name, "params": params })).await
+    }
+
+    pub async fn delete_saved_search(&self, id: Uuid) -> Result<()> {
+        self.send_no_content(self.request(Method::DELETE, self.url(&["searches", &id.to_string()]))).await
+    }
+
+    /// A page of what a saved search matches now, as `list_bookmarks` pages.
+    pub async fn saved_search_results(&self, id: Uuid, limit: Option<u32>, cursor: Option<&str>) -> Result<BookmarkPage> {
+        let mut url = self.url(&["searches", &id.to_string(), "results"]);
+        if let Some(limit) = limit {
+            url.query_pairs_mut().append_pair("limit", &limit.to_string());
+        }
+        if let Some(cursor) = cursor {
+            url.query_pairs_mut().append_pair("cursor", cursor);
+        }
+        self.get(url).await
+    }
+
+    pub async fn stats(&self) -> Result<LibraryStats> {
+        self.get(self.url(&["stats"])).await
+    }
+
+    /// The newest weekly digest; `Error::Status(NOT_FOUND)` before the first is written.
+    pub async fn latest_digest(&self) -> Result<Digest> {
+        self.get(self.url(&["digests", "latest"])).await
+    }
+
+    pub async fn collection_suggestions(&self) -> Result<Vec<CollectionSuggestion>> {
+        self.get(self.url(&["suggestions", "collections"])).await
+    }
+
+    /// Files a suggestion's bookmarks into a collection of its name, which is made if need be.
+    pub async fn accept_suggestion(&self, id: Uuid) -> Result<Collection> {
+        self.post_empty(self.url(&["suggestions", "collections", &id.to_string(), "accept"])).await
+    }
+
+    pub async fn dismiss_suggestion(&self, id: Uuid) -> Result<()> {
+        let url = self.url(&["suggestions", "collections", &id.to_string()]);
+        self.send_no_content(self.request(Method::DELETE, url)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_string() {
+        let filter = Filter {
+            q: Some("rust async".to_string()),
+            tag: Some("dev/rust".to_string()),
+            unread: Some(true),
+            sort: Some(Sort::MostVisited),
+            ..Default::default()
+        };
+        assert_eq!(query_string(&filter), "q=rust+async&sort=most_visited&tag=dev%2Frust&unread=true");
+        assert_eq!(query_string(&Filter::default()), "");
+    }
+
+    #[test]
+    fn test_url() {
+        let client = Client::new("https://example.com/linkman/", "key").unwrap();
+        assert_eq!(client.url(&["tags", "c++", "dev"]).as_str(), "https://example.com/linkman/tags/c++/dev");
+        assert_eq!(client.url(&["tags", "a b"]).as_str(), "https://example.com/linkman/tags/a%20b");
+        assert!(matches!(Client::new("ftp://example.com", "key"), Err(Error::Url(_))));
+        assert!(matches!(Client::new("localhost:3000", "key"), Err(Error::Url(_))));
+    }
+}
@@ -0,0 +1,275 @@
+//! The JSON the API sends and takes, as the server writes it.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub id: Uuid,
+    pub url: String,
+    pub title: Option<String>,
+    /// The title was taken from the page rather than given by the user
+    pub title_from_page: bool,
+    pub notes: Option<String>,
+    /// The page's own description of itself, from OpenGraph or JSON-LD
+    pub description: Option<String>,
+    /// Lead image of the page
+    pub image_url: Option<String>,
+    pub published_at: Option<DateTime<Utc>>,
+    pub author: Option<String>,
+    pub ai_summary: Option<String>,
+    /// ISO 639-1 code of the language the page is written in, if it could be told
+    pub language: Option<String>,
+    /// What the URL served when it was last fetched, e.g. `text/html` or `application/pdf`
+    pub content_type: Option<String>,
+    /// Provider, author, thumbnail and duration of a video or other oEmbed page
+    pub embed: Option<Value>,
+    /// Language, topics and star count of a repository synced from GitHub stars
+    pub github: Option<Value>,
+    /// Where the link was talked about, like its Hacker News or Reddit thread
+    pub discussion_url: Option<String>,
+    pub tags: Option<Vec<String>>,
+    /// Those of `tags` the user gave
+    pub user_tags: Option<Vec<String>>,
+    /// Those of `tags` the model added
+    pub ai_tags: Option<Vec<String>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// The site it's on, like `lobste.rs`
+    pub domain: Option<String>,
+    pub archived_at: Option<DateTime<Utc>>,
+    pub last_checked_at: Option<DateTime<Utc>>,
+    pub last_status: Option<i32>,
+    pub is_broken: bool,
+    /// `pending`, `fetching`, `tagging`, `done` or `failed`
+    pub processing_status: String,
+    /// Why processing last failed, while it's being retried or after it gave up
+    pub error_message: Option<String>,
+    /// `timeout`, `too_large`, `too_many_redirects`, `unavailable`, `private_address` or
+    /// `robots_disallowed` when the page itself was the problem
+    pub error_code: Option<String>,
+    /// The Wayback Machine snapshot the content came from, when the page itself was gone
+    pub snapshot_url: Option<String>,
+    pub snapshot_at: Option<DateTime<Utc>>,
+    /// The Wayback Machine's capture of the page, once one has been asked for
+    pub wayback_url: Option<String>,
+    pub wayback_saved_at: Option<DateTime<Utc>>,
+    /// When the page last changed enough to keep what it said before
+    pub content_changed_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub is_read: bool,
+    pub read_at: Option<DateTime<Utc>>,
+    pub is_favorite: bool,
+    pub is_public: bool,
+    /// Times it was opened through `/go/{id}`
+    pub visit_count: i32,
+    pub last_visited_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookmarkPage {
+    pub bookmarks: Vec<Bookmark>,
+    /// Where the next page starts, if there is one
+    pub next_cursor: Option<String>,
+}
+
+/// How the model's tags combine with the ones the user gave a bookmark.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMode {
+    /// Alongside the user's tags; only the model's previous tags are replaced
+    Merge,
+    /// Instead of every tag the bookmark had
+    Replace,
+}
+
+/// A bookmark to save, or to update if the URL is already saved.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NewBookmark {
+    pub url: String,
+    pub title: Option<String>,
+    pub notes: Option<String>,
+    /// Added to whatever tags the bookmark already has
+    pub tags: Option<Vec<String>>,
+    /// Defaults to the API key's
+    pub tag_mode: Option<TagMode>,
+}
+
+/// A partial update; what's left `None` stays as it is, and `tags` replaces the whole set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BookmarkUpdate {
+    pub url: Option<String>,
+    pub title: Option<String>,
+    pub notes: Option<String>,
+    pub tags: Option<Vec<String>>,
+    /// Whether the bookmark shows up on the owner's `/public/{username}` page
+    pub is_public: Option<bool>,
+}
+
+/// The page as Markdown, as it was last fetched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Content {
+    pub markdown: String,
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// What `GET /bookmarks` and search filter by. Everything left `None` matches anything.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Filter {
+    /// Substring of the URL, title, notes, summary or a tag. A `site:lobste.rs` word in it limits
+    /// the results to that site instead, like `domain`
+    pub q: Option<String>,
+    /// Natural-language query ranked by embedding similarity instead of substring matching
+    pub semantic: Option<String>,
+    /// `broken` or `ok`, as decided by the link checker, or a processing status such as `failed`
+    pub status: Option<String>,
+    /// A processing status on its own, to combine with a link checker `status`
+    pub processing: Option<String>,
+    /// `true` for bookmarks with no tags at all, neither the user's nor the model's
+    pub untagged: Option<bool>,
+    /// `true` for the read-later queue, `false` for what has already been read
+    pub unread: Option<bool>,
+    pub favorite: Option<bool>,
+    /// Only bookmarks filed directly in this collection
+    pub collection: Option<Uuid>,
+    /// A tag path; `dev/rust` also matches `dev/rust/async`
+    pub tag: Option<String>,
+    /// Like `tag`, but only tags the user gave
+    pub user_tag: Option<String>,
+    /// Like `tag`, but only tags the model added
+    pub ai_tag: Option<String>,
+    /// Language of the page, as an ISO 639-1 code like `en`
+    pub lang: Option<String>,
+    /// The site the bookmark is on, like `lobste.rs`
+    pub domain: Option<String>,
+    /// Only bookmarks saved before this time
+    pub created_before: Option<DateTime<Utc>>,
+    /// Only bookmarks saved at or after this time
+    pub created_after: Option<DateTime<Utc>>,
+    /// What to list by, `created_at` when omitted
+    pub sort: Option<Sort>,
+    /// Newest first for the dates and A to Z for the rest when omitted
+    pub order: Option<Order>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sort {
+    CreatedAt,
+    UpdatedAt,
+    Title,
+    Domain,
+    /// Most opened through `/go/{id}` first
+    MostVisited,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagCount {
+    pub name: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Collection {
+    pub id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub bookmark_count: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NewCollection {
+    pub name: String,
+    pub description: Option<String>,
+    /// Nests it inside another collection
+    pub parent_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSearch {
+    pub id: Uuid,
+    pub name: String,
+    pub params: Filter,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryStats {
+    pub total: i64,
+    pub unread: i64,
+    pub favorites: i64,
+    pub broken: i64,
+    pub untagged: i64,
+    pub failed: i64,
+    pub trashed: i64,
+    /// Bookmarks saved each week, oldest first, with empty weeks included
+    pub per_week: Vec<WeekCount>,
+    pub top_tags: Vec<TagCount>,
+    pub top_domains: Vec<DomainCount>,
+    pub storage: Storage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeekCount {
+    /// The Monday the week starts on
+    pub week: NaiveDate,
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DomainCount {
+    pub domain: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Storage {
+    pub archived_pages: i64,
+    pub archive_bytes: i64,
+    /// Page content kept in the database, earlier versions included
+    pub content_bytes: i64,
+    pub screenshot_bytes: i64,
+}
+
+/// A bookmark as a digest or a suggestion lists it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookmarkLink {
+    pub id: Uuid,
+    pub url: String,
+    pub title: Option<String>,
+}
+
+/// The model's write-up of a week's bookmarks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Digest {
+    /// The Monday the week starts on
+    pub week_start: NaiveDate,
+    pub summary: String,
+    pub themes: Vec<String>,
+    /// Everything saved that week; the write-up covers at most the newest 100
+    pub bookmark_count: i32,
+    /// The bookmarks it was written from, less any trashed since
+    pub bookmarks: Vec<BookmarkLink>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Uncollected bookmarks the server thinks belong together.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionSuggestion {
+    pub id: Uuid,
+    pub name: String,
+    /// Those still uncollected and out of the trash
+    pub bookmarks: Vec<BookmarkLink>,
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,5 @@
+-- Stable (created_at, id) ordering for cursor pagination
+UPDATE bookmarks SET created_at = now() WHERE created_at IS NULL;
+ALTER TABLE bookmarks ALTER COLUMN created_at SET NOT NULL;
+
+CREATE INDEX bookmarks_user_created_idx ON bookmarks (user_id, created_at DESC, id DESC);
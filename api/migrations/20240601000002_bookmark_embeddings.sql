@@ -0,0 +1,11 @@
+-- Embeddings for semantic search. pgvector is optional: without it the column is left out
+-- and the server refuses to start only if EMBEDDING_MODEL is configured.
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_available_extensions WHERE name = 'vector') THEN
+        CREATE EXTENSION IF NOT EXISTS vector;
+        -- Dimensionless so the embedding model can be swapped; re-run the backfill after doing so
+        ALTER TABLE bookmarks ADD COLUMN embedding vector;
+    END IF;
+END
+$$;
@@ -0,0 +1,6 @@
+-- Markdown copy of each bookmarked page, kept apart from bookmarks so listing stays cheap
+CREATE TABLE bookmark_contents (
+    bookmark_id UUID PRIMARY KEY REFERENCES bookmarks(id) ON DELETE CASCADE,
+    markdown TEXT NOT NULL,
+    fetched_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,3 @@
+-- Location of the raw HTML snapshot in object storage, when archival is enabled
+ALTER TABLE bookmarks ADD COLUMN archive_url TEXT;
+ALTER TABLE bookmarks ADD COLUMN archived_at TIMESTAMPTZ;
@@ -0,0 +1,7 @@
+-- Page thumbnails from the optional screenshot service
+CREATE TABLE bookmark_screenshots (
+    bookmark_id UUID PRIMARY KEY REFERENCES bookmarks(id) ON DELETE CASCADE,
+    content_type TEXT NOT NULL,
+    image BYTEA NOT NULL,
+    captured_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,7 @@
+-- Dead link checker state
+ALTER TABLE bookmarks ADD COLUMN last_checked_at TIMESTAMPTZ;
+ALTER TABLE bookmarks ADD COLUMN last_status INT;
+ALTER TABLE bookmarks ADD COLUMN link_failures INT NOT NULL DEFAULT 0;
+ALTER TABLE bookmarks ADD COLUMN is_broken BOOLEAN NOT NULL DEFAULT false;
+
+CREATE INDEX bookmarks_last_checked_idx ON bookmarks (last_checked_at NULLS FIRST);
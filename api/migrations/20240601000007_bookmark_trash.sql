@@ -0,0 +1,4 @@
+-- Soft delete: trashed bookmarks keep their row until the purge job removes them
+ALTER TABLE bookmarks ADD COLUMN deleted_at TIMESTAMPTZ;
+
+CREATE INDEX bookmarks_deleted_at_idx ON bookmarks (deleted_at) WHERE deleted_at IS NOT NULL;
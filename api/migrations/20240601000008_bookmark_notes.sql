@@ -0,0 +1,2 @@
+-- Free-text notes written by the user, as opposed to the AI summary
+ALTER TABLE bookmarks ADD COLUMN notes TEXT;
@@ -0,0 +1,4 @@
+-- Read-later tracking; NULL means unread
+ALTER TABLE bookmarks ADD COLUMN read_at TIMESTAMPTZ;
+
+CREATE INDEX bookmarks_unread_idx ON bookmarks (user_id, created_at DESC, id DESC) WHERE read_at IS NULL;
@@ -0,0 +1,4 @@
+-- Starred bookmarks
+ALTER TABLE bookmarks ADD COLUMN is_favorite BOOLEAN NOT NULL DEFAULT false;
+
+CREATE INDEX bookmarks_favorite_idx ON bookmarks (user_id, created_at DESC, id DESC) WHERE is_favorite;
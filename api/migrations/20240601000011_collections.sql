@@ -0,0 +1,22 @@
+-- Folder-style grouping of bookmarks, independent of tags
+CREATE TABLE collections (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    parent_id UUID REFERENCES collections(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    description TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Sibling names are unique, top-level collections included
+CREATE UNIQUE INDEX collections_sibling_name_idx
+    ON collections (user_id, COALESCE(parent_id, '00000000-0000-0000-0000-000000000000'), name);
+
+CREATE TABLE collection_bookmarks (
+    collection_id UUID NOT NULL REFERENCES collections(id) ON DELETE CASCADE,
+    bookmark_id UUID NOT NULL REFERENCES bookmarks(id) ON DELETE CASCADE,
+    added_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (collection_id, bookmark_id)
+);
+
+CREATE INDEX collection_bookmarks_bookmark_idx ON collection_bookmarks (bookmark_id);
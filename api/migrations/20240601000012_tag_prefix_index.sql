@@ -0,0 +1,2 @@
+-- Case-insensitive prefix lookups for tag autocomplete
+CREATE INDEX tags_name_prefix_idx ON tags (user_id, lower(name) text_pattern_ops);
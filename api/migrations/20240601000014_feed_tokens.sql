@@ -0,0 +1,2 @@
+-- Read-only token for feed readers, which can't send an Authorization header
+ALTER TABLE users ADD COLUMN feed_token UUID UNIQUE;
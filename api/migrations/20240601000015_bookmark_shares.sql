@@ -0,0 +1,6 @@
+-- Public links to a single bookmark; deleting the row revokes the link
+CREATE TABLE bookmark_shares (
+    token UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    bookmark_id UUID NOT NULL UNIQUE REFERENCES bookmarks(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,4 @@
+-- Bookmarks listed on the owner's public profile
+ALTER TABLE bookmarks ADD COLUMN is_public BOOLEAN NOT NULL DEFAULT false;
+
+CREATE INDEX bookmarks_public_idx ON bookmarks (user_id, created_at DESC, id DESC) WHERE is_public;
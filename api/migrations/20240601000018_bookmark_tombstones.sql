@@ -0,0 +1,12 @@
+-- Delta sync: changes are found by updated_at, and hard deletes leave a tombstone behind
+UPDATE bookmarks SET updated_at = created_at WHERE updated_at IS NULL;
+
+CREATE INDEX bookmarks_user_updated_at_idx ON bookmarks (user_id, updated_at);
+
+CREATE TABLE bookmark_tombstones (
+    bookmark_id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    deleted_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX bookmark_tombstones_user_deleted_at_idx ON bookmark_tombstones (user_id, deleted_at);
@@ -0,0 +1,2 @@
+-- What a token may do: `read` (GET and GraphQL) and/or `write` (everything else)
+ALTER TABLE api_tokens ADD COLUMN scopes TEXT[] NOT NULL DEFAULT '{read,write}';
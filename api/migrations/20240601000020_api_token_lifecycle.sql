@@ -0,0 +1,4 @@
+-- Tokens are secrets, so they get a separate id to be referred to by
+ALTER TABLE api_tokens ADD COLUMN id UUID NOT NULL UNIQUE DEFAULT gen_random_uuid();
+ALTER TABLE api_tokens ADD COLUMN expires_at TIMESTAMPTZ;
+ALTER TABLE api_tokens ADD COLUMN revoked_at TIMESTAMPTZ;
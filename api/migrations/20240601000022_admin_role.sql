@@ -0,0 +1,2 @@
+-- Granted with `linkman set-admin <username>`
+ALTER TABLE users ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,4 @@
+-- Usage stats, updated as each request authenticates
+ALTER TABLE api_tokens ADD COLUMN last_used_at TIMESTAMPTZ;
+ALTER TABLE api_tokens ADD COLUMN request_count BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE api_tokens ADD COLUMN last_ip TEXT;
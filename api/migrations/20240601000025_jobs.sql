@@ -0,0 +1,15 @@
+-- Bookmarks waiting to be fetched and tagged; rows are deleted once processed
+CREATE TABLE jobs (
+    id BIGSERIAL PRIMARY KEY,
+    bookmark_id UUID NOT NULL REFERENCES bookmarks(id) ON DELETE CASCADE,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'running', 'dead')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_run_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    locked_at TIMESTAMPTZ,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX jobs_due_idx ON jobs (next_run_at, id) WHERE status <> 'dead';
+-- At most one live job per bookmark
+CREATE UNIQUE INDEX jobs_bookmark_live_idx ON jobs (bookmark_id) WHERE status IN ('pending', 'running');
@@ -0,0 +1,19 @@
+-- Where each bookmark is in fetching and tagging, and why it last failed
+CREATE TYPE processing_status AS ENUM ('pending', 'fetching', 'tagging', 'done', 'failed');
+
+ALTER TABLE bookmarks
+    ADD COLUMN processing_status processing_status NOT NULL DEFAULT 'pending',
+    ADD COLUMN error_message TEXT;
+
+-- Anything without a job left has been processed already
+UPDATE bookmarks b SET
+    processing_status = CASE j.status WHEN 'dead' THEN 'failed'::processing_status ELSE 'pending' END,
+    error_message = j.last_error
+FROM jobs j
+WHERE j.bookmark_id = b.id;
+
+UPDATE bookmarks SET processing_status = 'done'
+WHERE NOT EXISTS (SELECT 1 FROM jobs WHERE jobs.bookmark_id = bookmarks.id);
+
+CREATE INDEX bookmarks_processing_status_idx ON bookmarks (user_id, processing_status)
+    WHERE processing_status <> 'done';
@@ -0,0 +1,12 @@
+-- Keep finished jobs, so how long processing took stays visible
+ALTER TABLE jobs
+    ADD COLUMN started_at TIMESTAMPTZ,
+    ADD COLUMN finished_at TIMESTAMPTZ,
+    DROP CONSTRAINT jobs_status_check,
+    ADD CONSTRAINT jobs_status_check CHECK (status IN ('pending', 'running', 'done', 'dead'));
+
+UPDATE jobs SET finished_at = created_at WHERE status = 'dead';
+
+DROP INDEX jobs_due_idx;
+CREATE INDEX jobs_due_idx ON jobs (next_run_at, id) WHERE status IN ('pending', 'running');
+CREATE INDEX jobs_bookmark_idx ON jobs (bookmark_id, id);
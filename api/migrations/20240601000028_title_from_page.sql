@@ -0,0 +1,3 @@
+-- Titles filled in from the page itself, which processing may refresh; a title the user gave is
+-- never replaced
+ALTER TABLE bookmarks ADD COLUMN title_from_page BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,6 @@
+-- Tags the AI may choose from; a user without any gets free-form tags
+CREATE TABLE tag_vocabulary (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    PRIMARY KEY (user_id, name)
+);
@@ -0,0 +1,12 @@
+-- Who put each tag on a bookmark, so re-tagging only swaps out the model's. There's no telling
+-- for tags that are already there, so they count as the user's.
+CREATE TYPE tag_source AS ENUM ('user', 'ai');
+
+ALTER TABLE bookmark_tags ADD COLUMN source tag_source NOT NULL DEFAULT 'user';
+
+-- How the model's tags combine with the user's: 'merge' adds them alongside, 'replace' drops the
+-- user's. Keys carry a default for what they save; each bookmark remembers what it was saved with.
+CREATE TYPE tag_mode AS ENUM ('merge', 'replace');
+
+ALTER TABLE api_tokens ADD COLUMN tag_mode tag_mode NOT NULL DEFAULT 'merge';
+ALTER TABLE bookmarks ADD COLUMN tag_mode tag_mode NOT NULL DEFAULT 'merge';
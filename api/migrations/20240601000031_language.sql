@@ -0,0 +1,4 @@
+-- ISO 639-1 code of the language each page is written in, where it could be told
+ALTER TABLE bookmarks ADD COLUMN language TEXT;
+
+CREATE INDEX bookmarks_language_idx ON bookmarks (user_id, language) WHERE language IS NOT NULL;
@@ -0,0 +1,2 @@
+-- What each bookmarked URL served when it was last fetched, e.g. text/html or application/pdf
+ALTER TABLE bookmarks ADD COLUMN content_type TEXT;
@@ -0,0 +1,6 @@
+-- What pages say about themselves in OpenGraph and JSON-LD, for clients to render
+ALTER TABLE bookmarks
+    ADD COLUMN description TEXT,
+    ADD COLUMN image_url TEXT,
+    ADD COLUMN published_at TIMESTAMPTZ,
+    ADD COLUMN author TEXT;
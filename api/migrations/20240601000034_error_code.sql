@@ -0,0 +1,3 @@
+-- Machine-readable reason processing failed, when the page itself was the problem:
+-- timeout, too_large, too_many_redirects or robots_disallowed
+ALTER TABLE bookmarks ADD COLUMN error_code TEXT;
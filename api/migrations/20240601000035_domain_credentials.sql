@@ -0,0 +1,12 @@
+-- Cookies or basic auth for fetching a user's pages behind a login, encrypted with
+-- FETCH_CREDENTIALS_KEY
+CREATE TABLE domain_credentials (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- Matches the domain and its subdomains
+    domain TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('cookie', 'basic')),
+    sealed BYTEA NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (user_id, domain)
+);
@@ -0,0 +1,2 @@
+-- Set when the page was gone and its Wayback Machine snapshot was processed instead
+ALTER TABLE bookmarks ADD COLUMN snapshot_url TEXT, ADD COLUMN snapshot_at TIMESTAMPTZ;
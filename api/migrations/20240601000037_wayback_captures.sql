@@ -0,0 +1,2 @@
+-- The Wayback Machine's capture of the page, made through Save Page Now
+ALTER TABLE bookmarks ADD COLUMN wayback_url TEXT, ADD COLUMN wayback_saved_at TIMESTAMPTZ;
@@ -0,0 +1,14 @@
+-- Noticing when a bookmarked page changes: a hash of the current content, the content it
+-- replaced, and when the page was last fetched again to compare
+ALTER TABLE bookmark_contents ADD COLUMN content_hash TEXT;
+ALTER TABLE bookmarks ADD COLUMN content_changed_at TIMESTAMPTZ, ADD COLUMN recrawled_at TIMESTAMPTZ;
+
+CREATE TABLE bookmark_content_versions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    bookmark_id UUID NOT NULL REFERENCES bookmarks(id) ON DELETE CASCADE,
+    markdown TEXT NOT NULL,
+    content_hash TEXT,
+    fetched_at TIMESTAMPTZ NOT NULL,
+    replaced_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX bookmark_content_versions_bookmark_idx ON bookmark_content_versions (bookmark_id, replaced_at DESC);
@@ -0,0 +1,2 @@
+-- Where a bookmarked link was found and talked about, like its Hacker News or Reddit thread
+ALTER TABLE bookmarks ADD COLUMN discussion_url TEXT;
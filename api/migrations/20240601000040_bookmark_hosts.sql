@@ -0,0 +1,4 @@
+-- The site a bookmark is on, for filtering by it: the URL's host, lowercased and without `www.`
+ALTER TABLE bookmarks ADD COLUMN host TEXT
+    GENERATED ALWAYS AS (lower(substring(url from '^[^:/]+://(?:[^/?#@]*@)?(?:[wW][wW][wW]\.)?([^/?#:]+)'))) STORED;
+CREATE INDEX bookmarks_host_idx ON bookmarks (user_id, host);
@@ -0,0 +1,2 @@
+-- How big the archived copy of the page is, for reporting storage use; unknown for older ones
+ALTER TABLE bookmarks ADD COLUMN archive_bytes BIGINT;
@@ -0,0 +1,11 @@
+-- Opening a bookmark through /go/{id}: each visit, and a running count for sorting by
+CREATE TABLE bookmark_visits (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    bookmark_id UUID NOT NULL REFERENCES bookmarks(id) ON DELETE CASCADE,
+    visited_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    referrer TEXT
+);
+CREATE INDEX bookmark_visits_bookmark_idx ON bookmark_visits (bookmark_id, visited_at DESC);
+
+ALTER TABLE bookmarks ADD COLUMN visit_count INTEGER NOT NULL DEFAULT 0, ADD COLUMN last_visited_at TIMESTAMPTZ;
+CREATE INDEX bookmarks_visits_idx ON bookmarks (user_id, visit_count DESC, id DESC);
@@ -0,0 +1,14 @@
+-- The model's write-up of each user's week of bookmarks
+CREATE TABLE digests (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- The Monday the week starts on
+    week_start DATE NOT NULL,
+    summary TEXT NOT NULL,
+    themes TEXT[] NOT NULL DEFAULT '{}',
+    -- The bookmarks it was written from
+    bookmark_ids UUID[] NOT NULL DEFAULT '{}',
+    bookmark_count INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (user_id, week_start)
+);
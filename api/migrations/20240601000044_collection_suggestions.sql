@@ -0,0 +1,9 @@
+-- Groups of uncollected bookmarks found by clustering their embeddings, each with a proposed name
+CREATE TABLE collection_suggestions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    bookmark_ids UUID[] NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX collection_suggestions_user_idx ON collection_suggestions (user_id);
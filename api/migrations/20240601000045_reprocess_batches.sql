@@ -0,0 +1,9 @@
+-- Bookmarks an admin queued for processing together, so progress can be followed as one
+CREATE TABLE reprocess_batches (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    -- Who it was limited to and the filters it matched, as sent
+    request JSONB NOT NULL,
+    bookmark_ids UUID[] NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,8 @@
+-- Slack users who connected their account with `/linkman connect`, and the API key they gave
+CREATE TABLE slack_users (
+    team_id TEXT NOT NULL,
+    slack_user_id TEXT NOT NULL,
+    key_id UUID NOT NULL REFERENCES api_tokens(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (team_id, slack_user_id)
+);
@@ -0,0 +1,7 @@
+-- Deliveries wait here for their next attempt, so retries survive a restart. NULL once the
+-- delivery got through or ran out of attempts.
+ALTER TABLE webhook_deliveries ADD COLUMN next_attempt_at TIMESTAMPTZ;
+-- Set while a worker is making an attempt
+ALTER TABLE webhook_deliveries ADD COLUMN locked_at TIMESTAMPTZ;
+
+CREATE INDEX webhook_deliveries_due_idx ON webhook_deliveries (next_attempt_at) WHERE next_attempt_at IS NOT NULL;
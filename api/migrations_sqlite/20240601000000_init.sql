@@ -0,0 +1,66 @@
+-- The SQLite backend's schema: the core of the Postgres one, for a single-user install.
+-- Ids are UUIDs stored as blobs and times are RFC 3339 text, both written by the server.
+
+CREATE TABLE users (
+    id BLOB PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE api_tokens (
+    token BLOB PRIMARY KEY,
+    -- The key's public id, as the Postgres schema has
+    id BLOB NOT NULL UNIQUE,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    device_name TEXT NOT NULL,
+    -- A JSON array of `read` and `write`
+    scopes TEXT NOT NULL DEFAULT '["read","write"]',
+    tag_mode TEXT NOT NULL DEFAULT 'merge' CHECK (tag_mode IN ('merge', 'replace')),
+    expires_at TEXT,
+    last_used_at TEXT,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE bookmarks (
+    id BLOB PRIMARY KEY,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    -- The site it's on, like `lobste.rs`, for `site:` and `domain=`
+    host TEXT,
+    title TEXT,
+    title_from_page INTEGER NOT NULL DEFAULT 0,
+    notes TEXT,
+    description TEXT,
+    ai_summary TEXT,
+    content_type TEXT,
+    tag_mode TEXT NOT NULL DEFAULT 'merge' CHECK (tag_mode IN ('merge', 'replace')),
+    processing_status TEXT NOT NULL DEFAULT 'pending'
+        CHECK (processing_status IN ('pending', 'fetching', 'tagging', 'done', 'failed')),
+    error_message TEXT,
+    is_public INTEGER NOT NULL DEFAULT 0,
+    is_favorite INTEGER NOT NULL DEFAULT 0,
+    read_at TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    deleted_at TEXT,
+    UNIQUE (user_id, url)
+);
+
+CREATE INDEX bookmarks_user_created_idx ON bookmarks (user_id, created_at, id);
+CREATE INDEX bookmarks_host_idx ON bookmarks (user_id, host);
+
+CREATE TABLE tags (
+    id INTEGER PRIMARY KEY,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    UNIQUE (user_id, name)
+);
+
+CREATE TABLE bookmark_tags (
+    bookmark_id BLOB NOT NULL REFERENCES bookmarks(id) ON DELETE CASCADE,
+    tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    source TEXT NOT NULL DEFAULT 'user' CHECK (source IN ('user', 'ai')),
+    PRIMARY KEY (bookmark_id, tag_id)
+);
+
+CREATE INDEX bookmark_tags_tag_idx ON bookmark_tags (tag_id);
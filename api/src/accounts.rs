@@ -0,0 +1,313 @@
+//! Password logins and browser sessions.
+//!
+//! Logging in sets an HTTP-only session cookie that authenticates requests like a full-access API
+//! token, for a web UI. Passwords are hashed with Argon2id. `/admin/register` only issues more
+//! tokens for an existing username along with its password. Browsers attach the cookie to
+//! requests other sites' pages make, too, so writes with it are refused unless they come from our
+//! own pages.
+
+use argon2::{
+    password_hash::{PasswordHasher, PasswordVerifier},
+    Argon2,
+};
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::put,
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{ApiKey, AppState, CurrentUser, TagMode, SCOPES};
+
+const SESSION_COOKIE: &str = "linkman_session";
+const SESSION_DAYS: i64 = 30;
+const MIN_PASSWORD_LEN: usize = 8;
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/auth/password", put(set_password))
+}
+
+fn db_error(e: sqlx::Error) -> StatusCode {
+    eprintln!("Accounts Error: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Argon2 is deliberately slow, so keep it off the async workers.
+async fn hash_password(password: String) -> Result<String, StatusCode> {
+    tokio::task::spawn_blocking(move || Argon2::default().hash_password(password.as_bytes()).map(|h| h.to_string()))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map_err(|e| {
+            eprintln!("Password Hash Error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+pub async fn verify_password(password: String, hash: String) -> bool {
+    tokio::task::spawn_blocking(move || Argon2::default().verify_password(password.as_bytes(), hash.as_str()).is_ok())
+        .await
+        .unwrap_or(false)
+}
+
+#[derive(Deserialize)]
+pub struct Credentials {
+    username: String,
+    password: String,
+}
+
+#[derive(Serialize)]
+pub struct Account {
+    username: String,
+}
+
+/// `Secure` unless the server is set up for plain HTTP, where the browser would never send it back.
+fn session_cookie(state: &AppState, token: Uuid, max_age: i64) -> String {
+    format!(
+        "{}={}; HttpOnly;{} SameSite=Lax; Path=/; Max-Age={}",
+        SESSION_COOKIE,
+        token,
+        if state.secure_cookies { " Secure;" } else { "" },
+        max_age
+    )
+}
+
+/// Starts a session for a user, returning the `Set-Cookie` value that carries it.
+pub async fn new_session(state: &AppState, user_id: Uuid) -> Result<String, StatusCode> {
+    sqlx::query("DELETE FROM sessions WHERE user_id = $1 AND expires_at <= now()")
+        .bind(user_id)
+        .execute(&state.db)
+        .await
+        .map_err(db_error)?;
+
+    let token: Uuid = sqlx::query_scalar(
+        "INSERT INTO sessions (user_id, expires_at) VALUES ($1, now() + make_interval(days => $2)) RETURNING token"
+    )
+    .bind(user_id)
+    .bind(SESSION_DAYS as i32)
+    .fetch_one(&state.db)
+    .await
+    .map_err(db_error)?;
+    Ok(session_cookie(state, token, SESSION_DAYS * 24 * 60 * 60))
+}
+
+async fn start_session(state: &AppState, user: CurrentUser) -> Result<Response, StatusCode> {
+    let cookie = new_session(state, user.id).await?;
+    Ok(([(header::SET_COOKIE, cookie)], Json(Account { username: user.username })).into_response())
+}
+
+/// Creates an account with a password and logs it in.
+pub async fn signup(
+    State(state): State<AppState>,
+    Json(payload): Json<Credentials>,
+) -> Result<Response, StatusCode> {
+    let username = payload.username.trim();
+    if username.is_empty() || payload.password.len() < MIN_PASSWORD_LEN {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let hash = hash_password(payload.password).await?;
+
+    let user = sqlx::query_as::<_, CurrentUser>(
+        "INSERT INTO users (username, password_hash) VALUES ($1, $2)
+         ON CONFLICT (username) DO NOTHING
+         RETURNING id, username"
+    )
+    .bind(username)
+    .bind(hash)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(db_error)?
+    .ok_or(StatusCode::CONFLICT)?;
+
+    let mut res = start_session(&state, user).await?;
+    *res.status_mut() = StatusCode::CREATED;
+    Ok(res)
+}
+
+pub async fn login(
+    State(state): State<AppState>,
+    Json(payload): Json<Credentials>,
+) -> Result<Response, StatusCode> {
+    let user = check_password(&state, &payload.username, payload.password).await?;
+    start_session(&state, user).await
+}
+
+/// The user with this username and password, or `401 Unauthorized`.
+pub async fn check_password(state: &AppState, username: &str, password: String) -> Result<CurrentUser, StatusCode> {
+    let (id, username, hash): (Uuid, String, Option<String>) =
+        sqlx::query_as("SELECT id, username, password_hash FROM users WHERE username = $1")
+            .bind(username.trim())
+            .fetch_optional(&state.db)
+            .await
+            .map_err(db_error)?
+            .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    // Accounts made by `/admin/register` have no password until one is set
+    let hash = hash.ok_or(StatusCode::UNAUTHORIZED)?;
+    if !verify_password(password, hash).await {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(CurrentUser { id, username })
+}
+
+fn session_token(headers: &HeaderMap) -> Option<Uuid> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .find_map(|pair| pair.trim().strip_prefix(SESSION_COOKIE)?.strip_prefix('='))
+        .and_then(|token| Uuid::parse_str(token).ok())
+}
+
+/// Whether the browser says the request came from a page on another origin: by `Sec-Fetch-Site`,
+/// or failing that an `Origin` that isn't the `Host`. Requests with neither aren't from a page.
+pub fn cross_site(headers: &HeaderMap) -> bool {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    if let Some(site) = header(header::HeaderName::from_static("sec-fetch-site")) {
+        return !matches!(site, "same-origin" | "none");
+    }
+    let Some(origin) = header(header::ORIGIN) else { return false };
+    let authority = url::Url::parse(origin).ok().and_then(|url| {
+        let host = url.host_str()?.to_string();
+        Some(url.port().map_or(host.clone(), |port| format!("{}:{}", host, port)))
+    });
+    authority.is_none_or(|authority| Some(authority.as_str()) != header(header::HOST))
+}
+
+pub async fn logout(State(state): State<AppState>, headers: HeaderMap) -> Result<Response, StatusCode> {
+    let cookie = end_session(&state, &headers).await?;
+    Ok((StatusCode::NO_CONTENT, [(header::SET_COOKIE, cookie)]).into_response())
+}
+
+/// Ends the session in the request's cookie, if there is one, returning the `Set-Cookie` value
+/// that clears it.
+pub async fn end_session(state: &AppState, headers: &HeaderMap) -> Result<String, StatusCode> {
+    if let Some(token) = session_token(headers) {
+        sqlx::query("DELETE FROM sessions WHERE token = $1")
+            .bind(token)
+            .execute(&state.db)
+            .await
+            .map_err(db_error)?;
+    }
+    Ok(session_cookie(state, Uuid::nil(), 0))
+}
+
+/// Resolves the session cookie, if any, to its user. Sessions carry every scope.
+pub async fn authenticate_session(state: &AppState, headers: &HeaderMap) -> Result<(CurrentUser, ApiKey), StatusCode> {
+    let token = session_token(headers).ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let user = sqlx::query_as::<_, CurrentUser>(
+        "SELECT u.id, u.username FROM users u JOIN sessions s ON s.user_id = u.id
+         WHERE s.token = $1 AND s.expires_at > now()"
+    )
+    .bind(token)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(db_error)?
+    .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    Ok((
+        user,
+        ApiKey {
+            token,
+            id: None,
+            scopes: SCOPES.map(String::from).to_vec(),
+            tag_mode: TagMode::default(),
+        },
+    ))
+}
+
+#[derive(Deserialize)]
+struct SetPasswordRequest {
+    password: String,
+    /// Required when the account already has a password
+    current_password: Option<String>,
+}
+
+/// Sets or changes the password, logging out every other session. The first password needs a key
+/// with every scope, since the sessions it lets the account start have them all.
+async fn set_password(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Extension(key): Extension<ApiKey>,
+    Json(payload): Json<SetPasswordRequest>,
+) -> Result<StatusCode, StatusCode> {
+    if payload.password.len() < MIN_PASSWORD_LEN {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let current: Option<String> = sqlx::query_scalar("SELECT password_hash FROM users WHERE id = $1")
+        .bind(user.id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(db_error)?;
+    match current {
+        Some(hash) => {
+            let given = payload.current_password.ok_or(StatusCode::FORBIDDEN)?;
+            if !verify_password(given, hash).await {
+                return Err(StatusCode::FORBIDDEN);
+            }
+        }
+        None if !key.is_full() => return Err(StatusCode::FORBIDDEN),
+        None => {}
+    }
+
+    let hash = hash_password(payload.password).await?;
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    sqlx::query("UPDATE users SET password_hash = $1 WHERE id = $2")
+        .bind(hash)
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    sqlx::query("DELETE FROM sessions WHERE user_id = $1 AND token <> $2")
+        .bind(user.id)
+        .bind(key.token)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    tx.commit().await.map_err(db_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_token() {
+        let token = Uuid::new_v4();
+        let mut headers = HeaderMap::new();
+        assert_eq!(session_token(&headers), None);
+
+        headers.insert(header::COOKIE, format!("theme=dark; {}={}", SESSION_COOKIE, token).parse().unwrap());
+        assert_eq!(session_token(&headers), Some(token));
+
+        headers.insert(header::COOKIE, format!("{}x={}", SESSION_COOKIE, token).parse().unwrap());
+        assert_eq!(session_token(&headers), None);
+    }
+
+    #[test]
+    fn test_cross_site() {
+        let mut headers = HeaderMap::new();
+        assert!(!cross_site(&headers));
+
+        headers.insert(header::HOST, "links.example:3000".parse().unwrap());
+        headers.insert(header::ORIGIN, "http://links.example:3000".parse().unwrap());
+        assert!(!cross_site(&headers));
+        headers.insert(header::ORIGIN, "https://evil.example".parse().unwrap());
+        assert!(cross_site(&headers));
+        headers.insert(header::ORIGIN, "null".parse().unwrap());
+        assert!(cross_site(&headers));
+
+        // Behind a proxy that rewrites `Host`, the browser's own word is what counts
+        headers.insert("sec-fetch-site", "same-origin".parse().unwrap());
+        assert!(!cross_site(&headers));
+        headers.insert("sec-fetch-site", "same-site".parse().unwrap());
+        assert!(cross_site(&headers));
+    }
+}
@@ -0,0 +1,431 @@
+//! Instance administration across every user's data.
+//!
+//! These routes sit behind the usual authentication plus a check that the caller's account has
+//! `is_admin` set, which only the `set-admin` CLI command can grant.
+
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::StatusCode,
+    middleware::{self, Next},
+    response::Response,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::{api_keys, jobs, push_bulk_filter, suggestions, AppState, CurrentUser, SearchParams, SCOPES};
+
+pub fn router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/admin/users", get(list_users))
+        .route("/admin/bookmarks/reprocess", post(reprocess_bookmarks))
+        .route("/admin/bookmarks/reprocess/{id}", get(reprocess_progress))
+        .route("/admin/bookmarks/{id}/reprocess", post(reprocess_bookmark))
+        .route("/admin/api-keys", get(list_keys))
+        .route("/admin/api-keys/{id}/revoke", post(revoke_key))
+        .route("/admin/api-keys/{id}/rotate", post(rotate_key))
+        .route("/admin/audit", get(list_audit))
+        .route("/admin/jobs", get(list_jobs))
+        .route("/admin/jobs/{id}/retry", post(retry_job))
+        .route("/admin/suggestions/collections", post(suggest_collections))
+        .layer(middleware::from_fn_with_state(state, require_admin))
+}
+
+const DEFAULT_AUDIT_ENTRIES: i64 = 100;
+const MAX_AUDIT_ENTRIES: i64 = 1000;
+
+fn db_error(e: sqlx::Error) -> StatusCode {
+    eprintln!("Admin Error: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Runs behind authentication, which provides the user to check.
+async fn require_admin(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    req: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let is_admin: bool = sqlx::query_scalar("SELECT is_admin FROM users WHERE id = $1")
+        .bind(user.id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(db_error)?;
+    if !is_admin {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(next.run(req).await)
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct UserSummary {
+    id: Uuid,
+    username: String,
+    is_admin: bool,
+    created_at: Option<DateTime<Utc>>,
+    bookmark_count: i64,
+    api_key_count: i64,
+}
+
+async fn list_users(State(state): State<AppState>) -> Result<Json<Vec<UserSummary>>, StatusCode> {
+    sqlx::query_as::<_, UserSummary>(
+        "SELECT u.id, u.username, u.is_admin, u.created_at,
+         (SELECT count(*) FROM bookmarks b WHERE b.user_id = u.id AND b.deleted_at IS NULL) as bookmark_count,
+         (SELECT count(*) FROM api_tokens t WHERE t.user_id = u.id AND t.revoked_at IS NULL) as api_key_count
+         FROM users u ORDER BY u.username"
+    )
+    .fetch_all(&state.db)
+    .await
+    .map(Json)
+    .map_err(db_error)
+}
+
+/// Fetches and tags a bookmark again, whoever owns it, e.g. after the model was changed.
+async fn reprocess_bookmark(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    sqlx::query_scalar::<_, Uuid>("SELECT id FROM bookmarks WHERE id = $1 AND deleted_at IS NULL")
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    jobs::enqueue(&state, &[id]).await.map_err(db_error)?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[derive(Serialize, Deserialize)]
+struct ReprocessRequest {
+    /// A username; everyone's bookmarks when omitted
+    user: Option<String>,
+    /// `GET /bookmarks` filters, such as `{"processing": "failed"}`, `{"untagged": true}`, or a
+    /// `tag` with `created_after` and `created_before`; at least one is needed, besides `sort`,
+    /// `order` or a blank `q`
+    filter: SearchParams,
+    /// Only count what would be queued
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Serialize)]
+struct ReprocessStarted {
+    /// For `GET /admin/bookmarks/reprocess/{id}`; none on a dry run
+    id: Option<Uuid>,
+    count: usize,
+    dry_run: bool,
+}
+
+/// Queues every bookmark matching a filter to be fetched and tagged again, across users.
+async fn reprocess_bookmarks(
+    State(state): State<AppState>,
+    Extension(admin): Extension<CurrentUser>,
+    Json(payload): Json<ReprocessRequest>,
+) -> Result<(StatusCode, Json<ReprocessStarted>), StatusCode> {
+    let mut query = QueryBuilder::<Postgres>::new("SELECT b.id FROM bookmarks b WHERE true");
+    if let Some(username) = &payload.user {
+        let user_id: Uuid = sqlx::query_scalar("SELECT id FROM users WHERE username = $1")
+            .bind(username)
+            .fetch_optional(&state.db)
+            .await
+            .map_err(db_error)?
+            .ok_or(StatusCode::NOT_FOUND)?;
+        query.push(" AND b.user_id = ").push_bind(user_id);
+    }
+    push_bulk_filter(&mut query, &payload.filter)?;
+    query.push(" ORDER BY b.created_at, b.id");
+    let ids: Vec<Uuid> = query.build_query_scalar().fetch_all(&state.db).await.map_err(db_error)?;
+    if payload.dry_run {
+        return Ok((StatusCode::OK, Json(ReprocessStarted { id: None, count: ids.len(), dry_run: true })));
+    }
+
+    let id: Uuid = sqlx::query_scalar(
+        "INSERT INTO reprocess_batches (created_by, request, bookmark_ids) VALUES ($1, $2, $3) RETURNING id"
+    )
+    .bind(admin.id)
+    .bind(serde_json::to_value(&payload).unwrap_or_default())
+    .bind(&ids)
+    .fetch_one(&state.db)
+    .await
+    .map_err(db_error)?;
+    jobs::enqueue(&state, &ids).await.map_err(db_error)?;
+    Ok((StatusCode::ACCEPTED, Json(ReprocessStarted { id: Some(id), count: ids.len(), dry_run: false })))
+}
+
+/// How far a bulk reprocess has got, from where each of its bookmarks is now.
+#[derive(Serialize, sqlx::FromRow)]
+struct ReprocessProgress {
+    id: Uuid,
+    created_at: DateTime<Utc>,
+    total: i64,
+    /// Waiting in the queue, retries included
+    pending: i64,
+    /// Being fetched or tagged
+    processing: i64,
+    done: i64,
+    /// Gave up after their last attempt
+    failed: i64,
+    /// Deleted since, trashed or not
+    removed: i64,
+    /// Nothing is left to wait for
+    finished: bool,
+}
+
+async fn reprocess_progress(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ReprocessProgress>, StatusCode> {
+    sqlx::query_as::<_, ReprocessProgress>(
+        "SELECT r.id, r.created_at, s.total, s.pending, s.processing, s.done, s.failed,
+             s.total - s.pending - s.processing - s.done - s.failed AS removed,
+             s.pending + s.processing = 0 AS finished
+         FROM reprocess_batches r, LATERAL (
+             SELECT cardinality(r.bookmark_ids)::bigint AS total,
+                 count(*) FILTER (WHERE b.processing_status = 'pending') AS pending,
+                 count(*) FILTER (WHERE b.processing_status IN ('fetching', 'tagging')) AS processing,
+                 count(*) FILTER (WHERE b.processing_status = 'done') AS done,
+                 count(*) FILTER (WHERE b.processing_status = 'failed') AS failed
+             FROM bookmarks b WHERE b.id = ANY(r.bookmark_ids) AND b.deleted_at IS NULL
+         ) s
+         WHERE r.id = $1"
+    )
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(db_error)?
+    .map(Json)
+    .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[derive(Deserialize)]
+struct JobParams {
+    /// `pending`, `running`, `dead` or `done`; everything but `done` by default
+    status: Option<String>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct JobInfo {
+    bookmark_id: Uuid,
+    username: String,
+    url: String,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    job: jobs::JobResponse,
+}
+
+/// The processing queue, oldest first; `?status=dead` shows what gave up.
+async fn list_jobs(
+    State(state): State<AppState>,
+    Query(params): Query<JobParams>,
+) -> Result<Json<Vec<JobInfo>>, StatusCode> {
+    sqlx::query_as::<_, JobInfo>(&format!(
+        "SELECT j.bookmark_id, u.username, b.url, {}
+         FROM jobs j JOIN bookmarks b ON b.id = j.bookmark_id JOIN users u ON u.id = b.user_id
+         WHERE ($1::text IS NULL AND j.status <> 'done') OR j.status = $1
+         ORDER BY j.created_at, j.id
+         LIMIT 1000",
+        jobs::JOB_COLUMNS
+    ))
+    .bind(&params.status)
+    .fetch_all(&state.db)
+    .await
+    .map(Json)
+    .map_err(db_error)
+}
+
+/// Gives a dead job a fresh set of attempts.
+async fn retry_job(State(state): State<AppState>, Path(id): Path<i64>) -> Result<StatusCode, StatusCode> {
+    let retried = sqlx::query(
+        "WITH retried AS (
+             UPDATE jobs SET status = 'pending', attempts = 0, next_run_at = now(), last_error = NULL, finished_at = NULL
+             WHERE id = $1 AND status = 'dead'
+             RETURNING bookmark_id
+         )
+         UPDATE bookmarks SET processing_status = 'pending', error_message = NULL, error_code = NULL
+         FROM retried WHERE bookmarks.id = retried.bookmark_id"
+    )
+    .bind(id)
+    .execute(&state.db)
+    .await
+    .map_err(|e| match e {
+        // The bookmark was queued again since
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => StatusCode::CONFLICT,
+        e => db_error(e),
+    })?;
+    if retried.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    state.job_wakeup.notify_one();
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[derive(Deserialize)]
+struct SuggestParams {
+    /// A username; everyone when omitted
+    user: Option<String>,
+}
+
+/// Starts clustering the uncollected bookmarks of one user, or everyone, into suggested
+/// collections. It runs in the background; the suggestions replace each user's earlier ones.
+async fn suggest_collections(
+    State(state): State<AppState>,
+    Query(params): Query<SuggestParams>,
+) -> Result<StatusCode, StatusCode> {
+    if state.embedding_model.is_none() {
+        return Err(StatusCode::NOT_IMPLEMENTED);
+    }
+    let users: Vec<(Uuid, String)> =
+        sqlx::query_as("SELECT id, username FROM users WHERE $1::text IS NULL OR username = $1 ORDER BY username")
+            .bind(&params.user)
+            .fetch_all(&state.db)
+            .await
+            .map_err(db_error)?;
+    if users.is_empty() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    tokio::spawn(async move {
+        let mut ok = true;
+        for (id, username) in users {
+            match suggestions::suggest(&state, id).await {
+                Ok(count) => println!("suggested {} collections for {}", count, username),
+                Err(e) => {
+                    eprintln!("Error suggesting collections for {}: {}", username, e);
+                    ok = false;
+                }
+            }
+        }
+        state.metrics.record_job("collection_suggestions", ok);
+    });
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct KeyUsage {
+    id: Uuid,
+    username: String,
+    device_name: String,
+    scopes: Vec<String>,
+    created_at: Option<DateTime<Utc>>,
+    expires_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+    last_used_at: Option<DateTime<Utc>>,
+    request_count: i64,
+    last_ip: Option<String>,
+}
+
+/// Every user's keys, least recently used first, so stale ones are easy to spot.
+async fn list_keys(State(state): State<AppState>) -> Result<Json<Vec<KeyUsage>>, StatusCode> {
+    sqlx::query_as::<_, KeyUsage>(
+        "SELECT t.id, u.username, t.device_name, t.scopes, t.created_at, t.expires_at, t.revoked_at,
+             t.last_used_at, t.request_count, t.last_ip
+         FROM api_tokens t JOIN users u ON u.id = t.user_id
+         ORDER BY t.last_used_at NULLS FIRST, t.created_at"
+    )
+    .fetch_all(&state.db)
+    .await
+    .map(Json)
+    .map_err(db_error)
+}
+
+async fn revoke_key(State(state): State<AppState>, Path(id): Path<Uuid>) -> Result<StatusCode, StatusCode> {
+    if !api_keys::revoke(&state.db, None, id).await.map_err(db_error)? {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn rotate_key(State(state): State<AppState>, Path(id): Path<Uuid>) -> Result<Json<api_keys::RotatedKey>, StatusCode> {
+    api_keys::rotate(&state.db, None, id, &SCOPES.map(String::from))
+        .await
+        .map_err(db_error)?
+        .map(|token| Json(api_keys::RotatedKey { token }))
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[derive(Deserialize)]
+struct AuditParams {
+    /// Username
+    user: Option<String>,
+    method: Option<String>,
+    /// Path prefix, e.g. `/bookmarks`
+    path: Option<String>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    /// Only entries older than this id, for paging back
+    before: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct AuditEntry {
+    id: i64,
+    user_id: Option<Uuid>,
+    username: Option<String>,
+    api_key_id: Option<Uuid>,
+    method: String,
+    path: String,
+    status: i32,
+    summary: Value,
+    created_at: DateTime<Utc>,
+}
+
+/// The audit log, newest first.
+async fn list_audit(
+    State(state): State<AppState>,
+    Query(params): Query<AuditParams>,
+) -> Result<Json<Vec<AuditEntry>>, StatusCode> {
+    let mut query = QueryBuilder::<Postgres>::new(
+        "SELECT id, user_id, username, api_key_id, method, path, status, summary, created_at
+         FROM audit_log WHERE true"
+    );
+    if let Some(user) = &params.user {
+        query.push(" AND username = ").push_bind(user);
+    }
+    if let Some(method) = &params.method {
+        query.push(" AND method = ").push_bind(method.to_uppercase());
+    }
+    if let Some(path) = &params.path {
+        query.push(" AND starts_with(path, ").push_bind(path).push(")");
+    }
+    if let Some(since) = params.since {
+        query.push(" AND created_at >= ").push_bind(since);
+    }
+    if let Some(until) = params.until {
+        query.push(" AND created_at < ").push_bind(until);
+    }
+    if let Some(before) = params.before {
+        query.push(" AND id < ").push_bind(before);
+    }
+    query
+        .push(" ORDER BY id DESC LIMIT ")
+        .push_bind(params.limit.unwrap_or(DEFAULT_AUDIT_ENTRIES).clamp(1, MAX_AUDIT_ENTRIES));
+
+    query
+        .build_query_as::<AuditEntry>()
+        .fetch_all(&state.db)
+        .await
+        .map(Json)
+        .map_err(db_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reprocess_needs_a_filter() {
+        let push = |json: &str| {
+            let payload: ReprocessRequest = serde_json::from_str(json).unwrap();
+            push_bulk_filter(&mut QueryBuilder::<Postgres>::new("SELECT b.id FROM bookmarks b WHERE true"), &payload.filter)
+        };
+        assert_eq!(push(r#"{"filter": {"sort": "title"}}"#), Err(StatusCode::BAD_REQUEST));
+        assert_eq!(push(r#"{"user": "alice", "filter": {"order": "asc", "q": " "}}"#), Err(StatusCode::BAD_REQUEST));
+        assert_eq!(push(r#"{"filter": {"processing": "failed"}}"#), Ok(()));
+    }
+}
@@ -0,0 +1,162 @@
+//! Managing API tokens after they've been issued: listing, changing how they tag, revoking and
+//! rotating.
+//!
+//! Tokens are the secret, so they're addressed by a separate `id`; the CLI and admins, who are
+//! trusted, may use either. Revoked tokens are kept so the listing shows what was killed and when.
+//! Only a session or a key with every scope may manage keys, or a limited key could rotate a
+//! sibling into a token with more access than its own.
+
+use axum::{
+    extract::{Path, Request, State},
+    http::StatusCode,
+    middleware::{self, Next},
+    response::Response,
+    routing::{get, patch, post},
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{ApiKey, AppState, CurrentUser, TagMode};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api-keys", get(list_keys))
+        .route("/api-keys/{id}", patch(update_key))
+        .route("/api-keys/{id}/revoke", post(revoke_key))
+        .route("/api-keys/{id}/rotate", post(rotate_key))
+        .route_layer(middleware::from_fn(require_full_key))
+}
+
+/// Runs behind authentication, which provides the key to check.
+async fn require_full_key(Extension(key): Extension<ApiKey>, req: Request, next: Next) -> Result<Response, StatusCode> {
+    if !key.is_full() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(next.run(req).await)
+}
+
+fn db_error(e: sqlx::Error) -> StatusCode {
+    eprintln!("API Keys Error: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct KeyInfo {
+    id: Uuid,
+    device_name: String,
+    scopes: Vec<String>,
+    tag_mode: TagMode,
+    created_at: Option<DateTime<Utc>>,
+    expires_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+    last_used_at: Option<DateTime<Utc>>,
+    request_count: i64,
+    last_ip: Option<String>,
+}
+
+async fn list_keys(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Result<Json<Vec<KeyInfo>>, StatusCode> {
+    sqlx::query_as::<_, KeyInfo>(
+        "SELECT id, device_name, scopes, tag_mode, created_at, expires_at, revoked_at, last_used_at, request_count, last_ip
+         FROM api_tokens WHERE user_id = $1 ORDER BY created_at, id"
+    )
+    .bind(user.id)
+    .fetch_all(&state.db)
+    .await
+    .map(Json)
+    .map_err(db_error)
+}
+
+#[derive(Deserialize)]
+struct UpdateKeyRequest {
+    /// How bookmarks saved with the key are tagged, unless the request says otherwise
+    tag_mode: TagMode,
+}
+
+async fn update_key(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdateKeyRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let updated = sqlx::query("UPDATE api_tokens SET tag_mode = $1 WHERE id = $2 AND user_id = $3 AND revoked_at IS NULL")
+        .bind(payload.tag_mode)
+        .bind(id)
+        .bind(user.id)
+        .execute(&state.db)
+        .await
+        .map_err(db_error)?;
+    if updated.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn revoke_key(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    if !revoke(&state.db, Some(user.id), id).await.map_err(db_error)? {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Serialize)]
+pub struct RotatedKey {
+    pub token: Uuid,
+}
+
+async fn rotate_key(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Extension(key): Extension<ApiKey>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<RotatedKey>, StatusCode> {
+    rotate(&state.db, Some(user.id), id, &key.scopes)
+        .await
+        .map_err(db_error)?
+        .map(|token| Json(RotatedKey { token }))
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Revokes a live key given its id, or for the CLI and admins (`owner` of `None`) its id or token.
+pub async fn revoke(db: &PgPool, owner: Option<Uuid>, key: Uuid) -> Result<bool, sqlx::Error> {
+    let revoked = sqlx::query(
+        "UPDATE api_tokens SET revoked_at = now()
+         WHERE (id = $1 OR ($2::uuid IS NULL AND token = $1)) AND ($2 IS NULL OR user_id = $2)
+             AND revoked_at IS NULL"
+    )
+    .bind(key)
+    .bind(owner)
+    .execute(db)
+    .await?;
+    Ok(revoked.rows_affected() > 0)
+}
+
+/// Revokes a live, unexpired key and issues a replacement with the same device, scopes, tag mode
+/// and expiry, returning the new token. Keys with a scope outside `within` are left alone.
+pub async fn rotate(db: &PgPool, owner: Option<Uuid>, key: Uuid, within: &[String]) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar(
+        "WITH old AS (
+             UPDATE api_tokens SET revoked_at = now()
+             WHERE (id = $1 OR ($2::uuid IS NULL AND token = $1)) AND ($2 IS NULL OR user_id = $2)
+                 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > now()) AND scopes <@ $3
+             RETURNING user_id, device_name, scopes, tag_mode, expires_at
+         )
+         INSERT INTO api_tokens (user_id, device_name, scopes, tag_mode, expires_at)
+         SELECT user_id, device_name, scopes, tag_mode, expires_at FROM old
+         RETURNING token"
+    )
+    .bind(key)
+    .bind(owner)
+    .bind(within)
+    .fetch_optional(db)
+    .await
+}
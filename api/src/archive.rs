@@ -0,0 +1,78 @@
+//! Raw page snapshots kept in S3-compatible object storage.
+//!
+//! Enabled by setting `ARCHIVE_S3_BUCKET`; credentials, region and endpoint come from the
+//! standard `AWS_*` variables (`AWS_ENDPOINT` and `AWS_ALLOW_HTTP` for MinIO/Garage and friends).
+
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use object_store::{aws::AmazonS3Builder, path::Path, ObjectStore, PutPayload};
+use uuid::Uuid;
+
+use crate::config::ArchiveConfig;
+
+pub struct Archive {
+    store: Arc<dyn ObjectStore>,
+    bucket: String,
+}
+
+impl Archive {
+    pub fn from_config(config: &ArchiveConfig) -> anyhow::Result<Option<Self>> {
+        let Some(bucket) = config.s3_bucket.clone() else {
+            return Ok(None);
+        };
+
+        let store = AmazonS3Builder::from_env()
+            .with_bucket_name(&bucket)
+            .build()
+            .map_err(|e| anyhow::anyhow!("Invalid archive storage configuration: {}", e))?;
+
+        Ok(Some(Archive {
+            store: Arc::new(store),
+            bucket,
+        }))
+    }
+
+    fn key(bookmark_id: Uuid) -> Path {
+        Path::from(format!("bookmarks/{}/page.html", bookmark_id))
+    }
+
+    /// Stores the page HTML for a bookmark, replacing any earlier snapshot, and returns its URL.
+    pub async fn put_page(&self, bookmark_id: Uuid, html: String) -> anyhow::Result<String> {
+        let key = Self::key(bookmark_id);
+        self.store.put(&key, PutPayload::from(html)).await?;
+        Ok(format!("s3://{}/{}", self.bucket, key))
+    }
+
+    pub async fn get_page(&self, bookmark_id: Uuid) -> anyhow::Result<Bytes> {
+        Ok(self.store.get(&Self::key(bookmark_id)).await?.bytes().await?)
+    }
+
+    pub async fn delete_page(&self, bookmark_id: Uuid) -> anyhow::Result<()> {
+        self.store.delete(&Self::key(bookmark_id)).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+
+    #[tokio::test]
+    async fn test_page_roundtrip() {
+        let archive = Archive {
+            store: Arc::new(InMemory::new()),
+            bucket: "linkman".to_string(),
+        };
+        let id = Uuid::new_v4();
+
+        let url = archive.put_page(id, "<h1>Saved</h1>".to_string()).await.unwrap();
+        assert_eq!(url, format!("s3://linkman/bookmarks/{}/page.html", id));
+        assert_eq!(archive.get_page(id).await.unwrap(), Bytes::from("<h1>Saved</h1>"));
+        assert!(archive.get_page(Uuid::new_v4()).await.is_err());
+
+        archive.delete_page(id).await.unwrap();
+        assert!(archive.get_page(id).await.is_err());
+    }
+}
@@ -0,0 +1,147 @@
+//! Append-only log of every request that changes something, for `GET /admin/audit`.
+//!
+//! Entries keep the user, the API key (none for browser sessions), the route, the response status
+//! and a summary of what was sent: small JSON bodies and query parameters with credentials
+//! blanked out, or just the size of anything else. The table refuses updates and deletes.
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::{OriginalUri, Request},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::{json, Map, Value};
+
+use crate::{ApiKey, AppState, CurrentUser};
+
+/// Larger bodies are summarised by size alone, without being buffered here.
+const MAX_LOGGED_BODY: usize = 64 * 1024;
+
+/// Field names whose values never reach the log.
+const REDACTED: [&str; 6] = ["password", "current_password", "secret", "token", "auth_token", "cookie"];
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                if REDACTED.contains(&key.as_str()) {
+                    *v = Value::String("[redacted]".to_string());
+                } else {
+                    redact(v);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+fn summarize(query: Option<&str>, content_type: Option<&str>, body: Option<&[u8]>, length: Option<usize>) -> Value {
+    let mut summary = Map::new();
+
+    if let Some(query) = query.filter(|q| !q.is_empty()) {
+        let params: Map<String, Value> = url::form_urlencoded::parse(query.as_bytes())
+            .map(|(k, v)| (k.into_owned(), Value::String(v.into_owned())))
+            .collect();
+        summary.insert("query".to_string(), Value::Object(params));
+    }
+
+    let json_body = body
+        .filter(|_| content_type.is_some_and(|t| t.starts_with("application/json")))
+        .and_then(|b| serde_json::from_slice::<Value>(b).ok());
+    match (json_body, length) {
+        (Some(body), _) => {
+            summary.insert("body".to_string(), body);
+        }
+        (None, Some(bytes)) if bytes > 0 => {
+            summary.insert("body".to_string(), json!({ "content_type": content_type, "bytes": bytes }));
+        }
+        _ => {}
+    }
+
+    let mut summary = Value::Object(summary);
+    redact(&mut summary);
+    summary
+}
+
+/// Runs a mutating request and logs it, called by the auth middlewares once they know who it is.
+/// A failed write to the log is reported but doesn't fail the request.
+pub async fn record(state: &AppState, req: Request, next: Next) -> Response {
+    let user = req.extensions().get::<CurrentUser>().cloned();
+    let key_id = req.extensions().get::<ApiKey>().and_then(|key| key.id);
+    let path = req
+        .extensions()
+        .get::<OriginalUri>()
+        .map_or_else(|| req.uri().path().to_string(), |uri| uri.path().to_string());
+    let method = req.method().to_string();
+    let query = req.uri().query().map(str::to_string);
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let length: Option<usize> = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok());
+
+    // Only small bodies of a declared size are read up front; the handler gets them back intact
+    let (req, body) = match length {
+        Some(len) if len <= MAX_LOGGED_BODY => {
+            let (parts, body) = req.into_parts();
+            match to_bytes(body, MAX_LOGGED_BODY).await {
+                Ok(bytes) => (Request::from_parts(parts, Body::from(bytes.clone())), Some(bytes)),
+                Err(_) => return StatusCode::BAD_REQUEST.into_response(),
+            }
+        }
+        _ => (req, None),
+    };
+
+    let res = next.run(req).await;
+
+    let summary = summarize(query.as_deref(), content_type.as_deref(), body.as_deref(), length);
+    let inserted = sqlx::query(
+        "INSERT INTO audit_log (user_id, username, api_key_id, method, path, status, summary)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)"
+    )
+    .bind(user.as_ref().map(|u| u.id))
+    .bind(user.as_ref().map(|u| u.username.as_str()))
+    .bind(key_id)
+    .bind(&method)
+    .bind(&path)
+    .bind(res.status().as_u16() as i32)
+    .bind(&summary)
+    .execute(&state.db)
+    .await;
+    if let Err(e) = inserted {
+        eprintln!("Audit Log Error: {}", e);
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize() {
+        let body = br#"{"username":"a","password":"hunter22","nested":[{"token":"x","url":"u"}]}"#;
+        let summary = summarize(Some("auth_token=a:secret&url=u"), Some("application/json"), Some(body), Some(body.len()));
+        assert_eq!(
+            summary,
+            json!({
+                "query": { "auth_token": "[redacted]", "url": "u" },
+                "body": { "username": "a", "password": "[redacted]", "nested": [{ "token": "[redacted]", "url": "u" }] },
+            })
+        );
+
+        assert_eq!(
+            summarize(None, Some("text/html"), None, Some(2048)),
+            json!({ "body": { "content_type": "text/html", "bytes": 2048 } })
+        );
+        assert_eq!(summarize(Some(""), None, None, Some(0)), json!({}));
+    }
+}
@@ -0,0 +1,595 @@
+//! Everything a user has saved, in one file, for moving to another server without `pg_dump`.
+//!
+//! A backup is gzipped JSON with the user's bookmarks, including their tags, stored page content
+//! and its earlier versions, plus their collections, saved searches, tag vocabulary and API keys.
+//! Keys are kept without their tokens; each restored key gets a new one, which rotating it hands
+//! out, so only keys whose scopes the restorer holds are brought back. Screenshots and embeddings aren't copied (`backfill-embeddings` makes the latter again),
+//! archives in object storage stay where they are, and webhooks and site credentials are left out
+//! because they hold secrets.
+//!
+//! Restoring adds to what the user already has, in one transaction. Bookmarks of URLs they've
+//! already saved are left as they are, and ids are kept unless something else has them by then.
+//! Bookmarks that weren't done processing when the backup was made are queued again.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+
+use axum::{
+    body::Bytes,
+    extract::{DefaultBodyLimit, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{attach_tags, events, jobs, AppState, CurrentUser, TagMode, TagSource, SCOPES};
+
+/// The format's version; backups from a newer one are refused.
+const VERSION: u32 = 1;
+
+/// Backups are mostly page content, so they can be big even compressed.
+const RESTORE_BODY_LIMIT: usize = 512 * 1024 * 1024;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/backup", get(download_backup))
+        .route("/restore", post(upload_backup).layer(DefaultBodyLimit::max(RESTORE_BODY_LIMIT)))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Backup {
+    version: u32,
+    created_at: DateTime<Utc>,
+    bookmarks: Vec<Bookmark>,
+    collections: Vec<Collection>,
+    saved_searches: Vec<SavedSearch>,
+    tag_vocabulary: Vec<String>,
+    api_keys: Vec<ApiKey>,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+struct Bookmark {
+    id: Uuid,
+    url: String,
+    title: Option<String>,
+    title_from_page: bool,
+    notes: Option<String>,
+    description: Option<String>,
+    image_url: Option<String>,
+    published_at: Option<DateTime<Utc>>,
+    author: Option<String>,
+    site_meta: Option<Value>,
+    ai_summary: Option<String>,
+    language: Option<String>,
+    content_type: Option<String>,
+    tag_mode: TagMode,
+    processing_status: String,
+    is_favorite: bool,
+    is_public: bool,
+    read_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+    updated_at: Option<DateTime<Utc>>,
+    deleted_at: Option<DateTime<Utc>>,
+    archive_url: Option<String>,
+    archive_bytes: Option<i64>,
+    archived_at: Option<DateTime<Utc>>,
+    snapshot_url: Option<String>,
+    snapshot_at: Option<DateTime<Utc>>,
+    wayback_url: Option<String>,
+    wayback_saved_at: Option<DateTime<Utc>>,
+    content_changed_at: Option<DateTime<Utc>>,
+    discussion_url: Option<String>,
+    #[serde(default)]
+    visit_count: i32,
+    last_visited_at: Option<DateTime<Utc>>,
+    #[sqlx(skip)]
+    #[serde(default)]
+    tags: Vec<Tag>,
+    #[sqlx(skip)]
+    #[serde(default)]
+    content: Option<Content>,
+    /// What the page's content used to be, oldest first
+    #[sqlx(skip)]
+    #[serde(default)]
+    versions: Vec<Content>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Tag {
+    name: String,
+    source: TagSource,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+struct Content {
+    markdown: String,
+    content_hash: Option<String>,
+    fetched_at: DateTime<Utc>,
+    /// For earlier versions, when the page changed away from it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    replaced_at: Option<DateTime<Utc>>,
+}
+
+#[derive(sqlx::FromRow)]
+struct StoredContent {
+    bookmark_id: Uuid,
+    #[sqlx(flatten)]
+    content: Content,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+struct Collection {
+    id: Uuid,
+    parent_id: Option<Uuid>,
+    name: String,
+    description: Option<String>,
+    created_at: DateTime<Utc>,
+    /// Ids of the bookmarks filed in it, from `bookmarks`
+    #[sqlx(skip)]
+    #[serde(default)]
+    bookmarks: Vec<Uuid>,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+struct SavedSearch {
+    name: String,
+    params: Value,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+struct ApiKey {
+    id: Uuid,
+    device_name: String,
+    scopes: Vec<String>,
+    tag_mode: TagMode,
+    created_at: Option<DateTime<Utc>>,
+    expires_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+/// What restoring a backup added.
+#[derive(Debug, Default, Serialize)]
+pub struct RestoreSummary {
+    pub bookmarks: usize,
+    /// Bookmarks of URLs the user already had
+    pub skipped: usize,
+    pub collections: usize,
+    pub saved_searches: usize,
+    pub api_keys: usize,
+}
+
+/// Reads everything of a user's a backup keeps, trashed bookmarks included.
+pub async fn create(db: &PgPool, user_id: Uuid) -> Result<Backup, sqlx::Error> {
+    let mut bookmarks: Vec<Bookmark> = sqlx::query_as(
+        "SELECT id, url, title, title_from_page, notes, description, image_url, published_at, author, site_meta,
+             ai_summary, language, content_type, tag_mode, processing_status::text AS processing_status,
+             is_favorite, is_public, read_at, created_at, updated_at, deleted_at, archive_url, archive_bytes,
+             archived_at, snapshot_url, snapshot_at, wayback_url, wayback_saved_at, content_changed_at, discussion_url,
+             visit_count, last_visited_at
+         FROM bookmarks WHERE user_id = $1 ORDER BY created_at, id"
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+    let positions: HashMap<Uuid, usize> = bookmarks.iter().enumerate().map(|(i, b)| (b.id, i)).collect();
+
+    let tags: Vec<(Uuid, String, TagSource)> = sqlx::query_as(
+        "SELECT bt.bookmark_id, t.name, bt.source FROM bookmark_tags bt
+         JOIN tags t ON t.id = bt.tag_id WHERE t.user_id = $1 ORDER BY t.name"
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+    for (bookmark_id, name, source) in tags {
+        if let Some(&i) = positions.get(&bookmark_id) {
+            bookmarks[i].tags.push(Tag { name, source });
+        }
+    }
+
+    let contents: Vec<StoredContent> = sqlx::query_as(
+        "SELECT c.bookmark_id, c.markdown, c.content_hash, c.fetched_at, NULL::timestamptz AS replaced_at
+         FROM bookmark_contents c JOIN bookmarks b ON b.id = c.bookmark_id WHERE b.user_id = $1"
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+    for stored in contents {
+        if let Some(&i) = positions.get(&stored.bookmark_id) {
+            bookmarks[i].content = Some(stored.content);
+        }
+    }
+
+    let versions: Vec<StoredContent> = sqlx::query_as(
+        "SELECT v.bookmark_id, v.markdown, v.content_hash, v.fetched_at, v.replaced_at
+         FROM bookmark_content_versions v JOIN bookmarks b ON b.id = v.bookmark_id WHERE b.user_id = $1
+         ORDER BY v.replaced_at"
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+    for stored in versions {
+        if let Some(&i) = positions.get(&stored.bookmark_id) {
+            bookmarks[i].versions.push(stored.content);
+        }
+    }
+
+    let mut collections: Vec<Collection> = sqlx::query_as(
+        "SELECT id, parent_id, name, description, created_at FROM collections WHERE user_id = $1 ORDER BY created_at, id"
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+    let members: Vec<(Uuid, Uuid)> = sqlx::query_as(
+        "SELECT cb.collection_id, cb.bookmark_id FROM collection_bookmarks cb
+         JOIN collections c ON c.id = cb.collection_id WHERE c.user_id = $1 ORDER BY cb.added_at"
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+    for (collection_id, bookmark_id) in members {
+        if let Some(collection) = collections.iter_mut().find(|c| c.id == collection_id) {
+            collection.bookmarks.push(bookmark_id);
+        }
+    }
+
+    Ok(Backup {
+        version: VERSION,
+        created_at: Utc::now(),
+        bookmarks,
+        collections,
+        saved_searches: sqlx::query_as(
+            "SELECT name, params, created_at FROM saved_searches WHERE user_id = $1 ORDER BY created_at"
+        )
+        .bind(user_id)
+        .fetch_all(db)
+        .await?,
+        tag_vocabulary: sqlx::query_scalar("SELECT name FROM tag_vocabulary WHERE user_id = $1 ORDER BY name")
+            .bind(user_id)
+            .fetch_all(db)
+            .await?,
+        api_keys: sqlx::query_as(
+            "SELECT id, device_name, scopes, tag_mode, created_at, expires_at, revoked_at
+             FROM api_tokens WHERE user_id = $1 ORDER BY created_at, id"
+        )
+        .bind(user_id)
+        .fetch_all(db)
+        .await?,
+    })
+}
+
+pub fn encode(backup: &Backup) -> anyhow::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    serde_json::to_writer(&mut encoder, backup)?;
+    encoder.flush()?;
+    Ok(encoder.finish()?)
+}
+
+/// Reads a backup, gzipped or not.
+pub fn decode(data: &[u8]) -> anyhow::Result<Backup> {
+    let backup: Backup = if data.starts_with(&[0x1f, 0x8b]) {
+        let mut json = Vec::new();
+        GzDecoder::new(data).read_to_end(&mut json)?;
+        serde_json::from_slice(&json)?
+    } else {
+        serde_json::from_slice(data)?
+    };
+    if backup.version > VERSION {
+        anyhow::bail!("backup is version {}, this server reads up to {}", backup.version, VERSION);
+    }
+    Ok(backup)
+}
+
+/// Swaps in a fresh id when `$1` is taken already, e.g. when restoring onto the same server.
+fn free_id(table: &str) -> String {
+    format!("CASE WHEN EXISTS (SELECT 1 FROM {} WHERE id = $1) THEN gen_random_uuid() ELSE $1 END", table)
+}
+
+/// Adds a backup's contents to a user's, in one transaction.
+/// Whether a key from a backup may be restored by someone holding `within`: its scopes have to be
+/// real ones, and ones they already have.
+fn restorable(scopes: &[String], within: &[String]) -> bool {
+    !scopes.is_empty() && scopes.iter().all(|scope| SCOPES.contains(&scope.as_str()) && within.contains(scope))
+}
+
+/// Restores `backup` for a user; `within` is the scopes of whoever is restoring it, which bounds
+/// the API keys it brings back.
+pub async fn restore(state: &AppState, user_id: Uuid, backup: Backup, within: &[String]) -> Result<RestoreSummary, sqlx::Error> {
+    let mut summary = RestoreSummary::default();
+    let mut tx = state.db.begin().await?;
+
+    // Old ids to new, for collections to find their bookmarks by
+    let mut bookmark_ids = HashMap::new();
+    let mut restored = Vec::new();
+    let mut unprocessed = Vec::new();
+    for bookmark in &backup.bookmarks {
+        let done = bookmark.processing_status == "done";
+        let inserted: Option<Uuid> = sqlx::query_scalar(&format!(
+            "INSERT INTO bookmarks (id, user_id, url, title, title_from_page, notes, description, image_url, published_at,
+                 author, site_meta, ai_summary, language, content_type, tag_mode, processing_status, is_favorite,
+                 is_public, read_at, created_at, updated_at, deleted_at, archive_url, archived_at, snapshot_url,
+                 snapshot_at, wayback_url, wayback_saved_at, content_changed_at, discussion_url, archive_bytes,
+                 visit_count, last_visited_at)
+             VALUES ({}, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16::processing_status, $17,
+                 $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30, $31, $32, $33)
+             ON CONFLICT (user_id, url) DO NOTHING
+             RETURNING id",
+            free_id("bookmarks")
+        ))
+        .bind(bookmark.id)
+        .bind(user_id)
+        .bind(&bookmark.url)
+        .bind(&bookmark.title)
+        .bind(bookmark.title_from_page)
+        .bind(&bookmark.notes)
+        .bind(&bookmark.description)
+        .bind(&bookmark.image_url)
+        .bind(bookmark.published_at)
+        .bind(&bookmark.author)
+        .bind(&bookmark.site_meta)
+        .bind(&bookmark.ai_summary)
+        .bind(&bookmark.language)
+        .bind(&bookmark.content_type)
+        .bind(bookmark.tag_mode)
+        .bind(if done { "done" } else { "pending" })
+        .bind(bookmark.is_favorite)
+        .bind(bookmark.is_public)
+        .bind(bookmark.read_at)
+        .bind(bookmark.created_at)
+        .bind(bookmark.updated_at)
+        .bind(bookmark.deleted_at)
+        .bind(&bookmark.archive_url)
+        .bind(bookmark.archived_at)
+        .bind(&bookmark.snapshot_url)
+        .bind(bookmark.snapshot_at)
+        .bind(&bookmark.wayback_url)
+        .bind(bookmark.wayback_saved_at)
+        .bind(bookmark.content_changed_at)
+        .bind(&bookmark.discussion_url)
+        .bind(bookmark.archive_bytes)
+        .bind(bookmark.visit_count)
+        .bind(bookmark.last_visited_at)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(id) = inserted else {
+            let existing: Uuid = sqlx::query_scalar("SELECT id FROM bookmarks WHERE user_id = $1 AND url = $2")
+                .bind(user_id)
+                .bind(&bookmark.url)
+                .fetch_one(&mut *tx)
+                .await?;
+            bookmark_ids.insert(bookmark.id, existing);
+            summary.skipped += 1;
+            continue;
+        };
+        bookmark_ids.insert(bookmark.id, id);
+        restored.push(id);
+        if !done {
+            unprocessed.push(id);
+        }
+
+        for source in [TagSource::User, TagSource::Ai] {
+            let names: Vec<String> =
+                bookmark.tags.iter().filter(|t| t.source == source).map(|t| t.name.clone()).collect();
+            attach_tags(&mut tx, user_id, id, &names, source).await?;
+        }
+        if let Some(content) = &bookmark.content {
+            sqlx::query(
+                "INSERT INTO bookmark_contents (bookmark_id, markdown, content_hash, fetched_at) VALUES ($1, $2, $3, $4)"
+            )
+            .bind(id)
+            .bind(&content.markdown)
+            .bind(&content.content_hash)
+            .bind(content.fetched_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+        for version in &bookmark.versions {
+            sqlx::query(
+                "INSERT INTO bookmark_content_versions (bookmark_id, markdown, content_hash, fetched_at, replaced_at)
+                 VALUES ($1, $2, $3, $4, COALESCE($5, now()))"
+            )
+            .bind(id)
+            .bind(&version.markdown)
+            .bind(&version.content_hash)
+            .bind(version.fetched_at)
+            .bind(version.replaced_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+    summary.bookmarks = restored.len();
+
+    // Parents go in before their children. One whose parent isn't in the backup goes at the top.
+    let known: HashSet<Uuid> = backup.collections.iter().map(|c| c.id).collect();
+    let mut collection_ids: HashMap<Uuid, Uuid> = HashMap::new();
+    let mut pending: Vec<&Collection> = backup.collections.iter().collect();
+    while !pending.is_empty() {
+        let (ready, waiting): (Vec<&Collection>, Vec<&Collection>) = pending.into_iter().partition(|c| {
+            c.parent_id.is_none_or(|parent| collection_ids.contains_key(&parent) || !known.contains(&parent))
+        });
+        if ready.is_empty() {
+            // Only a cycle is left, which the database can't have had
+            break;
+        }
+        for collection in ready {
+            let parent_id = collection.parent_id.and_then(|parent| collection_ids.get(&parent).copied());
+            let inserted: Option<Uuid> = sqlx::query_scalar(&format!(
+                "INSERT INTO collections (id, user_id, parent_id, name, description, created_at)
+                 VALUES ({}, $2, $3, $4, $5, $6)
+                 ON CONFLICT DO NOTHING
+                 RETURNING id",
+                free_id("collections")
+            ))
+            .bind(collection.id)
+            .bind(user_id)
+            .bind(parent_id)
+            .bind(&collection.name)
+            .bind(&collection.description)
+            .bind(collection.created_at)
+            .fetch_optional(&mut *tx)
+            .await?;
+            let id = match inserted {
+                Some(id) => {
+                    summary.collections += 1;
+                    id
+                }
+                // A sibling with the same name; its bookmarks are added to that one
+                None => {
+                    sqlx::query_scalar(
+                        "SELECT id FROM collections WHERE user_id = $1 AND parent_id IS NOT DISTINCT FROM $2 AND name = $3"
+                    )
+                    .bind(user_id)
+                    .bind(parent_id)
+                    .bind(&collection.name)
+                    .fetch_one(&mut *tx)
+                    .await?
+                }
+            };
+            collection_ids.insert(collection.id, id);
+
+            for bookmark_id in collection.bookmarks.iter().filter_map(|b| bookmark_ids.get(b)) {
+                sqlx::query(
+                    "INSERT INTO collection_bookmarks (collection_id, bookmark_id) VALUES ($1, $2) ON CONFLICT DO NOTHING"
+                )
+                .bind(id)
+                .bind(bookmark_id)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+        pending = waiting;
+    }
+
+    for search in &backup.saved_searches {
+        let inserted = sqlx::query(
+            "INSERT INTO saved_searches (user_id, name, params, created_at) VALUES ($1, $2, $3, $4)
+             ON CONFLICT (user_id, name) DO NOTHING"
+        )
+        .bind(user_id)
+        .bind(&search.name)
+        .bind(&search.params)
+        .bind(search.created_at)
+        .execute(&mut *tx)
+        .await?;
+        summary.saved_searches += inserted.rows_affected() as usize;
+    }
+
+    sqlx::query("INSERT INTO tag_vocabulary (user_id, name) SELECT $1, * FROM UNNEST($2::text[]) ON CONFLICT DO NOTHING")
+        .bind(user_id)
+        .bind(&backup.tag_vocabulary)
+        .execute(&mut *tx)
+        .await?;
+
+    for key in backup.api_keys.iter().filter(|key| restorable(&key.scopes, within)) {
+        let inserted = sqlx::query(
+            "INSERT INTO api_tokens (id, user_id, device_name, scopes, tag_mode, created_at, expires_at, revoked_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+             ON CONFLICT (id) DO NOTHING"
+        )
+        .bind(key.id)
+        .bind(user_id)
+        .bind(&key.device_name)
+        .bind(&key.scopes)
+        .bind(key.tag_mode)
+        .bind(key.created_at)
+        .bind(key.expires_at)
+        .bind(key.revoked_at)
+        .execute(&mut *tx)
+        .await?;
+        summary.api_keys += inserted.rows_affected() as usize;
+    }
+
+    jobs::queue(&mut *tx, &unprocessed).await?;
+    tx.commit().await?;
+    state.job_wakeup.notify_one();
+    for bookmark_id in &restored {
+        events::emit(state, user_id, events::Event::Created, *bookmark_id);
+    }
+    Ok(summary)
+}
+
+async fn download_backup(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Result<Response, StatusCode> {
+    let backup = create(&state.db, user.id).await.map_err(|e| {
+        eprintln!("Backup Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let body = encode(&backup).map_err(|e| {
+        eprintln!("Backup Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/gzip"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"linkman-backup.json.gz\""),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Takes a backup as the body, gzipped or plain JSON.
+async fn upload_backup(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Extension(key): Extension<crate::ApiKey>,
+    body: Bytes,
+) -> Result<Json<RestoreSummary>, StatusCode> {
+    let backup = decode(&body).map_err(|e| {
+        eprintln!("Restore Error: {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+    restore(&state, user.id, backup, &key.scopes).await.map(Json).map_err(|e| {
+        eprintln!("Restore Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let backup = Backup {
+            version: VERSION,
+            created_at: Utc::now(),
+            bookmarks: Vec::new(),
+            collections: Vec::new(),
+            saved_searches: Vec::new(),
+            tag_vocabulary: vec!["rust".to_string()],
+            api_keys: Vec::new(),
+        };
+        let gzipped = encode(&backup).unwrap();
+        assert!(gzipped.starts_with(&[0x1f, 0x8b]));
+        assert_eq!(decode(&gzipped).unwrap().tag_vocabulary, vec!["rust"]);
+
+        let plain = serde_json::to_vec(&backup).unwrap();
+        assert_eq!(decode(&plain).unwrap().tag_vocabulary, vec!["rust"]);
+
+        let newer = serde_json::to_vec(&Backup { version: VERSION + 1, ..backup }).unwrap();
+        assert!(decode(&newer).is_err());
+        assert!(decode(b"not a backup").is_err());
+    }
+
+    #[test]
+    fn test_restorable() {
+        let scopes = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        let full = scopes(&SCOPES);
+        assert!(restorable(&scopes(&["read", "write"]), &full));
+        assert!(restorable(&scopes(&["write"]), &scopes(&["write"])));
+        assert!(!restorable(&scopes(&["read", "write"]), &scopes(&["write"])));
+        assert!(!restorable(&scopes(&["admin"]), &full));
+        assert!(!restorable(&[], &full));
+    }
+}
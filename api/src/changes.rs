@@ -0,0 +1,162 @@
+//! Noticing when a bookmarked page changes.
+//!
+//! Each time a page's content is stored it's compared with what was there before. Its hash says
+//! whether anything changed at all; only when at least `CONTENT_CHANGE_THRESHOLD` of its lines
+//! differ does it count as changed. Then the old content is kept as a version, the bookmark's
+//! `content_changed_at` is set, and a `bookmark.content_changed` event goes out. Smaller edits,
+//! like a copyright year or a "last updated" line, just replace the stored content quietly.
+
+use std::collections::HashSet;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::get,
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{events, AppState, CurrentUser};
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/bookmarks/{id}/content/versions", get(list_versions))
+}
+
+/// The hash of `markdown`'s text, ignoring how it's spaced out.
+pub fn hash(markdown: &str) -> String {
+    let mut hasher = Sha256::new();
+    for line in lines(markdown) {
+        hasher.update(line.as_bytes());
+        hasher.update(b"\n");
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Each non-blank line with its spacing collapsed.
+fn lines(markdown: &str) -> impl Iterator<Item = String> + '_ {
+    markdown
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+}
+
+/// The fraction of lines in either version that aren't in the other, from 0 to 1.
+fn difference(old: &str, new: &str) -> f64 {
+    let old: HashSet<String> = lines(old).collect();
+    let new: HashSet<String> = lines(new).collect();
+    let all = old.union(&new).count();
+    if all == 0 {
+        return 0.0;
+    }
+    old.symmetric_difference(&new).count() as f64 / all as f64
+}
+
+/// Stores a bookmark's content, keeping what it replaces if the page changed enough to say so.
+/// Returns whether it did.
+pub async fn store(state: &AppState, user_id: Uuid, bookmark_id: Uuid, markdown: &str) -> anyhow::Result<bool> {
+    let content_hash = hash(markdown);
+    let mut tx = state.db.begin().await?;
+    let previous: Option<(String, Option<String>, DateTime<Utc>)> = sqlx::query_as(
+        "SELECT markdown, content_hash, fetched_at FROM bookmark_contents WHERE bookmark_id = $1 FOR UPDATE"
+    )
+    .bind(bookmark_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let changed = previous.as_ref().is_some_and(|(old, old_hash, _)| {
+        old_hash.as_deref() != Some(content_hash.as_str()) && difference(old, markdown) >= state.change_threshold
+    });
+    if changed && let Some((old, old_hash, fetched_at)) = &previous {
+        sqlx::query(
+            "INSERT INTO bookmark_content_versions (bookmark_id, markdown, content_hash, fetched_at) VALUES ($1, $2, $3, $4)"
+        )
+        .bind(bookmark_id)
+        .bind(old)
+        .bind(old_hash)
+        .bind(fetched_at)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("UPDATE bookmarks SET content_changed_at = now(), updated_at = now() WHERE id = $1")
+            .bind(bookmark_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    sqlx::query(
+        "INSERT INTO bookmark_contents (bookmark_id, markdown, content_hash) VALUES ($1, $2, $3)
+         ON CONFLICT (bookmark_id) DO UPDATE SET markdown = EXCLUDED.markdown, content_hash = EXCLUDED.content_hash, fetched_at = now()"
+    )
+    .bind(bookmark_id)
+    .bind(markdown)
+    .bind(&content_hash)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    if changed {
+        events::emit(state, user_id, events::Event::ContentChanged, bookmark_id);
+    }
+    Ok(changed)
+}
+
+/// Content a bookmark's page used to have.
+#[derive(Serialize, sqlx::FromRow)]
+struct ContentVersion {
+    id: Uuid,
+    markdown: String,
+    content_hash: Option<String>,
+    fetched_at: DateTime<Utc>,
+    /// When a changed page replaced it
+    replaced_at: DateTime<Utc>,
+}
+
+/// Earlier content of the bookmark's page, newest first.
+async fn list_versions(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<ContentVersion>>, StatusCode> {
+    let db_error = |e: sqlx::Error| {
+        eprintln!("List Content Versions Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let owned: Option<i32> = sqlx::query_scalar("SELECT 1 FROM bookmarks WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user.id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)?;
+    if owned.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    sqlx::query_as::<_, ContentVersion>(
+        "SELECT id, markdown, content_hash, fetched_at, replaced_at FROM bookmark_content_versions
+         WHERE bookmark_id = $1 ORDER BY replaced_at DESC"
+    )
+    .bind(id)
+    .fetch_all(&state.db)
+    .await
+    .map(Json)
+    .map_err(db_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_difference() {
+        let page = "# Pricing\n\nStarter: $10 a month\n\nTeam: $25 a month\n\nEnterprise: call us\n";
+        assert_eq!(hash(page), hash("# Pricing\nStarter:  $10 a month\n\n\nTeam: $25 a month\nEnterprise: call us"));
+        assert_ne!(hash(page), hash(&page.replace("$25", "$30")));
+
+        assert_eq!(difference(page, page), 0.0);
+        assert_eq!(difference(page, &page.replace("$25", "$30")), 2.0 / 5.0);
+        assert_eq!(difference("", "Now there's something"), 1.0);
+        assert_eq!(difference("", ""), 0.0);
+    }
+}
@@ -0,0 +1,138 @@
+//! Commands that go through a running server's API instead of the database, so they work from
+//! any machine with an API key: `linkman add`, `linkman list` and `linkman search`. They use
+//! the `linkman-client` crate, as any other program would.
+
+use linkman_client::{Bookmark, Client, Error, Filter, NewBookmark};
+use reqwest::StatusCode;
+
+use crate::{config::ClientConfig, quick_add::split_tags, SearchParams, MAX_PAGE_SIZE};
+
+/// Titles longer than this are cut short in the table.
+const TITLE_CHARS: usize = 60;
+
+fn connect(config: &ClientConfig) -> anyhow::Result<Client> {
+    Ok(Client::new(&config.url, config.token.as_deref().unwrap_or_default())?)
+}
+
+/// What to say when the server turns a request down; `bad_request` is what a 400 means for it.
+fn failure(config: &ClientConfig, e: Error, bad_request: &str, scope: &str) -> anyhow::Error {
+    let Error::Status(status) = e else { return e.into() };
+    let reason = match status {
+        StatusCode::BAD_REQUEST => format!(": {}", bad_request),
+        StatusCode::UNAUTHORIZED => ": check LINKMAN_TOKEN".to_string(),
+        StatusCode::FORBIDDEN => format!(": the API key needs the {} scope", scope),
+        StatusCode::NOT_IMPLEMENTED => ": the server has no embedding model".to_string(),
+        _ => String::new(),
+    };
+    anyhow::anyhow!("{} answered {}{}", config.url, status, reason)
+}
+
+/// Saves a bookmark as `POST /bookmarks/sync` does, printing what became of it.
+pub async fn add(
+    config: &ClientConfig,
+    url: String,
+    title: Option<String>,
+    notes: Option<String>,
+    tags: Option<&str>,
+) -> anyhow::Result<()> {
+    let bookmark = NewBookmark { url, title, notes, tags: split_tags(tags), tag_mode: None };
+    let saved = connect(config)?
+        .save_bookmark(&bookmark)
+        .await
+        .map_err(|e| failure(config, e, "not a URL that can be bookmarked", "write"))?;
+    let verb = if saved.created { "saved" } else { "already saved, updated" };
+    println!("{} {} ({})", verb, saved.bookmark.url, saved.bookmark.id);
+    Ok(())
+}
+
+/// One line per bookmark: its id, the day it was saved, its title and its tags.
+fn table(bookmarks: &[Bookmark]) -> String {
+    let titles: Vec<String> = bookmarks
+        .iter()
+        .map(|bookmark| {
+            let title = bookmark.title.as_deref().unwrap_or(&bookmark.url);
+            let mut short: String = title.chars().take(TITLE_CHARS).collect();
+            if title.chars().count() > TITLE_CHARS {
+                short.push('…');
+            }
+            short
+        })
+        .collect();
+    let width = titles.iter().map(|title| title.chars().count()).max().unwrap_or_default().max("TITLE".len());
+
+    let mut out = format!("{:<36}  {:<10}  {:<width$}  TAGS\n", "ID", "SAVED", "TITLE", width = width);
+    for (bookmark, title) in bookmarks.iter().zip(&titles) {
+        let line = format!(
+            "{}  {:<10}  {:<width$}  {}",
+            bookmark.id,
+            bookmark.created_at.format("%Y-%m-%d").to_string(),
+            title,
+            bookmark.tags.as_deref().unwrap_or_default().join(","),
+            width = width,
+        );
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// Prints up to `limit` of the bookmarks `filter` matches, following `next_cursor` from page to
+/// page, as a table or with `json` as the API's own JSON.
+pub async fn list(config: &ClientConfig, filter: &SearchParams, limit: usize, json: bool) -> anyhow::Result<()> {
+    let client = connect(config)?;
+    // The same query parameters under another name
+    let filter: Filter = serde_json::from_value(serde_json::to_value(filter)?)?;
+    let mut bookmarks = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let page_size = (limit - bookmarks.len()).min(MAX_PAGE_SIZE as usize);
+        let page = client
+            .list_bookmarks(&filter, Some(page_size as u32), cursor.as_deref())
+            .await
+            .map_err(|e| failure(config, e, "a filter it doesn't understand", "read"))?;
+        bookmarks.extend(page.bookmarks);
+        cursor = page.next_cursor;
+        if cursor.is_none() || bookmarks.len() >= limit {
+            break;
+        }
+    }
+    bookmarks.truncate(limit);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&bookmarks)?);
+    } else {
+        print!("{}", table(&bookmarks));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BookmarkResponse;
+
+    fn bookmark(url: &str, title: Option<&str>, created_at: &str, tags: Option<&[&str]>) -> Bookmark {
+        let response = BookmarkResponse {
+            url: url.to_string(),
+            title: title.map(str::to_string),
+            created_at: created_at.parse().unwrap(),
+            tags: tags.map(|tags| tags.iter().map(|tag| tag.to_string()).collect()),
+            ..Default::default()
+        };
+        serde_json::from_value(serde_json::to_value(response).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_table() {
+        let bookmarks = vec![
+            bookmark("https://blog.rust-lang.org/", Some("Rust Blog"), "2024-07-25T10:00:00Z", Some(&["rust", "release"])),
+            bookmark("https://example.com/", None, "2024-07-26T10:00:00Z", None),
+        ];
+        assert_eq!(
+            table(&bookmarks),
+            "ID                                    SAVED       TITLE                 TAGS\n\
+             00000000-0000-0000-0000-000000000000  2024-07-25  Rust Blog             rust,release\n\
+             00000000-0000-0000-0000-000000000000  2024-07-26  https://example.com/\n"
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! Collections: named, nestable folders that bookmarks can be filed into.
+//!
+//! A bookmark can sit in any number of collections. Deleting a collection deletes its
+//! sub-collections but never the bookmarks in them.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, patch, put},
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use uuid::Uuid;
+
+use crate::{AppState, CurrentUser};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/collections", get(list_collections).post(create_collection))
+        .route("/collections/{id}", patch(update_collection).delete(delete_collection))
+        .route(
+            "/collections/{id}/bookmarks/{bookmark_id}",
+            put(add_bookmark).delete(remove_bookmark),
+        )
+}
+
+fn db_error(e: sqlx::Error) -> StatusCode {
+    match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => StatusCode::CONFLICT,
+        e => {
+            eprintln!("Collections Error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Collection {
+    id: Uuid,
+    parent_id: Option<Uuid>,
+    name: String,
+    description: Option<String>,
+    created_at: DateTime<Utc>,
+    bookmark_count: i64,
+}
+
+pub const COLLECTION_SELECT: &str = "SELECT c.id, c.parent_id, c.name, c.description, c.created_at,
+     (SELECT count(*) FROM collection_bookmarks cb JOIN bookmarks b ON b.id = cb.bookmark_id
+      WHERE cb.collection_id = c.id AND b.deleted_at IS NULL) as bookmark_count
+     FROM collections c";
+
+/// Every collection the user has, flat; clients build the tree from `parent_id`.
+async fn list_collections(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Result<Json<Vec<Collection>>, StatusCode> {
+    sqlx::query_as::<_, Collection>(&format!("{} WHERE c.user_id = $1 ORDER BY c.name, c.id", COLLECTION_SELECT))
+        .bind(user.id)
+        .fetch_all(&state.db)
+        .await
+        .map(Json)
+        .map_err(db_error)
+}
+
+pub async fn fetch_collection(state: &AppState, user_id: Uuid, id: Uuid) -> Result<Collection, StatusCode> {
+    sqlx::query_as::<_, Collection>(&format!("{} WHERE c.user_id = $1 AND c.id = $2", COLLECTION_SELECT))
+        .bind(user_id)
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// A parent has to be one of the user's own collections.
+async fn check_parent(state: &AppState, user_id: Uuid, parent_id: Uuid) -> Result<(), StatusCode> {
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM collections WHERE id = $1 AND user_id = $2)")
+        .bind(parent_id)
+        .bind(user_id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(db_error)?;
+    if exists { Ok(()) } else { Err(StatusCode::BAD_REQUEST) }
+}
+
+#[derive(Deserialize)]
+struct CreateCollectionRequest {
+    name: String,
+    description: Option<String>,
+    parent_id: Option<Uuid>,
+}
+
+async fn create_collection(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Json(payload): Json<CreateCollectionRequest>,
+) -> Result<(StatusCode, Json<Collection>), StatusCode> {
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if let Some(parent_id) = payload.parent_id {
+        check_parent(&state, user.id, parent_id).await?;
+    }
+
+    let id: Uuid = sqlx::query_scalar(
+        "INSERT INTO collections (user_id, parent_id, name, description) VALUES ($1, $2, $3, $4) RETURNING id"
+    )
+    .bind(user.id)
+    .bind(payload.parent_id)
+    .bind(name)
+    .bind(&payload.description)
+    .fetch_one(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    Ok((StatusCode::CREATED, Json(fetch_collection(&state, user.id, id).await?)))
+}
+
+/// Lets a JSON `null` mean "clear this field" while an absent field still means "leave it".
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Partial update; `parent_id: null` moves the collection to the top level.
+#[derive(Deserialize)]
+struct UpdateCollectionRequest {
+    name: Option<String>,
+    description: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    parent_id: Option<Option<Uuid>>,
+}
+
+async fn update_collection(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdateCollectionRequest>,
+) -> Result<Json<Collection>, StatusCode> {
+    let name = payload.name.as_deref().map(str::trim);
+    if name.is_some_and(str::is_empty) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    if let Some(Some(parent_id)) = payload.parent_id {
+        check_parent(&state, user.id, parent_id).await?;
+        // Refuse to move a collection underneath itself
+        let cycle: bool = sqlx::query_scalar(
+            "WITH RECURSIVE ancestors AS (
+                 SELECT id, parent_id FROM collections WHERE id = $1
+                 UNION ALL
+                 SELECT c.id, c.parent_id FROM collections c JOIN ancestors a ON c.id = a.parent_id
+             )
+             SELECT EXISTS (SELECT 1 FROM ancestors WHERE id = $2)"
+        )
+        .bind(parent_id)
+        .bind(id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(db_error)?;
+        if cycle {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+
+    let updated = sqlx::query(
+        "UPDATE collections SET name = COALESCE($1, name), description = COALESCE($2, description),
+             parent_id = CASE WHEN $3 THEN $4 ELSE parent_id END
+         WHERE id = $5 AND user_id = $6"
+    )
+    .bind(name)
+    .bind(&payload.description)
+    .bind(payload.parent_id.is_some())
+    .bind(payload.parent_id.flatten())
+    .bind(id)
+    .bind(user.id)
+    .execute(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    if updated.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(Json(fetch_collection(&state, user.id, id).await?))
+}
+
+async fn delete_collection(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = sqlx::query("DELETE FROM collections WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user.id)
+        .execute(&state.db)
+        .await
+        .map_err(db_error)?;
+
+    if deleted.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn add_bookmark(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path((id, bookmark_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, StatusCode> {
+    // Both sides must belong to the caller; already being filed there is not an error
+    let linked: bool = sqlx::query_scalar(
+        "WITH target AS (
+             SELECT c.id AS collection_id, b.id AS bookmark_id FROM collections c, bookmarks b
+             WHERE c.id = $1 AND c.user_id = $3 AND b.id = $2 AND b.user_id = $3 AND b.deleted_at IS NULL
+         ), inserted AS (
+             INSERT INTO collection_bookmarks (collection_id, bookmark_id)
+             SELECT collection_id, bookmark_id FROM target
+             ON CONFLICT DO NOTHING
+         )
+         SELECT EXISTS (SELECT 1 FROM target)"
+    )
+    .bind(id)
+    .bind(bookmark_id)
+    .bind(user.id)
+    .fetch_one(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    if linked { Ok(StatusCode::NO_CONTENT) } else { Err(StatusCode::NOT_FOUND) }
+}
+
+async fn remove_bookmark(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path((id, bookmark_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, StatusCode> {
+    let removed = sqlx::query(
+        "DELETE FROM collection_bookmarks cb USING collections c
+         WHERE cb.collection_id = c.id AND c.id = $1 AND c.user_id = $2 AND cb.bookmark_id = $3"
+    )
+    .bind(id)
+    .bind(user.id)
+    .bind(bookmark_id)
+    .execute(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    if removed.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_request_parent() {
+        let keep: UpdateCollectionRequest = serde_json::from_str(r#"{"name": "Work"}"#).unwrap();
+        assert_eq!(keep.parent_id, None);
+
+        let top: UpdateCollectionRequest = serde_json::from_str(r#"{"parent_id": null}"#).unwrap();
+        assert_eq!(top.parent_id, Some(None));
+
+        let id = Uuid::new_v4();
+        let moved: UpdateCollectionRequest =
+            serde_json::from_str(&format!(r#"{{"parent_id": "{}"}}"#, id)).unwrap();
+        assert_eq!(moved.parent_id, Some(Some(id)));
+    }
+}
@@ -0,0 +1,1093 @@
+//! Server configuration, from an optional TOML file overridden by environment variables.
+//!
+//! `--config linkman.toml` reads the file; any of the environment variables listed by each
+//! field then takes precedence, so existing `.env` setups keep working without a file. Everything
+//! is checked up front and all the problems are reported together, instead of the first one
+//! surfacing halfway through startup. AWS credentials for the archive stay in the usual `AWS_*`
+//! variables.
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// `LISTEN_ADDR`, or `--bind`
+    pub listen: Listen,
+    /// `SOCKET_MODE`, the octal permissions a Unix socket is created with
+    pub socket_mode: String,
+    /// `RESOLVE_CANONICAL_URLS`
+    pub resolve_canonical_urls: bool,
+    /// `SHAARLI_API`, serving Shaarli's REST API under `/shaarli/USERNAME/api/v1`
+    pub shaarli_api: bool,
+    /// `SECURE_COOKIES`, marking the session cookie `Secure`; defaults to whether TLS is configured
+    pub secure_cookies: Option<bool>,
+    pub tls: TlsConfig,
+    pub database: DatabaseConfig,
+    pub ai: AiConfig,
+    pub tagging: TaggingConfig,
+    pub archive: ArchiveConfig,
+    pub screenshots: ScreenshotConfig,
+    pub link_check: LinkCheckConfig,
+    pub recrawl: RecrawlConfig,
+    pub trash: TrashConfig,
+    pub github: GithubConfig,
+    pub mastodon: MastodonConfig,
+    pub digests: DigestsConfig,
+    pub slack: SlackConfig,
+    pub discord: DiscordConfig,
+    pub client: ClientConfig,
+    pub rate_limit: RateLimitConfig,
+    pub metrics: MetricsConfig,
+    pub health: HealthConfig,
+    pub jobs: JobsConfig,
+    pub fetch: FetchConfig,
+    pub retry: RetryConfig,
+}
+
+/// Where the server accepts connections: `host:port`, or `unix:/path/to.sock`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Listen {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl FromStr for Listen {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("unix:") {
+            Some("") => Err("unix: needs a socket path".to_string()),
+            Some(path) => Ok(Listen::Unix(PathBuf::from(path))),
+            None => s
+                .parse()
+                .map(Listen::Tcp)
+                .map_err(|_| format!("{:?} is neither host:port nor unix:/path", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for Listen {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Listen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listen::Tcp(addr) => write!(f, "{}", addr),
+            Listen::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// `TLS_CERT`, a PEM certificate chain; serves HTTPS along with `key`
+    pub cert: Option<String>,
+    /// `TLS_KEY`, the certificate's PEM private key
+    pub key: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// `DATABASE_URL`, required; Postgres, or `sqlite:` for the core API only
+    pub url: String,
+    /// `DATABASE_MAX_CONNECTIONS`
+    pub max_connections: u32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AiConfig {
+    /// `AI_PROVIDER`, which API tagging talks to
+    pub provider: Provider,
+    /// `OPENAI_API_KEY`, required except for Ollama and `none`
+    pub api_key: String,
+    /// `OPENAI_API_BASE`, the provider's own address when unset
+    pub api_base: Option<String>,
+    /// `LLM_MODEL`
+    pub model: String,
+    /// `EMBEDDING_MODEL`; semantic search is off without one
+    pub embedding_model: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    /// OpenAI or any server with the same API
+    OpenAi,
+    Ollama,
+    Anthropic,
+    /// Don't tag or summarize at all
+    None,
+}
+
+impl FromStr for Provider {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "openai" => Ok(Provider::OpenAi),
+            "ollama" => Ok(Provider::Ollama),
+            "anthropic" => Ok(Provider::Anthropic),
+            "none" => Ok(Provider::None),
+            _ => Err(()),
+        }
+    }
+}
+
+impl AiConfig {
+    pub fn api_base(&self) -> &str {
+        self.api_base.as_deref().unwrap_or(match self.provider {
+            Provider::OpenAi | Provider::None => "https://api.openai.com/v1",
+            Provider::Ollama => "http://localhost:11434",
+            Provider::Anthropic => "https://api.anthropic.com",
+        })
+    }
+}
+
+/// How bookmarks are summarized and tagged. In both prompts `{url}` is replaced by the page's
+/// address, `{content}` by the metadata scraped from it as JSON and `{max_tags}` by `max_tags`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TaggingConfig {
+    /// `LLM_TEMPERATURE`; the endpoint's default when unset
+    pub temperature: Option<f64>,
+    /// `MAX_TAGS`
+    pub max_tags: usize,
+    /// `TAGGING_SYSTEM_PROMPT`
+    pub system_prompt: String,
+    /// `TAGGING_PROMPT`, the message describing the page
+    pub prompt: String,
+    /// `TAG_LANGUAGE`, e.g. `English`, for the tags and summary whatever the page is written in
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArchiveConfig {
+    /// `ARCHIVE_S3_BUCKET`; pages aren't archived without one
+    pub s3_bucket: Option<String>,
+    /// `ARCHIVE_TO_WAYBACK`, have the Wayback Machine capture every bookmarked page too
+    pub wayback: bool,
+    /// `WAYBACK_SAVE_URL`, the Wayback Machine's Save Page Now API
+    pub wayback_save_url: String,
+    /// `WAYBACK_ACCESS_KEY` and `WAYBACK_SECRET_KEY`, an archive.org account's S3 keys, for
+    /// higher capture limits than without
+    pub wayback_access_key: Option<String>,
+    pub wayback_secret_key: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScreenshotConfig {
+    /// `SCREENSHOT_SERVICE_URL`, with `{url}` where the page address goes
+    pub service_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinkCheckConfig {
+    /// `LINK_CHECK_INTERVAL_HOURS`, `0` disables
+    pub interval_hours: u64,
+    /// `LINK_CHECK_FAILURE_THRESHOLD`
+    pub failure_threshold: i32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecrawlConfig {
+    /// `RECRAWL_INTERVAL_HOURS`, how often pages are fetched again to see if they changed; `0`
+    /// disables
+    pub interval_hours: u64,
+    /// `CONTENT_CHANGE_THRESHOLD`, the fraction of a page's lines that must differ for it to
+    /// count as changed
+    pub change_threshold: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrashConfig {
+    /// `TRASH_RETENTION_DAYS`, `0` keeps trashed bookmarks forever
+    pub retention_days: i32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GithubConfig {
+    /// `GITHUB_TOKEN`, one that can read the account's stars; syncing them is off without it
+    pub token: Option<String>,
+    /// `GITHUB_SYNC_USER`, the linkman user the stars are bookmarked for
+    pub user: Option<String>,
+    /// `GITHUB_SYNC_INTERVAL_HOURS`
+    pub interval_hours: u64,
+    /// `GITHUB_API_URL`, for GitHub Enterprise Server
+    pub api_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MastodonConfig {
+    /// `MASTODON_URL`, the account's instance, such as `https://mastodon.social`
+    pub url: Option<String>,
+    /// `MASTODON_TOKEN`, an access token with the `read:bookmarks` scope, and `read:favourites`
+    /// for favourites; syncing is off without it
+    pub token: Option<String>,
+    /// `MASTODON_SYNC_USER`, the linkman user the posts are bookmarked for
+    pub user: Option<String>,
+    /// `MASTODON_SYNC_FAVOURITES`, to bring in favourited posts as well as bookmarked ones
+    pub favourites: bool,
+    /// `MASTODON_SYNC_INTERVAL_HOURS`
+    pub interval_hours: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DigestsConfig {
+    /// `DIGESTS_ENABLED`, to have the model write up each user's week of bookmarks once it's over
+    pub enabled: bool,
+}
+
+/// The Slack app whose `/linkman` command and link unfurls are served under `/slack`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SlackConfig {
+    /// `SLACK_SIGNING_SECRET`, from the app's Basic Information page; the endpoints are off without it
+    pub signing_secret: Option<String>,
+    /// `SLACK_BOT_TOKEN`, a bot token with the `links:write` scope, to unfurl saved links
+    pub bot_token: Option<String>,
+    /// `SLACK_API_URL`
+    pub api_url: String,
+}
+
+/// A Discord bot that saves the links posted in some channels, tagged with the channel's name.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiscordConfig {
+    /// `DISCORD_BOT_TOKEN`, for a bot with the Message Content intent; the bot is off without it
+    pub token: Option<String>,
+    /// `DISCORD_CHANNELS`, comma-separated ids of the channels to watch
+    pub channels: Vec<String>,
+    /// `DISCORD_API_KEY`, the write-scoped linkman API key the links are saved with
+    pub api_key: Option<String>,
+    /// `DISCORD_REPLY`, to answer each message with what its links turned out to be about
+    pub reply: bool,
+    /// `DISCORD_API_URL`
+    pub api_url: String,
+}
+
+/// Where commands like `add`, which go through a running server's API, send their requests.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientConfig {
+    /// `LINKMAN_URL`
+    pub url: String,
+    /// `LINKMAN_TOKEN`, an API key; `add` needs the write scope
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// `RATE_LIMIT_PER_MINUTE`, `0` disables
+    pub per_minute: u32,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// `METRICS_TOKEN`; `/metrics` is open to anyone without one
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    /// `READYZ_CHECK_AI`
+    pub check_ai: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobsConfig {
+    /// `JOB_CONCURRENCY`, bookmarks being processed at once
+    pub concurrency: usize,
+    /// `FETCH_CONCURRENCY`, pages being downloaded at once
+    pub fetch_concurrency: usize,
+    /// `AI_CONCURRENCY`, calls to the model at once, from processing or anything else
+    pub ai_concurrency: usize,
+}
+
+/// How page fetches and model calls are retried within one job attempt.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    /// `RETRY_ATTEMPTS`, tries per call including the first; `1` disables
+    pub attempts: u32,
+    /// `RETRY_BASE_DELAY_MS`, the wait after the first failure, doubled each time after
+    pub base_delay_ms: u64,
+    /// `RETRY_MAX_DELAY_MS`
+    pub max_delay_ms: u64,
+    /// `RETRY_JITTER`, to wait a random part of each delay
+    pub jitter: bool,
+    /// `RETRY_ON`, comma-separated, e.g. `timeout,server_error`
+    pub retry_on: Vec<RetryClass>,
+}
+
+/// A kind of failure that can go away by itself.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryClass {
+    Timeout,
+    /// The server couldn't be reached at all
+    Connect,
+    /// 429 Too Many Requests
+    RateLimited,
+    /// Any 5xx
+    ServerError,
+}
+
+impl FromStr for RetryClass {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "timeout" => Ok(RetryClass::Timeout),
+            "connect" => Ok(RetryClass::Connect),
+            "rate_limited" => Ok(RetryClass::RateLimited),
+            "server_error" => Ok(RetryClass::ServerError),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FetchConfig {
+    /// `FETCH_USER_AGENT`
+    pub user_agent: String,
+    /// `RESPECT_ROBOTS_TXT`
+    pub respect_robots: bool,
+    /// `FETCH_DOMAIN_INTERVAL_MS`, the least time between requests to one host; `0` disables
+    pub domain_interval_ms: u64,
+    /// `FETCH_TIMEOUT_SECS`, for the whole download
+    pub timeout_secs: u64,
+    /// `FETCH_MAX_BYTES`
+    pub max_bytes: u64,
+    /// `FETCH_MAX_REDIRECTS`
+    pub max_redirects: usize,
+    /// `FETCH_ALLOWED_NETWORKS`, comma-separated networks, addresses or host names that may be
+    /// fetched even though they aren't public, e.g. `10.1.0.0/16,wiki.internal`
+    pub allowed_networks: Vec<String>,
+    /// `FETCH_PROXY`, an `http://`, `https://`, `socks5://` or `socks5h://` proxy for every page
+    /// fetch; the model's API is never proxied through it
+    pub proxy: Option<String>,
+    /// `FETCH_PROXY_RULES`, comma-separated `domain=proxy` pairs taking precedence over `proxy`
+    /// for the domain and its subdomains, with `direct` for no proxy
+    pub proxy_rules: Vec<ProxyRule>,
+    /// `FETCH_CREDENTIALS_KEY`, any long random string, to store logins for sites with
+    /// pages behind one
+    pub credentials_key: Option<String>,
+    /// `WAYBACK_FALLBACK`, process the Wayback Machine's snapshot of pages that are gone or
+    /// time out
+    pub wayback_fallback: bool,
+    /// `WAYBACK_API_URL`, the Wayback Machine's availability API
+    pub wayback_api: String,
+}
+
+/// A proxy, or none, for the pages of one domain.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ProxyRule {
+    pub domain: String,
+    /// `None` for `direct`
+    pub proxy: Option<url::Url>,
+}
+
+/// `proxy` as a URL, if it's one reqwest can use.
+pub fn proxy_url(proxy: &str) -> Result<url::Url, String> {
+    let url = url::Url::parse(proxy).map_err(|_| format!("{:?} is not a URL", proxy))?;
+    match url.scheme() {
+        "http" | "https" | "socks5" | "socks5h" => Ok(url),
+        scheme => Err(format!("{:?} proxies aren't supported", scheme)),
+    }
+}
+
+impl FromStr for ProxyRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((domain, proxy)) = s.split_once('=') else {
+            return Err(format!("{:?} is not domain=proxy", s));
+        };
+        let domain = domain.trim().trim_start_matches('.').to_ascii_lowercase();
+        if domain.is_empty() {
+            return Err(format!("{:?} has no domain", s));
+        }
+        let proxy = match proxy.trim() {
+            "direct" => None,
+            proxy => Some(proxy_url(proxy)?),
+        };
+        Ok(ProxyRule { domain, proxy })
+    }
+}
+
+impl TryFrom<String> for ProxyRule {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen: Listen::Tcp(SocketAddr::from(([0, 0, 0, 0], 3000))),
+            socket_mode: "660".to_string(),
+            resolve_canonical_urls: true,
+            shaarli_api: false,
+            secure_cookies: None,
+            tls: TlsConfig::default(),
+            database: DatabaseConfig::default(),
+            ai: AiConfig::default(),
+            tagging: TaggingConfig::default(),
+            archive: ArchiveConfig::default(),
+            screenshots: ScreenshotConfig::default(),
+            link_check: LinkCheckConfig::default(),
+            recrawl: RecrawlConfig::default(),
+            trash: TrashConfig::default(),
+            github: GithubConfig::default(),
+            mastodon: MastodonConfig::default(),
+            digests: DigestsConfig::default(),
+            slack: SlackConfig::default(),
+            discord: DiscordConfig::default(),
+            client: ClientConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            metrics: MetricsConfig::default(),
+            health: HealthConfig::default(),
+            jobs: JobsConfig::default(),
+            fetch: FetchConfig::default(),
+            retry: RetryConfig::default(),
+        }
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: String::new(),
+            max_connections: 5,
+        }
+    }
+}
+
+impl Default for AiConfig {
+    fn default() -> Self {
+        AiConfig {
+            provider: Provider::OpenAi,
+            api_key: String::new(),
+            api_base: None,
+            model: "user.gemma-4-26B-A4B-it-GGUF".to_string(),
+            embedding_model: None,
+        }
+    }
+}
+
+impl Default for TaggingConfig {
+    fn default() -> Self {
+        TaggingConfig {
+            temperature: None,
+            max_tags: 5,
+            system_prompt: "You are a semantic analysis agent. Your task is to summarize the provided input and to extract exactly {max_tags} descriptive tags from it by identifying its core domains, specific technologies, and intent.\n\nRules:\n\nWrite the summary as 2-3 plain sentences saying what the page is about and why someone would keep it.\n\nOutput exactly {max_tags} tags.\n\nOrder them from most specific/relevant to most general.\n\nNormalize tags to lowercase with hyphens for spaces.\n\nExtract only what is explicitly stated or strongly implied.".to_string(),
+            prompt: "{content}".to_string(),
+            language: None,
+        }
+    }
+}
+
+impl TaggingConfig {
+    /// Fills in a prompt template for one page.
+    pub fn render(&self, template: &str, url: &str, content: &str) -> String {
+        // `{content}` goes last so placeholders inside the page itself are left alone
+        template
+            .replace("{max_tags}", &self.max_tags.to_string())
+            .replace("{url}", url)
+            .replace("{content}", content)
+    }
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        ArchiveConfig {
+            s3_bucket: None,
+            wayback: false,
+            wayback_save_url: "https://web.archive.org/save".to_string(),
+            wayback_access_key: None,
+            wayback_secret_key: None,
+        }
+    }
+}
+
+impl Default for LinkCheckConfig {
+    fn default() -> Self {
+        LinkCheckConfig {
+            interval_hours: 24,
+            failure_threshold: 3,
+        }
+    }
+}
+
+impl Default for RecrawlConfig {
+    fn default() -> Self {
+        RecrawlConfig {
+            interval_hours: 0,
+            change_threshold: 0.05,
+        }
+    }
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        TrashConfig { retention_days: 30 }
+    }
+}
+
+impl Default for GithubConfig {
+    fn default() -> Self {
+        GithubConfig {
+            token: None,
+            user: None,
+            interval_hours: 6,
+            api_url: "https://api.github.com".to_string(),
+        }
+    }
+}
+
+impl Default for SlackConfig {
+    fn default() -> Self {
+        SlackConfig {
+            signing_secret: None,
+            bot_token: None,
+            api_url: "https://slack.com/api".to_string(),
+        }
+    }
+}
+
+impl Default for DiscordConfig {
+    fn default() -> Self {
+        DiscordConfig {
+            token: None,
+            channels: Vec::new(),
+            api_key: None,
+            reply: true,
+            api_url: "https://discord.com/api/v10".to_string(),
+        }
+    }
+}
+
+impl Default for MastodonConfig {
+    fn default() -> Self {
+        MastodonConfig {
+            url: None,
+            token: None,
+            user: None,
+            favourites: false,
+            interval_hours: 1,
+        }
+    }
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig {
+            url: "http://localhost:3000".to_string(),
+            token: None,
+        }
+    }
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        FetchConfig {
+            user_agent: format!("linkman/{} (+https://github.com/jkbbwr/linkman)", env!("CARGO_PKG_VERSION")),
+            respect_robots: true,
+            domain_interval_ms: 1000,
+            timeout_secs: 30,
+            max_bytes: 20 * 1024 * 1024,
+            max_redirects: 5,
+            allowed_networks: Vec::new(),
+            proxy: None,
+            proxy_rules: Vec::new(),
+            credentials_key: None,
+            wayback_fallback: true,
+            wayback_api: "https://archive.org/wayback/available".to_string(),
+        }
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            attempts: 3,
+            base_delay_ms: 500,
+            max_delay_ms: 10_000,
+            jitter: true,
+            retry_on: vec![RetryClass::Timeout, RetryClass::Connect, RetryClass::RateLimited, RetryClass::ServerError],
+        }
+    }
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        JobsConfig {
+            concurrency: 4,
+            fetch_concurrency: 4,
+            ai_concurrency: 2,
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig { per_minute: 300 }
+    }
+}
+
+/// Replaces `slot` with the variable's value when it's set.
+fn env_value<T: FromStr>(name: &str, slot: &mut T, problems: &mut Vec<String>) {
+    if let Ok(value) = std::env::var(name) {
+        match value.parse() {
+            Ok(parsed) => *slot = parsed,
+            Err(_) => problems.push(format!("{} is not valid: {:?}", name, value)),
+        }
+    }
+}
+
+/// Like `env_value`, for settings that are unset by default.
+fn env_some<T: FromStr>(name: &str, slot: &mut Option<T>, problems: &mut Vec<String>) {
+    if let Ok(value) = std::env::var(name) {
+        match value.parse() {
+            Ok(parsed) => *slot = Some(parsed),
+            Err(_) => problems.push(format!("{} is not valid: {:?}", name, value)),
+        }
+    }
+}
+
+/// Like `env_value`, for a comma-separated list; an empty variable empties it.
+fn env_list<T: FromStr>(name: &str, slot: &mut Vec<T>, problems: &mut Vec<String>) {
+    if let Ok(value) = std::env::var(name) {
+        let items = value.split(',').map(str::trim).filter(|item| !item.is_empty());
+        match items.map(str::parse).collect() {
+            Ok(parsed) => *slot = parsed,
+            Err(_) => problems.push(format!("{} is not valid: {:?}", name, value)),
+        }
+    }
+}
+
+/// Like `env_value`, with an empty variable turning the setting off.
+fn env_optional(name: &str, slot: &mut Option<String>) {
+    if let Ok(value) = std::env::var(name) {
+        *slot = Some(value);
+    }
+    if slot.as_deref() == Some("") {
+        *slot = None;
+    }
+}
+
+impl Config {
+    /// Reads `path`, if given, then applies the environment and `bind` from the command line, and
+    /// validates the result.
+    pub fn load(path: Option<&Path>, bind: Option<Listen>) -> anyhow::Result<Self> {
+        let mut config = Self::read(path)?;
+        let mut problems = config.apply_env();
+        if let Some(bind) = bind {
+            config.listen = bind;
+        }
+        problems.extend(config.validate());
+        if !problems.is_empty() {
+            anyhow::bail!("Invalid configuration:\n  - {}", problems.join("\n  - "));
+        }
+        Ok(config)
+    }
+
+    fn read(path: Option<&Path>) -> anyhow::Result<Self> {
+        let Some(path) = path else {
+            return Ok(Config::default());
+        };
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Can't read config file {}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| anyhow::anyhow!("Invalid config file {}: {}", path.display(), e))
+    }
+
+    fn parse(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    fn apply_env(&mut self) -> Vec<String> {
+        let mut problems = Vec::new();
+        env_value("LISTEN_ADDR", &mut self.listen, &mut problems);
+        env_value("SOCKET_MODE", &mut self.socket_mode, &mut problems);
+        env_value("RESOLVE_CANONICAL_URLS", &mut self.resolve_canonical_urls, &mut problems);
+        env_value("SHAARLI_API", &mut self.shaarli_api, &mut problems);
+        env_some("SECURE_COOKIES", &mut self.secure_cookies, &mut problems);
+        env_optional("TLS_CERT", &mut self.tls.cert);
+        env_optional("TLS_KEY", &mut self.tls.key);
+        env_value("DATABASE_URL", &mut self.database.url, &mut problems);
+        env_value("DATABASE_MAX_CONNECTIONS", &mut self.database.max_connections, &mut problems);
+        env_value("OPENAI_API_KEY", &mut self.ai.api_key, &mut problems);
+        env_value("AI_PROVIDER", &mut self.ai.provider, &mut problems);
+        env_optional("OPENAI_API_BASE", &mut self.ai.api_base);
+        env_value("LLM_MODEL", &mut self.ai.model, &mut problems);
+        env_optional("EMBEDDING_MODEL", &mut self.ai.embedding_model);
+        env_some("LLM_TEMPERATURE", &mut self.tagging.temperature, &mut problems);
+        env_value("MAX_TAGS", &mut self.tagging.max_tags, &mut problems);
+        env_value("TAGGING_SYSTEM_PROMPT", &mut self.tagging.system_prompt, &mut problems);
+        env_value("TAGGING_PROMPT", &mut self.tagging.prompt, &mut problems);
+        env_optional("TAG_LANGUAGE", &mut self.tagging.language);
+        env_optional("ARCHIVE_S3_BUCKET", &mut self.archive.s3_bucket);
+        env_value("ARCHIVE_TO_WAYBACK", &mut self.archive.wayback, &mut problems);
+        env_value("WAYBACK_SAVE_URL", &mut self.archive.wayback_save_url, &mut problems);
+        env_optional("WAYBACK_ACCESS_KEY", &mut self.archive.wayback_access_key);
+        env_optional("WAYBACK_SECRET_KEY", &mut self.archive.wayback_secret_key);
+        env_optional("SCREENSHOT_SERVICE_URL", &mut self.screenshots.service_url);
+        env_value("LINK_CHECK_INTERVAL_HOURS", &mut self.link_check.interval_hours, &mut problems);
+        env_value("LINK_CHECK_FAILURE_THRESHOLD", &mut self.link_check.failure_threshold, &mut problems);
+        env_value("RECRAWL_INTERVAL_HOURS", &mut self.recrawl.interval_hours, &mut problems);
+        env_value("CONTENT_CHANGE_THRESHOLD", &mut self.recrawl.change_threshold, &mut problems);
+        env_value("TRASH_RETENTION_DAYS", &mut self.trash.retention_days, &mut problems);
+        env_optional("GITHUB_TOKEN", &mut self.github.token);
+        env_optional("GITHUB_SYNC_USER", &mut self.github.user);
+        env_value("GITHUB_SYNC_INTERVAL_HOURS", &mut self.github.interval_hours, &mut problems);
+        env_value("GITHUB_API_URL", &mut self.github.api_url, &mut problems);
+        env_optional("MASTODON_URL", &mut self.mastodon.url);
+        env_optional("MASTODON_TOKEN", &mut self.mastodon.token);
+        env_optional("MASTODON_SYNC_USER", &mut self.mastodon.user);
+        env_value("MASTODON_SYNC_FAVOURITES", &mut self.mastodon.favourites, &mut problems);
+        env_value("MASTODON_SYNC_INTERVAL_HOURS", &mut self.mastodon.interval_hours, &mut problems);
+        env_value("DIGESTS_ENABLED", &mut self.digests.enabled, &mut problems);
+        env_optional("SLACK_SIGNING_SECRET", &mut self.slack.signing_secret);
+        env_optional("SLACK_BOT_TOKEN", &mut self.slack.bot_token);
+        env_value("SLACK_API_URL", &mut self.slack.api_url, &mut problems);
+        env_optional("DISCORD_BOT_TOKEN", &mut self.discord.token);
+        env_list("DISCORD_CHANNELS", &mut self.discord.channels, &mut problems);
+        env_optional("DISCORD_API_KEY", &mut self.discord.api_key);
+        env_value("DISCORD_REPLY", &mut self.discord.reply, &mut problems);
+        env_value("DISCORD_API_URL", &mut self.discord.api_url, &mut problems);
+        self.client.apply_env();
+        env_value("RATE_LIMIT_PER_MINUTE", &mut self.rate_limit.per_minute, &mut problems);
+        env_optional("METRICS_TOKEN", &mut self.metrics.token);
+        env_value("READYZ_CHECK_AI", &mut self.health.check_ai, &mut problems);
+        env_value("JOB_CONCURRENCY", &mut self.jobs.concurrency, &mut problems);
+        env_value("FETCH_CONCURRENCY", &mut self.jobs.fetch_concurrency, &mut problems);
+        env_value("AI_CONCURRENCY", &mut self.jobs.ai_concurrency, &mut problems);
+        env_value("FETCH_USER_AGENT", &mut self.fetch.user_agent, &mut problems);
+        env_value("RESPECT_ROBOTS_TXT", &mut self.fetch.respect_robots, &mut problems);
+        env_value("FETCH_DOMAIN_INTERVAL_MS", &mut self.fetch.domain_interval_ms, &mut problems);
+        env_value("FETCH_TIMEOUT_SECS", &mut self.fetch.timeout_secs, &mut problems);
+        env_value("FETCH_MAX_BYTES", &mut self.fetch.max_bytes, &mut problems);
+        env_value("FETCH_MAX_REDIRECTS", &mut self.fetch.max_redirects, &mut problems);
+        env_list("FETCH_ALLOWED_NETWORKS", &mut self.fetch.allowed_networks, &mut problems);
+        env_optional("FETCH_PROXY", &mut self.fetch.proxy);
+        env_list("FETCH_PROXY_RULES", &mut self.fetch.proxy_rules, &mut problems);
+        env_optional("FETCH_CREDENTIALS_KEY", &mut self.fetch.credentials_key);
+        env_value("WAYBACK_FALLBACK", &mut self.fetch.wayback_fallback, &mut problems);
+        env_value("WAYBACK_API_URL", &mut self.fetch.wayback_api, &mut problems);
+        env_value("RETRY_ATTEMPTS", &mut self.retry.attempts, &mut problems);
+        env_value("RETRY_BASE_DELAY_MS", &mut self.retry.base_delay_ms, &mut problems);
+        env_value("RETRY_MAX_DELAY_MS", &mut self.retry.max_delay_ms, &mut problems);
+        env_value("RETRY_JITTER", &mut self.retry.jitter, &mut problems);
+        env_list("RETRY_ON", &mut self.retry.retry_on, &mut problems);
+        problems
+    }
+
+    /// `socket_mode` as permission bits.
+    pub fn socket_permissions(&self) -> Option<u32> {
+        u32::from_str_radix(&self.socket_mode, 8).ok().filter(|mode| *mode <= 0o777)
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.socket_permissions().is_none() {
+            problems.push(format!("socket_mode (SOCKET_MODE) must be octal permissions like 660: {:?}", self.socket_mode));
+        }
+        if matches!(self.listen, Listen::Unix(_)) && self.tls.cert.is_some() {
+            problems.push("TLS isn't supported on a Unix socket; terminate it in the proxy in front".to_string());
+        }
+        if self.tls.cert.is_some() != self.tls.key.is_some() {
+            problems.push("tls.cert (TLS_CERT) and tls.key (TLS_KEY) must be set together".to_string());
+        }
+        if self.database.url.is_empty() {
+            problems.push("database.url (DATABASE_URL) must be set".to_string());
+        }
+        if self.database.max_connections == 0 {
+            problems.push("database.max_connections must be at least 1".to_string());
+        }
+        if self.ai.api_key.is_empty() && matches!(self.ai.provider, Provider::OpenAi | Provider::Anthropic) {
+            problems.push("ai.api_key (OPENAI_API_KEY) must be set".to_string());
+        }
+        if url::Url::parse(self.ai.api_base()).is_err() {
+            problems.push(format!("ai.api_base (OPENAI_API_BASE) is not a URL: {:?}", self.ai.api_base()));
+        }
+        // Embeddings are only requested through the OpenAI-compatible API
+        if self.ai.embedding_model.is_some() && self.ai.provider != Provider::OpenAi {
+            problems.push("ai.embedding_model (EMBEDDING_MODEL) needs the openai provider".to_string());
+        }
+        if let Some(temperature) = self.tagging.temperature
+            && !(0.0..=2.0).contains(&temperature)
+        {
+            problems.push(format!("tagging.temperature (LLM_TEMPERATURE) must be between 0 and 2: {}", temperature));
+        }
+        if self.tagging.max_tags == 0 {
+            problems.push("tagging.max_tags (MAX_TAGS) must be at least 1".to_string());
+        }
+        if !self.tagging.prompt.contains("{content}") && !self.tagging.prompt.contains("{url}") {
+            problems.push("tagging.prompt (TAGGING_PROMPT) must contain {content} or {url}, or the model never sees the page".to_string());
+        }
+        if let Some(template) = &self.screenshots.service_url
+            && !template.contains("{url}")
+        {
+            problems.push("screenshots.service_url (SCREENSHOT_SERVICE_URL) must contain a {url} placeholder".to_string());
+        }
+        if reqwest::header::HeaderValue::from_str(&self.fetch.user_agent).is_err() || self.fetch.user_agent.is_empty() {
+            problems.push(format!("fetch.user_agent (FETCH_USER_AGENT) is not a valid header value: {:?}", self.fetch.user_agent));
+        }
+        if self.fetch.timeout_secs == 0 {
+            problems.push("fetch.timeout_secs (FETCH_TIMEOUT_SECS) must be at least 1".to_string());
+        }
+        if self.fetch.max_bytes == 0 {
+            problems.push("fetch.max_bytes (FETCH_MAX_BYTES) must be at least 1".to_string());
+        }
+        for entry in &self.fetch.allowed_networks {
+            if entry.contains('/') && entry.parse::<ipnet::IpNet>().is_err() {
+                problems.push(format!("fetch.allowed_networks (FETCH_ALLOWED_NETWORKS) has an invalid network: {:?}", entry));
+            }
+        }
+        if let Some(proxy) = &self.fetch.proxy
+            && let Err(e) = proxy_url(proxy)
+        {
+            problems.push(format!("fetch.proxy (FETCH_PROXY): {}", e));
+        }
+        if self.fetch.credentials_key.as_ref().is_some_and(|key| key.len() < 16) {
+            problems.push("fetch.credentials_key (FETCH_CREDENTIALS_KEY) must be at least 16 characters".to_string());
+        }
+        if url::Url::parse(&self.archive.wayback_save_url).is_err() {
+            problems.push(format!("archive.wayback_save_url (WAYBACK_SAVE_URL) is not a URL: {:?}", self.archive.wayback_save_url));
+        }
+        if self.archive.wayback_access_key.is_some() != self.archive.wayback_secret_key.is_some() {
+            problems.push("archive.wayback_access_key (WAYBACK_ACCESS_KEY) and archive.wayback_secret_key (WAYBACK_SECRET_KEY) must be set together".to_string());
+        }
+        if url::Url::parse(&self.fetch.wayback_api).is_err() {
+            problems.push(format!("fetch.wayback_api (WAYBACK_API_URL) is not a URL: {:?}", self.fetch.wayback_api));
+        }
+        if !(0.0..=1.0).contains(&self.recrawl.change_threshold) {
+            problems.push("recrawl.change_threshold (CONTENT_CHANGE_THRESHOLD) must be between 0 and 1".to_string());
+        }
+        if self.retry.attempts == 0 {
+            problems.push("retry.attempts (RETRY_ATTEMPTS) must be at least 1".to_string());
+        }
+        if self.retry.max_delay_ms < self.retry.base_delay_ms {
+            problems.push("retry.max_delay_ms (RETRY_MAX_DELAY_MS) can't be less than retry.base_delay_ms".to_string());
+        }
+        if self.link_check.failure_threshold < 1 {
+            problems.push("link_check.failure_threshold must be at least 1".to_string());
+        }
+        for (name, limit) in [
+            ("jobs.concurrency", self.jobs.concurrency),
+            ("jobs.fetch_concurrency", self.jobs.fetch_concurrency),
+            ("jobs.ai_concurrency", self.jobs.ai_concurrency),
+        ] {
+            if limit == 0 {
+                problems.push(format!("{} must be at least 1", name));
+            }
+        }
+        if self.trash.retention_days < 0 {
+            problems.push("trash.retention_days can't be negative".to_string());
+        }
+        if self.github.token.is_some() != self.github.user.is_some() {
+            problems.push("github.token (GITHUB_TOKEN) and github.user (GITHUB_SYNC_USER) must be set together".to_string());
+        }
+        if self.github.interval_hours == 0 {
+            problems.push("github.interval_hours (GITHUB_SYNC_INTERVAL_HOURS) must be at least 1".to_string());
+        }
+        if url::Url::parse(&self.github.api_url).is_err() {
+            problems.push(format!("github.api_url (GITHUB_API_URL) is not a URL: {:?}", self.github.api_url));
+        }
+        let mastodon = [&self.mastodon.url, &self.mastodon.token, &self.mastodon.user];
+        if mastodon.iter().any(|value| value.is_some()) && !mastodon.iter().all(|value| value.is_some()) {
+            problems.push(
+                "mastodon.url (MASTODON_URL), mastodon.token (MASTODON_TOKEN) and mastodon.user (MASTODON_SYNC_USER) must be set together"
+                    .to_string(),
+            );
+        }
+        if let Some(url) = &self.mastodon.url
+            && url::Url::parse(url).is_err()
+        {
+            problems.push(format!("mastodon.url (MASTODON_URL) is not a URL: {:?}", url));
+        }
+        if self.mastodon.interval_hours == 0 {
+            problems.push("mastodon.interval_hours (MASTODON_SYNC_INTERVAL_HOURS) must be at least 1".to_string());
+        }
+        if self.slack.bot_token.is_some() && self.slack.signing_secret.is_none() {
+            problems.push("slack.bot_token (SLACK_BOT_TOKEN) needs slack.signing_secret (SLACK_SIGNING_SECRET)".to_string());
+        }
+        if url::Url::parse(&self.slack.api_url).is_err() {
+            problems.push(format!("slack.api_url (SLACK_API_URL) is not a URL: {:?}", self.slack.api_url));
+        }
+        if self.discord.token.is_some() != self.discord.api_key.is_some() {
+            problems.push("discord.token (DISCORD_BOT_TOKEN) and discord.api_key (DISCORD_API_KEY) must be set together".to_string());
+        }
+        if self.discord.token.is_some() && self.discord.channels.is_empty() {
+            problems.push("discord.channels (DISCORD_CHANNELS) must list at least one channel for the bot to watch".to_string());
+        }
+        for channel in &self.discord.channels {
+            if channel.is_empty() || !channel.bytes().all(|b| b.is_ascii_digit()) {
+                problems.push(format!("discord.channels (DISCORD_CHANNELS) has something that isn't a channel id: {:?}", channel));
+            }
+        }
+        if url::Url::parse(&self.discord.api_url).is_err() {
+            problems.push(format!("discord.api_url (DISCORD_API_URL) is not a URL: {:?}", self.discord.api_url));
+        }
+        if self.digests.enabled && self.ai.provider == Provider::None {
+            problems.push("digests.enabled (DIGESTS_ENABLED) needs a model, and ai.provider is none".to_string());
+        }
+        problems
+    }
+}
+
+impl ClientConfig {
+    /// The `[client]` section of `path`, if given, with the environment applied. Nothing else
+    /// is checked: the server's own settings don't matter to a command that only talks to it.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let mut client = Config::read(path)?.client;
+        client.apply_env();
+        let problems = client.validate();
+        if !problems.is_empty() {
+            anyhow::bail!("Invalid configuration:\n  - {}", problems.join("\n  - "));
+        }
+        Ok(client)
+    }
+
+    fn apply_env(&mut self) {
+        if let Ok(url) = std::env::var("LINKMAN_URL") {
+            self.url = url;
+        }
+        env_optional("LINKMAN_TOKEN", &mut self.token);
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !url::Url::parse(&self.url).is_ok_and(|url| matches!(url.scheme(), "http" | "https")) {
+            problems.push(format!("client.url (LINKMAN_URL) is not an http(s) URL: {:?}", self.url));
+        }
+        if self.token.is_none() {
+            problems.push("client.token (LINKMAN_TOKEN) must be set to an API key".to_string());
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(
+            r#"
+            listen = "127.0.0.1:8080"
+
+            [database]
+            url = "postgres://localhost/linkman"
+
+            [ai]
+            api_key = "sk-test"
+            embedding_model = "text-embedding-3-small"
+
+            [trash]
+            retention_days = 0
+
+            [retry]
+            retry_on = ["timeout", "server_error"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.listen, Listen::Tcp(SocketAddr::from(([127, 0, 0, 1], 8080))));
+        assert_eq!(config.socket_permissions(), Some(0o660));
+        assert_eq!(config.database.max_connections, 5);
+        assert_eq!(config.ai.model, "user.gemma-4-26B-A4B-it-GGUF");
+        assert_eq!(config.ai.embedding_model.as_deref(), Some("text-embedding-3-small"));
+        assert_eq!(config.trash.retention_days, 0);
+        assert_eq!(config.link_check.interval_hours, 24);
+        assert_eq!(config.retry.retry_on, [RetryClass::Timeout, RetryClass::ServerError]);
+        assert_eq!(config.retry.attempts, 3);
+        assert!(config.validate().is_empty());
+
+        // Ollama needs no key and has its own default address
+        let config = Config::parse("[database]\nurl = \"postgres://localhost/linkman\"\n[ai]\nprovider = \"ollama\"\n").unwrap();
+        assert_eq!(config.ai.provider, Provider::Ollama);
+        assert_eq!(config.ai.api_base(), "http://localhost:11434");
+        assert!(config.validate().is_empty());
+
+        // Typos are errors rather than settings that silently do nothing
+        let err = Config::parse("[database]\nurl = \"x\"\nmax_connection = 3\n").unwrap_err();
+        assert!(err.to_string().contains("max_connection"));
+    }
+
+    #[test]
+    fn test_listen() {
+        assert_eq!("unix:/run/linkman.sock".parse(), Ok(Listen::Unix(PathBuf::from("/run/linkman.sock"))));
+        assert_eq!("[::1]:3000".parse(), Ok(Listen::Tcp(SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], 3000)))));
+        assert!("unix:".parse::<Listen>().is_err());
+        assert!("localhost".parse::<Listen>().is_err());
+        assert_eq!(Listen::Unix(PathBuf::from("/run/linkman.sock")).to_string(), "unix:/run/linkman.sock");
+    }
+
+    #[test]
+    fn test_render_prompt() {
+        let tagging = TaggingConfig {
+            prompt: "Tag {url} with up to {max_tags} tags:\n{content}".to_string(),
+            ..TaggingConfig::default()
+        };
+        assert_eq!(
+            tagging.render(&tagging.prompt, "https://example.com", "{\"title\":\"{url}\"}"),
+            "Tag https://example.com with up to 5 tags:\n{\"title\":\"{url}\"}"
+        );
+        assert!(tagging.render(&tagging.system_prompt, "", "").contains("exactly 5 descriptive tags"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = Config::default();
+        config.screenshots.service_url = Some("http://shots/capture".to_string());
+        config.trash.retention_days = -1;
+        config.tls.cert = Some("/etc/linkman/cert.pem".to_string());
+        let problems = config.validate();
+        assert_eq!(problems.len(), 5);
+        assert!(problems[0].contains("TLS_KEY"));
+        assert!(problems[1].contains("DATABASE_URL"));
+        assert!(problems[2].contains("OPENAI_API_KEY"));
+    }
+
+    #[test]
+    fn test_client_validate() {
+        let client = Config::parse("[client]\nurl = \"https://links.example.com\"\ntoken = \"secret\"\n").unwrap().client;
+        assert!(client.validate().is_empty());
+
+        let problems = ClientConfig { url: "links.example.com".to_string(), token: None }.validate();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("LINKMAN_URL"));
+        assert!(problems[1].contains("LINKMAN_TOKEN"));
+    }
+}
@@ -0,0 +1,128 @@
+//! What a bookmarked URL actually serves, and what can be got out of it for tagging.
+//!
+//! HTML goes through the usual scraping and markdown conversion. PDFs have their text extracted
+//! instead, and plain text is kept as it is. Images, video and audio only get what their headers
+//! (and, for images, their first few bytes) say about them, so nothing large is downloaded.
+
+use serde_json::{json, Value};
+
+/// Enough of an image to find its dimensions in.
+pub const IMAGE_HEADER_BYTES: u64 = 64 * 1024;
+
+/// How much extracted text is passed to the model, like the meta descriptions of a web page.
+const EXCERPT_CHARS: usize = 1500;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Html,
+    Pdf,
+    Text,
+    Image,
+    Video,
+    Audio,
+    Other,
+}
+
+impl Kind {
+    /// From a `Content-Type` header; servers that send none are assumed to be serving HTML.
+    pub fn of(content_type: Option<&str>) -> Kind {
+        let Some(essence) = content_type.map(essence) else {
+            return Kind::Html;
+        };
+        match essence.as_str() {
+            "text/html" | "application/xhtml+xml" => Kind::Html,
+            "application/pdf" => Kind::Pdf,
+            "text/plain" | "text/markdown" => Kind::Text,
+            e if e.starts_with("image/") => Kind::Image,
+            e if e.starts_with("video/") => Kind::Video,
+            e if e.starts_with("audio/") => Kind::Audio,
+            _ => Kind::Other,
+        }
+    }
+
+    /// Whether the whole body is worth downloading.
+    pub fn wants_body(self) -> bool {
+        matches!(self, Kind::Html | Kind::Pdf | Kind::Text)
+    }
+}
+
+/// `text/html; charset=utf-8` → `text/html`.
+pub fn essence(content_type: &str) -> String {
+    content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase()
+}
+
+/// The last path segment of a URL, which for files is often the best description there is.
+fn file_name(url: &str) -> Option<String> {
+    let path = url::Url::parse(url).ok()?.path().to_string();
+    let name = path.rsplit('/').next()?;
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Scraped metadata for anything that isn't HTML, in the same shape the tagger gets for pages.
+pub fn metadata(kind: Kind, content_type: Option<&str>, url: &str, length: Option<u64>, body: &[u8]) -> Value {
+    let mut meta = json!({
+        "scraped_at": chrono::Utc::now().to_rfc3339(),
+        "content_type": content_type.map(essence),
+    });
+    if let Some(name) = file_name(url) {
+        meta["file_name"] = json!(name);
+    }
+    if let Some(length) = length {
+        meta["size_bytes"] = json!(length);
+    }
+    if kind == Kind::Image
+        && let Ok(size) = imagesize::blob_size(body)
+    {
+        meta["width"] = json!(size.width);
+        meta["height"] = json!(size.height);
+    }
+    meta
+}
+
+/// Adds the start of a document's text to its metadata, for the model to go on.
+pub fn add_excerpt(meta: &mut Value, text: &str) {
+    let excerpt = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !excerpt.is_empty() {
+        meta["description"] = json!(excerpt.chars().take(EXCERPT_CHARS).collect::<String>());
+    }
+}
+
+/// The text of a PDF, or nothing for one that's scanned, encrypted or too broken to read.
+pub async fn pdf_text(body: Vec<u8>) -> Option<String> {
+    // The extractor is slow and can panic on malformed files, so keep it off the runtime
+    match tokio::task::spawn_blocking(move || pdf_extract::extract_text_from_mem(&body)).await {
+        Ok(Ok(text)) if !text.trim().is_empty() => Some(text),
+        Ok(Ok(_)) => None,
+        Ok(Err(e)) => {
+            eprintln!("Error extracting PDF text: {}", e);
+            None
+        }
+        Err(e) => {
+            eprintln!("Error extracting PDF text: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kind() {
+        assert_eq!(Kind::of(Some("text/html; charset=UTF-8")), Kind::Html);
+        assert_eq!(Kind::of(None), Kind::Html);
+        assert_eq!(Kind::of(Some("Application/PDF")), Kind::Pdf);
+        assert_eq!(Kind::of(Some("image/png")), Kind::Image);
+        assert_eq!(Kind::of(Some("video/mp4")), Kind::Video);
+        assert_eq!(Kind::of(Some("application/zip")), Kind::Other);
+        assert!(!Kind::Video.wants_body());
+
+        // A 1x1 GIF
+        let gif = b"GIF89a\x01\x00\x01\x00\x80\x00\x00";
+        let meta = metadata(Kind::Image, Some("image/gif"), "https://example.com/a/dot.gif", Some(42), gif);
+        assert_eq!(meta["file_name"], "dot.gif");
+        assert_eq!(meta["size_bytes"], 42);
+        assert_eq!((meta["width"].as_u64(), meta["height"].as_u64()), (Some(1), Some(1)));
+    }
+}
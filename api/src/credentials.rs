@@ -0,0 +1,274 @@
+//! Logins for fetching pages that are only worth archiving once signed in.
+//!
+//! Each user can keep a `Cookie` header or a basic auth username and password per domain; the
+//! fetcher sends the one for the most specific domain a bookmark is in, which covers its
+//! subdomains too. They're encrypted with `FETCH_CREDENTIALS_KEY` before they reach the
+//! database (AES-256 in CTR mode, authenticated with HMAC-SHA256) and never returned by the API,
+//! which only lists the domains. Without a key the endpoints answer 501.
+
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::{Aes256, Block};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::{header, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::config::FetchConfig;
+use crate::{AppState, CurrentUser};
+
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 32;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/credentials", get(list_credentials).post(save_credential))
+        .route("/credentials/{id}", delete(delete_credential))
+}
+
+fn db_error(e: sqlx::Error) -> StatusCode {
+    eprintln!("Credentials Error: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// What's sent with requests to a domain.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Credential {
+    Cookie { cookie: String },
+    Basic { username: String, password: String },
+}
+
+impl Credential {
+    fn kind(&self) -> &'static str {
+        match self {
+            Credential::Cookie { .. } => "cookie",
+            Credential::Basic { .. } => "basic",
+        }
+    }
+
+    pub fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        match self {
+            Credential::Cookie { cookie } => request.header(header::COOKIE, cookie),
+            Credential::Basic { username, password } => request.basic_auth(username, Some(password)),
+        }
+    }
+}
+
+/// Encrypts and decrypts stored credentials.
+pub struct Key {
+    cipher: Aes256,
+    mac_key: [u8; 32],
+}
+
+impl Key {
+    pub fn from_config(config: &FetchConfig) -> Option<Self> {
+        let secret = config.credentials_key.as_deref()?;
+        Some(Key {
+            cipher: Aes256::new(&derive(secret, b"linkman credentials encryption").into()),
+            mac_key: derive(secret, b"linkman credentials authentication"),
+        })
+    }
+
+    /// A random nonce, the ciphertext and a tag over both.
+    pub fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(plaintext);
+        self.apply_keystream(&nonce, &mut sealed[NONCE_LEN..]);
+        let tag = self.mac(&sealed).finalize().into_bytes();
+        sealed.extend_from_slice(&tag);
+        sealed
+    }
+
+    /// The plaintext, or `None` if `sealed` was tampered with or sealed with another key.
+    pub fn open(&self, sealed: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < NONCE_LEN + TAG_LEN {
+            return None;
+        }
+        let (message, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+        self.mac(message).verify_slice(tag).ok()?;
+        let (nonce, ciphertext) = message.split_at(NONCE_LEN);
+        let mut plaintext = ciphertext.to_vec();
+        self.apply_keystream(nonce.try_into().ok()?, &mut plaintext);
+        Some(plaintext)
+    }
+
+    fn mac(&self, message: &[u8]) -> Hmac<Sha256> {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.mac_key).expect("HMAC accepts any key length");
+        mac.update(message);
+        mac
+    }
+
+    /// CTR mode: XORs `data` with AES of the nonce followed by a block counter.
+    fn apply_keystream(&self, nonce: &[u8; NONCE_LEN], data: &mut [u8]) {
+        for (counter, chunk) in data.chunks_mut(16).enumerate() {
+            let mut block = Block::default();
+            block[..NONCE_LEN].copy_from_slice(nonce);
+            block[NONCE_LEN..].copy_from_slice(&(counter as u32).to_be_bytes());
+            self.cipher.encrypt_block(&mut block);
+            chunk.iter_mut().zip(block.iter()).for_each(|(byte, key)| *byte ^= key);
+        }
+    }
+}
+
+fn derive(secret: &str, label: &[u8]) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(label);
+    mac.finalize().into_bytes().into()
+}
+
+/// `*.Example.com.` → `example.com`, or `None` if it isn't a domain name.
+fn normalize_domain(domain: &str) -> Option<String> {
+    let domain = domain.trim().trim_start_matches("*.").trim_matches('.').to_ascii_lowercase();
+    match url::Host::parse(&domain).ok()? {
+        url::Host::Domain(domain) if !domain.is_empty() => Some(domain),
+        url::Host::Domain(_) => None,
+        ip => Some(ip.to_string()),
+    }
+}
+
+/// The stored credential for the most specific domain `host` is in, ready to send.
+pub async fn for_host(state: &AppState, user_id: Uuid, host: &str) -> anyhow::Result<Option<Credential>> {
+    let Some(key) = &state.credential_key else {
+        return Ok(None);
+    };
+    let host = host.to_ascii_lowercase();
+    let sealed: Option<(String, Vec<u8>)> = sqlx::query_as(
+        "SELECT domain, sealed FROM domain_credentials
+         WHERE user_id = $1 AND ($2 = domain OR right($2, length(domain) + 1) = '.' || domain)
+         ORDER BY length(domain) DESC LIMIT 1"
+    )
+    .bind(user_id)
+    .bind(&host)
+    .fetch_optional(&state.db)
+    .await?;
+    let Some((domain, sealed)) = sealed else {
+        return Ok(None);
+    };
+    // Most likely the key was changed; fetch without rather than not at all
+    match key.open(&sealed).and_then(|plaintext| serde_json::from_slice(&plaintext).ok()) {
+        Some(credential) => Ok(Some(credential)),
+        None => {
+            eprintln!("Error decrypting credentials for {}: wrong FETCH_CREDENTIALS_KEY?", domain);
+            Ok(None)
+        }
+    }
+}
+
+/// A stored credential, minus the secret.
+#[derive(Serialize, sqlx::FromRow)]
+struct CredentialInfo {
+    id: Uuid,
+    domain: String,
+    /// `cookie` or `basic`
+    kind: String,
+    created_at: DateTime<Utc>,
+}
+
+async fn list_credentials(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Result<Json<Vec<CredentialInfo>>, StatusCode> {
+    sqlx::query_as::<_, CredentialInfo>(
+        "SELECT id, domain, kind, created_at FROM domain_credentials WHERE user_id = $1 ORDER BY domain"
+    )
+    .bind(user.id)
+    .fetch_all(&state.db)
+    .await
+    .map(Json)
+    .map_err(db_error)
+}
+
+#[derive(Deserialize)]
+struct SaveCredentialRequest {
+    /// e.g. `wiki.example.com`, which also covers its subdomains
+    domain: String,
+    /// A whole `Cookie` header, e.g. `session=abc; theme=dark`
+    cookie: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+/// Stores a cookie or a username and password for a domain, replacing any it already had.
+async fn save_credential(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Json(payload): Json<SaveCredentialRequest>,
+) -> Result<(StatusCode, Json<CredentialInfo>), StatusCode> {
+    let key = state.credential_key.as_ref().ok_or(StatusCode::NOT_IMPLEMENTED)?;
+    let domain = normalize_domain(&payload.domain).ok_or(StatusCode::BAD_REQUEST)?;
+    let credential = match (payload.cookie, payload.username) {
+        (Some(cookie), None) if header::HeaderValue::from_str(&cookie).is_ok() && !cookie.is_empty() => {
+            Credential::Cookie { cookie }
+        }
+        (None, Some(username)) if !username.is_empty() => {
+            Credential::Basic { username, password: payload.password.unwrap_or_default() }
+        }
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+    let plaintext = serde_json::to_vec(&credential).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let info = sqlx::query_as::<_, CredentialInfo>(
+        "INSERT INTO domain_credentials (user_id, domain, kind, sealed) VALUES ($1, $2, $3, $4)
+         ON CONFLICT (user_id, domain) DO UPDATE SET kind = EXCLUDED.kind, sealed = EXCLUDED.sealed, created_at = now()
+         RETURNING id, domain, kind, created_at"
+    )
+    .bind(user.id)
+    .bind(&domain)
+    .bind(credential.kind())
+    .bind(key.seal(&plaintext))
+    .fetch_one(&state.db)
+    .await
+    .map_err(db_error)?;
+    Ok((StatusCode::CREATED, Json(info)))
+}
+
+async fn delete_credential(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = sqlx::query("DELETE FROM domain_credentials WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user.id)
+        .execute(&state.db)
+        .await
+        .map_err(db_error)?;
+
+    if deleted.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal() {
+        let config = FetchConfig { credentials_key: Some("correct horse battery staple".to_string()), ..FetchConfig::default() };
+        let key = Key::from_config(&config).unwrap();
+        let plaintext = b"{\"kind\":\"cookie\",\"cookie\":\"session=a-fairly-long-session-token\"}";
+        let sealed = key.seal(plaintext);
+        assert_eq!(key.open(&sealed).as_deref(), Some(&plaintext[..]));
+        assert_ne!(key.seal(plaintext), sealed);
+
+        let mut tampered = sealed.clone();
+        tampered[NONCE_LEN + 3] ^= 1;
+        assert_eq!(key.open(&tampered), None);
+        let other = Key::from_config(&FetchConfig { credentials_key: Some("another key".to_string()), ..config }).unwrap();
+        assert_eq!(other.open(&sealed), None);
+
+        assert_eq!(normalize_domain(" *.Wiki.Example.com. ").as_deref(), Some("wiki.example.com"));
+        assert_eq!(normalize_domain("https://example.com/"), None);
+    }
+}
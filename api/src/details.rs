@@ -0,0 +1,128 @@
+//! A page's description, lead image, publication date and author, for clients to render.
+//!
+//! OpenGraph and the usual `<meta>` tags come first, as scraped into the page's metadata (along
+//! with anything oEmbed said), and schema.org JSON-LD fills in whatever they leave out.
+
+use std::collections::VecDeque;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use scraper::{Html, Selector};
+use serde_json::Value;
+use url::Url;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Details {
+    pub description: Option<String>,
+    pub image_url: Option<String>,
+    pub published_at: Option<DateTime<Utc>>,
+    pub author: Option<String>,
+}
+
+/// The details of the page at `page_url`, from its markup and the metadata scraped from it.
+pub fn extract(html_content: &str, page_url: &str, site_meta: &Value) -> Details {
+    let nodes = json_ld(html_content);
+    let meta = |keys: &[&str]| keys.iter().find_map(|key| text(&site_meta[key]));
+    let linked = |key: &str| nodes.iter().find_map(|node| text(&node[key]));
+
+    let image = meta(&["og:image", "twitter:image"])
+        .or_else(|| nodes.iter().find_map(|node| image(&node["image"])))
+        .or_else(|| text(&site_meta["embed"]["thumbnail_url"]));
+    Details {
+        description: meta(&["og:description", "description", "twitter:description"]).or_else(|| linked("description")),
+        image_url: image.and_then(|image| Url::parse(page_url).ok()?.join(&image).ok()).map(String::from),
+        published_at: meta(&["article:published_time"])
+            .or_else(|| linked("datePublished"))
+            .and_then(|date| parse_date(&date)),
+        author: meta(&["article:author", "author"])
+            .or_else(|| nodes.iter().find_map(|node| name(&node["author"])))
+            .or_else(|| text(&site_meta["embed"]["author"])),
+    }
+}
+
+/// Every JSON-LD object on the page, with arrays and `@graph`s flattened out.
+fn json_ld(html_content: &str) -> Vec<Value> {
+    let document = Html::parse_document(html_content);
+    let selector = Selector::parse(r#"script[type="application/ld+json"]"#).unwrap();
+    let mut pending: VecDeque<Value> = document
+        .select(&selector)
+        .filter_map(|script| serde_json::from_str(&script.text().collect::<String>()).ok())
+        .collect();
+    let mut nodes = Vec::new();
+    while let Some(value) = pending.pop_front() {
+        match value {
+            Value::Array(items) => pending.extend(items),
+            Value::Object(mut object) => {
+                if let Some(graph) = object.remove("@graph") {
+                    pending.push_back(graph);
+                }
+                nodes.push(Value::Object(object));
+            }
+            _ => {}
+        }
+    }
+    nodes
+}
+
+fn text(value: &Value) -> Option<String> {
+    let text = value.as_str()?.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+/// A schema.org image, which may be a URL, an `ImageObject` or a list of either.
+fn image(value: &Value) -> Option<String> {
+    match value {
+        Value::Array(items) => items.iter().find_map(image),
+        Value::Object(_) => text(&value["url"]),
+        _ => text(value),
+    }
+}
+
+/// A schema.org author, which may be a name, a `Person` or a list of either.
+fn name(value: &Value) -> Option<String> {
+    match value {
+        Value::Array(items) => {
+            let names: Vec<String> = items.iter().filter_map(name).collect();
+            (!names.is_empty()).then(|| names.join(", "))
+        }
+        Value::Object(_) => text(&value["name"]),
+        _ => text(value),
+    }
+}
+
+/// RFC 3339, or a date or time without a zone, taken as UTC.
+fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(date)
+        .map(|t| t.with_timezone(&Utc))
+        .ok()
+        .or_else(|| NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S").ok().map(|t| t.and_utc()))
+        .or_else(|| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok().map(|d| d.and_hms_opt(0, 0, 0).unwrap().and_utc()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_extract() {
+        let html = r#"<html><head><script type="application/ld+json">
+            {"@context": "https://schema.org", "@graph": [
+                {"@type": "WebSite", "name": "Blog"},
+                {"@type": "BlogPosting", "description": "From JSON-LD", "datePublished": "2024-03-01",
+                 "image": {"@type": "ImageObject", "url": "/img/lead.png"},
+                 "author": [{"@type": "Person", "name": "Ada"}, {"@type": "Person", "name": "Grace"}]}
+            ]}
+        </script></head></html>"#;
+        let site_meta = json!({ "og:description": "From OpenGraph" });
+        assert_eq!(
+            extract(html, "https://blog.example.com/posts/1", &site_meta),
+            Details {
+                description: Some("From OpenGraph".to_string()),
+                image_url: Some("https://blog.example.com/img/lead.png".to_string()),
+                published_at: Some("2024-03-01T00:00:00Z".parse().unwrap()),
+                author: Some("Ada, Grace".to_string()),
+            }
+        );
+        assert_eq!(extract("<html></html>", "https://example.com/", &json!({})), Details::default());
+    }
+}
@@ -0,0 +1,219 @@
+//! A weekly roundup of what each user saved, written by the model.
+//!
+//! With `DIGESTS_ENABLED` set, once a week is over (weeks start on Monday) each user who
+//! saved something during it gets a write-up of those bookmarks and the themes they shared, built
+//! from the titles, sites, tags and summaries already stored for them; nothing is fetched again.
+//! Bookmarks still being processed go in with what they have. `GET /digests/latest` returns the
+//! newest one. linkman sends no email, so there is no email digest for it to go into.
+
+use std::time::Duration;
+
+use axum::{extract::State, http::StatusCode, routing::get, Extension, Json, Router};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{config::DigestsConfig, AppState, CurrentUser};
+
+/// The most bookmarks one write-up is given, the newest of the week's.
+const MAX_BOOKMARKS: i64 = 100;
+
+pub struct Config;
+
+impl Config {
+    pub fn from_config(config: &DigestsConfig) -> Option<Self> {
+        config.enabled.then_some(Config)
+    }
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/digests/latest", get(latest_digest))
+}
+
+pub async fn run(state: AppState, _config: Config) {
+    let mut tick = tokio::time::interval(Duration::from_secs(60 * 60));
+    loop {
+        tick.tick().await;
+        let result = write_due(&state).await;
+        state.metrics.record_job("digests", result.is_ok());
+        if let Err(e) = result {
+            eprintln!("Digest Error: {}", e);
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct WeekBookmark {
+    id: Uuid,
+    url: String,
+    title: Option<String>,
+    domain: Option<String>,
+    ai_summary: Option<String>,
+    tags: Option<Vec<String>>,
+}
+
+/// What the model is given: one entry per bookmark.
+fn prompt(week_start: NaiveDate, bookmarks: &[WeekBookmark]) -> String {
+    let mut prompt = format!("Bookmarks saved in the week of {}:\n", week_start.format("%B %-d, %Y"));
+    for bookmark in bookmarks {
+        prompt.push_str(&format!("\n- {}", bookmark.title.as_deref().unwrap_or(&bookmark.url)));
+        if let Some(domain) = &bookmark.domain {
+            prompt.push_str(&format!(" ({})", domain));
+        }
+        if let Some(tags) = bookmark.tags.as_ref().filter(|tags| !tags.is_empty()) {
+            prompt.push_str(&format!(" [{}]", tags.join(", ")));
+        }
+        if let Some(summary) = &bookmark.ai_summary {
+            prompt.push_str(&format!("\n  {}", summary.trim()));
+        }
+    }
+    prompt
+}
+
+/// Writes last week's digest for every user who saved something then and doesn't have one yet.
+async fn write_due(state: &AppState) -> anyhow::Result<()> {
+    let due: Vec<(Uuid, NaiveDate)> = sqlx::query_as(
+        "SELECT u.id, (date_trunc('week', now()) - interval '1 week')::date FROM users u
+         WHERE EXISTS (SELECT 1 FROM bookmarks b WHERE b.user_id = u.id AND b.deleted_at IS NULL
+                           AND date_trunc('week', b.created_at) = date_trunc('week', now()) - interval '1 week')
+           AND NOT EXISTS (SELECT 1 FROM digests d WHERE d.user_id = u.id
+                               AND d.week_start = (date_trunc('week', now()) - interval '1 week')::date)"
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    for (user_id, week_start) in due {
+        if let Err(e) = write(state, user_id, week_start).await {
+            eprintln!("Error writing the digest for user {}: {}", user_id, e);
+        }
+    }
+    Ok(())
+}
+
+async fn write(state: &AppState, user_id: Uuid, week_start: NaiveDate) -> anyhow::Result<()> {
+    let week = "b.user_id = $1 AND b.deleted_at IS NULL AND date_trunc('week', b.created_at) = $2::timestamptz";
+    let bookmark_count: i64 = sqlx::query_scalar(&format!("SELECT count(*) FROM bookmarks b WHERE {}", week))
+        .bind(user_id)
+        .bind(week_start)
+        .fetch_one(&state.db)
+        .await?;
+    let bookmarks: Vec<WeekBookmark> = sqlx::query_as(&format!(
+        "SELECT b.id, b.url, b.title, b.host AS domain, b.ai_summary,
+             (SELECT array_agg(t.name ORDER BY t.name) FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id
+              WHERE bt.bookmark_id = b.id) AS tags
+         FROM bookmarks b WHERE {} ORDER BY b.created_at DESC, b.id DESC LIMIT $3",
+        week
+    ))
+    .bind(user_id)
+    .bind(week_start)
+    .bind(MAX_BOOKMARKS)
+    .fetch_all(&state.db)
+    .await?;
+
+    let Some(roundup) = state.tagger.roundup(&prompt(week_start, &bookmarks)).await? else {
+        return Ok(());
+    };
+    let ids: Vec<Uuid> = bookmarks.iter().map(|bookmark| bookmark.id).collect();
+    sqlx::query(
+        "INSERT INTO digests (user_id, week_start, summary, themes, bookmark_ids, bookmark_count)
+         VALUES ($1, $2, $3, $4, $5, $6)
+         ON CONFLICT (user_id, week_start) DO NOTHING"
+    )
+    .bind(user_id)
+    .bind(week_start)
+    .bind(roundup.summary.trim())
+    .bind(&roundup.themes)
+    .bind(&ids)
+    .bind(bookmark_count as i32)
+    .execute(&state.db)
+    .await?;
+    Ok(())
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct DigestBookmark {
+    id: Uuid,
+    url: String,
+    title: Option<String>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct Digest {
+    /// The Monday the week starts on
+    week_start: NaiveDate,
+    summary: String,
+    themes: Vec<String>,
+    /// Everything saved that week; the write-up covers at most the newest 100
+    bookmark_count: i32,
+    #[serde(skip)]
+    bookmark_ids: Vec<Uuid>,
+    /// The bookmarks it was written from, less any trashed since
+    #[sqlx(skip)]
+    bookmarks: Vec<DigestBookmark>,
+    created_at: DateTime<Utc>,
+}
+
+async fn latest_digest(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Result<Json<Digest>, StatusCode> {
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Get Digest Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let latest: Option<Digest> = sqlx::query_as(
+        "SELECT week_start, summary, themes, bookmark_ids, bookmark_count, created_at FROM digests
+         WHERE user_id = $1 ORDER BY week_start DESC LIMIT 1"
+    )
+    .bind(user.id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(db_error)?;
+    let mut digest = latest.ok_or(StatusCode::NOT_FOUND)?;
+
+    digest.bookmarks = sqlx::query_as(
+        "SELECT id, url, title FROM bookmarks
+         WHERE user_id = $1 AND id = ANY($2) AND deleted_at IS NULL ORDER BY created_at DESC, id DESC"
+    )
+    .bind(user.id)
+    .bind(&digest.bookmark_ids)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    Ok(Json(digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt() {
+        let bookmarks = vec![
+            WeekBookmark {
+                id: Uuid::new_v4(),
+                url: "https://blog.rust-lang.org/2024/07/25/Rust-1.80.0.html".to_string(),
+                title: Some("Announcing Rust 1.80".to_string()),
+                domain: Some("blog.rust-lang.org".to_string()),
+                ai_summary: Some(" The release notes for Rust 1.80. ".to_string()),
+                tags: Some(vec!["release".to_string(), "rust".to_string()]),
+            },
+            WeekBookmark {
+                id: Uuid::new_v4(),
+                url: "https://example.com/new".to_string(),
+                title: None,
+                domain: None,
+                ai_summary: None,
+                tags: None,
+            },
+        ];
+        let prompt = prompt(NaiveDate::from_ymd_opt(2024, 7, 22).unwrap(), &bookmarks);
+        assert_eq!(
+            prompt,
+            "Bookmarks saved in the week of July 22, 2024:\n\
+             \n- Announcing Rust 1.80 (blog.rust-lang.org) [release, rust]\n  The release notes for Rust 1.80.\
+             \n- https://example.com/new"
+        );
+    }
+}
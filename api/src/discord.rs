@@ -0,0 +1,530 @@
+//! A Discord bot that keeps the links posted in some channels, turning a link-dump channel into
+//! a searchable archive.
+//!
+//! With `DISCORD_BOT_TOKEN`, `DISCORD_API_KEY` and `DISCORD_CHANNELS` set, the bot stays connected
+//! to Discord's gateway and every link posted in one of the channels is bookmarked as `POST
+//! /bookmarks/sync` would with the API key, tagged with the channel's name. Other bots' messages
+//! are passed over, the bot's own replies included. Unless `DISCORD_REPLY` is off, each message is
+//! then answered with its links' titles and summaries once they've been fetched and tagged. The
+//! bot needs the Message Content intent turned on in the Developer Portal, and permission to read
+//! and send messages in the channels; it only sees what's posted while it's connected.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio::time::Instant;
+use tokio_tungstenite::{
+    tungstenite::{protocol::CloseFrame, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+use uuid::Uuid;
+
+use crate::{
+    authenticate, config::DiscordConfig, events, jobs, normalize::normalize_url, upsert_bookmark, AppState,
+    SyncBookmarkRequest,
+};
+
+/// `GUILDS`, for channel names, `GUILD_MESSAGES` and `MESSAGE_CONTENT`.
+const INTENTS: u64 = (1 << 0) | (1 << 9) | (1 << 15);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long a reply waits for its links to be processed before saying what it has.
+const REPLY_WAIT: Duration = Duration::from_secs(10 * 60);
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+/// A connection that lasted this long was fine, and the next failure starts the backoff over.
+const STABLE: Duration = Duration::from_secs(60);
+const SUMMARY_CHARS: usize = 300;
+/// The most Discord takes in one message.
+const MESSAGE_CHARS: usize = 2000;
+
+// Gateway opcodes
+const DISPATCH: u8 = 0;
+const HEARTBEAT: u8 = 1;
+const IDENTIFY: u8 = 2;
+const RESUME: u8 = 6;
+const RECONNECT: u8 = 7;
+const INVALID_SESSION: u8 = 9;
+const HELLO: u8 = 10;
+const HEARTBEAT_ACK: u8 = 11;
+
+pub struct Config {
+    client: reqwest::Client,
+    token: String,
+    api_key: String,
+    channels: HashSet<String>,
+    reply: bool,
+    api_url: String,
+}
+
+impl Config {
+    pub fn from_config(config: &DiscordConfig) -> anyhow::Result<Option<Self>> {
+        let (Some(token), Some(api_key)) = (&config.token, &config.api_key) else {
+            return Ok(None);
+        };
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent(format!("DiscordBot (https://github.com/jkbbwr/linkman, {})", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(Some(Config {
+            client,
+            token: token.clone(),
+            api_key: api_key.clone(),
+            channels: config.channels.iter().cloned().collect(),
+            reply: config.reply,
+            api_url: config.api_url.trim_end_matches('/').to_string(),
+        }))
+    }
+
+    fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        self.client.get(format!("{}{}", self.api_url, path)).header("Authorization", format!("Bot {}", self.token))
+    }
+}
+
+/// What outlives a single connection to the gateway.
+#[derive(Default)]
+struct Gateway {
+    /// The session to resume, and where
+    session: Option<(String, String)>,
+    /// The last event's sequence number
+    seq: Option<u64>,
+    /// Names of the watched channels, by id
+    names: HashMap<String, String>,
+}
+
+/// Why a connection to the gateway ended.
+enum Ended {
+    Reconnect,
+    /// Reconnecting won't help
+    Fatal(String),
+}
+
+pub async fn run(state: AppState, config: Config) {
+    // The gateway's TLS goes through rustls, which has more than one provider to choose from
+    let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+    let mut gateway = Gateway::default();
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        let started = Instant::now();
+        match connect(&state, &config, &mut gateway).await {
+            Ok(Ended::Reconnect) => {}
+            Ok(Ended::Fatal(reason)) => {
+                eprintln!("Discord Bot Error: {}; the bot has stopped", reason);
+                return;
+            }
+            Err(e) => eprintln!("Discord Gateway Error: {}", e),
+        }
+        if started.elapsed() > STABLE {
+            backoff = MIN_BACKOFF;
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+#[derive(Deserialize)]
+struct Payload {
+    op: u8,
+    #[serde(default)]
+    d: Value,
+    s: Option<u64>,
+    t: Option<String>,
+}
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn send(socket: &mut Socket, payload: Value) -> anyhow::Result<()> {
+    socket.send(Message::text(payload.to_string())).await?;
+    Ok(())
+}
+
+/// What to do about the gateway closing the connection with `code`.
+fn closed(code: u16, gateway: &mut Gateway) -> Ended {
+    match code {
+        4004 => Ended::Fatal("Discord turned DISCORD_BOT_TOKEN down".to_string()),
+        4014 => Ended::Fatal("the bot isn't allowed the Message Content intent; turn it on in the Developer Portal".to_string()),
+        4010..=4013 => Ended::Fatal(format!("the gateway closed the connection with {}", code)),
+        // The session can't be resumed
+        4007 | 4009 => {
+            gateway.session = None;
+            gateway.seq = None;
+            Ended::Reconnect
+        }
+        _ => Ended::Reconnect,
+    }
+}
+
+async fn connect(state: &AppState, config: &Config, gateway: &mut Gateway) -> anyhow::Result<Ended> {
+    let url = match &gateway.session {
+        Some((_, resume_url)) => resume_url.clone(),
+        None => {
+            let bot: Value = config.get("/gateway/bot").send().await?.error_for_status()?.json().await?;
+            bot["url"].as_str().ok_or_else(|| anyhow::anyhow!("no gateway URL in {}", bot))?.to_string()
+        }
+    };
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("{}/?v=10&encoding=json", url.trim_end_matches('/'))).await?;
+
+    let hello = match socket.next().await {
+        Some(Ok(Message::Text(text))) => serde_json::from_str::<Payload>(&text)?,
+        other => anyhow::bail!("expected the gateway's hello, got {:?}", other),
+    };
+    let interval = match (hello.op, hello.d["heartbeat_interval"].as_u64()) {
+        (HELLO, Some(millis)) => Duration::from_millis(millis),
+        _ => anyhow::bail!("expected the gateway's hello, got opcode {}", hello.op),
+    };
+    let opening = match &gateway.session {
+        Some((session_id, _)) => json!({
+            "op": RESUME,
+            "d": { "token": config.token, "session_id": session_id, "seq": gateway.seq },
+        }),
+        None => json!({
+            "op": IDENTIFY,
+            "d": {
+                "token": config.token,
+                "intents": INTENTS,
+                "properties": { "os": std::env::consts::OS, "browser": "linkman", "device": "linkman" },
+            },
+        }),
+    };
+    send(&mut socket, opening).await?;
+
+    // The first heartbeat goes out at a random point in the interval, so a restart doesn't have
+    // every bot beating at once
+    let mut heartbeat = tokio::time::interval_at(Instant::now() + interval.mul_f64(rand::random()), interval);
+    let mut acked = true;
+    loop {
+        tokio::select! {
+            _ = heartbeat.tick() => {
+                // No answer to the last one: the connection is dead without having closed
+                if !acked {
+                    return Ok(Ended::Reconnect);
+                }
+                acked = false;
+                send(&mut socket, json!({ "op": HEARTBEAT, "d": gateway.seq })).await?;
+            }
+            message = socket.next() => {
+                let payload = match message {
+                    Some(Ok(Message::Text(text))) => serde_json::from_str::<Payload>(&text)?,
+                    Some(Ok(Message::Close(frame))) => {
+                        return Ok(closed(frame.map_or(1000, |frame: CloseFrame| frame.code.into()), gateway));
+                    }
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => return Err(e.into()),
+                    None => return Ok(Ended::Reconnect),
+                };
+                if payload.s.is_some() {
+                    gateway.seq = payload.s;
+                }
+                match payload.op {
+                    DISPATCH => dispatch(state, config, gateway, payload).await,
+                    HEARTBEAT => send(&mut socket, json!({ "op": HEARTBEAT, "d": gateway.seq })).await?,
+                    HEARTBEAT_ACK => acked = true,
+                    RECONNECT => return Ok(Ended::Reconnect),
+                    INVALID_SESSION => {
+                        // `d` says whether the session can still be resumed
+                        if payload.d != true {
+                            gateway.session = None;
+                            gateway.seq = None;
+                        }
+                        return Ok(Ended::Reconnect);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Channel {
+    id: String,
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PostedMessage {
+    id: String,
+    channel_id: String,
+    #[serde(default)]
+    content: String,
+    author: Author,
+}
+
+#[derive(Deserialize)]
+struct Author {
+    #[serde(default)]
+    bot: bool,
+}
+
+async fn dispatch(state: &AppState, config: &Config, gateway: &mut Gateway, payload: Payload) {
+    match payload.t.as_deref() {
+        Some("READY") => {
+            let session_id = payload.d["session_id"].as_str();
+            let resume_url = payload.d["resume_gateway_url"].as_str();
+            if let (Some(session_id), Some(resume_url)) = (session_id, resume_url) {
+                gateway.session = Some((session_id.to_string(), resume_url.to_string()));
+            }
+        }
+        Some("GUILD_CREATE") => {
+            let channels: Vec<Channel> = serde_json::from_value(payload.d["channels"].clone()).unwrap_or_default();
+            for channel in channels {
+                remember(config, gateway, channel);
+            }
+        }
+        Some("CHANNEL_UPDATE") => {
+            if let Ok(channel) = serde_json::from_value(payload.d) {
+                remember(config, gateway, channel);
+            }
+        }
+        Some("MESSAGE_CREATE") => {
+            let Ok(message) = serde_json::from_value::<PostedMessage>(payload.d) else { return };
+            if message.author.bot || !config.channels.contains(&message.channel_id) {
+                return;
+            }
+            if let Err(e) = save_links(state, config, gateway, message).await {
+                eprintln!("Discord Bot Error: {}", e);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn remember(config: &Config, gateway: &mut Gateway, channel: Channel) {
+    if let Some(name) = channel.name
+        && config.channels.contains(&channel.id)
+    {
+        gateway.names.insert(channel.id, name);
+    }
+}
+
+/// The http(s) links in a message, in the order they first appear.
+fn links(content: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    // `<https://…>` is a link posted without a preview
+    for word in content.split(|c: char| c.is_whitespace() || c == '<' || c == '>') {
+        let Some(start) = [word.find("http://"), word.find("https://")].into_iter().flatten().min() else { continue };
+        let mut link = &word[start..];
+        // Punctuation ending a sentence or Markdown, and the `)` closing `[text](url)`, aren't
+        // part of the link; a `)` closing one the URL opened is
+        loop {
+            let mut trimmed = link.trim_end_matches(['.', ',', '!', '?', ';', ':', '\'', '"', '*', '_', '~', '|', ']']);
+            if trimmed.ends_with(')') && trimmed.matches(')').count() > trimmed.matches('(').count() {
+                trimmed = &trimmed[..trimmed.len() - 1];
+            }
+            if trimmed == link {
+                break;
+            }
+            link = trimmed;
+        }
+        if let Ok(url) = normalize_url(link)
+            && (url.starts_with("http://") || url.starts_with("https://"))
+            && !found.contains(&url)
+        {
+            found.push(url);
+        }
+    }
+    found
+}
+
+async fn channel_name(config: &Config, gateway: &mut Gateway, channel_id: &str) -> Option<String> {
+    if let Some(name) = gateway.names.get(channel_id) {
+        return Some(name.clone());
+    }
+    let response = config.get(&format!("/channels/{}", channel_id)).send().await.and_then(|r| r.error_for_status());
+    let channel: Channel = match response {
+        Ok(response) => response.json().await.ok()?,
+        Err(e) => {
+            eprintln!("Discord Channel Error: {}", e);
+            return None;
+        }
+    };
+    let name = channel.name?;
+    gateway.names.insert(channel_id.to_string(), name.clone());
+    Some(name)
+}
+
+async fn save_links(state: &AppState, config: &Config, gateway: &mut Gateway, message: PostedMessage) -> anyhow::Result<()> {
+    let urls = links(&message.content);
+    if urls.is_empty() {
+        return Ok(());
+    }
+    // Checked every time, so revoking the key stops the bot saving
+    let (user, key) = authenticate(state, &config.api_key, None)
+        .await
+        .map_err(|status| anyhow::anyhow!("DISCORD_API_KEY was turned down: {}", status))?;
+    if !key.allows("write") {
+        anyhow::bail!("DISCORD_API_KEY needs the write scope");
+    }
+    let tags = channel_name(config, gateway, &message.channel_id).await.map(|name| vec![name]);
+
+    let mut tx = state.db.begin().await?;
+    let mut saved = Vec::new();
+    for url in &urls {
+        let payload = SyncBookmarkRequest { url: url.clone(), title: None, notes: None, tags: tags.clone(), tag_mode: None };
+        saved.push(upsert_bookmark(&mut tx, user.id, key.tag_mode, url, &payload).await?);
+    }
+    let ids: Vec<Uuid> = saved.iter().map(|&(bookmark_id, _)| bookmark_id).collect();
+    jobs::queue(&mut *tx, &ids).await?;
+    tx.commit().await?;
+    state.job_wakeup.notify_one();
+
+    for &(bookmark_id, inserted) in &saved {
+        let event = if inserted { events::Event::Created } else { events::Event::Updated };
+        events::emit(state, user.id, event, bookmark_id);
+    }
+
+    if config.reply {
+        let state = state.clone();
+        let request = config
+            .client
+            .post(format!("{}/channels/{}/messages", config.api_url, message.channel_id))
+            .header("Authorization", format!("Bot {}", config.token));
+        tokio::spawn(async move {
+            if let Err(e) = reply(&state, request, &message.id, ids).await {
+                eprintln!("Discord Reply Error: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+#[derive(sqlx::FromRow)]
+struct Processed {
+    url: String,
+    title: Option<String>,
+    ai_summary: Option<String>,
+    processing_status: String,
+}
+
+/// Text shown as it is in a Discord message rather than as Markdown.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '~' | '`' | '|' | '>' | '#' | '[' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The reply: a line for each link's title, with its summary under it. Links are in `<>` so
+/// Discord doesn't preview them all over again.
+fn reply_text(bookmarks: &[Processed]) -> String {
+    let mut text = String::new();
+    for bookmark in bookmarks {
+        let mut entry = match &bookmark.title {
+            Some(title) => format!("**{}** <{}>\n", escape(title), bookmark.url),
+            None => format!("<{}>\n", bookmark.url),
+        };
+        if let Some(summary) = &bookmark.ai_summary {
+            let mut short: String = summary.chars().take(SUMMARY_CHARS).collect();
+            if summary.chars().count() > SUMMARY_CHARS {
+                short.push('…');
+            }
+            entry.push_str(&escape(&short));
+            entry.push('\n');
+        } else if bookmark.processing_status == "failed" {
+            entry.push_str("Saved, but the page couldn't be fetched.\n");
+        }
+        if text.chars().count() + entry.chars().count() > MESSAGE_CHARS {
+            break;
+        }
+        text.push_str(&entry);
+    }
+    text.trim_end().to_string()
+}
+
+async fn reply(state: &AppState, request: reqwest::RequestBuilder, message_id: &str, ids: Vec<Uuid>) -> anyhow::Result<()> {
+    let deadline = Instant::now() + REPLY_WAIT;
+    let bookmarks = loop {
+        let bookmarks: Vec<Processed> = sqlx::query_as(
+            "SELECT url, title, ai_summary, processing_status::text AS processing_status FROM bookmarks
+             WHERE id = ANY($1) ORDER BY array_position($1, id)"
+        )
+        .bind(&ids)
+        .fetch_all(&state.db)
+        .await?;
+        let pending = bookmarks.iter().any(|b| b.processing_status != "done" && b.processing_status != "failed");
+        if !pending || Instant::now() >= deadline {
+            break bookmarks;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    };
+    let content = reply_text(&bookmarks);
+    if content.is_empty() {
+        return Ok(());
+    }
+    request
+        .json(&json!({
+            "content": content,
+            "message_reference": { "message_id": message_id, "fail_if_not_exists": false },
+            // A summary quoting `@everyone` shouldn't ping anybody
+            "allowed_mentions": { "parse": [] },
+        }))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_links() {
+        assert_eq!(
+            links(
+                "worth a read: https://blog.rust-lang.org/2024/07/25/Rust-1.80.0.html, and <https://example.com/a> \
+                 (see [the docs](https://doc.rust-lang.org/std/)) https://en.wikipedia.org/wiki/Rust_(programming_language). \
+                 ||https://example.com/a|| ftp://example.com/ http://"
+            ),
+            vec![
+                "https://blog.rust-lang.org/2024/07/25/Rust-1.80.0.html",
+                "https://example.com/a",
+                "https://doc.rust-lang.org/std",
+                "https://en.wikipedia.org/wiki/Rust_(programming_language)",
+            ]
+        );
+        assert!(links("no links here").is_empty());
+    }
+
+    #[test]
+    fn test_closed() {
+        let mut gateway = Gateway { session: Some(("s".to_string(), "wss://resume".to_string())), seq: Some(5), ..Default::default() };
+        assert!(matches!(closed(4000, &mut gateway), Ended::Reconnect));
+        assert_eq!(gateway.seq, Some(5));
+        assert!(matches!(closed(4009, &mut gateway), Ended::Reconnect));
+        assert!(gateway.session.is_none() && gateway.seq.is_none());
+        assert!(matches!(closed(4004, &mut gateway), Ended::Fatal(_)));
+        assert!(matches!(closed(4014, &mut gateway), Ended::Fatal(reason) if reason.contains("Message Content")));
+    }
+
+    #[test]
+    fn test_reply_text() {
+        let bookmark = |url: &str, title: Option<&str>, summary: Option<&str>, status: &str| Processed {
+            url: url.to_string(),
+            title: title.map(str::to_string),
+            ai_summary: summary.map(str::to_string),
+            processing_status: status.to_string(),
+        };
+        assert_eq!(
+            reply_text(&[
+                bookmark("https://blog.rust-lang.org/", Some("Rust *Blog*"), Some("News from the Rust team"), "done"),
+                bookmark("https://example.com/gone", None, None, "failed"),
+            ]),
+            "**Rust \\*Blog\\*** <https://blog.rust-lang.org/>\nNews from the Rust team\n\
+             <https://example.com/gone>\nSaved, but the page couldn't be fetched."
+        );
+        let long = "a".repeat(SUMMARY_CHARS + 1);
+        let many: Vec<Processed> = (0..20).map(|i| bookmark(&format!("https://example.com/{}", i), None, Some(&long), "done")).collect();
+        let text = reply_text(&many);
+        assert!(text.chars().count() <= MESSAGE_CHARS);
+        assert!(text.contains('…') && !text.contains("/19>"));
+    }
+}
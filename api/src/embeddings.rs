@@ -0,0 +1,109 @@
+//! Vector embeddings of bookmarks for semantic search, stored in a pgvector column.
+//!
+//! Embeddings are only produced when `EMBEDDING_MODEL` is set, and that requires the
+//! pgvector extension to have been available when migrations ran.
+
+use pgvector::Vector;
+use rig::client::EmbeddingsClient;
+use rig::embeddings::EmbeddingModel;
+use sqlx::{Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::{AppState, BookmarkResponse, BOOKMARK_SELECT};
+
+/// Refuses to start with embeddings configured if the database can't store them.
+pub async fn ensure_available(db: &sqlx::PgPool) -> anyhow::Result<()> {
+    let has_column: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM information_schema.columns
+         WHERE table_name = 'bookmarks' AND column_name = 'embedding')"
+    )
+    .fetch_one(db)
+    .await?;
+
+    if !has_column {
+        anyhow::bail!(
+            "EMBEDDING_MODEL is set but bookmarks.embedding is missing; install pgvector and run \
+             `CREATE EXTENSION vector; ALTER TABLE bookmarks ADD COLUMN embedding vector;`"
+        );
+    }
+    Ok(())
+}
+
+/// The text a bookmark is embedded from: what it is called, what it is about, and how it was tagged.
+pub fn input(url: &str, title: Option<&str>, summary: Option<&str>, tags: &[String]) -> String {
+    let mut text = String::from(title.unwrap_or(url));
+    if let Some(summary) = summary {
+        text.push('\n');
+        text.push_str(summary);
+    }
+    if !tags.is_empty() {
+        text.push_str("\nTags: ");
+        text.push_str(&tags.join(", "));
+    }
+    text
+}
+
+/// Embeds `text` with the configured model; `None` when embeddings are disabled.
+pub async fn embed(state: &AppState, text: &str) -> anyhow::Result<Option<Vector>> {
+    let Some(model) = &state.embedding_model else {
+        return Ok(None);
+    };
+
+    let embedding = {
+        let _permit = state.ai_limit.acquire().await?;
+        state.openai.embedding_model(model.as_str()).embed_text(text).await
+    };
+    // The embedding API's token counts don't make it through rig, so only calls are counted
+    state.metrics.record_ai_call("embedding", embedding.is_ok(), None);
+    let embedding = embedding.map_err(|e| anyhow::anyhow!("Rig embedding error: {}", e))?;
+
+    Ok(Some(Vector::from(
+        embedding.vec.into_iter().map(|v| v as f32).collect::<Vec<_>>(),
+    )))
+}
+
+pub async fn store(db: &sqlx::PgPool, bookmark_id: Uuid, embedding: &Vector) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE bookmarks SET embedding = $1 WHERE id = $2")
+        .bind(embedding)
+        .bind(bookmark_id)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+/// Embeds every bookmark that doesn't have a vector yet, from what's already stored about it.
+pub async fn backfill(state: &AppState) -> anyhow::Result<usize> {
+    let mut query = QueryBuilder::<Postgres>::new(BOOKMARK_SELECT);
+    query.push(" WHERE b.embedding IS NULL AND b.deleted_at IS NULL ORDER BY b.created_at");
+    let pending: Vec<BookmarkResponse> = query.build_query_as().fetch_all(&state.db).await?;
+
+    let mut embedded = 0;
+    for b in pending {
+        let tags = b.tags.unwrap_or_default();
+        let text = input(&b.url, b.title.as_deref(), b.ai_summary.as_deref(), &tags);
+        match embed(state, &text).await {
+            Ok(Some(embedding)) => {
+                store(&state.db, b.id, &embedding).await?;
+                embedded += 1;
+            }
+            Ok(None) => anyhow::bail!("EMBEDDING_MODEL must be set to backfill embeddings"),
+            Err(e) => eprintln!("Error embedding bookmark {}: {}", b.id, e),
+        }
+    }
+    Ok(embedded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input() {
+        let tags = vec!["rust".to_string(), "async".to_string()];
+        assert_eq!(
+            input("https://tokio.rs", Some("Tokio"), Some("An async runtime."), &tags),
+            "Tokio\nAn async runtime.\nTags: rust, async"
+        );
+        assert_eq!(input("https://tokio.rs", None, None, &[]), "https://tokio.rs");
+    }
+}
@@ -0,0 +1,82 @@
+//! Conditional requests for bookmark listings, so polling clients can get a `304` instead of
+//! the same payload again.
+//!
+//! The tag is derived from a cheap per-user version (row counts and latest change times) plus the
+//! query string, rather than from the response body, so a match skips the listing query entirely.
+
+use axum::{
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// A weak ETag covering everything a listing of the user's bookmarks can depend on.
+pub async fn for_bookmarks(db: &PgPool, user_id: Uuid, query: Option<&str>) -> Result<String, sqlx::Error> {
+    let version: String = sqlx::query_scalar(
+        "SELECT concat_ws('/', count(*), max(b.updated_at), max(b.last_checked_at), max(b.last_visited_at),
+             (SELECT count(*) FROM collection_bookmarks cb JOIN collections c ON c.id = cb.collection_id
+              WHERE c.user_id = $1),
+             (SELECT max(cb.added_at) FROM collection_bookmarks cb JOIN collections c ON c.id = cb.collection_id
+              WHERE c.user_id = $1))
+         FROM bookmarks b WHERE b.user_id = $1"
+    )
+    .bind(user_id)
+    .fetch_one(db)
+    .await?;
+
+    Ok(tag(&version, query.unwrap_or_default()))
+}
+
+fn tag(version: &str, query: &str) -> String {
+    let digest = Sha256::new()
+        .chain_update(version)
+        .chain_update([0])
+        .chain_update(query)
+        .finalize();
+    format!("W/\"{}\"", hex::encode(&digest[..16]))
+}
+
+/// Whether the client's `If-None-Match` already names `etag`.
+pub fn matches(headers: &HeaderMap, etag: &str) -> bool {
+    // Weak comparison, which is what If-None-Match calls for
+    let opaque = |t: &str| t.trim().trim_start_matches("W/").to_string();
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|t| t.trim() == "*" || opaque(t) == opaque(etag))
+}
+
+pub fn not_modified(etag: String) -> Response {
+    (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        let etag = tag("1", "limit=10");
+        assert_ne!(etag, tag("1", "limit=20"));
+        assert_ne!(etag, tag("2", "limit=10"));
+
+        let mut headers = HeaderMap::new();
+        assert!(!matches(&headers, &etag));
+
+        headers.insert(header::IF_NONE_MATCH, format!("\"stale\", {}", etag).parse().unwrap());
+        assert!(matches(&headers, &etag));
+
+        headers.insert(header::IF_NONE_MATCH, etag.trim_start_matches("W/").parse().unwrap());
+        assert!(matches(&headers, &etag));
+
+        headers.insert(header::IF_NONE_MATCH, "\"stale\"".parse().unwrap());
+        assert!(!matches(&headers, &etag));
+
+        headers.insert(header::IF_NONE_MATCH, "*".parse().unwrap());
+        assert!(matches(&headers, &etag));
+    }
+}
@@ -0,0 +1,109 @@
+//! Bookmark change events, fanned out to webhooks and to live `GET /bookmarks/events` streams.
+//!
+//! The stream only carries the bookmark id; clients fetch whatever they need. A client that falls
+//! too far behind gets a `resync` event and should catch up with `/bookmarks/sync` or a listing.
+
+use std::convert::Infallible;
+
+use axum::{
+    extract::State,
+    response::sse::{Event as SseEvent, KeepAlive, Sse},
+    routing::get,
+    Extension, Router,
+};
+use chrono::{DateTime, Utc};
+use futures::{stream, Stream};
+use serde_json::json;
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+use crate::{webhooks, AppState, CurrentUser};
+
+/// How many changes a slow stream may fall behind before it is told to resync.
+const CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    Created,
+    Updated,
+    Deleted,
+    Tagged,
+    /// The page's content changed on a later fetch
+    ContentChanged,
+}
+
+impl Event {
+    pub const ALL: [Event; 5] = [Event::Created, Event::Updated, Event::Deleted, Event::Tagged, Event::ContentChanged];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Event::Created => "bookmark.created",
+            Event::Updated => "bookmark.updated",
+            Event::Deleted => "bookmark.deleted",
+            Event::Tagged => "bookmark.tagged",
+            Event::ContentChanged => "bookmark.content_changed",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Change {
+    user_id: Uuid,
+    event: Event,
+    bookmark_id: Uuid,
+    occurred_at: DateTime<Utc>,
+}
+
+pub fn channel() -> broadcast::Sender<Change> {
+    broadcast::channel(CHANNEL_CAPACITY).0
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/bookmarks/events", get(stream_events))
+}
+
+/// Announces a change to a user's bookmark to open streams and, in the background, their webhooks.
+pub fn emit(state: &AppState, user_id: Uuid, event: Event, bookmark_id: Uuid) {
+    // Sending only fails when nobody is listening
+    let _ = state.events.send(Change {
+        user_id,
+        event,
+        bookmark_id,
+        occurred_at: Utc::now(),
+    });
+
+    let state = state.clone();
+    tokio::spawn(async move {
+        if let Err(e) = webhooks::dispatch(&state, user_id, event, bookmark_id).await {
+            eprintln!("Error dispatching {} for bookmark {}: {}", event.name(), bookmark_id, e);
+        }
+    });
+}
+
+async fn stream_events(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Sse<impl Stream<Item = Result<SseEvent, Infallible>>> {
+    let user_id = user.id;
+    let events = stream::unfold(state.events.subscribe(), move |mut rx| async move {
+        loop {
+            let event = match rx.recv().await {
+                Ok(change) if change.user_id == user_id => SseEvent::default()
+                    .event(change.event.name())
+                    .data(
+                        json!({
+                            "bookmark_id": change.bookmark_id,
+                            "occurred_at": change.occurred_at,
+                        })
+                        .to_string(),
+                    ),
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => SseEvent::default().event("resync").data(missed.to_string()),
+                Err(RecvError::Closed) => return None,
+            };
+            return Some((Ok(event), rx));
+        }
+    });
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
@@ -0,0 +1,150 @@
+//! Atom feed of a user's latest bookmarks.
+//!
+//! Feed readers can't send a Bearer header, so the feed is authenticated with a separate
+//! `?token=` that only grants read access to the feed. `POST /feeds/token` issues (or rotates) it.
+
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Extension, Json, Router,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::{netscape::escape, AppState, BookmarkResponse, CurrentUser, BOOKMARK_SELECT};
+
+const FEED_ENTRIES: i64 = 50;
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/feeds/token", post(rotate_token))
+}
+
+#[derive(Serialize)]
+struct FeedToken {
+    token: Uuid,
+    url: String,
+}
+
+/// Issues a new feed token, invalidating the previous one.
+async fn rotate_token(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Result<Json<FeedToken>, StatusCode> {
+    let token: Uuid = sqlx::query_scalar("UPDATE users SET feed_token = gen_random_uuid() WHERE id = $1 RETURNING feed_token")
+        .bind(user.id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|e| {
+            eprintln!("Feed Token Error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(FeedToken {
+        token,
+        url: format!("/feeds/bookmarks.atom?token={}", token),
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct FeedParams {
+    token: Uuid,
+}
+
+pub async fn bookmarks_feed(
+    State(state): State<AppState>,
+    Query(params): Query<FeedParams>,
+) -> Result<Response, StatusCode> {
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Bookmarks Feed Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let user = sqlx::query_as::<_, CurrentUser>("SELECT id, username FROM users WHERE feed_token = $1")
+        .bind(params.token)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let mut query = QueryBuilder::<Postgres>::new(BOOKMARK_SELECT);
+    query
+        .push(" WHERE b.user_id = ")
+        .push_bind(user.id)
+        .push(" AND b.deleted_at IS NULL ORDER BY b.created_at DESC, b.id DESC LIMIT ")
+        .push_bind(FEED_ENTRIES);
+    let bookmarks: Vec<BookmarkResponse> = query.build_query_as().fetch_all(&state.db).await.map_err(db_error)?;
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/atom+xml; charset=utf-8")],
+        render(&user, &bookmarks),
+    )
+        .into_response())
+}
+
+fn atom_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn render(user: &CurrentUser, bookmarks: &[BookmarkResponse]) -> String {
+    let updated = bookmarks.first().map_or(DateTime::UNIX_EPOCH, |b| b.created_at);
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    out.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    out.push_str(&format!("  <id>urn:uuid:{}</id>\n", user.id));
+    out.push_str(&format!("  <title>{}'s bookmarks</title>\n", escape(&user.username)));
+    out.push_str(&format!("  <author><name>{}</name></author>\n", escape(&user.username)));
+    out.push_str(&format!("  <updated>{}</updated>\n", atom_time(updated)));
+
+    for b in bookmarks {
+        out.push_str("  <entry>\n");
+        out.push_str(&format!("    <id>urn:uuid:{}</id>\n", b.id));
+        out.push_str(&format!("    <title>{}</title>\n", escape(b.title.as_deref().unwrap_or(&b.url))));
+        out.push_str(&format!("    <link href=\"{}\"/>\n", escape(&b.url)));
+        out.push_str(&format!("    <updated>{}</updated>\n", atom_time(b.created_at)));
+        if let Some(summary) = b.notes.as_deref().or(b.ai_summary.as_deref()) {
+            out.push_str(&format!("    <summary>{}</summary>\n", escape(summary)));
+        }
+        for tag in b.tags.iter().flatten() {
+            out.push_str(&format!("    <category term=\"{}\"/>\n", escape(tag)));
+        }
+        out.push_str("  </entry>\n");
+    }
+
+    out.push_str("</feed>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let user = CurrentUser {
+            id: Uuid::new_v4(),
+            username: "ada".to_string(),
+        };
+        let created_at = DateTime::from_timestamp(1700000000, 0).unwrap();
+        let bookmark = BookmarkResponse {
+            id: Uuid::new_v4(),
+            url: "https://example.com/?a=1&b=2".to_string(),
+            title: Some("Tips & <Tricks>".to_string()),
+            ai_summary: Some("A summary".to_string()),
+            tags: Some(vec!["rust".to_string()]),
+            created_at,
+            ..Default::default()
+        };
+
+        let feed = render(&user, &[bookmark]);
+        assert!(feed.contains("<title>ada's bookmarks</title>"));
+        assert!(feed.contains("<updated>2023-11-14T22:13:20Z</updated>"));
+        assert!(feed.contains("<title>Tips &amp; &lt;Tricks&gt;</title>"));
+        assert!(feed.contains("<link href=\"https://example.com/?a=1&amp;b=2\"/>"));
+        assert!(feed.contains("<summary>A summary</summary>"));
+        assert!(feed.contains("<category term=\"rust\"/>"));
+    }
+}
@@ -0,0 +1,371 @@
+//! Downloading bookmarked pages without getting the server's address blocked.
+//!
+//! Every fetch says who it's from in its `User-Agent`, waits out `FETCH_DOMAIN_INTERVAL_MS`
+//! since the last request to the same host, and, unless `RESPECT_ROBOTS_TXT` is off, checks the
+//! site's robots.txt first. Those are cached per site for a day. A bulk import of thousands of
+//! links from one site then trickles in rather than hammering it.
+//!
+//! Fetches also give up after `FETCH_TIMEOUT_SECS`, `FETCH_MAX_REDIRECTS` redirects or
+//! `FETCH_MAX_BYTES` of body, so a hostile or enormous URL can't stall a worker or fill memory,
+//! and refuse hosts on the server's own network, as `ssrf` explains.
+//!
+//! `FETCH_PROXY` sends every fetch through a proxy, and `FETCH_PROXY_RULES` through another, or
+//! none, for particular domains, e.g. Tor for `.onion` sites. The model's client is separate and
+//! never uses them. A proxy is trusted with the addresses it connects to, so the proxies
+//! themselves may be on a private network; that doesn't let fetches reach its network directly.
+//! Timeouts, failed connections, 429s and 5xx responses are tried again as the `retry` policy
+//! says; a page still unavailable after that fails with `FetchError::Unavailable`. Each of those,
+//! and robots.txt saying no, is a `FetchError`, which the job queue records on the bookmark as its
+//! `error_code`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use reqwest::{redirect, Client, Proxy, Response};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::Instant;
+use url::Url;
+
+use crate::config::{self, FetchConfig, ProxyRule};
+use crate::credentials::Credential;
+use crate::retry::{self, Policy};
+use crate::robots::Robots;
+use crate::ssrf::{self, Guard};
+
+/// The name we go by in robots.txt.
+pub const AGENT_TOKEN: &str = "linkman";
+
+const ROBOTS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const ROBOTS_TIMEOUT: Duration = Duration::from_secs(10);
+/// Hosts remembered before the ones no longer waited on are forgotten.
+const MAX_HOSTS: usize = 10_000;
+
+/// Why a page wasn't fetched, when it's down to the page rather than the network.
+#[derive(Debug, Clone)]
+pub enum FetchError {
+    /// robots.txt rules the URL out
+    Blocked(String),
+    Timeout(Duration),
+    TooLarge(u64),
+    TooManyRedirects(usize),
+    /// Still a 429 or 5xx after retrying
+    Unavailable(u16),
+    /// The host is, or resolves to, a loopback, private or otherwise non-public address
+    PrivateAddress(String),
+}
+
+impl FetchError {
+    /// Short name for the failure, stored as the bookmark's `error_code`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            FetchError::Blocked(_) => "robots_disallowed",
+            FetchError::Timeout(_) => "timeout",
+            FetchError::TooLarge(_) => "too_large",
+            FetchError::TooManyRedirects(_) => "too_many_redirects",
+            FetchError::Unavailable(_) => "unavailable",
+            FetchError::PrivateAddress(_) => "private_address",
+        }
+    }
+
+    /// Whether trying again later could go differently; a slow or overloaded server might not be
+    /// next time.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, FetchError::Timeout(_) | FetchError::Unavailable(_))
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Blocked(url) => write!(f, "robots.txt disallows fetching {}", url),
+            FetchError::Timeout(timeout) => write!(f, "the page took longer than {}s to fetch", timeout.as_secs()),
+            FetchError::TooLarge(limit) => write!(f, "the page is larger than the {} byte limit", limit),
+            FetchError::TooManyRedirects(limit) => write!(f, "the page redirected more than {} times", limit),
+            FetchError::Unavailable(status) => write!(f, "the site answered {}", status),
+            FetchError::PrivateAddress(host) => write!(f, "{} is not a public address", host),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+/// The `FetchError` a request failed with deep inside reqwest, from a redirect or a lookup.
+pub fn cause(e: &reqwest::Error) -> Option<&FetchError> {
+    let mut source = std::error::Error::source(e);
+    while let Some(e) = source {
+        if let Some(fetch_error) = e.downcast_ref::<FetchError>() {
+            return Some(fetch_error);
+        }
+        source = e.source();
+    }
+    None
+}
+
+/// A response, holding one of the `FETCH_CONCURRENCY` download slots until it's dropped.
+pub struct Fetched<'a> {
+    pub response: Response,
+    fetcher: &'a Fetcher,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl Fetched<'_> {
+    /// The whole body, or `TooLarge` if it's over `FETCH_MAX_BYTES`.
+    pub async fn bytes(self) -> anyhow::Result<Vec<u8>> {
+        let limit = self.fetcher.max_bytes;
+        self.read(limit, true).await
+    }
+
+    /// At most the first `limit` bytes of the body, for sniffing at a file too big to want.
+    pub async fn head(self, limit: u64) -> anyhow::Result<Vec<u8>> {
+        self.read(limit, false).await
+    }
+
+    async fn read(mut self, limit: u64, whole: bool) -> anyhow::Result<Vec<u8>> {
+        if whole && self.response.content_length().is_some_and(|length| length > limit) {
+            return Err(FetchError::TooLarge(limit).into());
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = self.response.chunk().await.map_err(|e| self.fetcher.classify(e))? {
+            body.extend_from_slice(&chunk);
+            if body.len() as u64 > limit {
+                if whole {
+                    return Err(FetchError::TooLarge(limit).into());
+                }
+                body.truncate(limit as usize);
+                break;
+            }
+        }
+        Ok(body)
+    }
+}
+
+pub struct Fetcher {
+    client: Client,
+    timeout: Duration,
+    max_bytes: u64,
+    max_redirects: usize,
+    guard: Arc<Guard>,
+    retry: Policy,
+    respect_robots: bool,
+    interval: Duration,
+    limit: Semaphore,
+    robots: Mutex<HashMap<String, (Instant, Arc<Robots>)>>,
+    next_request: Mutex<HashMap<String, Instant>>,
+}
+
+impl Fetcher {
+    pub fn from_config(config: &FetchConfig, retry: Policy, concurrency: usize) -> anyhow::Result<Self> {
+        let timeout = Duration::from_secs(config.timeout_secs);
+        let default_proxy = config.proxy.as_deref().map(config::proxy_url).transpose().map_err(anyhow::Error::msg)?;
+        let proxies = default_proxy.iter().chain(config.proxy_rules.iter().filter_map(|rule| rule.proxy.as_ref()));
+        let proxy_hosts = proxies.filter_map(|proxy| proxy.host_str()).map(|host| host.trim_matches(['[', ']']).to_string());
+        let guard = Arc::new(Guard::from_config(&config.allowed_networks).with_proxies(proxy_hosts));
+        let max_redirects = config.max_redirects;
+        let redirect_guard = guard.clone();
+        // Hosts that are addresses already are never looked up, so they're checked here
+        let redirects = redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() > max_redirects {
+                attempt.error(FetchError::TooManyRedirects(max_redirects))
+            } else if let Err(e) = redirect_guard.check_url(attempt.url()) {
+                attempt.error(e)
+            } else {
+                attempt.follow()
+            }
+        });
+        let mut client = Client::builder()
+            .user_agent(&config.user_agent)
+            .timeout(timeout)
+            .redirect(redirects)
+            .dns_resolver(Arc::new(ssrf::Resolver(guard.clone())));
+        if default_proxy.is_some() || !config.proxy_rules.is_empty() {
+            let rules = config.proxy_rules.clone();
+            client = client.proxy(Proxy::custom(move |url| proxy_for(url, default_proxy.as_ref(), &rules)));
+        }
+        Ok(Fetcher {
+            client: client.build()?,
+            timeout,
+            max_bytes: config.max_bytes,
+            max_redirects,
+            guard,
+            retry,
+            respect_robots: config.respect_robots,
+            interval: Duration::from_millis(config.domain_interval_ms),
+            limit: Semaphore::new(concurrency),
+            robots: Mutex::new(HashMap::new()),
+            next_request: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// The client every fetch goes through, for requests that skip robots.txt, like link checks.
+    /// Check the URL first with `check_url`; reqwest never looks up a host that's an address.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn check_url(&self, url: &Url) -> Result<(), FetchError> {
+        self.guard.check_url(url)
+    }
+
+    /// What fetches are held to, for other requests to user-supplied URLs like webhooks.
+    pub fn guard(&self) -> Arc<Guard> {
+        self.guard.clone()
+    }
+
+    pub async fn get(&self, url: &str) -> anyhow::Result<Fetched<'_>> {
+        self.get_as(url, None).await
+    }
+
+    /// Like `get`, signed in with `credential`. reqwest drops it if a redirect leaves the host.
+    pub async fn get_as(&self, url: &str, credential: Option<&Credential>) -> anyhow::Result<Fetched<'_>> {
+        let parsed = Url::parse(url)?;
+        self.guard.check_url(&parsed)?;
+        if self.respect_robots {
+            let mut path = parsed.path().to_string();
+            if let Some(query) = parsed.query() {
+                path = format!("{}?{}", path, query);
+            }
+            if !self.robots_for(&parsed).await.is_allowed(&path) {
+                return Err(FetchError::Blocked(url.to_string()).into());
+            }
+        }
+
+        let (response, permit) = self
+            .retry
+            .run(url, || async {
+                self.wait_turn(&parsed).await;
+                let permit = self.limit.acquire().await.expect("fetch semaphore closed");
+                let mut request = self.client.get(url);
+                if let Some(credential) = credential {
+                    request = credential.apply(request);
+                }
+                let response = request.send().await?;
+                // Other errors, like a 404, still have a page worth looking at
+                if retry::status_class(response.status().as_u16()).is_some() {
+                    return Err(response.error_for_status().unwrap_err());
+                }
+                Ok((response, permit))
+            })
+            .await
+            .map_err(|e| self.classify(e))?;
+        Ok(Fetched { response, fetcher: self, _permit: permit })
+    }
+
+    /// Turns the limits reqwest enforces into `FetchError`s.
+    fn classify(&self, e: reqwest::Error) -> anyhow::Error {
+        if let Some(fetch_error) = cause(&e) {
+            fetch_error.clone().into()
+        } else if e.is_timeout() {
+            FetchError::Timeout(self.timeout).into()
+        } else if e.is_redirect() {
+            FetchError::TooManyRedirects(self.max_redirects).into()
+        } else if let Some(status) = e.status() {
+            FetchError::Unavailable(status.as_u16()).into()
+        } else {
+            e.into()
+        }
+    }
+
+    /// Sleeps until the host of `url` is due another request, and books the one after.
+    pub async fn wait_turn(&self, url: &Url) {
+        let Some(host) = url.host_str() else {
+            return;
+        };
+        let at = {
+            let mut next_request = self.next_request.lock().unwrap();
+            let now = Instant::now();
+            if next_request.len() > MAX_HOSTS {
+                next_request.retain(|_, at| *at > now);
+            }
+            let at = next_request.get(host).copied().unwrap_or(now).max(now);
+            next_request.insert(host.to_string(), at + self.interval);
+            at
+        };
+        tokio::time::sleep_until(at).await;
+    }
+
+    async fn robots_for(&self, url: &Url) -> Arc<Robots> {
+        let origin = url.origin().ascii_serialization();
+        if let Some((fetched_at, robots)) = self.robots.lock().unwrap().get(&origin)
+            && fetched_at.elapsed() < ROBOTS_TTL
+        {
+            return robots.clone();
+        }
+
+        // Sites without one, or that can't be reached for it, are taken to allow everything
+        let robots = Arc::new(match self.fetch_robots(url, &origin).await {
+            Ok(Some(txt)) => Robots::parse(&txt, AGENT_TOKEN),
+            Ok(None) => Robots::allow_all(),
+            Err(e) => {
+                eprintln!("Error fetching robots.txt for {}: {}", origin, e);
+                Robots::allow_all()
+            }
+        });
+        let mut cache = self.robots.lock().unwrap();
+        if cache.len() > MAX_HOSTS {
+            cache.retain(|_, (fetched_at, _)| fetched_at.elapsed() < ROBOTS_TTL);
+        }
+        cache.insert(origin, (Instant::now(), robots.clone()));
+        robots
+    }
+
+    async fn fetch_robots(&self, url: &Url, origin: &str) -> anyhow::Result<Option<String>> {
+        self.wait_turn(url).await;
+        let _permit = self.limit.acquire().await?;
+        let response = self
+            .client
+            .get(format!("{}/robots.txt", origin))
+            .timeout(ROBOTS_TIMEOUT)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Ok(None);
+        }
+        Ok(Some(response.text().await?))
+    }
+}
+
+/// The proxy for `url`: the rule for the most specific domain it's in, or else `default`.
+fn proxy_for(url: &Url, default: Option<&Url>, rules: &[ProxyRule]) -> Option<Url> {
+    let host = url.host_str()?.to_ascii_lowercase();
+    let in_domain = |domain: &str| host == domain || host.ends_with(&format!(".{}", domain));
+    match rules.iter().filter(|rule| in_domain(&rule.domain)).max_by_key(|rule| rule.domain.len()) {
+        Some(rule) => rule.proxy.clone(),
+        None => default.cloned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proxy_for() {
+        let rules: Vec<ProxyRule> = ["onion=socks5h://127.0.0.1:9050", "corp.example=direct", "wiki.corp.example=http://wiki-proxy:8080"]
+            .iter()
+            .map(|rule| rule.parse().unwrap())
+            .collect();
+        let default = Url::parse("http://proxy.example:3128").unwrap();
+        let proxy = |url: &str| proxy_for(&Url::parse(url).unwrap(), Some(&default), &rules).map(String::from);
+
+        assert_eq!(proxy("http://abc.onion/").as_deref(), Some("socks5h://127.0.0.1:9050"));
+        assert_eq!(proxy("https://git.corp.example/"), None);
+        assert_eq!(proxy("https://wiki.corp.example/page").as_deref(), Some("http://wiki-proxy:8080/"));
+        assert_eq!(proxy("https://notcorp.example/").as_deref(), Some("http://proxy.example:3128/"));
+        assert!("onion=ftp://x".parse::<ProxyRule>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_proxy_isnt_allowed() {
+        let config = FetchConfig {
+            proxy_rules: vec!["onion=socks5h://127.0.0.1:9050".parse().unwrap()],
+            respect_robots: false,
+            ..FetchConfig::default()
+        };
+        let fetcher = Fetcher::from_config(&config, Policy::from_config(&config::RetryConfig::default()), 1).unwrap();
+        let error = fetcher.get("http://127.0.0.1:5432/").await.err().unwrap();
+        assert!(matches!(error.downcast_ref::<FetchError>(), Some(FetchError::PrivateAddress(_))));
+        assert!(fetcher.check_url(&Url::parse("http://127.0.0.1:9050/").unwrap()).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! Keeping a GitHub account's starred repositories bookmarked.
+//!
+//! With `GITHUB_TOKEN` and `GITHUB_SYNC_USER` set, the account's stars are listed every
+//! `GITHUB_SYNC_INTERVAL_HOURS` and each repository is bookmarked for that linkman user, tagged
+//! `github`. The repository's description becomes the bookmark's, and its language, topics and
+//! star count are kept in the page metadata under `github`, where fetching the page again leaves
+//! them. Repositories already bookmarked are updated instead, unless they're in the trash, and
+//! bookmarks of repositories unstarred since the last sync are moved to the trash.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::{attach_tags, config::GithubConfig, events, jobs, normalize::normalize_url, AppState, TagSource};
+
+const TAG: &str = "github";
+const PER_PAGE: usize = 100;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Config {
+    client: reqwest::Client,
+    token: String,
+    username: String,
+    interval: Duration,
+    api_url: String,
+}
+
+impl Config {
+    pub fn from_config(config: &GithubConfig) -> anyhow::Result<Option<Self>> {
+        let (Some(token), Some(username)) = (&config.token, &config.user) else {
+            return Ok(None);
+        };
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent(format!("linkman/{}", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(Some(Config {
+            client,
+            token: token.clone(),
+            username: username.clone(),
+            interval: Duration::from_secs(config.interval_hours * 60 * 60),
+            api_url: config.api_url.trim_end_matches('/').to_string(),
+        }))
+    }
+}
+
+pub async fn run(state: AppState, config: Config) {
+    let mut tick = tokio::time::interval(config.interval);
+    loop {
+        tick.tick().await;
+        let result = sync(&state, &config).await;
+        state.metrics.record_job("github_sync", result.is_ok());
+        if let Err(e) = result {
+            eprintln!("GitHub Sync Error: {}", e);
+        }
+    }
+}
+
+/// One of the account's stars, as `application/vnd.github.star+json` lists them.
+#[derive(Deserialize)]
+struct Star {
+    starred_at: Option<DateTime<Utc>>,
+    repo: Repo,
+}
+
+#[derive(Deserialize)]
+struct Repo {
+    full_name: String,
+    html_url: String,
+    description: Option<String>,
+    language: Option<String>,
+    #[serde(default)]
+    topics: Vec<String>,
+    #[serde(default)]
+    stargazers_count: i64,
+}
+
+impl Repo {
+    /// What's kept under `github` in the bookmark's page metadata.
+    fn metadata(&self) -> Value {
+        json!({
+            "full_name": self.full_name,
+            "language": self.language,
+            "topics": self.topics,
+            "stars": self.stargazers_count,
+        })
+    }
+}
+
+/// Every star, newest first. Any page failing fails the whole list, so a partial one is never
+/// mistaken for repositories having been unstarred.
+async fn stars(config: &Config) -> anyhow::Result<Vec<Star>> {
+    let mut stars = Vec::new();
+    for page in 1.. {
+        let batch: Vec<Star> = config
+            .client
+            .get(format!("{}/user/starred?per_page={}&page={}", config.api_url, PER_PAGE, page))
+            .bearer_auth(&config.token)
+            .header(reqwest::header::ACCEPT, "application/vnd.github.star+json")
+            .header("X-GitHub-Api-Version", "2022-11-28")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let last = batch.len() < PER_PAGE;
+        stars.extend(batch);
+        if last {
+            break;
+        }
+    }
+    Ok(stars)
+}
+
+async fn sync(state: &AppState, config: &Config) -> anyhow::Result<()> {
+    let user_id = crate::user_id(&state.db, &config.username).await?;
+    let stars = stars(config).await?;
+
+    let mut tx = state.db.begin().await?;
+    let (mut created, mut updated, mut starred) = (Vec::new(), Vec::new(), Vec::new());
+    for star in &stars {
+        let Ok(url) = normalize_url(&star.repo.html_url) else { continue };
+        starred.push(url.clone());
+
+        // Nothing comes back for a bookmark in the trash or one already up to date
+        let saved: Option<(Uuid, bool)> = sqlx::query_as(
+            "INSERT INTO bookmarks (user_id, url, title, description, site_meta, created_at)
+             VALUES ($1, $2, $3, $4, jsonb_build_object('github', $5::jsonb), COALESCE($6, now()))
+             ON CONFLICT (user_id, url) DO UPDATE SET
+                 description = COALESCE(EXCLUDED.description, bookmarks.description),
+                 site_meta = COALESCE(bookmarks.site_meta, '{}') || EXCLUDED.site_meta,
+                 updated_at = now()
+             WHERE bookmarks.deleted_at IS NULL
+                 AND (bookmarks.site_meta->'github' IS DISTINCT FROM EXCLUDED.site_meta->'github'
+                      OR EXCLUDED.description IS DISTINCT FROM bookmarks.description)
+             RETURNING id, xmax = 0"
+        )
+        .bind(user_id)
+        .bind(&url)
+        .bind(&star.repo.full_name)
+        .bind(&star.repo.description)
+        .bind(star.repo.metadata())
+        .bind(star.starred_at)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        match saved {
+            Some((id, true)) => {
+                attach_tags(&mut tx, user_id, id, &[TAG.to_string()], TagSource::User).await?;
+                created.push(id);
+            }
+            Some((id, false)) => updated.push(id),
+            None => {}
+        }
+    }
+
+    let unstarred: Vec<Uuid> = sqlx::query_scalar(
+        "UPDATE bookmarks SET deleted_at = now(), updated_at = now()
+         WHERE user_id = $1 AND deleted_at IS NULL AND site_meta ? 'github' AND NOT url = ANY($2)
+         RETURNING id"
+    )
+    .bind(user_id)
+    .bind(&starred)
+    .fetch_all(&mut *tx)
+    .await?;
+    jobs::queue(&mut *tx, &created).await?;
+    tx.commit().await?;
+    state.job_wakeup.notify_one();
+
+    for (ids, event) in [
+        (&created, events::Event::Created),
+        (&updated, events::Event::Updated),
+        (&unstarred, events::Event::Deleted),
+    ] {
+        for id in ids {
+            events::emit(state, user_id, event, *id);
+        }
+    }
+    if !(created.is_empty() && updated.is_empty() && unstarred.is_empty()) {
+        println!(
+            "synced {} GitHub stars: {} new, {} updated, {} unstarred",
+            stars.len(),
+            created.len(),
+            updated.len(),
+            unstarred.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_star() {
+        let star: Star = serde_json::from_str(
+            r#"{
+                "starred_at": "2024-03-04T05:06:07Z",
+                "repo": {
+                    "id": 724712,
+                    "full_name": "rust-lang/rust",
+                    "html_url": "https://github.com/rust-lang/rust",
+                    "description": "Empowering everyone to build reliable and efficient software.",
+                    "language": "Rust",
+                    "topics": ["compiler", "language"],
+                    "stargazers_count": 98000,
+                    "owner": { "login": "rust-lang" }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(star.starred_at, "2024-03-04T05:06:07Z".parse().ok());
+        assert_eq!(
+            star.repo.metadata(),
+            json!({
+                "full_name": "rust-lang/rust",
+                "language": "Rust",
+                "topics": ["compiler", "language"],
+                "stars": 98000,
+            })
+        );
+    }
+}
@@ -0,0 +1,454 @@
+//! GraphQL endpoint (`POST /graphql`) over bookmarks, tags and collections.
+//!
+//! It is read-only and shares its filters and cursors with `GET /bookmarks`. Nested fields cost a
+//! query each, so the query depth is capped.
+
+use async_graphql::{
+    ComplexObject, Context, EmptyMutation, EmptySubscription, Enum, InputObject, Object, Schema, SimpleObject,
+};
+use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use axum::{extract::State, http::StatusCode, routing::post, Extension, Router};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{
+    collections::COLLECTION_SELECT, fetch_bookmark, search_page, AppState, BookmarkResponse, CurrentUser, Order,
+    PageParams, SearchParams, Sort,
+};
+
+type LinkmanSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+const MAX_DEPTH: usize = 8;
+
+pub fn router() -> Router<AppState> {
+    let schema = Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_DEPTH)
+        .finish();
+    Router::new()
+        .route("/graphql", post(graphql))
+        .layer(Extension(schema))
+}
+
+async fn graphql(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Extension(schema): Extension<LinkmanSchema>,
+    req: GraphQLRequest,
+) -> GraphQLResponse {
+    schema.execute(req.into_inner().data(state).data(user)).await.into()
+}
+
+fn status_error(status: StatusCode) -> async_graphql::Error {
+    async_graphql::Error::new(status.canonical_reason().unwrap_or("Request failed"))
+}
+
+fn db_error(e: sqlx::Error) -> async_graphql::Error {
+    eprintln!("GraphQL Error: {}", e);
+    status_error(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn session<'a>(ctx: &Context<'a>) -> (&'a AppState, &'a CurrentUser) {
+    (ctx.data_unchecked(), ctx.data_unchecked())
+}
+
+/// What `GET /bookmarks` can be ordered by.
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+enum BookmarkSort {
+    CreatedAt,
+    UpdatedAt,
+    Title,
+    Domain,
+    MostVisited,
+}
+
+impl From<BookmarkSort> for Sort {
+    fn from(sort: BookmarkSort) -> Self {
+        match sort {
+            BookmarkSort::CreatedAt => Sort::CreatedAt,
+            BookmarkSort::UpdatedAt => Sort::UpdatedAt,
+            BookmarkSort::Title => Sort::Title,
+            BookmarkSort::Domain => Sort::Domain,
+            BookmarkSort::MostVisited => Sort::MostVisited,
+        }
+    }
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl From<SortOrder> for Order {
+    fn from(order: SortOrder) -> Self {
+        match order {
+            SortOrder::Asc => Order::Asc,
+            SortOrder::Desc => Order::Desc,
+        }
+    }
+}
+
+/// The filters of `GET /bookmarks`.
+#[derive(InputObject, Default)]
+struct BookmarkFilter {
+    q: Option<String>,
+    semantic: Option<String>,
+    status: Option<String>,
+    processing: Option<String>,
+    untagged: Option<bool>,
+    unread: Option<bool>,
+    favorite: Option<bool>,
+    collection: Option<Uuid>,
+    tag: Option<String>,
+    user_tag: Option<String>,
+    ai_tag: Option<String>,
+    lang: Option<String>,
+    domain: Option<String>,
+    created_before: Option<DateTime<Utc>>,
+    created_after: Option<DateTime<Utc>>,
+    sort: Option<BookmarkSort>,
+    order: Option<SortOrder>,
+}
+
+impl From<BookmarkFilter> for SearchParams {
+    fn from(f: BookmarkFilter) -> Self {
+        SearchParams {
+            q: f.q,
+            semantic: f.semantic,
+            status: f.status,
+            processing: f.processing,
+            untagged: f.untagged,
+            unread: f.unread,
+            favorite: f.favorite,
+            collection: f.collection,
+            tag: f.tag,
+            user_tag: f.user_tag,
+            ai_tag: f.ai_tag,
+            lang: f.lang,
+            domain: f.domain,
+            created_before: f.created_before,
+            created_after: f.created_after,
+            sort: f.sort.map(Sort::from),
+            order: f.order.map(Order::from),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct BookmarkConnection {
+    nodes: Vec<Bookmark>,
+    /// Pass as `after` for the next page
+    next_cursor: Option<String>,
+}
+
+async fn bookmark_page(
+    ctx: &Context<'_>,
+    params: SearchParams,
+    first: Option<i32>,
+    after: Option<String>,
+) -> async_graphql::Result<BookmarkConnection> {
+    let (state, user) = session(ctx);
+    let page = PageParams {
+        limit: first.map(i64::from),
+        cursor: after,
+    };
+    let page = search_page(state, user, &params, &page).await.map_err(status_error)?;
+    Ok(BookmarkConnection {
+        nodes: page.bookmarks.into_iter().map(Bookmark).collect(),
+        next_cursor: page.next_cursor,
+    })
+}
+
+struct Bookmark(BookmarkResponse);
+
+#[Object]
+impl Bookmark {
+    async fn id(&self) -> Uuid {
+        self.0.id
+    }
+
+    async fn url(&self) -> &str {
+        &self.0.url
+    }
+
+    async fn title(&self) -> Option<&str> {
+        self.0.title.as_deref()
+    }
+
+    async fn notes(&self) -> Option<&str> {
+        self.0.notes.as_deref()
+    }
+
+    async fn description(&self) -> Option<&str> {
+        self.0.description.as_deref()
+    }
+
+    async fn image_url(&self) -> Option<&str> {
+        self.0.image_url.as_deref()
+    }
+
+    async fn published_at(&self) -> Option<DateTime<Utc>> {
+        self.0.published_at
+    }
+
+    async fn author(&self) -> Option<&str> {
+        self.0.author.as_deref()
+    }
+
+    async fn ai_summary(&self) -> Option<&str> {
+        self.0.ai_summary.as_deref()
+    }
+
+    async fn language(&self) -> Option<&str> {
+        self.0.language.as_deref()
+    }
+
+    async fn tags(&self) -> Vec<String> {
+        self.0.tags.clone().unwrap_or_default()
+    }
+
+    async fn user_tags(&self) -> Vec<String> {
+        self.0.user_tags.clone().unwrap_or_default()
+    }
+
+    async fn ai_tags(&self) -> Vec<String> {
+        self.0.ai_tags.clone().unwrap_or_default()
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.0.created_at
+    }
+
+    async fn updated_at(&self) -> DateTime<Utc> {
+        self.0.updated_at
+    }
+
+    async fn domain(&self) -> Option<&str> {
+        self.0.domain.as_deref()
+    }
+
+    async fn archived_at(&self) -> Option<DateTime<Utc>> {
+        self.0.archived_at
+    }
+
+    async fn last_checked_at(&self) -> Option<DateTime<Utc>> {
+        self.0.last_checked_at
+    }
+
+    async fn last_status(&self) -> Option<i32> {
+        self.0.last_status
+    }
+
+    async fn is_broken(&self) -> bool {
+        self.0.is_broken
+    }
+
+    async fn processing_status(&self) -> &str {
+        &self.0.processing_status
+    }
+
+    async fn error_message(&self) -> Option<&str> {
+        self.0.error_message.as_deref()
+    }
+
+    async fn error_code(&self) -> Option<&str> {
+        self.0.error_code.as_deref()
+    }
+
+    async fn snapshot_url(&self) -> Option<&str> {
+        self.0.snapshot_url.as_deref()
+    }
+
+    async fn snapshot_at(&self) -> Option<DateTime<Utc>> {
+        self.0.snapshot_at
+    }
+
+    async fn wayback_url(&self) -> Option<&str> {
+        self.0.wayback_url.as_deref()
+    }
+
+    async fn wayback_saved_at(&self) -> Option<DateTime<Utc>> {
+        self.0.wayback_saved_at
+    }
+
+    async fn content_changed_at(&self) -> Option<DateTime<Utc>> {
+        self.0.content_changed_at
+    }
+
+    async fn is_read(&self) -> bool {
+        self.0.is_read
+    }
+
+    async fn read_at(&self) -> Option<DateTime<Utc>> {
+        self.0.read_at
+    }
+
+    async fn is_favorite(&self) -> bool {
+        self.0.is_favorite
+    }
+
+    async fn is_public(&self) -> bool {
+        self.0.is_public
+    }
+
+    /// Times it was opened through `/go/{id}`.
+    async fn visit_count(&self) -> i32 {
+        self.0.visit_count
+    }
+
+    async fn last_visited_at(&self) -> Option<DateTime<Utc>> {
+        self.0.last_visited_at
+    }
+
+    /// Collections the bookmark is filed in.
+    async fn collections(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Collection>> {
+        let (state, user) = session(ctx);
+        sqlx::query_as::<_, Collection>(&format!(
+            "{} JOIN collection_bookmarks cb ON cb.collection_id = c.id
+             WHERE c.user_id = $1 AND cb.bookmark_id = $2 ORDER BY c.name, c.id",
+            COLLECTION_SELECT
+        ))
+        .bind(user.id)
+        .bind(self.0.id)
+        .fetch_all(&state.db)
+        .await
+        .map_err(db_error)
+    }
+}
+
+#[derive(SimpleObject, sqlx::FromRow)]
+#[graphql(complex)]
+struct Tag {
+    name: String,
+    /// Live bookmarks carrying the tag
+    count: i64,
+}
+
+#[ComplexObject]
+impl Tag {
+    /// The tag's bookmarks, including those under nested tags.
+    async fn bookmarks(
+        &self,
+        ctx: &Context<'_>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> async_graphql::Result<BookmarkConnection> {
+        let params = SearchParams {
+            tag: Some(self.name.clone()),
+            ..Default::default()
+        };
+        bookmark_page(ctx, params, first, after).await
+    }
+}
+
+#[derive(SimpleObject, sqlx::FromRow)]
+#[graphql(complex)]
+struct Collection {
+    id: Uuid,
+    parent_id: Option<Uuid>,
+    name: String,
+    description: Option<String>,
+    created_at: DateTime<Utc>,
+    bookmark_count: i64,
+}
+
+#[ComplexObject]
+impl Collection {
+    async fn parent(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Collection>> {
+        let Some(parent_id) = self.parent_id else {
+            return Ok(None);
+        };
+        fetch_collection(ctx, parent_id).await
+    }
+
+    async fn children(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Collection>> {
+        let (state, user) = session(ctx);
+        sqlx::query_as::<_, Collection>(&format!(
+            "{} WHERE c.user_id = $1 AND c.parent_id = $2 ORDER BY c.name, c.id",
+            COLLECTION_SELECT
+        ))
+        .bind(user.id)
+        .bind(self.id)
+        .fetch_all(&state.db)
+        .await
+        .map_err(db_error)
+    }
+
+    /// Bookmarks filed directly in this collection.
+    async fn bookmarks(
+        &self,
+        ctx: &Context<'_>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> async_graphql::Result<BookmarkConnection> {
+        let params = SearchParams {
+            collection: Some(self.id),
+            ..Default::default()
+        };
+        bookmark_page(ctx, params, first, after).await
+    }
+}
+
+async fn fetch_collection(ctx: &Context<'_>, id: Uuid) -> async_graphql::Result<Option<Collection>> {
+    let (state, user) = session(ctx);
+    sqlx::query_as::<_, Collection>(&format!("{} WHERE c.user_id = $1 AND c.id = $2", COLLECTION_SELECT))
+        .bind(user.id)
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)
+}
+
+struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Newest first, like `GET /bookmarks`.
+    async fn bookmarks(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<BookmarkFilter>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> async_graphql::Result<BookmarkConnection> {
+        bookmark_page(ctx, filter.unwrap_or_default().into(), first, after).await
+    }
+
+    async fn bookmark(&self, ctx: &Context<'_>, id: Uuid) -> async_graphql::Result<Option<Bookmark>> {
+        let (state, user) = session(ctx);
+        let bookmark = fetch_bookmark(&state.db, user.id, id).await.map_err(db_error)?;
+        Ok(bookmark.map(Bookmark))
+    }
+
+    /// Tags in use, most used first, optionally only those starting with `prefix` (case-insensitive).
+    async fn tags(&self, ctx: &Context<'_>, prefix: Option<String>) -> async_graphql::Result<Vec<Tag>> {
+        let (state, user) = session(ctx);
+        sqlx::query_as::<_, Tag>(
+            "SELECT t.name, count(*) as count FROM tags t
+             JOIN bookmark_tags bt ON bt.tag_id = t.id
+             JOIN bookmarks b ON b.id = bt.bookmark_id
+             WHERE t.user_id = $1 AND starts_with(lower(t.name), $2) AND b.deleted_at IS NULL
+             GROUP BY t.name
+             ORDER BY count DESC, t.name"
+        )
+        .bind(user.id)
+        .bind(prefix.unwrap_or_default().trim().to_lowercase())
+        .fetch_all(&state.db)
+        .await
+        .map_err(db_error)
+    }
+
+    /// Every collection the user has, flat; use `children` or `parent` to walk the tree.
+    async fn collections(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Collection>> {
+        let (state, user) = session(ctx);
+        sqlx::query_as::<_, Collection>(&format!("{} WHERE c.user_id = $1 ORDER BY c.name, c.id", COLLECTION_SELECT))
+            .bind(user.id)
+            .fetch_all(&state.db)
+            .await
+            .map_err(db_error)
+    }
+
+    async fn collection(&self, ctx: &Context<'_>, id: Uuid) -> async_graphql::Result<Option<Collection>> {
+        fetch_collection(ctx, id).await
+    }
+}
@@ -0,0 +1,135 @@
+//! Hacker News favorites: a saved copy of `news.ycombinator.com/favorites?id=USERNAME`, or a
+//! JSON array of the items as HN's API returns them.
+//!
+//! Each story's link is bookmarked with its comment thread as the discussion URL. Ask HN and other
+//! text posts have no link of their own, so the thread is the bookmark.
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use scraper::{ElementRef, Html, Selector};
+use serde::Deserialize;
+
+use crate::import::ExternalBookmark;
+
+const ITEM_URL: &str = "https://news.ycombinator.com/item?id=";
+
+/// An item from `hacker-news.firebaseio.com/v0/item/ID.json`.
+#[derive(Deserialize)]
+struct Item {
+    id: u64,
+    title: Option<String>,
+    url: Option<String>,
+    /// Unix seconds
+    time: Option<i64>,
+}
+
+fn bookmark(id: &str, title: Option<String>, url: Option<String>) -> ExternalBookmark {
+    let thread = format!("{}{}", ITEM_URL, id);
+    let url = url.filter(|url| url.starts_with("http://") || url.starts_with("https://"));
+    ExternalBookmark {
+        discussion_url: url.is_some().then(|| thread.clone()),
+        url: url.unwrap_or(thread),
+        title: title.map(|t| t.trim().to_string()).filter(|t| !t.is_empty()),
+        ..Default::default()
+    }
+}
+
+/// When a story was posted, from the subtext row under it: `title="2024-05-01T12:00:00 1714564800"`,
+/// or only the first half in older pages.
+fn posted_at(row: ElementRef) -> Option<DateTime<Utc>> {
+    let age = Selector::parse("span.age[title]").unwrap();
+    let subtext = row.next_siblings().filter_map(ElementRef::wrap).next()?;
+    let title = subtext.select(&age).next()?.value().attr("title")?;
+    let iso = title.split_whitespace().next()?;
+    NaiveDateTime::parse_from_str(iso, "%Y-%m-%dT%H:%M:%S").ok().map(|t| t.and_utc())
+}
+
+fn parse_page(html: &str) -> Vec<ExternalBookmark> {
+    let document = Html::parse_document(html);
+    let rows = Selector::parse("tr.athing[id]").unwrap();
+    let link = Selector::parse("span.titleline > a").unwrap();
+    document
+        .select(&rows)
+        .filter_map(|row| {
+            let id = row.value().attr("id")?;
+            let a = row.select(&link).next()?;
+            let href = a.value().attr("href")?;
+            // Text posts link to their own thread, relative to the site
+            let url = if href.starts_with("item?id=") { None } else { Some(href.to_string()) };
+            let mut bookmark = bookmark(id, Some(a.text().collect()), url);
+            bookmark.added_at = posted_at(row);
+            Some(bookmark)
+        })
+        .collect()
+}
+
+pub fn parse(content: &str) -> anyhow::Result<Vec<ExternalBookmark>> {
+    if content.trim_start().starts_with('<') {
+        let bookmarks = parse_page(content);
+        // An empty favorites page is still HN's, with the site's name in it
+        if bookmarks.is_empty() && !content.contains("ycombinator") {
+            anyhow::bail!("not a Hacker News favorites page");
+        }
+        return Ok(bookmarks);
+    }
+    let items: Vec<Option<Item>> = serde_json::from_str(content)?;
+    Ok(items
+        .into_iter()
+        .flatten()
+        .map(|item| {
+            let mut bookmark = bookmark(&item.id.to_string(), item.title, item.url);
+            bookmark.added_at = item.time.and_then(|secs| DateTime::from_timestamp(secs, 0));
+            bookmark
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_page() {
+        let html = r#"<html><body><table>
+            <tr class="athing submission" id="40100001">
+              <td class="title"><span class="rank">1.</span></td>
+              <td class="title"><span class="titleline"><a href="https://example.com/post">A post</a>
+                <span class="sitebit comhead"> (<a href="from?site=example.com"><span class="sitestr">example.com</span></a>)</span></span></td>
+            </tr>
+            <tr><td colspan="2"></td><td class="subtext"><span class="subline">
+              <span class="age" title="2024-04-20T10:00:00 1713607200"><a href="item?id=40100001">1 day ago</a></span>
+            </span></td></tr>
+            <tr class="spacer"></tr>
+            <tr class="athing submission" id="40100002">
+              <td class="title"><span class="titleline"><a href="item?id=40100002">Ask HN: Favorite tools?</a></span></td>
+            </tr>
+            <tr><td class="subtext"><span class="age" title="2024-04-21T08:30:00"><a href="item?id=40100002">2 hours ago</a></span></td></tr>
+        </table></body></html>"#;
+        let bookmarks = parse(html).unwrap();
+        assert_eq!(bookmarks.len(), 2);
+        assert_eq!(bookmarks[0].url, "https://example.com/post");
+        assert_eq!(bookmarks[0].title.as_deref(), Some("A post"));
+        assert_eq!(bookmarks[0].discussion_url.as_deref(), Some("https://news.ycombinator.com/item?id=40100001"));
+        assert_eq!(bookmarks[0].added_at, DateTime::from_timestamp(1713607200, 0));
+
+        assert_eq!(bookmarks[1].url, "https://news.ycombinator.com/item?id=40100002");
+        assert_eq!(bookmarks[1].discussion_url, None);
+        assert_eq!(bookmarks[1].added_at, "2024-04-21T08:30:00Z".parse().ok());
+
+        assert!(parse("<html><p>Not it</p></html>").is_err());
+    }
+
+    #[test]
+    fn test_parse_items() {
+        let items = r#"[
+            {"by": "pg", "id": 1, "time": 1160418111, "title": "Y Combinator", "type": "story", "url": "http://ycombinator.com"},
+            null,
+            {"id": 121003, "time": 1203647620, "title": "Ask HN: The Arc Effect", "type": "story", "text": "..."}
+        ]"#;
+        let bookmarks = parse(items).unwrap();
+        assert_eq!(bookmarks.len(), 2);
+        assert_eq!(bookmarks[0].url, "http://ycombinator.com");
+        assert_eq!(bookmarks[0].discussion_url.as_deref(), Some("https://news.ycombinator.com/item?id=1"));
+        assert_eq!(bookmarks[0].added_at, DateTime::from_timestamp(1160418111, 0));
+        assert_eq!(bookmarks[1].url, "https://news.ycombinator.com/item?id=121003");
+    }
+}
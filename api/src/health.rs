@@ -0,0 +1,131 @@
+//! Unauthenticated probes for orchestrators.
+//!
+//! `GET /healthz` answers as long as the process is serving requests. `GET /readyz` also checks
+//! that Postgres answers and every bundled migration has been applied, and with
+//! `READYZ_CHECK_AI=true` that the AI provider lists models for our key. It answers `503` with
+//! the failing checks when any of them fail.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Serialize;
+
+use crate::{
+    config::{Config, Provider},
+    AppState, MIGRATOR,
+};
+
+/// Probes usually give up after a few seconds; answering before they do says more than a timeout.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub struct AiCheck {
+    provider: Provider,
+    models_url: String,
+    api_key: String,
+    client: reqwest::Client,
+}
+
+impl AiCheck {
+    pub fn from_config(config: &Config) -> anyhow::Result<Option<Self>> {
+        if !config.health.check_ai || config.ai.provider == Provider::None {
+            return Ok(None);
+        }
+        let client = reqwest::Client::builder().timeout(CHECK_TIMEOUT).build()?;
+        let base = config.ai.api_base().trim_end_matches('/');
+        let models_url = match config.ai.provider {
+            Provider::Ollama => format!("{}/api/tags", base),
+            Provider::Anthropic => format!("{}/v1/models", base),
+            Provider::OpenAi | Provider::None => format!("{}/models", base),
+        };
+
+        Ok(Some(AiCheck {
+            provider: config.ai.provider,
+            models_url,
+            api_key: config.ai.api_key.clone(),
+            client,
+        }))
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        let request = self.client.get(&self.models_url);
+        let request = match self.provider {
+            Provider::Anthropic => request.header("x-api-key", &self.api_key).header("anthropic-version", "2023-06-01"),
+            Provider::Ollama if self.api_key.is_empty() => request,
+            _ => request.bearer_auth(&self.api_key),
+        };
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+pub async fn healthz() -> &'static str {
+    "ok"
+}
+
+#[derive(Serialize)]
+pub struct Readiness {
+    ready: bool,
+    /// "ok", or what went wrong
+    checks: BTreeMap<&'static str, String>,
+}
+
+/// Versions bundled with this build that the database is missing.
+fn pending_migrations(applied: &[i64]) -> Vec<i64> {
+    MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration() && !applied.contains(&m.version))
+        .map(|m| m.version)
+        .collect()
+}
+
+async fn check_database(state: &AppState) -> anyhow::Result<()> {
+    sqlx::query("SELECT 1").execute(&state.db).await?;
+    Ok(())
+}
+
+async fn check_migrations(state: &AppState) -> anyhow::Result<()> {
+    let applied: Vec<i64> = sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+        .fetch_all(&state.db)
+        .await?;
+    let pending = pending_migrations(&applied);
+    if !pending.is_empty() {
+        anyhow::bail!("migrations not applied: {:?}", pending);
+    }
+    Ok(())
+}
+
+pub async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<Readiness>) {
+    let mut checks = BTreeMap::new();
+    let database = tokio::time::timeout(CHECK_TIMEOUT, check_database(&state)).await;
+    checks.insert("database", outcome(database));
+    let migrations = tokio::time::timeout(CHECK_TIMEOUT, check_migrations(&state)).await;
+    checks.insert("migrations", outcome(migrations));
+    if let Some(ai) = &state.ai_check {
+        checks.insert("ai", outcome(tokio::time::timeout(CHECK_TIMEOUT, ai.run()).await));
+    }
+
+    let ready = checks.values().all(|c| c == "ok");
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(Readiness { ready, checks }))
+}
+
+fn outcome(result: Result<anyhow::Result<()>, tokio::time::error::Elapsed>) -> String {
+    match result {
+        Ok(Ok(())) => "ok".to_string(),
+        Ok(Err(e)) => e.to_string(),
+        Err(_) => "timed out".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_migrations() {
+        let all: Vec<i64> = MIGRATOR.iter().map(|m| m.version).collect();
+        assert!(pending_migrations(&all).is_empty());
+        assert_eq!(pending_migrations(&all[1..]), vec![all[0]]);
+    }
+}
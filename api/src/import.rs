@@ -0,0 +1,226 @@
+//! Shared plumbing for bringing bookmarks in from other tools' export files.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::Postgres;
+use uuid::Uuid;
+
+use crate::{attach_tags, events, jobs, normalize::normalize_url, AppState, TagSource};
+
+/// A bookmark as described by another tool's export, before it becomes a linkman bookmark.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ExternalBookmark {
+    pub url: String,
+    pub title: Option<String>,
+    pub tags: Vec<String>,
+    /// Tags the other tool's model added
+    pub ai_tags: Vec<String>,
+    pub added_at: Option<DateTime<Utc>>,
+    pub description: Option<String>,
+    /// The other tool's model's summary of the page
+    pub summary: Option<String>,
+    pub favorite: bool,
+    /// Already read, in read-later services' terms
+    pub archived: bool,
+    /// The page's own description, as the other tool kept it
+    pub excerpt: Option<String>,
+    pub image_url: Option<String>,
+    /// Names of the collection to file it in, outermost first; empty for none
+    pub collection: Vec<String>,
+    /// Where the link was talked about, like its Hacker News thread
+    pub discussion_url: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportSummary {
+    pub imported: usize,
+    pub skipped: usize,
+}
+
+/// Inserts `bookmarks` for a user in one transaction, skipping URLs they already have
+/// (after normalization) and ones that don't parse.
+///
+/// Enrichment is queued in the same transaction for every bookmark that was created.
+pub async fn save(state: &AppState, user_id: Uuid, bookmarks: Vec<ExternalBookmark>) -> Result<ImportSummary, sqlx::Error> {
+    let total = bookmarks.len();
+    let mut tx = state.db.begin().await?;
+    let mut seen = HashSet::new();
+    let mut created = Vec::new();
+    let mut collections = HashMap::new();
+
+    for mut bookmark in bookmarks {
+        let Ok(url) = normalize_url(&bookmark.url) else {
+            continue;
+        };
+        bookmark.url = url;
+        if !seen.insert(bookmark.url.clone()) {
+            continue;
+        }
+
+        let bookmark_id: Option<Uuid> = sqlx::query_scalar(
+            "INSERT INTO bookmarks (user_id, url, title, notes, created_at, read_at, is_favorite, description, image_url, ai_summary,
+                 discussion_url)
+             VALUES ($1, $2, $3, $4, COALESCE($5, now()), CASE WHEN $6 THEN now() END, $7, $8, $9, $10, $11)
+             ON CONFLICT (user_id, url) DO NOTHING
+             RETURNING id"
+        )
+        .bind(user_id)
+        .bind(&bookmark.url)
+        .bind(&bookmark.title)
+        .bind(&bookmark.description)
+        .bind(bookmark.added_at)
+        .bind(bookmark.archived)
+        .bind(bookmark.favorite)
+        .bind(&bookmark.excerpt)
+        .bind(&bookmark.image_url)
+        .bind(&bookmark.summary)
+        .bind(&bookmark.discussion_url)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if let Some(bookmark_id) = bookmark_id {
+            attach_tags(&mut tx, user_id, bookmark_id, &bookmark.tags, TagSource::User).await?;
+            attach_tags(&mut tx, user_id, bookmark_id, &bookmark.ai_tags, TagSource::Ai).await?;
+            if let Some(collection_id) = collection_at(&mut tx, user_id, &bookmark.collection, &mut collections).await? {
+                sqlx::query("INSERT INTO collection_bookmarks (collection_id, bookmark_id) VALUES ($1, $2)")
+                    .bind(collection_id)
+                    .bind(bookmark_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            created.push(bookmark_id);
+        }
+    }
+
+    jobs::queue(&mut *tx, &created).await?;
+    tx.commit().await?;
+    state.job_wakeup.notify_one();
+    for bookmark_id in &created {
+        events::emit(state, user_id, events::Event::Created, *bookmark_id);
+    }
+
+    Ok(ImportSummary {
+        imported: created.len(),
+        skipped: total - created.len(),
+    })
+}
+
+/// What importing would do, without saving anything.
+#[derive(Debug, Default)]
+pub struct Preview {
+    /// URLs that would become bookmarks, normalized, in the file's order
+    pub new: Vec<String>,
+    /// Already among the user's bookmarks, the trash included
+    pub saved: usize,
+    /// Repeats of a URL earlier in the file
+    pub repeated: usize,
+    /// Not URLs that can be bookmarked
+    pub invalid: usize,
+}
+
+/// The file's URLs normalized, without repeats, counting those left out as `save` would.
+fn distinct_urls(bookmarks: &[ExternalBookmark]) -> Preview {
+    let mut preview = Preview::default();
+    let mut seen = HashSet::new();
+    for bookmark in bookmarks {
+        match normalize_url(&bookmark.url) {
+            Err(_) => preview.invalid += 1,
+            Ok(url) if seen.insert(url.clone()) => preview.new.push(url),
+            Ok(_) => preview.repeated += 1,
+        }
+    }
+    preview
+}
+
+pub async fn preview(state: &AppState, user_id: Uuid, bookmarks: &[ExternalBookmark]) -> Result<Preview, sqlx::Error> {
+    let mut preview = distinct_urls(bookmarks);
+    let saved: HashSet<String> = sqlx::query_scalar("SELECT url FROM bookmarks WHERE user_id = $1 AND url = ANY($2)")
+        .bind(user_id)
+        .bind(&preview.new)
+        .fetch_all(&state.db)
+        .await?
+        .into_iter()
+        .collect();
+    preview.saved = saved.len();
+    preview.new.retain(|url| !saved.contains(url));
+    Ok(preview)
+}
+
+/// The user's collection at `path`, creating whichever part of it they don't have yet, or none
+/// for an empty path. `known` remembers the ones already found during an import.
+async fn collection_at(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+    path: &[String],
+    known: &mut HashMap<Vec<String>, Uuid>,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let mut parent_id: Option<Uuid> = None;
+    for depth in 1..=path.len() {
+        let prefix = &path[..depth];
+        if let Some(id) = known.get(prefix) {
+            parent_id = Some(*id);
+            continue;
+        }
+        let name = &path[depth - 1];
+        let existing: Option<Uuid> = sqlx::query_scalar(
+            "SELECT id FROM collections WHERE user_id = $1 AND parent_id IS NOT DISTINCT FROM $2 AND name = $3"
+        )
+        .bind(user_id)
+        .bind(parent_id)
+        .bind(name)
+        .fetch_optional(&mut **tx)
+        .await?;
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                sqlx::query_scalar("INSERT INTO collections (user_id, parent_id, name) VALUES ($1, $2, $3) RETURNING id")
+                    .bind(user_id)
+                    .bind(parent_id)
+                    .bind(name)
+                    .fetch_one(&mut **tx)
+                    .await?
+            }
+        };
+        known.insert(prefix.to_vec(), id);
+        parent_id = Some(id);
+    }
+    Ok(parent_id)
+}
+
+/// Lowercase with hyphens for spaces, matching the shape of AI-generated tags.
+///
+/// Each `/`-separated segment of a nested tag is normalized on its own.
+pub fn normalize_tag(name: &str) -> String {
+    name.split('/')
+        .map(|segment| segment.split_whitespace().collect::<Vec<_>>().join("-").to_lowercase())
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distinct_urls() {
+        let bookmark = |url: &str| ExternalBookmark { url: url.to_string(), ..Default::default() };
+        let preview = distinct_urls(&[
+            bookmark("https://example.com/a"),
+            bookmark("not a url"),
+            bookmark("https://example.com/b"),
+            bookmark("https://example.com/a"),
+        ]);
+        assert_eq!(preview.new, vec!["https://example.com/a".to_string(), "https://example.com/b".to_string()]);
+        assert_eq!((preview.repeated, preview.invalid), (1, 1));
+    }
+
+    #[test]
+    fn test_normalize_tag() {
+        assert_eq!(normalize_tag("Systems Programming"), "systems-programming");
+        assert_eq!(normalize_tag("Dev / Rust / Async IO"), "dev/rust/async-io");
+        assert_eq!(normalize_tag("  "), "");
+    }
+}
@@ -0,0 +1,96 @@
+//! Instapaper's CSV export.
+//!
+//! The columns are `URL,Title,Selection,Folder,Timestamp`, and newer exports add `Tags` as a JSON
+//! list. The folder is where Instapaper kept the link: "Unread", "Archive" (read), "Starred", or
+//! one of the user's own, which becomes a collection. Text selected when the link was saved goes
+//! in the notes.
+
+use chrono::DateTime;
+
+use crate::import::{normalize_tag, ExternalBookmark};
+
+const UNREAD: &str = "Unread";
+const ARCHIVE: &str = "Archive";
+const STARRED: &str = "Starred";
+
+/// Tags as `["a","b"]`, or plainly comma-separated.
+fn tags(value: &str) -> Vec<String> {
+    let names = serde_json::from_str::<Vec<String>>(value)
+        .unwrap_or_else(|_| value.split(',').map(str::to_string).collect());
+    names.iter().map(|name| normalize_tag(name)).filter(|name| !name.is_empty()).collect()
+}
+
+pub fn parse(content: &str) -> anyhow::Result<Vec<ExternalBookmark>> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(content.as_bytes());
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|h| h.trim().eq_ignore_ascii_case(name));
+    let (url, title, selection, folder, timestamp, tag_column) = (
+        column("url").ok_or_else(|| anyhow::anyhow!("Instapaper CSV is missing a URL column"))?,
+        column("title"),
+        column("selection"),
+        column("folder"),
+        column("timestamp"),
+        column("tags"),
+    );
+
+    let mut bookmarks = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let field = |idx: Option<usize>| {
+            idx.and_then(|i| record.get(i))
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+
+        let Some(url) = field(Some(url)) else { continue };
+        let folder = field(folder).unwrap_or(UNREAD);
+        bookmarks.push(ExternalBookmark {
+            url: url.to_string(),
+            title: field(title).map(str::to_string),
+            tags: field(tag_column).map(tags).unwrap_or_default(),
+            added_at: field(timestamp)
+                .and_then(|t| t.parse::<i64>().ok())
+                .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+            description: field(selection).map(str::to_string),
+            favorite: folder == STARRED,
+            archived: folder == ARCHIVE,
+            collection: match folder {
+                UNREAD | ARCHIVE | STARRED => Vec::new(),
+                folder => vec![folder.to_string()],
+            },
+            ..Default::default()
+        });
+    }
+    Ok(bookmarks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let csv = "URL,Title,Selection,Folder,Timestamp,Tags\n\
+                   https://example.com/a,Long read,\"The key part\",Archive,1700000000,\"[\"\"Essays\"\",\"\"history\"\"]\"\n\
+                   https://example.com/b,Recipe,,Cooking,1700000100,\n\
+                   https://example.com/c,,,Starred,1700000200,\"a, B c\"\n\
+                   ,Nothing,,Unread,1700000300,\n";
+        let bookmarks = parse(csv).unwrap();
+        assert_eq!(bookmarks.len(), 3);
+
+        assert_eq!(bookmarks[0].tags, vec!["essays", "history"]);
+        assert_eq!(bookmarks[0].description.as_deref(), Some("The key part"));
+        assert_eq!(bookmarks[0].added_at, DateTime::from_timestamp(1700000000, 0));
+        assert!(bookmarks[0].archived && !bookmarks[0].favorite);
+        assert!(bookmarks[0].collection.is_empty());
+
+        assert_eq!(bookmarks[1].collection, vec!["Cooking"]);
+        assert!(!bookmarks[1].archived);
+
+        assert_eq!(bookmarks[2].title, None);
+        assert_eq!(bookmarks[2].tags, vec!["a", "b-c"]);
+        assert!(bookmarks[2].favorite);
+
+        assert!(parse("Title,Folder\nA,Unread\n").is_err());
+    }
+}
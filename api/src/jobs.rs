@@ -0,0 +1,266 @@
+//! Durable queue for fetching and tagging bookmarks.
+//!
+//! Saving a bookmark adds a row to `jobs`; the worker started by `serve` claims due rows with
+//! `FOR UPDATE SKIP LOCKED`, so several server processes can share the queue. A failed job is
+//! retried with exponential backoff and, after `MAX_ATTEMPTS`, left as `dead` for an admin to
+//! look at and retry. Jobs claimed by a process that then died are picked up again once their
+//! lock is older than `LOCK_TIMEOUT`. The latest finished job for each bookmark is kept, so
+//! `GET /bookmarks/{id}/jobs` can show how long processing took. The same worker makes webhook
+//! deliveries, which `webhooks` queues in a table of its own.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::get,
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+use crate::{fetcher::FetchError, process_bookmark, webhooks, AppState, CurrentUser};
+
+const MAX_ATTEMPTS: i32 = 5;
+/// Also how long a job that was never announced, e.g. queued by the CLI, can wait.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const LOCK_TIMEOUT: &str = "15 minutes";
+const FIRST_RETRY_SECS: i32 = 30;
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/bookmarks/{id}/jobs", get(list_bookmark_jobs))
+}
+
+/// Queues bookmarks to be fetched and tagged, skipping any already waiting or being processed.
+pub async fn enqueue(state: &AppState, bookmark_ids: &[Uuid]) -> Result<(), sqlx::Error> {
+    queue(&state.db, bookmark_ids).await?;
+    state.job_wakeup.notify_one();
+    Ok(())
+}
+
+/// `enqueue` as part of a caller's transaction, so the bookmarks and their jobs are saved
+/// together. Notify `job_wakeup` once it commits.
+pub async fn queue<'e>(executor: impl sqlx::PgExecutor<'e>, bookmark_ids: &[Uuid]) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "WITH queued AS (
+             INSERT INTO jobs (bookmark_id) SELECT * FROM UNNEST($1::uuid[])
+             ON CONFLICT (bookmark_id) WHERE status IN ('pending', 'running') DO NOTHING
+             RETURNING bookmark_id
+         )
+         UPDATE bookmarks SET processing_status = 'pending', error_message = NULL, error_code = NULL
+         FROM queued WHERE bookmarks.id = queued.bookmark_id"
+    )
+    .bind(bookmark_ids)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+struct Claimed {
+    id: i64,
+    bookmark_id: Uuid,
+    user_id: Uuid,
+    url: String,
+    attempts: i32,
+}
+
+async fn claim(state: &AppState) -> Result<Option<Claimed>, sqlx::Error> {
+    let row: Option<(i64, Uuid, Uuid, String, i32)> = sqlx::query_as(&format!(
+        "UPDATE jobs j SET status = 'running', attempts = j.attempts + 1, locked_at = now(), started_at = now()
+         FROM bookmarks b
+         WHERE b.id = j.bookmark_id AND j.id = (
+             SELECT id FROM jobs
+             WHERE (status = 'pending' AND next_run_at <= now())
+                OR (status = 'running' AND locked_at < now() - interval '{}')
+             ORDER BY next_run_at, id
+             LIMIT 1
+             FOR UPDATE SKIP LOCKED
+         )
+         RETURNING j.id, j.bookmark_id, b.user_id, b.url, j.attempts",
+        LOCK_TIMEOUT
+    ))
+    .fetch_optional(&state.db)
+    .await?;
+
+    Ok(row.map(|(id, bookmark_id, user_id, url, attempts)| Claimed { id, bookmark_id, user_id, url, attempts }))
+}
+
+/// Seconds to wait before the next attempt: 30s, 2m, 8m, 32m. Webhook deliveries back off the
+/// same way.
+pub fn retry_delay(attempts: i32) -> i32 {
+    FIRST_RETRY_SECS * 4i32.pow(attempts.clamp(1, MAX_ATTEMPTS) as u32 - 1)
+}
+
+async fn execute(state: &AppState, job: Claimed) -> Result<(), sqlx::Error> {
+    let result = process_bookmark(state.clone(), job.user_id, job.bookmark_id, job.url).await;
+    state.metrics.record_job("bookmark_processing", result.is_ok());
+
+    let Err(e) = result else {
+        sqlx::query(
+            "UPDATE jobs SET status = 'done', locked_at = NULL, last_error = NULL, finished_at = now() WHERE id = $1"
+        )
+        .bind(job.id)
+        .execute(&state.db)
+        .await?;
+        sqlx::query("DELETE FROM jobs WHERE bookmark_id = $1 AND status = 'done' AND id <> $2")
+            .bind(job.bookmark_id)
+            .bind(job.id)
+            .execute(&state.db)
+            .await?;
+        return Ok(());
+    };
+    eprintln!("Error processing bookmark {} (attempt {}): {}", job.bookmark_id, job.attempts, e);
+    // Pages that are too big or ruled out by robots.txt will be the next time too
+    let fetch_error = e.downcast_ref::<FetchError>();
+    let max_attempts = match fetch_error {
+        Some(fetch_error) if !fetch_error.is_transient() => job.attempts,
+        _ => MAX_ATTEMPTS,
+    };
+
+    // The bookmark only shows as failed once there are no retries left
+    sqlx::query(
+        "WITH failed AS (
+             UPDATE jobs SET
+                 status = CASE WHEN attempts >= $2 THEN 'dead' ELSE 'pending' END,
+                 next_run_at = CASE WHEN attempts >= $2 THEN next_run_at ELSE now() + make_interval(secs => $3) END,
+                 finished_at = CASE WHEN attempts >= $2 THEN now() END,
+                 locked_at = NULL,
+                 last_error = $4
+             WHERE id = $1
+             RETURNING bookmark_id, status
+         )
+         UPDATE bookmarks SET
+             processing_status = CASE WHEN failed.status = 'dead' THEN 'failed'::processing_status ELSE 'pending' END,
+             error_message = $4,
+             error_code = $5
+         FROM failed WHERE bookmarks.id = failed.bookmark_id"
+    )
+    .bind(job.id)
+    .bind(max_attempts)
+    .bind(retry_delay(job.attempts) as f64)
+    .bind(e.to_string())
+    .bind(fetch_error.map(FetchError::code))
+    .execute(&state.db)
+    .await?;
+    Ok(())
+}
+
+enum Work {
+    Bookmark(Claimed),
+    Delivery(webhooks::Claimed),
+}
+
+/// Works through the queue until the process exits, `concurrency` bookmarks at a time. Fetches
+/// and model calls are further limited by `AppState`'s semaphores, so a big import backs up in
+/// the queue instead of piling onto the network or the model.
+pub async fn run(state: AppState, concurrency: usize) {
+    let limit = Arc::new(Semaphore::new(concurrency));
+    loop {
+        let permit = limit.clone().acquire_owned().await.expect("job semaphore closed");
+        // Deliveries are quick, and shouldn't wait behind a big import
+        let claimed = match webhooks::claim(&state).await {
+            Ok(Some(delivery)) => Ok(Some(Work::Delivery(delivery))),
+            Ok(None) => claim(&state).await.map(|job| job.map(Work::Bookmark)),
+            Err(e) => Err(e),
+        };
+        match claimed {
+            Ok(Some(Work::Bookmark(job))) => {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    let id = job.id;
+                    if let Err(e) = execute(&state, job).await {
+                        eprintln!("Error recording job {}: {}", id, e);
+                    }
+                    drop(permit);
+                });
+            }
+            Ok(Some(Work::Delivery(delivery))) => {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = webhooks::deliver(&state, delivery).await {
+                        eprintln!("Error recording webhook delivery: {}", e);
+                    }
+                    drop(permit);
+                });
+            }
+            Ok(None) => {
+                drop(permit);
+                tokio::select! {
+                    _ = state.job_wakeup.notified() => {}
+                    _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                }
+            }
+            Err(e) => {
+                drop(permit);
+                eprintln!("Job Queue Error: {}", e);
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        }
+    }
+}
+
+/// How one job for a bookmark went; `duration_ms` is the latest attempt's, once it has finished.
+#[derive(Serialize, sqlx::FromRow)]
+pub struct JobResponse {
+    id: i64,
+    /// `pending`, `running`, `done` or `dead`
+    status: String,
+    attempts: i32,
+    last_error: Option<String>,
+    created_at: DateTime<Utc>,
+    next_run_at: DateTime<Utc>,
+    started_at: Option<DateTime<Utc>>,
+    finished_at: Option<DateTime<Utc>>,
+    duration_ms: Option<i64>,
+}
+
+/// `SELECT` list for `JobResponse`, on `jobs j`.
+pub const JOB_COLUMNS: &str = "j.id, j.status, j.attempts, j.last_error, j.created_at, j.next_run_at,
+     j.started_at, j.finished_at,
+     (EXTRACT(EPOCH FROM j.finished_at - j.started_at) * 1000)::bigint as duration_ms";
+
+async fn list_bookmark_jobs(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<JobResponse>>, StatusCode> {
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Bookmark Jobs Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let owned: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM bookmarks WHERE id = $1 AND user_id = $2)")
+        .bind(id)
+        .bind(user.id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(db_error)?;
+    if !owned {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    sqlx::query_as::<_, JobResponse>(&format!(
+        "SELECT {} FROM jobs j WHERE j.bookmark_id = $1 ORDER BY j.id DESC",
+        JOB_COLUMNS
+    ))
+    .bind(id)
+    .fetch_all(&state.db)
+    .await
+    .map(Json)
+    .map_err(db_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(1), 30);
+        assert_eq!(retry_delay(2), 120);
+        assert_eq!(retry_delay(4), 1920);
+        assert_eq!(retry_delay(MAX_ATTEMPTS + 3), retry_delay(MAX_ATTEMPTS));
+    }
+}
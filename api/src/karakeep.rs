@@ -0,0 +1,193 @@
+//! Karakeep's (formerly Hoarder) JSON export, and the bookmark lists its API returns.
+//!
+//! The export is `{"bookmarks": [...]}` with tags as plain names and times in Unix seconds. The
+//! API's lists have the same shape, plus tags saying who attached them, the model's summary and
+//! the page's description and image, all of which are kept. Tags Karakeep's model attached come
+//! in as the model's, so they're swapped for linkman's own once it tags the bookmark, if it has
+//! a provider to. Text and asset bookmarks, which have no URL, are skipped.
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::import::{normalize_tag, ExternalBookmark};
+
+#[derive(Deserialize)]
+struct Export {
+    bookmarks: Vec<Bookmark>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Bookmark {
+    created_at: Option<Time>,
+    title: Option<String>,
+    #[serde(default)]
+    tags: Vec<Tag>,
+    content: Option<Content>,
+    note: Option<String>,
+    summary: Option<String>,
+    #[serde(default)]
+    archived: bool,
+    #[serde(default)]
+    favourited: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Content {
+    /// `link` for bookmarks of a page; `text` and `asset` ones have no URL
+    #[serde(rename = "type")]
+    kind: String,
+    url: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    image_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Tag {
+    Name(String),
+    Attached {
+        name: String,
+        #[serde(rename = "attachedBy")]
+        attached_by: Option<String>,
+    },
+}
+
+/// Unix seconds in exports, RFC 3339 from the API.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Time {
+    Seconds(i64),
+    Text(String),
+}
+
+impl Time {
+    fn parse(&self) -> Option<DateTime<Utc>> {
+        match self {
+            // Milliseconds, however the export was made
+            Time::Seconds(ms) if *ms > 100_000_000_000 => DateTime::from_timestamp_millis(*ms),
+            Time::Seconds(secs) => DateTime::from_timestamp(*secs, 0),
+            Time::Text(text) => DateTime::parse_from_rfc3339(text).ok().map(|t| t.to_utc()),
+        }
+    }
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+pub fn parse(content: &str) -> anyhow::Result<Vec<ExternalBookmark>> {
+    let export: Export = serde_json::from_str(content)?;
+    Ok(export
+        .bookmarks
+        .into_iter()
+        .filter_map(|bookmark| {
+            let content = bookmark.content.filter(|c| c.kind == "link")?;
+            let url = non_empty(content.url)?;
+
+            let (mut tags, mut ai_tags) = (Vec::new(), Vec::new());
+            for tag in bookmark.tags {
+                let (name, by_ai) = match tag {
+                    Tag::Name(name) => (name, false),
+                    Tag::Attached { name, attached_by } => (name, attached_by.as_deref() == Some("ai")),
+                };
+                let name = normalize_tag(&name);
+                if name.is_empty() {
+                    continue;
+                }
+                if by_ai {
+                    ai_tags.push(name);
+                } else {
+                    tags.push(name);
+                }
+            }
+
+            Some(ExternalBookmark {
+                url,
+                title: non_empty(bookmark.title).or(non_empty(content.title)),
+                tags,
+                ai_tags,
+                added_at: bookmark.created_at.as_ref().and_then(Time::parse),
+                description: non_empty(bookmark.note),
+                summary: non_empty(bookmark.summary),
+                favorite: bookmark.favourited,
+                archived: bookmark.archived,
+                excerpt: non_empty(content.description),
+                image_url: non_empty(content.image_url),
+                ..Default::default()
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_export() {
+        let export = r#"{"bookmarks": [
+            {
+                "createdAt": 1716000000,
+                "title": null,
+                "tags": ["Self Hosting", "rust"],
+                "content": { "type": "link", "url": "https://karakeep.app/" },
+                "note": "Try the import",
+                "archived": true
+            },
+            {
+                "createdAt": 1716000100,
+                "title": "Shopping list",
+                "tags": [],
+                "content": { "type": "text", "text": "milk" },
+                "note": null
+            },
+            { "createdAt": 1716000200, "title": "Nothing", "tags": [], "content": null, "note": null }
+        ]}"#;
+        let bookmarks = parse(export).unwrap();
+        assert_eq!(bookmarks.len(), 1);
+        assert_eq!(bookmarks[0].url, "https://karakeep.app/");
+        assert_eq!(bookmarks[0].title, None);
+        assert_eq!(bookmarks[0].tags, vec!["self-hosting", "rust"]);
+        assert!(bookmarks[0].ai_tags.is_empty());
+        assert_eq!(bookmarks[0].description.as_deref(), Some("Try the import"));
+        assert_eq!(bookmarks[0].added_at, DateTime::from_timestamp(1716000000, 0));
+        assert!(bookmarks[0].archived && !bookmarks[0].favorite);
+    }
+
+    #[test]
+    fn test_parse_api() {
+        let page = r#"{"bookmarks": [{
+            "id": "ieidlxygmwj87oxz5hxttoc8",
+            "createdAt": "2024-05-18T02:40:00.000Z",
+            "title": null,
+            "archived": false,
+            "favourited": true,
+            "note": "",
+            "summary": "A guide to running services at home.",
+            "tags": [
+                { "id": "t1", "name": "homelab", "attachedBy": "human" },
+                { "id": "t2", "name": "Docker", "attachedBy": "ai" }
+            ],
+            "content": {
+                "type": "link",
+                "url": "https://example.com/homelab",
+                "title": "Homelab basics",
+                "description": "Start small.",
+                "imageUrl": "https://example.com/cover.jpg"
+            }
+        }], "nextCursor": null}"#;
+        let bookmarks = parse(page).unwrap();
+        assert_eq!(bookmarks[0].title.as_deref(), Some("Homelab basics"));
+        assert_eq!(bookmarks[0].tags, vec!["homelab"]);
+        assert_eq!(bookmarks[0].ai_tags, vec!["docker"]);
+        assert_eq!(bookmarks[0].description, None);
+        assert_eq!(bookmarks[0].summary.as_deref(), Some("A guide to running services at home."));
+        assert_eq!(bookmarks[0].excerpt.as_deref(), Some("Start small."));
+        assert_eq!(bookmarks[0].image_url.as_deref(), Some("https://example.com/cover.jpg"));
+        assert_eq!(bookmarks[0].added_at, "2024-05-18T02:40:00Z".parse().ok());
+        assert!(bookmarks[0].favorite);
+    }
+}
@@ -0,0 +1,61 @@
+//! Working out what language a page is written in, as an ISO 639-1 code like `en`.
+//!
+//! The page's own `<html lang>` wins when it has one; otherwise the text is guessed at with
+//! `whatlang`, and a guess it isn't sure of is left out rather than stored wrong.
+
+use serde_json::Value;
+
+/// How much of the page to guess from; more only slows it down.
+const SAMPLE_CHARS: usize = 2000;
+
+/// `whatlang`'s ISO 639-3 codes and their two-letter equivalents.
+const ISO_639_1: [(&str, &str); 69] = [
+    ("afr", "af"), ("aka", "ak"), ("amh", "am"), ("ara", "ar"), ("aze", "az"), ("bel", "be"),
+    ("ben", "bn"), ("bul", "bg"), ("cat", "ca"), ("ces", "cs"), ("cmn", "zh"), ("cym", "cy"),
+    ("dan", "da"), ("deu", "de"), ("ell", "el"), ("eng", "en"), ("est", "et"), ("fin", "fi"),
+    ("fra", "fr"), ("guj", "gu"), ("heb", "he"), ("hin", "hi"), ("hrv", "hr"), ("hun", "hu"),
+    ("hye", "hy"), ("ind", "id"), ("ita", "it"), ("jav", "jv"), ("jpn", "ja"), ("kan", "kn"),
+    ("kat", "ka"), ("khm", "km"), ("kor", "ko"), ("lat", "la"), ("lav", "lv"), ("lit", "lt"),
+    ("mal", "ml"), ("mar", "mr"), ("mkd", "mk"), ("mya", "my"), ("nep", "ne"), ("nld", "nl"),
+    ("nob", "nb"), ("ori", "or"), ("pan", "pa"), ("pes", "fa"), ("pol", "pl"), ("por", "pt"),
+    ("ron", "ro"), ("rus", "ru"), ("sin", "si"), ("slk", "sk"), ("slv", "sl"), ("sna", "sn"),
+    ("spa", "es"), ("srp", "sr"), ("swe", "sv"), ("tam", "ta"), ("tel", "te"), ("tgl", "tl"),
+    ("tha", "th"), ("tuk", "tk"), ("tur", "tr"), ("ukr", "uk"), ("urd", "ur"), ("uzb", "uz"),
+    ("vie", "vi"), ("yid", "yi"), ("zul", "zu"),
+];
+
+/// The primary subtag of a language tag such as `en-GB`, if it looks like one.
+pub fn normalize(tag: &str) -> Option<String> {
+    let primary = tag.trim().split(['-', '_']).next()?.to_ascii_lowercase();
+    ((2..=3).contains(&primary.len()) && primary.chars().all(|c| c.is_ascii_lowercase())).then_some(primary)
+}
+
+/// The language of a page, from the `lang` in its scraped metadata or else its text.
+pub fn detect(site_meta: &Value, text: &str) -> Option<String> {
+    if let Some(lang) = site_meta["lang"].as_str().and_then(normalize) {
+        return Some(lang);
+    }
+    let sample: String = text.chars().take(SAMPLE_CHARS).collect();
+    let info = whatlang::detect(&sample).filter(|info| info.is_reliable())?;
+    ISO_639_1
+        .iter()
+        .find(|(code, _)| *code == info.lang().code())
+        .map(|(_, iso)| iso.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(&json!({ "lang": "en-GB" }), "Der schnelle braune Fuchs"), Some("en".to_string()));
+        let german = "Die Bundesregierung hat am Mittwoch beschlossen, dass die neuen Regeln für den \
+                      Straßenverkehr ab dem kommenden Jahr in ganz Deutschland gelten sollen.";
+        assert_eq!(detect(&json!({}), german), Some("de".to_string()));
+        assert_eq!(detect(&json!({ "lang": "" }), "ok"), None);
+        assert_eq!(normalize("PT_br"), Some("pt".to_string()));
+        assert_eq!(normalize("x-klingon"), None);
+    }
+}
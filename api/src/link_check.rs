@@ -0,0 +1,145 @@
+//! Periodic dead-link checker.
+//!
+//! Every `LINK_CHECK_INTERVAL_HOURS` (default 24, `0` disables) each bookmark whose last check
+//! is older than the interval gets a HEAD request. After `LINK_CHECK_FAILURE_THRESHOLD`
+//! (default 3) failures in a row the bookmark is marked broken; one success clears it. Checks go
+//! through the page fetcher's client and per-host spacing, but don't consult robots.txt: they
+//! only ask whether the page is still there.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::{Method, StatusCode};
+use tokio::{sync::Semaphore, task::JoinSet};
+use url::Url;
+use uuid::Uuid;
+
+use crate::{config::LinkCheckConfig, fetcher::Fetcher, AppState};
+
+const CHECK_CONCURRENCY: usize = 4;
+const CHECK_BATCH: i64 = 500;
+const CHECK_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Config {
+    pub interval: Duration,
+    pub failure_threshold: i32,
+}
+
+impl Config {
+    pub fn from_config(config: &LinkCheckConfig) -> Option<Self> {
+        (config.interval_hours > 0).then(|| Config {
+            interval: Duration::from_secs(config.interval_hours * 60 * 60),
+            failure_threshold: config.failure_threshold,
+        })
+    }
+}
+
+pub async fn run(state: AppState, config: Config) {
+    let config = Arc::new(config);
+
+    // Wake up far more often than the interval so a restart doesn't delay checks by a whole period
+    let mut tick = tokio::time::interval(Duration::from_secs(15 * 60).min(config.interval));
+    loop {
+        tick.tick().await;
+        let result = check_due(&state, &config).await;
+        state.metrics.record_job("link_check", result.is_ok());
+        if let Err(e) = result {
+            eprintln!("Link Check Error: {}", e);
+        }
+    }
+}
+
+async fn check_due(state: &AppState, config: &Arc<Config>) -> anyhow::Result<()> {
+    loop {
+        let due: Vec<(Uuid, String)> = sqlx::query_as(
+            "SELECT id, url FROM bookmarks
+             WHERE deleted_at IS NULL
+               AND (last_checked_at IS NULL OR last_checked_at < now() - make_interval(secs => $1))
+             ORDER BY last_checked_at NULLS FIRST
+             LIMIT $2"
+        )
+        .bind(config.interval.as_secs_f64())
+        .bind(CHECK_BATCH)
+        .fetch_all(&state.db)
+        .await?;
+
+        if due.is_empty() {
+            return Ok(());
+        }
+
+        let limit = Arc::new(Semaphore::new(CHECK_CONCURRENCY));
+        let mut tasks = JoinSet::new();
+        for (id, url) in due {
+            let permit = limit.clone().acquire_owned().await?;
+            let (state, config) = (state.clone(), config.clone());
+            tasks.spawn(async move {
+                let status = check(&state.fetcher, &url).await;
+                if let Err(e) = record(&state, &config, id, status).await {
+                    eprintln!("Error recording link check for bookmark {}: {}", id, e);
+                }
+                drop(permit);
+            });
+        }
+        tasks.join_all().await;
+    }
+}
+
+/// The HTTP status the URL answered with, or `None` if it couldn't be reached at all.
+async fn check(fetcher: &Fetcher, url: &str) -> Option<StatusCode> {
+    let client = fetcher.client();
+    let parsed = Url::parse(url).ok()?;
+    fetcher.check_url(&parsed).ok()?;
+    fetcher.wait_turn(&parsed).await;
+    let status = client.request(Method::HEAD, url).timeout(CHECK_TIMEOUT).send().await.ok()?.status();
+    if status == StatusCode::METHOD_NOT_ALLOWED || status == StatusCode::NOT_IMPLEMENTED {
+        // Plenty of servers only implement GET
+        return client.get(url).timeout(CHECK_TIMEOUT).send().await.ok().map(|r| r.status());
+    }
+    Some(status)
+}
+
+/// Login walls and rate limits mean the page is there, just not for us right now.
+fn is_alive(status: Option<StatusCode>) -> bool {
+    match status {
+        Some(s) => {
+            !(s.is_client_error() || s.is_server_error())
+                || matches!(s, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS)
+        }
+        None => false,
+    }
+}
+
+async fn record(state: &AppState, config: &Config, id: Uuid, status: Option<StatusCode>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE bookmarks SET
+             last_checked_at = now(),
+             last_status = $1,
+             link_failures = CASE WHEN $2 THEN 0 ELSE link_failures + 1 END,
+             is_broken = CASE WHEN $2 THEN false ELSE link_failures + 1 >= $3 END
+         WHERE id = $4"
+    )
+    .bind(status.map(|s| s.as_u16() as i32))
+    .bind(is_alive(status))
+    .bind(config.failure_threshold)
+    .bind(id)
+    .execute(&state.db)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_alive() {
+        assert!(is_alive(Some(StatusCode::OK)));
+        assert!(is_alive(Some(StatusCode::MOVED_PERMANENTLY)));
+        assert!(is_alive(Some(StatusCode::FORBIDDEN)));
+        assert!(is_alive(Some(StatusCode::TOO_MANY_REQUESTS)));
+        assert!(!is_alive(Some(StatusCode::NOT_FOUND)));
+        assert!(!is_alive(Some(StatusCode::GONE)));
+        assert!(!is_alive(Some(StatusCode::BAD_GATEWAY)));
+        assert!(!is_alive(None));
+    }
+}
@@ -0,0 +1,621 @@
+//! The parts of linkding's REST API its browser extension and mobile apps use, so they can be
+//! pointed at linkman instead.
+//!
+//! Clients send `Authorization: Token TOKEN`, as linkding expects. Linkding's `description` is
+//! the bookmark's notes here, and the page's own title and description come back as
+//! `website_title` and `website_description`. Its `notes` aren't kept separately, and linkman has
+//! no archive, so nothing is ever `is_archived`. Ids are UUIDs rather than linkding's numbers.
+//! Searches take linkding's syntax: words to match, `#tag` and `!unread` or `!untagged`.
+
+use axum::{
+    extract::{OriginalUri, Path, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::Response,
+    routing::get,
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::{
+    audit, authenticate, client_ip, events, jobs, normalize::normalize_url, page_title, rate_limit, replace_tags,
+    required_scope, scrape_metadata, tag_path, upsert_bookmark, ApiKey, AppState, CurrentUser, SyncBookmarkRequest,
+};
+
+/// Linkding's page size when a client doesn't ask for one.
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+pub fn router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/bookmarks/", get(list_bookmarks).post(create_bookmark))
+        .route("/bookmarks/archived/", get(list_archived))
+        .route("/bookmarks/check/", get(check_url))
+        .route(
+            "/bookmarks/{id}/",
+            get(get_bookmark).put(update_bookmark).patch(update_bookmark).delete(delete_bookmark),
+        )
+        .route("/tags/", get(list_tags).post(create_tag))
+        .route("/tags/{id}/", get(get_tag))
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit::middleware))
+        .layer(middleware::from_fn_with_state(state, auth_middleware))
+}
+
+async fn auth_middleware(State(state): State<AppState>, mut req: Request, next: Next) -> Result<Response, StatusCode> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Token ").or_else(|| header.strip_prefix("Bearer ")))
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let (user, key) = authenticate(&state, token, client_ip(&req)).await?;
+
+    let scope = required_scope(req.method(), req.uri().path());
+    if !key.allows(scope) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    req.extensions_mut().insert(user);
+    req.extensions_mut().insert(key);
+    if scope == "write" {
+        return Ok(audit::record(&state, req, next).await);
+    }
+    Ok(next.run(req).await)
+}
+
+fn db_error(e: sqlx::Error) -> StatusCode {
+    eprintln!("Linkding API Error: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// A bookmark in linkding's shape.
+#[derive(Serialize, sqlx::FromRow)]
+struct Bookmark {
+    id: Uuid,
+    url: String,
+    /// Only a title the user gave; one from the page is `website_title`
+    title: String,
+    description: String,
+    notes: String,
+    web_archive_snapshot_url: String,
+    favicon_url: Option<String>,
+    preview_image_url: Option<String>,
+    is_archived: bool,
+    unread: bool,
+    shared: bool,
+    tag_names: Vec<String>,
+    date_added: DateTime<Utc>,
+    date_modified: DateTime<Utc>,
+    website_title: Option<String>,
+    website_description: Option<String>,
+}
+
+const BOOKMARK_SELECT: &str = "SELECT b.id, b.url,
+     CASE WHEN b.title_from_page THEN '' ELSE COALESCE(b.title, '') END AS title,
+     COALESCE(b.notes, '') AS description, '' AS notes,
+     COALESCE(b.wayback_url, b.snapshot_url, '') AS web_archive_snapshot_url,
+     NULL::text AS favicon_url, b.image_url AS preview_image_url, false AS is_archived,
+     b.read_at IS NULL AS unread, b.is_public AS shared,
+     COALESCE((SELECT array_agg(t.name ORDER BY t.name) FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id
+      WHERE bt.bookmark_id = b.id), '{}') AS tag_names,
+     b.created_at AS date_added, COALESCE(b.updated_at, b.created_at) AS date_modified,
+     CASE WHEN b.title_from_page THEN b.title END AS website_title,
+     COALESCE(b.description, b.ai_summary) AS website_description
+     FROM bookmarks b";
+
+/// Linkding's paginated lists. `next` and `previous` are full URLs, which clients follow as given.
+#[derive(Serialize)]
+struct Page<T> {
+    count: i64,
+    next: Option<String>,
+    previous: Option<String>,
+    results: Vec<T>,
+}
+
+#[derive(Deserialize)]
+struct PageParams {
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+impl PageParams {
+    fn limit(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+
+    fn offset(&self) -> i64 {
+        self.offset.unwrap_or(0).max(0)
+    }
+}
+
+/// This request's URL with another `offset`, as the client reached it. Behind a proxy that ends
+/// TLS, the scheme comes from `X-Forwarded-Proto`.
+fn page_url(headers: &HeaderMap, uri: &OriginalUri, limit: i64, offset: i64) -> Option<String> {
+    let host = headers.get(header::HOST)?.to_str().ok()?;
+    let scheme = headers
+        .get("x-forwarded-proto")
+        .and_then(|proto| proto.to_str().ok())
+        .unwrap_or("http");
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    for (name, value) in url::form_urlencoded::parse(uri.query().unwrap_or_default().as_bytes()) {
+        if name != "limit" && name != "offset" {
+            query.append_pair(&name, &value);
+        }
+    }
+    query.append_pair("limit", &limit.to_string()).append_pair("offset", &offset.to_string());
+    Some(format!("{}://{}{}?{}", scheme, host, uri.path(), query.finish()))
+}
+
+fn page<T>(headers: &HeaderMap, uri: &OriginalUri, params: &PageParams, count: i64, results: Vec<T>) -> Page<T> {
+    let (limit, offset) = (params.limit(), params.offset());
+    Page {
+        count,
+        next: (offset + limit < count).then(|| page_url(headers, uri, limit, offset + limit)).flatten(),
+        previous: (offset > 0).then(|| page_url(headers, uri, limit, (offset - limit).max(0))).flatten(),
+        results,
+    }
+}
+
+/// A linkding search: every word must match somewhere, and every `#tag` be on the bookmark.
+#[derive(Debug, Default, PartialEq)]
+struct Search {
+    words: Vec<String>,
+    tags: Vec<String>,
+    unread: bool,
+    untagged: bool,
+}
+
+fn parse_search(q: &str) -> Search {
+    let mut search = Search::default();
+    for term in q.split_whitespace() {
+        match term {
+            "!unread" => search.unread = true,
+            "!untagged" => search.untagged = true,
+            _ => match term.strip_prefix('#').and_then(tag_path) {
+                Some(tag) => search.tags.push(tag),
+                None => search.words.push(term.to_string()),
+            },
+        }
+    }
+    search
+}
+
+#[derive(Deserialize)]
+struct ListParams {
+    q: Option<String>,
+    added_since: Option<DateTime<Utc>>,
+    modified_since: Option<DateTime<Utc>>,
+}
+
+fn push_filters(query: &mut QueryBuilder<Postgres>, user_id: Uuid, params: &ListParams) {
+    query.push(" WHERE b.user_id = ").push_bind(user_id).push(" AND b.deleted_at IS NULL");
+    if let Some(added_since) = params.added_since {
+        query.push(" AND b.created_at >= ").push_bind(added_since);
+    }
+    if let Some(modified_since) = params.modified_since {
+        query.push(" AND COALESCE(b.updated_at, b.created_at) >= ").push_bind(modified_since);
+    }
+
+    let search = parse_search(params.q.as_deref().unwrap_or_default());
+    for word in search.words {
+        let pattern = format!("%{}%", word);
+        query
+            .push(" AND (b.url ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR b.title ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR b.notes ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR b.description ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR EXISTS (SELECT 1 FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id WHERE bt.bookmark_id = b.id AND t.name ILIKE ")
+            .push_bind(pattern)
+            .push("))");
+    }
+    for tag in search.tags {
+        query
+            .push(" AND EXISTS (SELECT 1 FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id WHERE bt.bookmark_id = b.id AND lower(t.name) = lower(")
+            .push_bind(tag)
+            .push("))");
+    }
+    if search.unread {
+        query.push(" AND b.read_at IS NULL");
+    }
+    if search.untagged {
+        query.push(" AND NOT EXISTS (SELECT 1 FROM bookmark_tags bt WHERE bt.bookmark_id = b.id)");
+    }
+}
+
+async fn list_bookmarks(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    headers: HeaderMap,
+    uri: OriginalUri,
+    Query(params): Query<ListParams>,
+    Query(paging): Query<PageParams>,
+) -> Result<Json<Page<Bookmark>>, StatusCode> {
+    let mut count = QueryBuilder::<Postgres>::new("SELECT count(*) FROM bookmarks b");
+    push_filters(&mut count, user.id, &params);
+    let total: i64 = count.build_query_scalar().fetch_one(&state.db).await.map_err(db_error)?;
+
+    let mut query = QueryBuilder::<Postgres>::new(BOOKMARK_SELECT);
+    push_filters(&mut query, user.id, &params);
+    query
+        .push(" ORDER BY b.created_at DESC, b.id DESC LIMIT ")
+        .push_bind(paging.limit())
+        .push(" OFFSET ")
+        .push_bind(paging.offset());
+    let bookmarks = query.build_query_as().fetch_all(&state.db).await.map_err(db_error)?;
+
+    Ok(Json(page(&headers, &uri, &paging, total, bookmarks)))
+}
+
+/// Nothing is archived in linkman, so this is always empty.
+async fn list_archived(
+    headers: HeaderMap,
+    uri: OriginalUri,
+    Query(paging): Query<PageParams>,
+) -> Json<Page<Bookmark>> {
+    Json(page(&headers, &uri, &paging, 0, Vec::new()))
+}
+
+async fn fetch_bookmark(state: &AppState, user_id: Uuid, id: Uuid) -> Result<Bookmark, StatusCode> {
+    QueryBuilder::<Postgres>::new(BOOKMARK_SELECT)
+        .push(" WHERE b.id = ")
+        .push_bind(id)
+        .push(" AND b.user_id = ")
+        .push_bind(user_id)
+        .push(" AND b.deleted_at IS NULL")
+        .build_query_as()
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn get_bookmark(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Bookmark>, StatusCode> {
+    fetch_bookmark(&state, user.id, id).await.map(Json)
+}
+
+/// The fields clients send when creating or editing a bookmark; all optional, as for `PATCH`.
+#[derive(Deserialize)]
+struct BookmarkRequest {
+    url: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    tag_names: Option<Vec<String>>,
+    unread: Option<bool>,
+    shared: Option<bool>,
+}
+
+/// Applies what's given of `unread` and `shared`.
+async fn set_flags(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    bookmark_id: Uuid,
+    payload: &BookmarkRequest,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE bookmarks SET
+             read_at = CASE WHEN $2 IS NULL THEN read_at WHEN $2 THEN NULL ELSE COALESCE(read_at, now()) END,
+             is_public = COALESCE($3, is_public)
+         WHERE id = $1"
+    )
+    .bind(bookmark_id)
+    .bind(payload.unread)
+    .bind(payload.shared)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Like linkding, saving a URL that's already bookmarked updates that bookmark.
+async fn create_bookmark(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Extension(key): Extension<ApiKey>,
+    Json(payload): Json<BookmarkRequest>,
+) -> Result<(StatusCode, Json<Bookmark>), StatusCode> {
+    let url = payload.url.as_deref().ok_or(StatusCode::BAD_REQUEST)?;
+    let url = normalize_url(url).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let request = SyncBookmarkRequest {
+        url: url.clone(),
+        // Clients send empty strings for what the user left blank
+        title: payload.title.clone().filter(|title| !title.is_empty()),
+        notes: payload.description.clone().filter(|notes| !notes.is_empty()),
+        tags: None,
+        tag_mode: None,
+    };
+
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    let (bookmark_id, inserted) = upsert_bookmark(&mut tx, user.id, key.tag_mode, &url, &request).await.map_err(db_error)?;
+    if let Some(tag_names) = &payload.tag_names {
+        replace_tags(&mut tx, user.id, bookmark_id, tag_names).await.map_err(db_error)?;
+    }
+    set_flags(&mut tx, bookmark_id, &payload).await.map_err(db_error)?;
+    if inserted {
+        jobs::queue(&mut *tx, &[bookmark_id]).await.map_err(db_error)?;
+    }
+    tx.commit().await.map_err(db_error)?;
+    state.job_wakeup.notify_one();
+
+    let event = if inserted { events::Event::Created } else { events::Event::Updated };
+    events::emit(&state, user.id, event, bookmark_id);
+    let status = if inserted { StatusCode::CREATED } else { StatusCode::OK };
+    Ok((status, Json(fetch_bookmark(&state, user.id, bookmark_id).await?)))
+}
+
+/// `PUT` and `PATCH` alike: whatever is given is changed.
+async fn update_bookmark(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<BookmarkRequest>,
+) -> Result<Json<Bookmark>, StatusCode> {
+    let url = match payload.url.as_deref() {
+        Some(url) => Some(normalize_url(url).map_err(|_| StatusCode::BAD_REQUEST)?),
+        None => None,
+    };
+
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    let updated: Option<Uuid> = sqlx::query_scalar(
+        "UPDATE bookmarks SET url = COALESCE($3, url),
+             title = CASE WHEN $4 IS NULL OR $4 = '' THEN title ELSE $4 END,
+             title_from_page = title_from_page AND ($4 IS NULL OR $4 = ''),
+             notes = CASE WHEN $5 IS NULL THEN notes ELSE NULLIF($5, '') END,
+             updated_at = now()
+         WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL
+         RETURNING id"
+    )
+    .bind(id)
+    .bind(user.id)
+    .bind(&url)
+    .bind(&payload.title)
+    .bind(&payload.description)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => StatusCode::CONFLICT,
+        e => db_error(e),
+    })?;
+    if updated.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    if let Some(tag_names) = &payload.tag_names {
+        replace_tags(&mut tx, user.id, id, tag_names).await.map_err(db_error)?;
+    }
+    set_flags(&mut tx, id, &payload).await.map_err(db_error)?;
+    tx.commit().await.map_err(db_error)?;
+
+    events::emit(&state, user.id, events::Event::Updated, id);
+    fetch_bookmark(&state, user.id, id).await.map(Json)
+}
+
+/// Moves the bookmark to the trash, where the rest of linkman can still restore it.
+async fn delete_bookmark(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = sqlx::query(
+        "UPDATE bookmarks SET deleted_at = now(), updated_at = now() WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL"
+    )
+    .bind(id)
+    .bind(user.id)
+    .execute(&state.db)
+    .await
+    .map_err(db_error)?;
+    if deleted.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    events::emit(&state, user.id, events::Event::Deleted, id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Serialize)]
+struct Metadata {
+    url: String,
+    title: Option<String>,
+    description: Option<String>,
+    preview_image: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Check {
+    bookmark: Option<Bookmark>,
+    metadata: Metadata,
+    auto_tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct CheckParams {
+    url: String,
+}
+
+/// What the extension shows when it opens on a page: the bookmark if there is one, otherwise
+/// the page's title and description to start from.
+async fn check_url(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Query(params): Query<CheckParams>,
+) -> Result<Json<Check>, StatusCode> {
+    let url = normalize_url(&params.url).unwrap_or_else(|_| params.url.clone());
+    let bookmark: Option<Bookmark> = QueryBuilder::<Postgres>::new(BOOKMARK_SELECT)
+        .push(" WHERE b.user_id = ")
+        .push_bind(user.id)
+        .push(" AND b.url = ")
+        .push_bind(&url)
+        .push(" AND b.deleted_at IS NULL")
+        .build_query_as()
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)?;
+
+    let metadata = match &bookmark {
+        Some(bookmark) => Metadata {
+            url: bookmark.url.clone(),
+            title: bookmark.website_title.clone().or_else(|| Some(bookmark.title.clone())),
+            description: bookmark.website_description.clone(),
+            preview_image: bookmark.preview_image_url.clone(),
+        },
+        None => page_metadata(&state, &url).await.unwrap_or_else(|e| {
+            eprintln!("Linkding Check Error: {}", e);
+            Metadata { url: url.clone(), title: None, description: None, preview_image: None }
+        }),
+    };
+    Ok(Json(Check { bookmark, metadata, auto_tags: Vec::new() }))
+}
+
+/// Fetches a page not bookmarked yet for its title and description.
+async fn page_metadata(state: &AppState, url: &str) -> anyhow::Result<Metadata> {
+    let download = crate::download(state.fetcher.get(url).await?).await?;
+    let site_meta = match download.kind {
+        crate::content::Kind::Html if download.status.is_success() => {
+            scrape_metadata(&String::from_utf8_lossy(&download.body))
+        }
+        _ => Value::Null,
+    };
+    let field = |names: &[&str]| names.iter().find_map(|name| site_meta[name].as_str()).map(str::to_string);
+    Ok(Metadata {
+        url: url.to_string(),
+        title: page_title(&site_meta),
+        description: field(&["og:description", "description", "twitter:description"]),
+        preview_image: field(&["og:image", "twitter:image"]),
+    })
+}
+
+/// A tag in linkding's shape. Tags aren't dated here, so `date_added` is when the first bookmark
+/// with it was saved.
+#[derive(Serialize, sqlx::FromRow)]
+struct Tag {
+    id: Uuid,
+    name: String,
+    date_added: DateTime<Utc>,
+}
+
+const TAG_SELECT: &str = "SELECT t.id, t.name,
+     COALESCE((SELECT min(b.created_at) FROM bookmark_tags bt JOIN bookmarks b ON b.id = bt.bookmark_id
+      WHERE bt.tag_id = t.id), now()) AS date_added
+     FROM tags t";
+
+async fn list_tags(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    headers: HeaderMap,
+    uri: OriginalUri,
+    Query(paging): Query<PageParams>,
+) -> Result<Json<Page<Tag>>, StatusCode> {
+    let total: i64 = sqlx::query_scalar("SELECT count(*) FROM tags WHERE user_id = $1")
+        .bind(user.id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(db_error)?;
+    let tags = QueryBuilder::<Postgres>::new(TAG_SELECT)
+        .push(" WHERE t.user_id = ")
+        .push_bind(user.id)
+        .push(" ORDER BY t.name LIMIT ")
+        .push_bind(paging.limit())
+        .push(" OFFSET ")
+        .push_bind(paging.offset())
+        .build_query_as()
+        .fetch_all(&state.db)
+        .await
+        .map_err(db_error)?;
+    Ok(Json(page(&headers, &uri, &paging, total, tags)))
+}
+
+async fn get_tag(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Tag>, StatusCode> {
+    QueryBuilder::<Postgres>::new(TAG_SELECT)
+        .push(" WHERE t.id = ")
+        .push_bind(id)
+        .push(" AND t.user_id = ")
+        .push_bind(user.id)
+        .build_query_as()
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[derive(Deserialize)]
+struct TagRequest {
+    name: String,
+}
+
+/// Creates a tag, or returns the one the user already has by that name.
+async fn create_tag(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Json(payload): Json<TagRequest>,
+) -> Result<(StatusCode, Json<Tag>), StatusCode> {
+    let name = tag_path(&payload.name).ok_or(StatusCode::BAD_REQUEST)?;
+    let id: Uuid = sqlx::query_scalar(
+        "INSERT INTO tags (user_id, name) VALUES ($1, $2)
+         ON CONFLICT (user_id, name) DO UPDATE SET name = EXCLUDED.name
+         RETURNING id"
+    )
+    .bind(user.id)
+    .bind(&name)
+    .fetch_one(&state.db)
+    .await
+    .map_err(db_error)?;
+    let tag = QueryBuilder::<Postgres>::new(TAG_SELECT)
+        .push(" WHERE t.id = ")
+        .push_bind(id)
+        .build_query_as()
+        .fetch_one(&state.db)
+        .await
+        .map_err(db_error)?;
+    Ok((StatusCode::CREATED, Json(tag)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_search() {
+        assert_eq!(
+            parse_search("rust  #Dev/rust !unread async #"),
+            Search {
+                words: vec!["rust".to_string(), "async".to_string(), "#".to_string()],
+                tags: vec!["Dev/rust".to_string()],
+                unread: true,
+                untagged: false,
+            }
+        );
+        assert_eq!(parse_search(""), Search::default());
+    }
+
+    #[test]
+    fn test_page_url() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, "links.example.com".parse().unwrap());
+        headers.insert("x-forwarded-proto", "https".parse().unwrap());
+        let uri = OriginalUri("/api/bookmarks/?q=rust&limit=2&offset=4".parse().unwrap());
+        assert_eq!(
+            page_url(&headers, &uri, 2, 6).as_deref(),
+            Some("https://links.example.com/api/bookmarks/?q=rust&limit=2&offset=6")
+        );
+
+        let paging = PageParams { limit: Some(2), offset: Some(4) };
+        let page = page(&headers, &uri, &paging, 5, vec![()]);
+        assert_eq!(page.next, None);
+        assert_eq!(
+            page.previous.as_deref(),
+            Some("https://links.example.com/api/bookmarks/?q=rust&limit=2&offset=2")
+        );
+    }
+}
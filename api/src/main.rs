@@ -1,27 +1,133 @@
 use axum::{
-    extract::{Path, Query, Request, State},
-    http::StatusCode,
+    body::Bytes,
+    extract::{ConnectInfo, DefaultBodyLimit, Path, Query, RawQuery, Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
     middleware::{self, Next},
-    response::Response,
-    routing::{delete, get, post},
+    response::{IntoResponse, Response},
+    routing::{get, post},
     Extension, Json, Router,
 };
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use sqlx::migrate::Migrator;
 use sqlx::postgres::PgPoolOptions;
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, QueryBuilder};
 use rig::providers::openai;
 use schemars::JsonSchema;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use dotenvy::dotenv;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use scraper::{Html, Selector};
 use serde_json::{json, Value};
+use clap::{Parser, Subcommand};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use tokio::sync::{broadcast, Notify, Semaphore};
+
+mod accounts;
+mod admin;
+mod api_keys;
+mod archive;
+mod audit;
+mod backup;
+mod changes;
+mod client;
+mod collections;
+mod config;
+mod content;
+mod credentials;
+mod details;
+mod digests;
+mod discord;
+mod embeddings;
+mod etag;
+mod events;
+mod feed;
+mod fetcher;
+mod github;
+mod health;
+mod graphql;
+mod hackernews;
+mod import;
+mod instapaper;
+mod jobs;
+mod karakeep;
+mod language;
+mod link_check;
+mod linkding;
+mod mastodon;
+mod metrics;
+mod netscape;
+mod normalize;
+mod oembed;
+mod omnivore;
+mod pinboard;
+mod pocket;
+mod public;
+mod quick_add;
+mod raindrop;
+mod rate_limit;
+mod reader;
+mod recrawl;
+mod reddit;
+mod retry;
+mod robots;
+mod screenshot;
+mod searches;
+mod shaarli;
+mod slack;
+mod share;
+mod spreadsheet;
+mod sqlite;
+mod ssrf;
+mod stats;
+mod suggestions;
+mod tagging;
+mod tags;
+mod tls;
+mod trash;
+mod ui;
+mod wallabag;
+mod wayback;
+mod webhooks;
 
 #[derive(Clone)]
 struct AppState {
     db: PgPool,
     openai: Arc<openai::Client>,
-    model: String,
+    tagger: Arc<dyn tagging::Tagger>,
+    embedding_model: Option<String>,
+    archive: Option<Arc<archive::Archive>>,
+    screenshots: Option<Arc<screenshot::ScreenshotService>>,
+    resolve_canonical: bool,
+    shaarli_api: bool,
+    /// Whether the session cookie is marked `Secure`
+    secure_cookies: bool,
+    events: broadcast::Sender<events::Change>,
+    rate_limiter: Option<Arc<rate_limit::RateLimiter>>,
+    metrics: Arc<metrics::Metrics>,
+    ai_check: Option<Arc<health::AiCheck>>,
+    /// Tells the job worker something was queued
+    job_wakeup: Arc<Notify>,
+    /// Downloads pages politely, `FETCH_CONCURRENCY` at a time
+    fetcher: Arc<fetcher::Fetcher>,
+    /// Makes webhook deliveries, held to the fetcher's `ssrf` guard
+    webhook_client: reqwest::Client,
+    /// Seals the logins pages are fetched with; they can't be stored without it
+    credential_key: Option<Arc<credentials::Key>>,
+    /// Where pages that have gone are looked for instead, unless `WAYBACK_FALLBACK` is off
+    wayback: Option<Arc<wayback::Wayback>>,
+    /// Asks the Wayback Machine to capture pages, for every bookmark if `wayback_every_bookmark`
+    save_page_now: Arc<wayback::SavePageNow>,
+    wayback_every_bookmark: bool,
+    /// `CONTENT_CHANGE_THRESHOLD`
+    change_threshold: f64,
+    /// Held for every call to the language or embedding model
+    ai_limit: Arc<Semaphore>,
+    /// The Slack app, when `SLACK_SIGNING_SECRET` is set
+    slack: Option<Arc<slack::Slack>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
@@ -30,64 +136,499 @@ struct CurrentUser {
     username: String,
 }
 
+/// The API token, or login session, a request was authenticated with.
+#[derive(Debug, Clone)]
+struct ApiKey {
+    token: Uuid,
+    /// The key's public id; sessions have none
+    id: Option<Uuid>,
+    scopes: Vec<String>,
+    /// What bookmarks saved with this key are tagged with by default
+    tag_mode: TagMode,
+}
+
+/// How the model's tags combine with the ones the user gave a bookmark.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "tag_mode", rename_all = "lowercase")]
+enum TagMode {
+    /// Alongside the user's tags; only the model's previous tags are replaced
+    #[default]
+    Merge,
+    /// Instead of every tag the bookmark had
+    Replace,
+}
+
+const SCOPES: [&str; 2] = ["read", "write"];
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+impl ApiKey {
+    fn allows(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+
+    /// Holds every scope, as sessions do.
+    fn is_full(&self) -> bool {
+        SCOPES.iter().all(|scope| self.allows(scope))
+    }
+}
+
+#[derive(Parser)]
+#[command(name = "linkman", about = "AI-assisted bookmark sync and organization")]
+struct Cli {
+    /// TOML configuration file; environment variables override what it sets
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Address to serve on, `host:port` or `unix:/path/to.sock`; overrides the config
+    #[arg(long, global = true)]
+    bind: Option<config::Listen>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the HTTP API (the default when no command is given)
+    Serve,
+    /// Import an export file from a browser or another bookmarking service
+    Import {
+        #[arg(long, value_enum, default_value_t = ImportFormat::Netscape)]
+        format: ImportFormat,
+        /// Username that will own the imported bookmarks
+        #[arg(long)]
+        user: String,
+        /// For CSV, which headers hold which fields, e.g. `url=Link,tags=Labels`
+        #[arg(long)]
+        columns: Option<String>,
+        /// List what would be created and what would be skipped, saving nothing
+        #[arg(long)]
+        dry_run: bool,
+        file: PathBuf,
+    },
+    /// Write everything a user has saved to a gzipped JSON file, for `restore` on another server
+    Backup {
+        #[arg(long)]
+        user: String,
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Add a file written by `backup` to a user's bookmarks
+    Restore {
+        /// Username that will own the restored bookmarks
+        #[arg(long)]
+        user: String,
+        file: PathBuf,
+    },
+    /// Compute embeddings for bookmarks saved before semantic search was enabled
+    BackfillEmbeddings,
+    /// Revoke an API token so it stops working immediately
+    RevokeApiKey {
+        /// The key's id or the token itself
+        key: Uuid,
+    },
+    /// Replace an API token with a new one for the same device, revoking the old one
+    RotateApiKey {
+        /// The key's id or the token itself
+        key: Uuid,
+    },
+    /// Bookmark a URL through a running server, the one `LINKMAN_URL` (or `[client]` in the
+    /// config) points at, with the API key in `LINKMAN_TOKEN`
+    Add {
+        url: String,
+        #[arg(long)]
+        title: Option<String>,
+        #[arg(long)]
+        notes: Option<String>,
+        /// Comma-separated, e.g. `rust,to-read`
+        #[arg(long)]
+        tags: Option<String>,
+    },
+    /// List bookmarks through a running server, as `GET /bookmarks` does, newest first
+    List {
+        #[command(flatten)]
+        filter: SearchParams,
+        /// How many to show at most
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+        /// Print the bookmarks as the API returns them instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Search bookmarks through a running server, with the same filters as `list`
+    Search {
+        /// Matched against the URL, title, notes, summary and tags; `site:lobste.rs` limits it to a site
+        query: String,
+        /// Rank by meaning instead, if the server has an embedding model
+        #[arg(long)]
+        semantic: bool,
+        #[command(flatten)]
+        filter: SearchParams,
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+        #[arg(long)]
+        json: bool,
+    },
+    /// Give a user access to the `/admin` endpoints
+    SetAdmin {
+        username: String,
+        /// Take the access away instead
+        #[arg(long)]
+        revoke: bool,
+    },
+}
+
 #[tokio::main]
-async fn main() {
+async fn main() -> anyhow::Result<()> {
     dotenv().ok();
+    let cli = Cli::parse();
+    // These need neither the database nor the server's settings
+    match cli.command {
+        Some(Command::Add { url, title, notes, tags }) => {
+            let client = config::ClientConfig::load(cli.config.as_deref())?;
+            return client::add(&client, url, title, notes, tags.as_deref()).await;
+        }
+        Some(Command::List { filter, limit, json }) => {
+            let client = config::ClientConfig::load(cli.config.as_deref())?;
+            return client::list(&client, &filter, limit, json).await;
+        }
+        Some(Command::Search { query, semantic, mut filter, limit, json }) => {
+            if semantic {
+                filter.semantic = Some(query);
+            } else {
+                filter.q = Some(query);
+            }
+            let client = config::ClientConfig::load(cli.config.as_deref())?;
+            return client::list(&client, &filter, limit, json).await;
+        }
+        _ => {}
+    }
+    let config = config::Config::load(cli.config.as_deref(), cli.bind.clone())?;
+
+    // SQLite serves the core of the API on its own schema; the other commands need Postgres
+    if sqlite::is_url(&config.database.url) {
+        return match cli.command.unwrap_or(Command::Serve) {
+            Command::Serve => sqlite::serve(&config).await,
+            _ => anyhow::bail!("this command needs a Postgres DATABASE_URL"),
+        };
+    }
 
-    let database_url = std::env::var("DATABASE_URL")
-        .expect("DATABASE_URL must be set in .env");
-    
     let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
+        .max_connections(config.database.max_connections)
+        .connect(&config.database.url)
         .await
         .expect("Failed to connect to Postgres");
 
     // Run migrations
-    sqlx::migrate!("./migrations")
+    MIGRATOR
         .run(&pool)
         .await
         .expect("Failed to run database migrations");
 
-    let openai_api_key = std::env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY must be set");
-    let openai_api_base = std::env::var("OPENAI_API_BASE").unwrap_or_else(|_| "https://api.openai.com/v1".to_string());
-    let model = std::env::var("LLM_MODEL").unwrap_or_else(|_| "user.gemma-4-26B-A4B-it-GGUF".to_string());
+    if config.ai.embedding_model.is_some() {
+        embeddings::ensure_available(&pool).await?;
+    }
 
-    // Initialize Rig OpenAI client using builder
+    // Initialize Rig OpenAI client using builder; tagging has its own, for whichever provider
     let openai_client = openai::Client::builder()
-        .api_key(&openai_api_key)
-        .base_url(&openai_api_base)
+        .api_key(&config.ai.api_key)
+        .base_url(config.ai.api_base())
         .build()
         .expect("Failed to create OpenAI client");
+    let metrics = Arc::new(metrics::Metrics::from_config(&config.metrics));
+    let retry = retry::Policy::from_config(&config.retry);
+    let fetcher = Arc::new(fetcher::Fetcher::from_config(&config.fetch, retry.clone(), config.jobs.fetch_concurrency)?);
 
     let state = AppState {
         db: pool,
         openai: Arc::new(openai_client),
-        model,
+        tagger: tagging::from_config(&config.ai, &config.tagging, retry, metrics.clone())?,
+        embedding_model: config.ai.embedding_model.clone(),
+        archive: archive::Archive::from_config(&config.archive)?.map(Arc::new),
+        screenshots: screenshot::ScreenshotService::from_config(&config.screenshots)?.map(Arc::new),
+        resolve_canonical: config.resolve_canonical_urls,
+        shaarli_api: config.shaarli_api,
+        secure_cookies: config.secure_cookies.unwrap_or(config.tls.cert.is_some()),
+        events: events::channel(),
+        rate_limiter: rate_limit::RateLimiter::from_config(&config.rate_limit).map(Arc::new),
+        metrics,
+        ai_check: health::AiCheck::from_config(&config)?.map(Arc::new),
+        job_wakeup: Arc::new(Notify::new()),
+        webhook_client: webhooks::client(fetcher.guard())?,
+        fetcher,
+        credential_key: credentials::Key::from_config(&config.fetch).map(Arc::new),
+        wayback: wayback::Wayback::from_config(&config.fetch).map(Arc::new),
+        save_page_now: Arc::new(wayback::SavePageNow::from_config(&config.archive)?),
+        wayback_every_bookmark: config.archive.wayback,
+        change_threshold: config.recrawl.change_threshold,
+        ai_limit: Arc::new(Semaphore::new(config.jobs.ai_concurrency)),
+        slack: slack::Slack::from_config(&config.slack)?.map(Arc::new),
+    };
+
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(state, &config).await,
+        Command::Add { .. } | Command::List { .. } | Command::Search { .. } => {
+            unreachable!("client commands run before connecting to the database")
+        }
+        Command::Import { format, user, columns, dry_run, file } => {
+            import_file(state, format, &user, columns.as_deref(), dry_run, &file).await
+        }
+        Command::Backup { user, out } => {
+            let backup = backup::create(&state.db, user_id(&state.db, &user).await?).await?;
+            std::fs::write(&out, backup::encode(&backup)?)?;
+            println!("wrote {}", out.display());
+            Ok(())
+        }
+        Command::Restore { user, file } => {
+            let backup = backup::decode(&std::fs::read(&file)?)?;
+            let summary = backup::restore(&state, user_id(&state.db, &user).await?, backup, &SCOPES.map(String::from)).await?;
+            println!(
+                "restored {} bookmarks, skipped {} already saved, {} collections, {} saved searches and {} API keys",
+                summary.bookmarks, summary.skipped, summary.collections, summary.saved_searches, summary.api_keys
+            );
+            if summary.api_keys > 0 {
+                println!("restored keys have new tokens; rotate one to get its token");
+            }
+            Ok(())
+        }
+        Command::BackfillEmbeddings => {
+            let embedded = embeddings::backfill(&state).await?;
+            println!("embedded {} bookmarks", embedded);
+            Ok(())
+        }
+        Command::RevokeApiKey { key } => {
+            if !api_keys::revoke(&state.db, None, key).await? {
+                anyhow::bail!("no live API key {}", key);
+            }
+            println!("revoked");
+            Ok(())
+        }
+        Command::RotateApiKey { key } => {
+            let token = api_keys::rotate(&state.db, None, key, &SCOPES.map(String::from))
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("no live API key {}", key))?;
+            println!("{}", token);
+            Ok(())
+        }
+        Command::SetAdmin { username, revoke } => {
+            let updated = sqlx::query("UPDATE users SET is_admin = $1 WHERE username = $2")
+                .bind(!revoke)
+                .bind(&username)
+                .execute(&state.db)
+                .await?;
+            if updated.rows_affected() == 0 {
+                anyhow::bail!("no such user {:?}", username);
+            }
+            println!("done");
+            Ok(())
+        }
+    }
+}
+
+async fn serve(state: AppState, config: &config::Config) -> anyhow::Result<()> {
+    if let Some(link_check) = link_check::Config::from_config(&config.link_check) {
+        tokio::spawn(link_check::run(state.clone(), link_check));
+    }
+    if let Some(recrawl) = recrawl::Config::from_config(&config.recrawl) {
+        tokio::spawn(recrawl::run(state.clone(), recrawl));
+    }
+    if let Some(trash) = trash::Config::from_config(&config.trash) {
+        tokio::spawn(trash::run(state.clone(), trash));
+    }
+    if let Some(github) = github::Config::from_config(&config.github)? {
+        tokio::spawn(github::run(state.clone(), github));
+    }
+    if let Some(mastodon) = mastodon::Config::from_config(&config.mastodon)? {
+        tokio::spawn(mastodon::run(state.clone(), mastodon));
+    }
+    if let Some(discord) = discord::Config::from_config(&config.discord)? {
+        tokio::spawn(discord::run(state.clone(), discord));
+    }
+    if let Some(digests) = digests::Config::from_config(&config.digests) {
+        tokio::spawn(digests::run(state.clone(), digests));
+    }
+    tokio::spawn(jobs::run(state.clone(), config.jobs.concurrency));
+    listen(app(state), config).await
+}
+
+/// Serves `app` where the config says to, with TLS if it's configured.
+async fn listen(app: Router, config: &config::Config) -> anyhow::Result<()> {
+    let addr = match &config.listen {
+        config::Listen::Tcp(addr) => *addr,
+        config::Listen::Unix(path) => {
+            let mode = config.socket_permissions().expect("socket_mode is validated on load");
+            return serve_unix(path, mode, app).await;
+        }
     };
+    if let Some(tls) = tls::Tls::from_config(&config.tls) {
+        return tls.serve(addr, app).await;
+    }
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    println!("listening on {}", listener.local_addr()?);
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
+    Ok(())
+}
+
+/// CLI counterpart of `POST /bookmarks/import`; waits for enrichment to finish before exiting.
+async fn import_file(
+    state: AppState,
+    format: ImportFormat,
+    username: &str,
+    columns: Option<&str>,
+    dry_run: bool,
+    file: &std::path::Path,
+) -> anyhow::Result<()> {
+    let content = std::fs::read(file)?;
+    let bookmarks = format.parse(&content, columns)?;
+    let user_id = user_id(&state.db, username).await?;
+
+    if dry_run {
+        let preview = import::preview(&state, user_id, &bookmarks).await?;
+        for url in &preview.new {
+            println!("would import {}", url);
+        }
+        println!(
+            "would import {}, skip {} already saved, {} repeated in the file and {} that aren't URLs",
+            preview.new.len(),
+            preview.saved,
+            preview.repeated,
+            preview.invalid
+        );
+        return Ok(());
+    }
+
+    // Saved a chunk at a time, each in its own transaction, so a large file shows progress and
+    // its first bookmarks are being processed while the rest go in. Running it again after a
+    // failure skips what was already saved.
+    let total = bookmarks.len();
+    let (mut imported, mut skipped) = (0, 0);
+    let mut chunks = bookmarks.into_iter().peekable();
+    while chunks.peek().is_some() {
+        let chunk: Vec<_> = chunks.by_ref().take(IMPORT_CHUNK).collect();
+        let summary = import::save(&state, user_id, chunk).await?;
+        imported += summary.imported;
+        skipped += summary.skipped;
+        if total > IMPORT_CHUNK {
+            println!("{}/{}: imported {}, skipped {}", imported + skipped, total, imported, skipped);
+        }
+    }
+    println!(
+        "imported {}, skipped {}; the server will fetch and tag them in the background",
+        imported, skipped
+    );
+    Ok(())
+}
+
+/// A user's id, for the commands that act on their bookmarks.
+async fn user_id(db: &PgPool, username: &str) -> anyhow::Result<Uuid> {
+    sqlx::query_scalar("SELECT id FROM users WHERE username = $1")
+        .bind(username)
+        .fetch_optional(db)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("no such user {:?}; register a device first", username))
+}
 
-    let app = app(state);
+/// For a reverse proxy on the same machine. There's no client address to record on a socket.
+#[cfg(unix)]
+async fn serve_unix(path: &std::path::Path, mode: u32, app: Router) -> anyhow::Result<()> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    // A socket left behind by an earlier run would make the bind fail
+    if std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+    let listener = tokio::net::UnixListener::bind(path)
+        .map_err(|e| anyhow::anyhow!("Can't bind {}: {}", path.display(), e))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+
+    println!("listening on unix:{}", path.display());
+    axum::serve(listener, app.into_make_service()).await?;
+    Ok(())
+}
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    println!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+#[cfg(not(unix))]
+async fn serve_unix(_path: &std::path::Path, _mode: u32, _app: Router) -> anyhow::Result<()> {
+    anyhow::bail!("Unix sockets aren't available on this platform")
 }
 
 fn app(state: AppState) -> Router {
     // Protected routes
     let api_routes = Router::new()
-        .route("/bookmarks/sync", post(sync_bookmark))
+        .route("/bookmarks", get(list_bookmarks))
+        .route("/bookmarks/sync", get(sync_changes).post(sync_bookmark))
+        .route("/bookmarks/export", get(export_bookmarks))
+        .route(
+            "/bookmarks/import",
+            post(import_bookmarks).layer(DefaultBodyLimit::max(IMPORT_BODY_LIMIT)),
+        )
         .route("/bookmarks/search", get(search_bookmarks))
         .route("/bookmarks/suggest-folders", post(suggest_folders))
-        .route("/bookmarks/:id", delete(delete_bookmark))
+        .route("/bookmarks/trash", get(list_trash))
+        .route("/bookmarks/bulk-delete", post(bulk_delete_bookmarks))
+        .route("/bookmarks/reprocess", post(bulk_reprocess_bookmarks))
+        .route("/bookmarks/batch", post(batch_bookmarks))
+        .route("/bookmarks/check", post(check_bookmarks))
+        .route("/bookmarks/{id}", get(get_bookmark).patch(update_bookmark).delete(delete_bookmark))
+        .route("/bookmarks/{id}/content", get(get_bookmark_content))
+        .route("/bookmarks/{id}/reader", get(get_bookmark_reader))
+        .route("/bookmarks/{id}/archive", get(get_bookmark_archive))
+        .route("/bookmarks/{id}/archive-external", post(archive_externally))
+        .route("/bookmarks/{id}/screenshot", get(get_bookmark_screenshot))
+        .route("/go/{id}", get(visit_bookmark))
+        .route("/bookmarks/{id}/restore", post(restore_bookmark))
+        .route("/bookmarks/{id}/read", post(toggle_read))
+        .route("/bookmarks/{id}/favorite", post(toggle_favorite))
+        .route("/bookmarks/{id}/ai-tags", post(retag_bookmark).delete(discard_ai_tags))
         .route("/health", get(health_check))
+        .merge(events::router())
+        .merge(collections::router())
+        .merge(tags::router())
+        .merge(searches::router())
+        .merge(stats::router())
+        .merge(digests::router())
+        .merge(suggestions::router())
+        .merge(feed::router())
+        .merge(share::router())
+        .merge(jobs::router())
+        .merge(webhooks::router())
+        .merge(credentials::router())
+        .merge(changes::router())
+        .merge(backup::router())
+        .merge(graphql::router())
+        .merge(api_keys::router())
+        .merge(accounts::router())
+        .merge(admin::router(state.clone()))
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit::middleware))
         .layer(middleware::from_fn_with_state(state.clone(), auth_middleware));
 
     // Public routes (Admin for token generation)
-    Router::new()
+    let mut routes = Router::new()
         .route("/", get(hello))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/metrics", get(metrics::export))
         .route("/admin/register", post(register_user))
+        .route("/auth/signup", post(accounts::signup))
+        .route("/auth/login", post(accounts::login))
+        .route("/auth/logout", post(accounts::logout))
+        .route("/feeds/bookmarks.atom", get(feed::bookmarks_feed))
+        .route("/share/{token}", get(share::shared_bookmark))
+        .route("/public/{username}", get(public::profile))
+        .route("/share/{token}/archive", get(share::shared_archive))
         .merge(api_routes)
+        .merge(quick_add::router(state.clone()))
+        .merge(ui::router(state.clone()))
+        .nest("/pinboard/v1", pinboard::router(state.clone()))
+        .nest("/api", linkding::router(state.clone()));
+    if state.shaarli_api {
+        routes = routes.nest("/shaarli/{username}/api/v1", shaarli::router(state.clone()));
+    }
+    if state.slack.is_some() {
+        routes = routes.merge(slack::router(state.clone()));
+    }
+    routes
+        .layer(middleware::from_fn_with_state(state.clone(), metrics::middleware))
         .with_state(state)
 }
 
@@ -97,25 +638,64 @@ async fn auth_middleware(
     next: Next,
 ) -> Result<Response, StatusCode> {
     let auth_header = req.headers()
-        .get(axum::http::header::AUTHORIZATION)
-        .and_then(|header| header.to_str().ok());
+        .get(header::AUTHORIZATION)
+        .map(|header| header.to_str().ok());
 
-    let token_str = if let Some(auth_header) = auth_header {
-        if auth_header.starts_with("Bearer ") {
-            &auth_header[7..]
-        } else {
-            return Err(StatusCode::UNAUTHORIZED);
+    // Browsers logged in with a password send a session cookie instead
+    let (user, key) = match auth_header {
+        Some(header) => {
+            let token_str = header
+                .and_then(|header| header.strip_prefix("Bearer "))
+                .ok_or(StatusCode::UNAUTHORIZED)?;
+            authenticate(&state, token_str, client_ip(&req)).await?
+        }
+        None => {
+            if !req.method().is_safe() && accounts::cross_site(req.headers()) {
+                return Err(StatusCode::FORBIDDEN);
+            }
+            accounts::authenticate_session(&state, req.headers()).await?
         }
-    } else {
-        return Err(StatusCode::UNAUTHORIZED);
     };
 
+    let scope = required_scope(req.method(), req.uri().path());
+    if !key.allows(scope) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    req.extensions_mut().insert(user);
+    req.extensions_mut().insert(key);
+    if scope == "write" {
+        return Ok(audit::record(&state, req, next).await);
+    }
+    Ok(next.run(req).await)
+}
+
+/// The peer address; behind a reverse proxy this is the proxy's.
+fn client_ip(req: &Request) -> Option<IpAddr> {
+    req.extensions().get::<ConnectInfo<SocketAddr>>().map(|info| info.0.ip())
+}
+
+/// Safe methods only read; GraphQL and checking which URLs are saved are read-only too, whatever
+/// the method.
+fn required_scope(method: &Method, path: &str) -> &'static str {
+    if method.is_safe() || path == "/graphql" || path == "/bookmarks/check" { "read" } else { "write" }
+}
+
+/// Resolves an API token to its owner, for every auth scheme the server accepts.
+/// Also records the use on the token, for spotting stale ones.
+async fn authenticate(state: &AppState, token_str: &str, ip: Option<IpAddr>) -> Result<(CurrentUser, ApiKey), StatusCode> {
     let token_uuid = Uuid::parse_str(token_str).map_err(|_| StatusCode::UNAUTHORIZED)?;
 
-    let user = sqlx::query_as::<_, CurrentUser>(
-        "SELECT u.id, u.username FROM users u JOIN api_tokens t ON u.id = t.user_id WHERE t.token = $1"
+    let (id, username, key_id, scopes, tag_mode): (Uuid, String, Uuid, Vec<String>, TagMode) = sqlx::query_as(
+        "UPDATE api_tokens t SET last_used_at = now(), request_count = t.request_count + 1,
+             last_ip = COALESCE($2, t.last_ip)
+         FROM users u
+         WHERE u.id = t.user_id AND t.token = $1
+             AND t.revoked_at IS NULL AND (t.expires_at IS NULL OR t.expires_at > now())
+         RETURNING u.id, u.username, t.id, t.scopes, t.tag_mode"
     )
     .bind(token_uuid)
+    .bind(ip.map(|ip| ip.to_string()))
     .fetch_optional(&state.db)
     .await
     .map_err(|e| {
@@ -124,8 +704,7 @@ async fn auth_middleware(
     })?
     .ok_or(StatusCode::UNAUTHORIZED)?;
 
-    req.extensions_mut().insert(user);
-    Ok(next.run(req).await)
+    Ok((CurrentUser { id, username }, ApiKey { token: token_uuid, id: Some(key_id), scopes, tag_mode }))
 }
 
 async fn hello() -> &'static str {
@@ -136,6 +715,12 @@ async fn hello() -> &'static str {
 struct RegisterRequest {
     username: String,
     device_name: String,
+    /// Any of `read` and `write`; full access when omitted
+    scopes: Option<Vec<String>>,
+    /// The token never expires when omitted
+    expires_at: Option<DateTime<Utc>>,
+    /// Required for an account that already exists
+    password: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -147,24 +732,41 @@ async fn register_user(
     State(state): State<AppState>,
     Json(payload): Json<RegisterRequest>,
 ) -> Result<Json<RegisterResponse>, StatusCode> {
+    let scopes = payload.scopes.unwrap_or_else(|| SCOPES.map(String::from).to_vec());
+    if scopes.is_empty() || !scopes.iter().all(|s| SCOPES.contains(&s.as_str())) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
     let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let user_id: Uuid = sqlx::query_scalar(
-        "INSERT INTO users (username) VALUES ($1) ON CONFLICT (username) DO UPDATE SET username = EXCLUDED.username RETURNING id"
-    )
-    .bind(&payload.username)
-    .fetch_one(&mut *tx)
-    .await
-    .map_err(|e| {
+    let db_error = |e: sqlx::Error| {
         eprintln!("Register User Error: {}", e);
         StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    };
+    let created: Option<Uuid> =
+        sqlx::query_scalar("INSERT INTO users (username) VALUES ($1) ON CONFLICT (username) DO NOTHING RETURNING id")
+            .bind(&payload.username)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(db_error)?;
+
+    // Anyone can call this, so an existing account has to prove it's theirs. One without a password
+    // sets one with `PUT /auth/password` first, using a token it already has.
+    let user_id = match created {
+        Some(id) => id,
+        None => {
+            let user = accounts::check_password(&state, &payload.username, payload.password.unwrap_or_default()).await?;
+            user.id
+        }
+    };
 
     let token: Uuid = sqlx::query_scalar(
-        "INSERT INTO api_tokens (user_id, device_name) VALUES ($1, $2) RETURNING token"
+        "INSERT INTO api_tokens (user_id, device_name, scopes, expires_at) VALUES ($1, $2, $3, $4) RETURNING token"
     )
     .bind(user_id)
     .bind(&payload.device_name)
+    .bind(&scopes)
+    .bind(payload.expires_at)
     .fetch_one(&mut *tx)
     .await
     .map_err(|e| {
@@ -181,238 +783,2033 @@ async fn register_user(
 struct SyncBookmarkRequest {
     url: String,
     title: Option<String>,
+    notes: Option<String>,
+    /// Added to whatever tags the bookmark already has
+    tags: Option<Vec<String>>,
+    /// Defaults to the key's
+    tag_mode: Option<TagMode>,
 }
 
+/// Saves a bookmark and answers with it: `201 Created` for a new one, `200 OK` when the URL was
+/// already saved and has been updated.
 async fn sync_bookmark(
     State(state): State<AppState>,
     Extension(user): Extension<CurrentUser>,
+    Extension(key): Extension<ApiKey>,
     Json(payload): Json<SyncBookmarkRequest>,
-) -> Result<StatusCode, StatusCode> {
-    let bookmark_id: Uuid = sqlx::query_scalar(
-        "INSERT INTO bookmarks (user_id, url, title) VALUES ($1, $2, $3) 
-         ON CONFLICT (user_id, url) DO UPDATE SET title = EXCLUDED.title, updated_at = now() 
-         RETURNING id"
-    )
-    .bind(user.id)
-    .bind(&payload.url)
-    .bind(&payload.title)
-    .fetch_one(&state.db)
-    .await
-    .map_err(|e| {
+) -> Result<(StatusCode, Json<BookmarkResponse>), StatusCode> {
+    let url = normalize::normalize_url(&payload.url).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let db_error = |e: sqlx::Error| {
         eprintln!("Sync Bookmark Error: {}", e);
         StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-
+    };
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    let (bookmark_id, inserted) = upsert_bookmark(&mut tx, user.id, key.tag_mode, &url, &payload).await.map_err(db_error)?;
     // Trigger Phase 2 (Async AI enrichment)
-    let url = payload.url.clone();
-    let user_id = user.id;
-    tokio::spawn(async move {
-        if let Err(e) = process_bookmark(state, user_id, bookmark_id, url).await {
-            eprintln!("Error processing bookmark {}: {}", bookmark_id, e);
-        }
-    });
-    
-    Ok(StatusCode::OK)
-}
+    jobs::queue(&mut *tx, &[bookmark_id]).await.map_err(db_error)?;
+    let bookmark = fetch_bookmark(&mut *tx, user.id, bookmark_id)
+        .await
+        .map_err(db_error)?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit().await.map_err(db_error)?;
+    state.job_wakeup.notify_one();
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
-struct AiEnrichmentResponse {
-    summary: String,
-    tags: Vec<String>,
+    let event = if inserted { events::Event::Created } else { events::Event::Updated };
+    events::emit(&state, user.id, event, bookmark_id);
+
+    let status = if inserted { StatusCode::CREATED } else { StatusCode::OK };
+    Ok((status, Json(bookmark)))
 }
 
-fn scrape_metadata(html_content: &str) -> Value {
-    let document = Html::parse_document(html_content);
-    let mut site_meta = json!({
-        "scraped_at": chrono::Utc::now().to_rfc3339()
-    });
-    
-    // Scrape <title>
-    let title_selector = Selector::parse("title").unwrap();
-    if let Some(title_elem) = document.select(&title_selector).next() {
-        let title = title_elem.text().collect::<Vec<_>>().join(" ");
-        site_meta["title"] = json!(title.chars().take(300).collect::<String>());
-    }
+/// Saves a bookmark, or updates the one already at `url`; `true` if it's new.
+async fn upsert_bookmark(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+    default_tag_mode: TagMode,
+    url: &str,
+    payload: &SyncBookmarkRequest,
+) -> Result<(Uuid, bool), sqlx::Error> {
+    // xmax is only zero for rows this statement inserted
+    let (bookmark_id, inserted): (Uuid, bool) = sqlx::query_as(
+        "INSERT INTO bookmarks (user_id, url, title, notes, tag_mode) VALUES ($1, $2, $3, $4, $5) 
+         ON CONFLICT (user_id, url) DO UPDATE SET title = COALESCE(EXCLUDED.title, bookmarks.title),
+             title_from_page = bookmarks.title_from_page AND EXCLUDED.title IS NULL,
+             notes = COALESCE(EXCLUDED.notes, bookmarks.notes), tag_mode = EXCLUDED.tag_mode,
+             updated_at = now(), deleted_at = NULL
+         RETURNING id, xmax = 0"
+    )
+    .bind(user_id)
+    .bind(url)
+    .bind(&payload.title)
+    .bind(&payload.notes)
+    .bind(payload.tag_mode.unwrap_or(default_tag_mode))
+    .fetch_one(&mut **tx)
+    .await?;
 
-    // Scrape only essential meta tags to avoid flooding context
-    let meta_selector = Selector::parse("meta").unwrap();
-    let important_names = ["description", "keywords", "og:title", "og:description", "og:site_name", "twitter:title", "twitter:description"];
-    
-    for element in document.select(&meta_selector) {
-        let name = element.value().attr("name").or_else(|| element.value().attr("property"));
-        let content = element.value().attr("content");
-        
-        if let (Some(n), Some(c)) = (name, content) {
-            if important_names.contains(&n) {
-                // Truncate long content to 1500 chars to save context
-                let truncated = c.chars().take(1500).collect::<String>();
-                site_meta[n] = json!(truncated);
-            }
-        }
+    if let Some(tags) = &payload.tags {
+        attach_tags(tx, user_id, bookmark_id, tags, TagSource::User).await?;
     }
-    site_meta
+    Ok((bookmark_id, inserted))
 }
 
-async fn process_bookmark(state: AppState, user_id: Uuid, bookmark_id: Uuid, url: String) -> anyhow::Result<()> {
-    // 1. Fetch and Scrape
-    let client = reqwest::Client::new();
-    let res = client.get(&url).send().await?.text().await?;
-    
-    // Perform scraping in a scope to ensure non-Send types are dropped
-    let site_meta = scrape_metadata(&res);
-
-    // 2. AI Enrichment using Rig
-    let extractor = state.openai
-        .extractor::<AiEnrichmentResponse>(&state.model)
-        .preamble("You are a semantic analysis agent. Your sole task is to extract exactly 5 descriptive tags from the provided input by identifying its core domains, specific technologies, and intent.\n\nRules:\n\nOutput exactly 5 tags.\n\nOrder them from most specific/relevant to most general.\n\nNormalize tags to lowercase with hyphens for spaces.\n\nExtract only what is explicitly stated or strongly implied.")
-        .additional_params(json!({ "enable_thinking": false }))
-        .build();
-
-    let ai_data = extractor.extract(&site_meta.to_string()).await.map_err(|e| anyhow::anyhow!("Rig extraction error: {}", e))?;
+/// The most bookmarks `POST /bookmarks/batch` takes at once.
+const MAX_BATCH_BOOKMARKS: usize = 200;
 
-    // 3. Update Database
-    let mut tx = state.db.begin().await?;
+#[derive(Deserialize)]
+struct BatchBookmarkRequest {
+    bookmarks: Vec<SyncBookmarkRequest>,
+}
 
-    sqlx::query(
-        "UPDATE bookmarks SET site_meta = $1, ai_summary = $2 WHERE id = $3"
-    )
-    .bind(&site_meta)
-    .bind(&ai_data.summary)
-    .bind(bookmark_id)
-    .execute(&mut *tx)
-    .await?;
+#[derive(Serialize)]
+struct BatchBookmarkResult {
+    /// As it was sent
+    url: String,
+    id: Option<Uuid>,
+    /// `created`, `updated`, or `invalid` for a URL that couldn't be saved
+    status: &'static str,
+}
 
-    for tag_name in ai_data.tags {
-        let tag_id: Uuid = sqlx::query_scalar(
-            "INSERT INTO tags (user_id, name) VALUES ($1, $2) 
-             ON CONFLICT (user_id, name) DO UPDATE SET name = EXCLUDED.name 
-             RETURNING id"
-        )
-        .bind(user_id)
-        .bind(&tag_name)
-        .fetch_one(&mut *tx)
-        .await?;
+/// Saves many bookmarks at once, like a browser's open tabs, all or nothing. Processing is queued
+/// for all of them together and works through them at the job queue's usual pace.
+async fn batch_bookmarks(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Extension(key): Extension<ApiKey>,
+    Json(payload): Json<BatchBookmarkRequest>,
+) -> Result<Json<Vec<BatchBookmarkResult>>, StatusCode> {
+    if payload.bookmarks.len() > MAX_BATCH_BOOKMARKS {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Batch Bookmarks Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
 
-        sqlx::query(
-            "INSERT INTO bookmark_tags (bookmark_id, tag_id) VALUES ($1, $2) 
-             ON CONFLICT DO NOTHING"
-        )
-        .bind(bookmark_id)
-        .bind(tag_id)
-        .execute(&mut *tx)
-        .await?;
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    let mut results = Vec::with_capacity(payload.bookmarks.len());
+    let mut saved = Vec::new();
+    for bookmark in &payload.bookmarks {
+        let Ok(url) = normalize::normalize_url(&bookmark.url) else {
+            results.push(BatchBookmarkResult { url: bookmark.url.clone(), id: None, status: "invalid" });
+            continue;
+        };
+        let (id, inserted) = upsert_bookmark(&mut tx, user.id, key.tag_mode, &url, bookmark).await.map_err(db_error)?;
+        let status = if inserted { "created" } else { "updated" };
+        results.push(BatchBookmarkResult { url: bookmark.url.clone(), id: Some(id), status });
+        saved.push((id, inserted));
     }
+    let ids: Vec<Uuid> = saved.iter().map(|&(id, _)| id).collect();
+    jobs::queue(&mut *tx, &ids).await.map_err(db_error)?;
+    tx.commit().await.map_err(db_error)?;
+    state.job_wakeup.notify_one();
 
-    tx.commit().await?;
+    for &(id, inserted) in &saved {
+        let event = if inserted { events::Event::Created } else { events::Event::Updated };
+        events::emit(&state, user.id, event, id);
+    }
 
-    Ok(())
+    Ok(Json(results))
 }
 
+/// The most URLs `POST /bookmarks/check` takes at once.
+const MAX_CHECK_URLS: usize = 100;
+
 #[derive(Deserialize)]
-struct SearchQuery {
-    q: Option<String>,
+struct CheckRequest {
+    urls: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, sqlx::FromRow, JsonSchema)]
-struct BookmarkResponse {
-    id: Uuid,
+#[derive(Serialize)]
+struct CheckResult {
+    /// As it was sent
     url: String,
-    title: Option<String>,
-    ai_summary: Option<String>,
-    tags: Option<Vec<String>>,
+    /// What it's saved as, or `None` if it isn't a URL
+    normalized_url: Option<String>,
+    saved: bool,
+    id: Option<Uuid>,
+    tags: Vec<String>,
 }
 
-async fn search_bookmarks(
+/// Which of a list of URLs, like a browser's open tabs, are already bookmarked, in the order
+/// they were sent. Bookmarks in the trash don't count.
+async fn check_bookmarks(
     State(state): State<AppState>,
     Extension(user): Extension<CurrentUser>,
-    Query(params): Query<SearchQuery>,
-) -> Result<Json<Vec<BookmarkResponse>>, StatusCode> {
-    let q = params.q.unwrap_or_default();
-    let q = format!("%{}%", q);
-
-    let bookmarks = sqlx::query_as::<_, BookmarkResponse>(
-        "SELECT b.id, b.url, b.title, b.ai_summary, 
-         array_agg(t.name) FILTER (WHERE t.name IS NOT NULL) as tags
+    Json(payload): Json<CheckRequest>,
+) -> Result<Json<Vec<CheckResult>>, StatusCode> {
+    if payload.urls.len() > MAX_CHECK_URLS {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    let normalized: Vec<Option<String>> = payload.urls.iter().map(|url| normalize::normalize_url(url).ok()).collect();
+    let wanted: Vec<&String> = normalized.iter().flatten().collect();
+    let saved: Vec<(String, Uuid, Vec<String>)> = sqlx::query_as(
+        "SELECT b.url, b.id, COALESCE((SELECT array_agg(t.name ORDER BY t.name) FROM bookmark_tags bt
+             JOIN tags t ON bt.tag_id = t.id WHERE bt.bookmark_id = b.id), '{}')
          FROM bookmarks b
-         LEFT JOIN bookmark_tags bt ON b.id = bt.bookmark_id
-         LEFT JOIN tags t ON bt.tag_id = t.id
-         WHERE b.user_id = $1 AND (b.url ILIKE $2 OR b.title ILIKE $2 OR b.ai_summary ILIKE $2 OR t.name ILIKE $2)
-         GROUP BY b.id"
+         WHERE b.user_id = $1 AND b.deleted_at IS NULL AND b.url = ANY($2)"
     )
     .bind(user.id)
-    .bind(q)
+    .bind(&wanted)
     .fetch_all(&state.db)
     .await
     .map_err(|e| {
-        eprintln!("Search Bookmarks Error: {}", e);
+        eprintln!("Check Bookmarks Error: {}", e);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
+    let saved: HashMap<String, (Uuid, Vec<String>)> = saved.into_iter().map(|(url, id, tags)| (url, (id, tags))).collect();
 
-    Ok(Json(bookmarks))
+    let results = payload
+        .urls
+        .into_iter()
+        .zip(normalized)
+        .map(|(url, normalized_url)| {
+            let bookmark = normalized_url.as_ref().and_then(|normalized| saved.get(normalized));
+            CheckResult {
+                url,
+                saved: bookmark.is_some(),
+                id: bookmark.map(|(id, _)| *id),
+                tags: bookmark.map(|(_, tags)| tags.clone()).unwrap_or_default(),
+                normalized_url,
+            }
+        })
+        .collect();
+    Ok(Json(results))
 }
 
 #[derive(Deserialize)]
-struct SuggestFoldersRequest {
+struct DeltaParams {
+    /// `synced_at` from the previous call; everything is returned without it
+    since: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+struct SyncDelta {
+    /// Live bookmarks created or changed since `since`
     bookmarks: Vec<BookmarkResponse>,
-    folders: Vec<String>,
+    /// Bookmarks trashed or purged since `since`
+    deleted: Vec<Uuid>,
+    /// Pass back as `since` on the next call
+    synced_at: DateTime<Utc>,
 }
 
-#[derive(Serialize, Deserialize, JsonSchema)]
-struct Suggestion {
-    bookmark_id: Uuid,
-    folder_name: String,
+/// Incremental sync: what changed since the client last asked.
+async fn sync_changes(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Query(params): Query<DeltaParams>,
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Sync Changes Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let etag = etag::for_bookmarks(&state.db, user.id, query.as_deref()).await.map_err(db_error)?;
+    if etag::matches(&headers, &etag) {
+        return Ok(etag::not_modified(etag));
+    }
+
+    // One snapshot, so nothing slips between the queries and `synced_at`
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    let synced_at: DateTime<Utc> = sqlx::query_scalar("SELECT now()")
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+    let mut query = QueryBuilder::<Postgres>::new(BOOKMARK_SELECT);
+    query
+        .push(" WHERE b.user_id = ")
+        .push_bind(user.id)
+        .push(" AND b.deleted_at IS NULL");
+    if let Some(since) = params.since {
+        query.push(" AND b.updated_at > ").push_bind(since);
+    }
+    query.push(" ORDER BY b.updated_at, b.id");
+    let bookmarks = query.build_query_as().fetch_all(&mut *tx).await.map_err(db_error)?;
+
+    let deleted = match params.since {
+        Some(since) => sqlx::query_scalar(
+            "SELECT id FROM bookmarks WHERE user_id = $1 AND deleted_at IS NOT NULL AND updated_at > $2
+             UNION ALL
+             SELECT bookmark_id FROM bookmark_tombstones WHERE user_id = $1 AND deleted_at > $2"
+        )
+        .bind(user.id)
+        .bind(since)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(db_error)?,
+        None => Vec::new(),
+    };
+
+    tx.commit().await.map_err(db_error)?;
+
+    Ok(([(header::ETAG, etag)], Json(SyncDelta { bookmarks, deleted, synced_at })).into_response())
 }
 
-#[derive(Serialize, Deserialize, JsonSchema)]
-struct SuggestFoldersResponse {
-    suggestions: Vec<Suggestion>,
+const IMPORT_BODY_LIMIT: usize = 32 * 1024 * 1024;
+/// Bookmarks `linkman import` saves in one transaction.
+const IMPORT_CHUNK: usize = 500;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum ImportFormat {
+    /// Browser `bookmarks.html` export
+    #[default]
+    Netscape,
+    /// Pocket CSV or HTML export
+    Pocket,
+    /// A spreadsheet's `url,title,tags,created_at,notes` columns
+    Csv,
+    /// Wallabag JSON export
+    Wallabag,
+    /// Raindrop.io CSV export
+    Raindrop,
+    /// Karakeep (Hoarder) JSON export
+    Karakeep,
+    /// Shaarli's HTML export, or the links its API lists
+    Shaarli,
+    /// Instapaper CSV export
+    Instapaper,
+    /// Omnivore's export zip, or one of the metadata files in it
+    Omnivore,
+    /// A saved Hacker News favorites page, or the items from HN's API
+    Hackernews,
+    /// Reddit's saved posts or comments CSV, or its saved listing JSON
+    Reddit,
 }
 
-async fn suggest_folders(
-    State(state): State<AppState>,
-    Extension(_user): Extension<CurrentUser>,
-    Json(payload): Json<SuggestFoldersRequest>,
-) -> Result<Json<SuggestFoldersResponse>, StatusCode> {
-    if payload.folders.is_empty() {
-        return Ok(Json(SuggestFoldersResponse { suggestions: vec![] }));
+impl ImportFormat {
+    /// Every format but Omnivore's archive is text.
+    fn parse(self, content: &[u8], columns: Option<&str>) -> anyhow::Result<Vec<import::ExternalBookmark>> {
+        let text = std::str::from_utf8(content);
+        match self {
+            ImportFormat::Netscape => Ok(netscape::parse(text?)),
+            ImportFormat::Pocket => pocket::parse(text?),
+            ImportFormat::Csv => spreadsheet::parse(text?, columns),
+            ImportFormat::Wallabag => wallabag::parse(text?),
+            ImportFormat::Raindrop => raindrop::parse(text?),
+            ImportFormat::Karakeep => karakeep::parse(text?),
+            ImportFormat::Shaarli => shaarli::parse(text?),
+            ImportFormat::Instapaper => instapaper::parse(text?),
+            ImportFormat::Omnivore => omnivore::parse(content),
+            ImportFormat::Hackernews => hackernews::parse(text?),
+            ImportFormat::Reddit => reddit::parse(text?),
+        }
     }
+}
 
-    let prompt = format!(
-        "Folders: {:?}\n\nBookmarks: {}", 
-        payload.folders,
-        serde_json::to_string(&payload.bookmarks).unwrap()
-    );
+#[derive(Deserialize)]
+struct ImportParams {
+    #[serde(default)]
+    format: ImportFormat,
+    /// For CSV, which headers hold which fields, e.g. `url=Link,tags=Labels`
+    columns: Option<String>,
+}
 
-    // AI suggestion using Rig
-    let extractor = state.openai
-        .extractor::<SuggestFoldersResponse>(&state.model)
-        .preamble("You are a semantic classification agent. Your sole task is to analyze the input text and select the single most appropriate folder from the provided list.\n\nRules:\n\nOutput exactly one folder path from the list for each bookmark. Do not create new folders.\n\nBase your selection on the closest match to the input's primary domain, technology, or intent.\n\nExtract only what is explicitly stated or strongly implied.")
-        .additional_params(json!({ "enable_thinking": false }))
-        .build();
+/// Imports another tool's export file. URLs the user already has are skipped.
+async fn import_bookmarks(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Query(params): Query<ImportParams>,
+    body: Bytes,
+) -> Result<Json<import::ImportSummary>, StatusCode> {
+    let parsed = params.format.parse(&body, params.columns.as_deref()).map_err(|e| {
+        eprintln!("Import Parse Error: {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
 
-    let suggestions = extractor.extract(&prompt).await.map_err(|e| {
-        eprintln!("Rig extraction error (Suggest Folders): {}", e);
+    let summary = import::save(&state, user.id, parsed).await.map_err(|e| {
+        eprintln!("Import Bookmarks Error: {}", e);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
 
-    Ok(Json(suggestions))
+    Ok(Json(summary))
 }
 
-async fn delete_bookmark(
+#[derive(Deserialize)]
+struct ExportParams {
+    format: Option<String>,
+}
+
+async fn export_bookmarks(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Query(params): Query<ExportParams>,
+) -> Result<Response, StatusCode> {
+    let csv = match params.format.as_deref() {
+        None | Some("netscape") => false,
+        Some("csv") => true,
+        Some(_) => return Err(StatusCode::BAD_REQUEST),
+    };
+
+    let mut query = QueryBuilder::<Postgres>::new(BOOKMARK_SELECT);
+    query
+        .push(" WHERE b.user_id = ")
+        .push_bind(user.id)
+        .push(" AND b.deleted_at IS NULL ORDER BY b.created_at, b.id");
+
+    let bookmarks: Vec<import::ExternalBookmark> = query
+        .build_query_as::<BookmarkResponse>()
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| {
+            eprintln!("Export Bookmarks Error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .into_iter()
+        .map(|b| import::ExternalBookmark {
+            url: b.url,
+            title: b.title,
+            tags: b.tags.unwrap_or_default(),
+            added_at: Some(b.created_at),
+            description: b.notes.or(b.ai_summary),
+            ..Default::default()
+        })
+        .collect();
+
+    if csv {
+        let body = spreadsheet::render(&bookmarks).map_err(|e| {
+            eprintln!("Export Bookmarks Error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        return Ok((
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+                (header::CONTENT_DISPOSITION, "attachment; filename=\"bookmarks.csv\""),
+            ],
+            body,
+        )
+            .into_response());
+    }
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"bookmarks.html\""),
+        ],
+        netscape::render(&bookmarks),
+    )
+        .into_response())
+}
+
+fn scrape_metadata(html_content: &str) -> Value {
+    let document = Html::parse_document(html_content);
+    let mut site_meta = json!({
+        "scraped_at": chrono::Utc::now().to_rfc3339()
+    });
+
+    let html_selector = Selector::parse("html").unwrap();
+    if let Some(lang) = document.select(&html_selector).next().and_then(|html| html.value().attr("lang")) {
+        site_meta["lang"] = json!(lang.chars().take(35).collect::<String>());
+    }
+    
+    // Scrape <title>
+    let title_selector = Selector::parse("title").unwrap();
+    if let Some(title_elem) = document.select(&title_selector).next() {
+        let title = title_elem.text().collect::<Vec<_>>().join(" ");
+        site_meta["title"] = json!(title.chars().take(300).collect::<String>());
+    }
+
+    // Scrape only essential meta tags to avoid flooding context
+    let meta_selector = Selector::parse("meta").unwrap();
+    let important_names = [
+        "description", "keywords", "author", "og:title", "og:description", "og:site_name", "og:image", "twitter:title",
+        "twitter:description", "twitter:image", "article:published_time", "article:author",
+    ];
+    
+    for element in document.select(&meta_selector) {
+        let name = element.value().attr("name").or_else(|| element.value().attr("property"));
+        let content = element.value().attr("content");
+        
+        if let (Some(n), Some(c)) = (name, content)
+            && important_names.contains(&n)
+        {
+            // Truncate long content to 1500 chars to save context
+            let truncated = c.chars().take(1500).collect::<String>();
+            site_meta[n] = json!(truncated);
+        }
+    }
+    site_meta
+}
+
+/// The page's own name for itself from scraped metadata, preferring `og:title` over `<title>`.
+fn page_title(site_meta: &Value) -> Option<String> {
+    ["og:title", "title"]
+        .iter()
+        .filter_map(|key| site_meta[key].as_str())
+        .map(|title| title.split_whitespace().collect::<Vec<_>>().join(" "))
+        .find(|title| !title.is_empty())
+}
+
+/// Readable markdown of a page, without the scripts, styles and chrome around the content.
+fn page_markdown(html_content: &str) -> String {
+    htmd::HtmlToMarkdown::builder()
+        .skip_tags(vec!["head", "script", "style", "noscript", "nav", "header", "footer", "form", "iframe", "svg"])
+        .build()
+        .convert(html_content)
+        .unwrap_or_default()
+}
+
+/// What a URL served, as far as processing needs it.
+struct Download {
+    status: StatusCode,
+    kind: content::Kind,
+    content_type: Option<String>,
+    length: Option<u64>,
+    body: Vec<u8>,
+}
+
+async fn download(fetched: fetcher::Fetched<'_>) -> anyhow::Result<Download> {
+    let status = fetched.response.status();
+    let content_type = fetched
+        .response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(content::essence);
+    let kind = content::Kind::of(content_type.as_deref());
+    let length = fetched.response.content_length();
+    // Just enough to size an image; video and audio aren't downloaded at all
+    let body = match kind {
+        _ if kind.wants_body() => fetched.bytes().await?,
+        content::Kind::Image => fetched.head(content::IMAGE_HEADER_BYTES).await?,
+        _ => Vec::new(),
+    };
+    Ok(Download { status, kind, content_type, length, body })
+}
+
+/// The Wayback Machine's latest good copy of a page that's gone.
+async fn fetch_snapshot(
+    state: &AppState,
+    wayback: &wayback::Wayback,
+    url: &str,
+) -> anyhow::Result<Option<(wayback::Snapshot, Download)>> {
+    let Some(snapshot) = wayback.closest(&state.fetcher, url).await? else {
+        return Ok(None);
+    };
+    let download = download(state.fetcher.get(&snapshot.raw_url).await?).await?;
+    Ok(download.status.is_success().then_some((snapshot, download)))
+}
+
+/// Records how far processing has got; `failed` is set by the job queue once it gives up.
+async fn set_processing_status(db: &PgPool, bookmark_id: Uuid, status: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE bookmarks SET processing_status = $1::processing_status WHERE id = $2")
+        .bind(status)
+        .bind(bookmark_id)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+async fn process_bookmark(state: AppState, user_id: Uuid, bookmark_id: Uuid, mut url: String) -> anyhow::Result<()> {
+    // 1. Fetch and Scrape
+    set_processing_status(&state.db, bookmark_id, "fetching").await?;
+    let host = url::Url::parse(&url)?.host_str().unwrap_or_default().to_string();
+    let credential = credentials::for_host(&state, user_id, &host).await?;
+    let fetched = match state.fetcher.get_as(&url, credential.as_ref()).await {
+        Ok(fetched) => download(fetched).await,
+        Err(e) => Err(e),
+    };
+    let gone = match &fetched {
+        Ok(download) => matches!(download.status, StatusCode::NOT_FOUND | StatusCode::GONE),
+        Err(e) => matches!(e.downcast_ref::<fetcher::FetchError>(), Some(fetcher::FetchError::Timeout(_))),
+    };
+    let mut snapshot = None;
+    let download = match &state.wayback {
+        Some(wayback) if gone => match fetch_snapshot(&state, wayback, &url).await {
+            Ok(Some((found, download))) => {
+                snapshot = Some(found);
+                download
+            }
+            Ok(None) => fetched?,
+            Err(e) => {
+                eprintln!("Error fetching Wayback snapshot of {}: {}", url, e);
+                fetched?
+            }
+        },
+        _ => fetched?,
+    };
+    let Download { kind, content_type, length, body, .. } = download;
+    let page = (kind == content::Kind::Html).then(|| String::from_utf8_lossy(&body).into_owned());
+
+    if state.resolve_canonical
+        && let Some(page) = &page
+        && let Some(canonical) = normalize::canonical_url(page, &url).filter(|c| *c != url)
+    {
+        // Leave it alone if the canonical address is already bookmarked separately
+        let moved = sqlx::query(
+            "UPDATE bookmarks SET url = $1, updated_at = now() WHERE id = $2
+             AND NOT EXISTS (SELECT 1 FROM bookmarks WHERE user_id = $3 AND url = $1)"
+        )
+        .bind(&canonical)
+        .bind(bookmark_id)
+        .bind(user_id)
+        .execute(&state.db)
+        .await?;
+        if moved.rows_affected() > 0 {
+            url = canonical;
+        }
+    }
+    
+    // Perform scraping in a scope to ensure non-Send types are dropped
+    let (mut site_meta, markdown) = match kind {
+        content::Kind::Html => {
+            let page = page.as_deref().unwrap_or_default();
+            (scrape_metadata(page), Some(page_markdown(page)))
+        }
+        content::Kind::Pdf | content::Kind::Text => {
+            let mut meta = content::metadata(kind, content_type.as_deref(), &url, length, &[]);
+            let text = match kind {
+                content::Kind::Pdf => content::pdf_text(body).await,
+                _ => Some(String::from_utf8_lossy(&body).into_owned()),
+            };
+            if let Some(text) = &text {
+                content::add_excerpt(&mut meta, text);
+            }
+            (meta, text)
+        }
+        _ => (content::metadata(kind, content_type.as_deref(), &url, length, &body), None),
+    };
+
+    // Video sites say more about themselves through oEmbed than through their markup
+    if let Some(endpoint) = page.as_deref().and_then(|page| oembed::endpoint(&url, page)) {
+        match oembed::fetch(&state.fetcher, &endpoint).await {
+            Ok(embed) => oembed::merge(&mut site_meta, embed),
+            Err(e) => eprintln!("Error fetching oEmbed for bookmark {}: {}", bookmark_id, e),
+        }
+    }
+    let language = language::detect(&site_meta, markdown.as_deref().unwrap_or_default());
+    let details = page
+        .as_deref()
+        .map(|page| details::extract(page, &url, &site_meta))
+        .unwrap_or_default();
+
+    if let Some(title) = page_title(&site_meta) {
+        sqlx::query(
+            "UPDATE bookmarks SET title = $1, title_from_page = true, updated_at = now()
+             WHERE id = $2 AND (title IS NULL OR title_from_page) AND title IS DISTINCT FROM $1"
+        )
+        .bind(&title)
+        .bind(bookmark_id)
+        .execute(&state.db)
+        .await?;
+    }
+
+    // A description and image brought in by an import are kept over the page's, and a synced
+    // Mastodon post keeps its own author, time and content: its page is mostly Mastodon's app
+    let synced_post: bool = sqlx::query_scalar(
+        "UPDATE bookmarks SET description = COALESCE(description, $1), image_url = COALESCE(image_url, $2),
+             published_at = CASE WHEN site_meta ? 'mastodon' THEN published_at ELSE $3 END,
+             author = CASE WHEN site_meta ? 'mastodon' THEN author ELSE $4 END
+         WHERE id = $5
+         RETURNING COALESCE(site_meta ? 'mastodon', false)"
+    )
+        .bind(&details.description)
+        .bind(&details.image_url)
+        .bind(details.published_at)
+        .bind(&details.author)
+        .bind(bookmark_id)
+        .fetch_one(&state.db)
+        .await?;
+
+    // Keep the page itself before anything else can fail, so it survives link rot
+    if let Some(markdown) = &markdown
+        && !synced_post
+    {
+        changes::store(&state, user_id, bookmark_id, markdown).await?;
+    }
+
+    if let Some(archive) = &state.archive
+        && let Some(page) = page
+    {
+        let archive_bytes = page.len() as i64;
+        let archive_url = archive.put_page(bookmark_id, page).await?;
+        sqlx::query(
+            "UPDATE bookmarks SET archive_url = $1, archive_bytes = $2, archived_at = now(), updated_at = now()
+             WHERE id = $3"
+        )
+            .bind(&archive_url)
+            .bind(archive_bytes)
+            .bind(bookmark_id)
+            .execute(&state.db)
+            .await?;
+    }
+
+    // A missing thumbnail shouldn't hold up tagging
+    if let Some(screenshots) = &state.screenshots
+        && kind == content::Kind::Html
+        && snapshot.is_none()
+        && let Err(e) = store_screenshot(&state, screenshots, bookmark_id, &url).await
+    {
+        eprintln!("Error capturing screenshot for bookmark {}: {}", bookmark_id, e);
+    }
+
+    // Captures take minutes, too long to hold up tagging. A page fetched with a login would be
+    // captured without it, so it isn't sent at all.
+    if state.wayback_every_bookmark && snapshot.is_none() && credential.is_none() {
+        let (state, url) = (state.clone(), url.clone());
+        tokio::spawn(async move {
+            if let Err(e) = save_to_wayback(&state, user_id, bookmark_id, &url).await {
+                eprintln!("Error saving bookmark {} to the Wayback Machine: {}", bookmark_id, e);
+            }
+        });
+    }
+
+    // 2. AI Enrichment, unless no provider is configured
+    set_processing_status(&state.db, bookmark_id, "tagging").await?;
+    let vocabulary: Vec<String> = sqlx::query_scalar("SELECT name FROM tag_vocabulary WHERE user_id = $1 ORDER BY name")
+        .bind(user_id)
+        .fetch_all(&state.db)
+        .await?;
+    let enrichment = {
+        let _permit = state.ai_limit.acquire().await?;
+        state.tagger.tag(&url, &site_meta.to_string(), &vocabulary).await?
+    };
+    let summary = enrichment.as_ref().map(|e| e.summary.as_str());
+    let tags = enrichment.as_ref().map_or(&[][..], |e| e.tags.as_slice());
+
+    // 3. Update Database
+    let mut tx = state.db.begin().await?;
+
+    if enrichment.is_some() {
+        // Merging keeps the user's tags and swaps out the model's from last time
+        sqlx::query(
+            "DELETE FROM bookmark_tags bt USING bookmarks b
+             WHERE b.id = bt.bookmark_id AND b.id = $1 AND (b.tag_mode = 'replace' OR bt.source = 'ai')"
+        )
+        .bind(bookmark_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    sqlx::query(
+        // A synced repository's or post's details aren't part of the page, so they outlive fetching it again
+        "UPDATE bookmarks SET site_meta = $1 || jsonb_strip_nulls(jsonb_build_object(
+                 'github', site_meta->'github', 'mastodon', site_meta->'mastodon')),
+             ai_summary = COALESCE($2, ai_summary), language = $3, content_type = $4,
+             snapshot_url = $5, snapshot_at = $6, updated_at = now()
+         WHERE id = $7"
+    )
+    .bind(&site_meta)
+    .bind(summary)
+    .bind(&language)
+    .bind(&content_type)
+    .bind(snapshot.as_ref().map(|snapshot| &snapshot.url))
+    .bind(snapshot.as_ref().map(|snapshot| snapshot.captured_at))
+    .bind(bookmark_id)
+    .execute(&mut *tx)
+    .await?;
+
+    attach_tags(&mut tx, user_id, bookmark_id, tags, TagSource::Ai).await?;
+
+    tx.commit().await?;
+    if enrichment.is_some() {
+        events::emit(&state, user_id, events::Event::Tagged, bookmark_id);
+    }
+
+    // 4. Embed for semantic search
+    let title = site_meta["title"].as_str();
+    let text = embeddings::input(&url, title, summary, tags);
+    if let Some(embedding) = embeddings::embed(&state, &text).await? {
+        embeddings::store(&state.db, bookmark_id, &embedding).await?;
+    }
+
+    sqlx::query("UPDATE bookmarks SET processing_status = 'done', error_message = NULL, error_code = NULL WHERE id = $1")
+        .bind(bookmark_id)
+        .execute(&state.db)
+        .await?;
+    Ok(())
+}
+
+/// Has the Wayback Machine capture the page and records where the capture is.
+async fn save_to_wayback(state: &AppState, user_id: Uuid, bookmark_id: Uuid, url: &str) -> anyhow::Result<()> {
+    let wayback_url = state.save_page_now.save(state.fetcher.client(), url).await?;
+    sqlx::query("UPDATE bookmarks SET wayback_url = $1, wayback_saved_at = now(), updated_at = now() WHERE id = $2")
+        .bind(&wayback_url)
+        .bind(bookmark_id)
+        .execute(&state.db)
+        .await?;
+    events::emit(state, user_id, events::Event::Updated, bookmark_id);
+    Ok(())
+}
+
+async fn store_screenshot(
+    state: &AppState,
+    screenshots: &screenshot::ScreenshotService,
+    bookmark_id: Uuid,
+    url: &str,
+) -> anyhow::Result<()> {
+    let shot = screenshots.capture(url).await?;
+    sqlx::query(
+        "INSERT INTO bookmark_screenshots (bookmark_id, content_type, image) VALUES ($1, $2, $3)
+         ON CONFLICT (bookmark_id) DO UPDATE SET content_type = EXCLUDED.content_type, image = EXCLUDED.image, captured_at = now()"
+    )
+    .bind(bookmark_id)
+    .bind(&shot.content_type)
+    .bind(shot.image.as_ref())
+    .execute(&state.db)
+    .await?;
+    Ok(())
+}
+
+/// Tags nest with `/` (`dev/rust/async`); stray whitespace and empty segments are dropped.
+fn tag_path(name: &str) -> Option<String> {
+    let path = name
+        .split('/')
+        .map(str::trim)
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>()
+        .join("/");
+    (!path.is_empty()).then_some(path)
+}
+
+/// Who put a tag on a bookmark.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "tag_source", rename_all = "lowercase")]
+enum TagSource {
+    User,
+    Ai,
+}
+
+/// Links `tags` to a bookmark, creating any tags the user doesn't have yet. A tag the user adds
+/// becomes theirs even if the model put it there first.
+async fn attach_tags(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+    bookmark_id: Uuid,
+    tags: &[String],
+    source: TagSource,
+) -> Result<(), sqlx::Error> {
+    for tag_name in tags.iter().filter_map(|t| tag_path(t)) {
+        let tag_id: Uuid = sqlx::query_scalar(
+            "INSERT INTO tags (user_id, name) VALUES ($1, $2) 
+             ON CONFLICT (user_id, name) DO UPDATE SET name = EXCLUDED.name 
+             RETURNING id"
+        )
+        .bind(user_id)
+        .bind(tag_name)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        sqlx::query(
+            "INSERT INTO bookmark_tags (bookmark_id, tag_id, source) VALUES ($1, $2, $3) 
+             ON CONFLICT (bookmark_id, tag_id) DO UPDATE SET source = 'user' WHERE EXCLUDED.source = 'user'"
+        )
+        .bind(bookmark_id)
+        .bind(tag_id)
+        .bind(source)
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Makes a bookmark's tags exactly `names`. Tags it keeps stay as they were, whoever added them.
+async fn replace_tags(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+    bookmark_id: Uuid,
+    names: &[String],
+) -> Result<(), sqlx::Error> {
+    let names: Vec<String> = names.iter().filter_map(|name| tag_path(name)).collect();
+    let kept: HashSet<String> = sqlx::query_scalar(
+        "WITH removed AS (
+             DELETE FROM bookmark_tags bt USING tags t
+             WHERE bt.tag_id = t.id AND bt.bookmark_id = $1 AND NOT t.name = ANY($2)
+         )
+         SELECT t.name FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id
+         WHERE bt.bookmark_id = $1 AND t.name = ANY($2)"
+    )
+    .bind(bookmark_id)
+    .bind(&names)
+    .fetch_all(&mut **tx)
+    .await?
+    .into_iter()
+    .collect();
+    let added: Vec<String> = names.into_iter().filter(|name| !kept.contains(name)).collect();
+    attach_tags(tx, user_id, bookmark_id, &added, TagSource::User).await
+}
+
+/// What to search for, shared by listing, search, saved searches and `linkman list`.
+#[derive(Clone, Default, Serialize, Deserialize, clap::Args)]
+struct SearchParams {
+    /// Substring of the URL, title, notes, summary or a tag. A `site:lobste.rs` word in it limits
+    /// the results to that site instead, like `domain`
+    #[arg(skip)]
+    q: Option<String>,
+    /// Natural-language query ranked by embedding similarity instead of substring matching
+    #[arg(skip)]
+    semantic: Option<String>,
+    /// `broken` or `ok`, as decided by the link checker, or a processing status such as `failed`
+    #[arg(long)]
+    status: Option<String>,
+    /// A processing status on its own, to combine with a link checker `status`
+    #[arg(long)]
+    processing: Option<String>,
+    /// `true` for bookmarks with no tags at all, neither the user's nor the model's
+    #[arg(long)]
+    untagged: Option<bool>,
+    /// `true` for the read-later queue, `false` for what has already been read
+    #[arg(long)]
+    unread: Option<bool>,
+    #[arg(long)]
+    favorite: Option<bool>,
+    /// Only bookmarks filed directly in this collection
+    #[arg(long)]
+    collection: Option<Uuid>,
+    /// A tag path; `dev/rust` also matches `dev/rust/async`
+    #[arg(long)]
+    tag: Option<String>,
+    /// Like `tag`, but only tags the user gave
+    #[arg(long)]
+    user_tag: Option<String>,
+    /// Like `tag`, but only tags the model added
+    #[arg(long)]
+    ai_tag: Option<String>,
+    /// Language of the page, as an ISO 639-1 code like `en`
+    #[arg(long)]
+    lang: Option<String>,
+    /// The site the bookmark is on, like `lobste.rs`; `www.` doesn't matter, and subdomains are
+    /// sites of their own
+    #[arg(long)]
+    domain: Option<String>,
+    /// Only bookmarks saved before this time
+    #[arg(long)]
+    created_before: Option<DateTime<Utc>>,
+    /// Only bookmarks saved at or after this time
+    #[arg(long)]
+    created_after: Option<DateTime<Utc>>,
+    /// What to list by, `created_at` when omitted
+    #[arg(long)]
+    sort: Option<Sort>,
+    /// Newest first for the dates and A to Z for the rest when omitted
+    #[arg(long)]
+    order: Option<Order>,
+}
+
+/// What `GET /bookmarks` can be ordered by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+enum Sort {
+    #[default]
+    CreatedAt,
+    UpdatedAt,
+    Title,
+    Domain,
+    /// Most opened through `/go/{id}` first
+    MostVisited,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum Order {
+    Asc,
+    Desc,
+}
+
+impl Sort {
+    /// The expression rows are ordered by, matching what [`Sort::cursor`] reads off a result.
+    fn column(self) -> &'static str {
+        match self {
+            Sort::CreatedAt => "b.created_at",
+            Sort::UpdatedAt => "COALESCE(b.updated_at, b.created_at)",
+            Sort::Title => "COALESCE(b.title, b.url)",
+            Sort::Domain => "COALESCE(b.host, '')",
+            Sort::MostVisited => "b.visit_count",
+        }
+    }
+
+    fn default_order(self) -> Order {
+        match self {
+            Sort::CreatedAt | Sort::UpdatedAt | Sort::MostVisited => Order::Desc,
+            Sort::Title | Sort::Domain => Order::Asc,
+        }
+    }
+
+    /// Where the next page starts after `last`.
+    fn cursor(self, last: &BookmarkResponse) -> String {
+        match self {
+            Sort::CreatedAt => encode_cursor(last.created_at, last.id),
+            Sort::UpdatedAt => encode_cursor(last.updated_at, last.id),
+            Sort::Title => encode_text_cursor(last.title.as_deref().unwrap_or(&last.url), last.id),
+            Sort::Domain => encode_text_cursor(last.domain.as_deref().unwrap_or_default(), last.id),
+            Sort::MostVisited => encode_count_cursor(last.visit_count, last.id),
+        }
+    }
+}
+
+const SEMANTIC_RESULTS: i64 = 50;
+
+#[derive(Default, Serialize, sqlx::FromRow)]
+struct BookmarkResponse {
+    id: Uuid,
+    url: String,
+    title: Option<String>,
+    /// The title was taken from the page rather than given by the user
+    title_from_page: bool,
+    notes: Option<String>,
+    /// The page's own description of itself, from OpenGraph or JSON-LD
+    description: Option<String>,
+    /// Lead image of the page
+    image_url: Option<String>,
+    published_at: Option<DateTime<Utc>>,
+    author: Option<String>,
+    ai_summary: Option<String>,
+    /// ISO 639-1 code of the language the page is written in, if it could be told
+    language: Option<String>,
+    /// What the URL served when it was last fetched, e.g. `text/html` or `application/pdf`
+    content_type: Option<String>,
+    /// Provider, author, thumbnail and duration of a video or other oEmbed page
+    embed: Option<Value>,
+    /// Language, topics and star count of a repository synced from GitHub stars
+    github: Option<Value>,
+    /// Where the link was talked about, like its Hacker News or Reddit thread
+    discussion_url: Option<String>,
+    tags: Option<Vec<String>>,
+    /// Those of `tags` the user gave
+    user_tags: Option<Vec<String>>,
+    /// Those of `tags` the model added
+    ai_tags: Option<Vec<String>>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    /// The site it's on, like `lobste.rs`, as `domain=` filters by
+    domain: Option<String>,
+    archived_at: Option<DateTime<Utc>>,
+    last_checked_at: Option<DateTime<Utc>>,
+    last_status: Option<i32>,
+    is_broken: bool,
+    /// `pending`, `fetching`, `tagging`, `done` or `failed`
+    processing_status: String,
+    /// Why processing last failed, while it's being retried or after it gave up
+    error_message: Option<String>,
+    /// `timeout`, `too_large`, `too_many_redirects`, `unavailable`, `private_address` or
+    /// `robots_disallowed` when the page itself was the problem
+    error_code: Option<String>,
+    /// The Wayback Machine snapshot the content came from, when the page itself was gone
+    snapshot_url: Option<String>,
+    snapshot_at: Option<DateTime<Utc>>,
+    /// The Wayback Machine's capture of the page, once one has been asked for
+    wayback_url: Option<String>,
+    wayback_saved_at: Option<DateTime<Utc>>,
+    /// When the page last changed enough to keep what it said before
+    content_changed_at: Option<DateTime<Utc>>,
+    deleted_at: Option<DateTime<Utc>>,
+    is_read: bool,
+    read_at: Option<DateTime<Utc>>,
+    is_favorite: bool,
+    is_public: bool,
+    /// Times it was opened through `/go/{id}`
+    visit_count: i32,
+    last_visited_at: Option<DateTime<Utc>>,
+}
+
+const BOOKMARK_SELECT: &str = "SELECT b.id, b.url, b.title, b.title_from_page, b.notes, b.description, b.image_url, b.published_at, b.author,
+     b.ai_summary, b.language, b.content_type, b.site_meta->'embed' as embed, b.site_meta->'github' as github, b.discussion_url, b.created_at,
+     COALESCE(b.updated_at, b.created_at) as updated_at, b.host as domain, b.archived_at,
+     b.last_checked_at, b.last_status, b.is_broken, b.processing_status::text, b.error_message, b.error_code, b.snapshot_url, b.snapshot_at, b.wayback_url, b.wayback_saved_at, b.content_changed_at, b.deleted_at, b.read_at IS NOT NULL as is_read, b.read_at,
+     b.is_favorite, b.is_public, b.visit_count, b.last_visited_at,
+     (SELECT array_agg(t.name ORDER BY t.name) FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id
+      WHERE bt.bookmark_id = b.id) as tags,
+     (SELECT array_agg(t.name ORDER BY t.name) FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id
+      WHERE bt.bookmark_id = b.id AND bt.source = 'user') as user_tags,
+     (SELECT array_agg(t.name ORDER BY t.name) FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id
+      WHERE bt.bookmark_id = b.id AND bt.source = 'ai') as ai_tags
+     FROM bookmarks b";
+
+/// Unpaginated search, kept in this shape for the extension; new clients can use `GET /bookmarks`.
+async fn search_bookmarks(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Query(mut params): Query<SearchParams>,
+) -> Result<Json<Vec<BookmarkResponse>>, StatusCode> {
+    if let Some(semantic) = params.semantic.take() {
+        return semantic_search(&state, &user, &semantic, &params).await.map(Json);
+    }
+
+    let mut query = QueryBuilder::<Postgres>::new(BOOKMARK_SELECT);
+    query.push(" WHERE b.user_id = ").push_bind(user.id);
+    push_list_filters(&mut query, &params)?;
+    query.push(" ORDER BY b.created_at DESC, b.id DESC");
+
+    let bookmarks = query
+        .build_query_as::<BookmarkResponse>()
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| {
+            eprintln!("Search Bookmarks Error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(bookmarks))
+}
+
+/// The closest matches to `text` by embedding, narrowed by the other filters in `params`.
+async fn semantic_search(
+    state: &AppState,
+    user: &CurrentUser,
+    text: &str,
+    params: &SearchParams,
+) -> Result<Vec<BookmarkResponse>, StatusCode> {
+    let embedding = embeddings::embed(state, text)
+        .await
+        .map_err(|e| {
+            eprintln!("Semantic Search Error: {}", e);
+            StatusCode::BAD_GATEWAY
+        })?
+        .ok_or(StatusCode::NOT_IMPLEMENTED)?;
+
+    let mut query = QueryBuilder::<Postgres>::new(BOOKMARK_SELECT);
+    query.push(" WHERE b.user_id = ").push_bind(user.id);
+    push_list_filters(&mut query, params)?;
+    query
+        .push(" AND b.embedding IS NOT NULL ORDER BY b.embedding <=> ")
+        .push_bind(embedding)
+        .push(" LIMIT ")
+        .push_bind(SEMANTIC_RESULTS);
+
+    query
+        .build_query_as()
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| {
+            eprintln!("Semantic Search Error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+#[derive(Deserialize)]
+struct PageParams {
+    limit: Option<i64>,
+    cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+struct BookmarkPage {
+    bookmarks: Vec<BookmarkResponse>,
+    next_cursor: Option<String>,
+}
+
+/// Cursors are opaque to clients but encode the `(created_at, id)` of the last row on a page.
+fn encode_cursor(created_at: DateTime<Utc>, id: Uuid) -> String {
+    format!("{}_{}", created_at.timestamp_micros(), id.simple())
+}
+
+fn decode_cursor(cursor: &str) -> Option<(DateTime<Utc>, Uuid)> {
+    let (micros, id) = cursor.split_once('_')?;
+    let created_at = DateTime::from_timestamp_micros(micros.parse().ok()?)?;
+    Some((created_at, Uuid::parse_str(id).ok()?))
+}
+
+/// The same for ordering by text, which is encoded to be safe in a query string.
+fn encode_text_cursor(key: &str, id: Uuid) -> String {
+    format!("{}_{}", URL_SAFE_NO_PAD.encode(key), id.simple())
+}
+
+fn decode_text_cursor(cursor: &str) -> Option<(String, Uuid)> {
+    // The encoding can have underscores of its own; the id can't
+    let (key, id) = cursor.rsplit_once('_')?;
+    let key = String::from_utf8(URL_SAFE_NO_PAD.decode(key).ok()?).ok()?;
+    Some((key, Uuid::parse_str(id).ok()?))
+}
+
+/// The same for ordering by a count.
+fn encode_count_cursor(count: i32, id: Uuid) -> String {
+    format!("{}_{}", count, id.simple())
+}
+
+fn decode_count_cursor(cursor: &str) -> Option<(i32, Uuid)> {
+    let (count, id) = cursor.split_once('_')?;
+    Some((count.parse().ok()?, Uuid::parse_str(id).ok()?))
+}
+
+/// Splits the `site:` words out of a `q`, leaving the text to search for.
+fn split_query(q: &str) -> Result<(String, Vec<String>), StatusCode> {
+    let (mut text, mut sites) = (Vec::new(), Vec::new());
+    for word in q.split_whitespace() {
+        match word.get(..5).filter(|prefix| prefix.eq_ignore_ascii_case("site:")) {
+            Some(_) => sites.push(normalize::site(&word[5..]).ok_or(StatusCode::BAD_REQUEST)?),
+            None => text.push(word),
+        }
+    }
+    Ok((text.join(" "), sites))
+}
+
+fn push_processing_filter(query: &mut QueryBuilder<Postgres>, status: &str) -> Result<(), StatusCode> {
+    if !matches!(status, "pending" | "fetching" | "tagging" | "done" | "failed") {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    query
+        .push(" AND b.processing_status = ")
+        .push_bind(status.to_string())
+        .push("::processing_status");
+    Ok(())
+}
+
+/// Every filter in `params` except `semantic`, which changes the ordering rather than the rows.
+fn push_list_filters(query: &mut QueryBuilder<Postgres>, params: &SearchParams) -> Result<(), StatusCode> {
+    query.push(" AND b.deleted_at IS NULL");
+    let (q, sites) = split_query(params.q.as_deref().unwrap_or_default())?;
+    if !sites.is_empty() {
+        query.push(" AND b.host = ANY(").push_bind(sites).push(")");
+    }
+    if let Some(domain) = params.domain.as_deref() {
+        let site = normalize::site(domain).ok_or(StatusCode::BAD_REQUEST)?;
+        query.push(" AND b.host = ").push_bind(site);
+    }
+    if !q.is_empty() {
+        let pattern = format!("%{}%", q);
+        query
+            .push(" AND (b.url ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR b.title ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR b.notes ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR b.ai_summary ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR EXISTS (SELECT 1 FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id WHERE bt.bookmark_id = b.id AND t.name ILIKE ")
+            .push_bind(pattern)
+            .push("))");
+    }
+    match params.status.as_deref() {
+        None => {}
+        Some("broken") => {
+            query.push(" AND b.is_broken");
+        }
+        Some("ok") => {
+            query.push(" AND NOT b.is_broken");
+        }
+        Some(status) => push_processing_filter(query, status)?,
+    }
+    if let Some(processing) = params.processing.as_deref() {
+        push_processing_filter(query, processing)?;
+    }
+    if let Some(untagged) = params.untagged {
+        query
+            .push(if untagged { " AND NOT" } else { " AND" })
+            .push(" EXISTS (SELECT 1 FROM bookmark_tags bt WHERE bt.bookmark_id = b.id)");
+    }
+    match params.unread {
+        None => {}
+        Some(true) => {
+            query.push(" AND b.read_at IS NULL");
+        }
+        Some(false) => {
+            query.push(" AND b.read_at IS NOT NULL");
+        }
+    }
+    if let Some(favorite) = params.favorite {
+        query.push(" AND b.is_favorite = ").push_bind(favorite);
+    }
+    if let Some(lang) = params.lang.as_deref() {
+        let lang = language::normalize(lang).ok_or(StatusCode::BAD_REQUEST)?;
+        query.push(" AND b.language = ").push_bind(lang);
+    }
+    if let Some(created_before) = params.created_before {
+        query.push(" AND b.created_at < ").push_bind(created_before);
+    }
+    if let Some(created_after) = params.created_after {
+        query.push(" AND b.created_at >= ").push_bind(created_after);
+    }
+    if let Some(collection) = params.collection {
+        query
+            .push(" AND EXISTS (SELECT 1 FROM collection_bookmarks cb WHERE cb.bookmark_id = b.id AND cb.collection_id = ")
+            .push_bind(collection)
+            .push(")");
+    }
+    for (tag, source) in [
+        (&params.tag, None),
+        (&params.user_tag, Some(TagSource::User)),
+        (&params.ai_tag, Some(TagSource::Ai)),
+    ] {
+        if let Some(tag) = tag.as_deref() {
+            let tag = tag_path(tag).ok_or(StatusCode::BAD_REQUEST)?;
+            query
+                .push(" AND EXISTS (SELECT 1 FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id WHERE bt.bookmark_id = b.id AND (t.name = ")
+                .push_bind(tag.clone())
+                .push(" OR starts_with(t.name, ")
+                .push_bind(format!("{}/", tag))
+                .push("))");
+            if let Some(source) = source {
+                query.push(" AND bt.source = ").push_bind(source);
+            }
+            query.push(")");
+        }
+    }
+    Ok(())
+}
+
+async fn list_bookmarks(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Query(page): Query<PageParams>,
+    Query(params): Query<SearchParams>,
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let etag = etag::for_bookmarks(&state.db, user.id, query.as_deref())
+        .await
+        .map_err(|e| {
+            eprintln!("List Bookmarks Error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if etag::matches(&headers, &etag) {
+        return Ok(etag::not_modified(etag));
+    }
+
+    let page = search_page(&state, &user, &params, &page).await?;
+    Ok(([(header::ETAG, etag)], Json(page)).into_response())
+}
+
+/// One page of results, newest first unless `sort` says otherwise; semantic searches come back
+/// as a single ranked page.
+async fn search_page(
+    state: &AppState,
+    user: &CurrentUser,
+    params: &SearchParams,
+    page: &PageParams,
+) -> Result<BookmarkPage, StatusCode> {
+    if let Some(semantic) = params.semantic.as_deref() {
+        let bookmarks = semantic_search(state, user, semantic, params).await?;
+        return Ok(BookmarkPage { bookmarks, next_cursor: None });
+    }
+
+    let limit = page.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let sort = params.sort.unwrap_or_default();
+    let (direction, after) = match params.order.unwrap_or(sort.default_order()) {
+        Order::Asc => ("ASC", ">"),
+        Order::Desc => ("DESC", "<"),
+    };
+
+    let mut query = QueryBuilder::<Postgres>::new(BOOKMARK_SELECT);
+    query.push(" WHERE b.user_id = ").push_bind(user.id);
+    push_list_filters(&mut query, params)?;
+    if let Some(cursor) = page.cursor.as_deref() {
+        query.push(format!(" AND ({}, b.id) {} (", sort.column(), after));
+        match sort {
+            Sort::CreatedAt | Sort::UpdatedAt => {
+                let (key, id) = decode_cursor(cursor).ok_or(StatusCode::BAD_REQUEST)?;
+                query.push_bind(key).push(", ").push_bind(id);
+            }
+            Sort::Title | Sort::Domain => {
+                let (key, id) = decode_text_cursor(cursor).ok_or(StatusCode::BAD_REQUEST)?;
+                query.push_bind(key).push(", ").push_bind(id);
+            }
+            Sort::MostVisited => {
+                let (key, id) = decode_count_cursor(cursor).ok_or(StatusCode::BAD_REQUEST)?;
+                query.push_bind(key).push(", ").push_bind(id);
+            }
+        }
+        query.push(")");
+    }
+    // Fetch one extra row to find out whether another page exists
+    query
+        .push(format!(" ORDER BY {} {dir}, b.id {dir} LIMIT ", sort.column(), dir = direction))
+        .push_bind(limit + 1);
+
+    let mut bookmarks = query
+        .build_query_as::<BookmarkResponse>()
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| {
+            eprintln!("List Bookmarks Error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let next_cursor = if bookmarks.len() as i64 > limit {
+        bookmarks.truncate(limit as usize);
+        bookmarks.last().map(|b| sort.cursor(b))
+    } else {
+        None
+    };
+
+    Ok(BookmarkPage { bookmarks, next_cursor })
+}
+
+async fn fetch_bookmark<'e>(
+    executor: impl sqlx::PgExecutor<'e>,
+    user_id: Uuid,
+    id: Uuid,
+) -> Result<Option<BookmarkResponse>, sqlx::Error> {
+    let mut query = QueryBuilder::<Postgres>::new(BOOKMARK_SELECT);
+    query
+        .push(" WHERE b.user_id = ")
+        .push_bind(user_id)
+        .push(" AND b.id = ")
+        .push_bind(id)
+        .push(" AND b.deleted_at IS NULL");
+    query.build_query_as().fetch_optional(executor).await
+}
+
+/// Partial update; omitted fields are left untouched and `tags` replaces the whole set.
+#[derive(Deserialize)]
+struct UpdateBookmarkRequest {
+    url: Option<String>,
+    title: Option<String>,
+    notes: Option<String>,
+    tags: Option<Vec<String>>,
+    /// Whether the bookmark shows up on the owner's `/public/{username}` page
+    is_public: Option<bool>,
+}
+
+async fn update_bookmark(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdateBookmarkRequest>,
+) -> Result<Json<BookmarkResponse>, StatusCode> {
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Update Bookmark Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let url = payload
+        .url
+        .as_deref()
+        .map(normalize::normalize_url)
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+
+    let updated = sqlx::query(
+        "UPDATE bookmarks SET url = COALESCE($1, url), title = COALESCE($2, title),
+             title_from_page = title_from_page AND $2 IS NULL, notes = COALESCE($3, notes),
+             is_public = COALESCE($4, is_public), updated_at = now()
+         WHERE id = $5 AND user_id = $6 AND deleted_at IS NULL"
+    )
+    .bind(&url)
+    .bind(&payload.title)
+    .bind(&payload.notes)
+    .bind(payload.is_public)
+    .bind(id)
+    .bind(user.id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => StatusCode::CONFLICT,
+        e => db_error(e),
+    })?;
+
+    if updated.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    if let Some(tags) = &payload.tags {
+        replace_tags(&mut tx, user.id, id, tags).await.map_err(db_error)?;
+    }
+
+    let bookmark = fetch_bookmark(&mut *tx, user.id, id)
+        .await
+        .map_err(db_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    tx.commit().await.map_err(db_error)?;
+    events::emit(&state, user.id, events::Event::Updated, id);
+
+    Ok(Json(bookmark))
+}
+
+/// Flips a bookmark between read and unread.
+async fn toggle_read(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<BookmarkResponse>, StatusCode> {
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Toggle Read Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let updated = sqlx::query(
+        "UPDATE bookmarks SET read_at = CASE WHEN read_at IS NULL THEN now() END, updated_at = now()
+         WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL"
+    )
+    .bind(id)
+    .bind(user.id)
+    .execute(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    if updated.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    events::emit(&state, user.id, events::Event::Updated, id);
+
+    fetch_bookmark(&state.db, user.id, id)
+        .await
+        .map_err(db_error)?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn toggle_favorite(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<BookmarkResponse>, StatusCode> {
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Toggle Favorite Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let updated = sqlx::query(
+        "UPDATE bookmarks SET is_favorite = NOT is_favorite, updated_at = now()
+         WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL"
+    )
+    .bind(id)
+    .bind(user.id)
+    .execute(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    if updated.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    events::emit(&state, user.id, events::Event::Updated, id);
+
+    fetch_bookmark(&state.db, user.id, id)
+        .await
+        .map_err(db_error)?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Queues the bookmark to be tagged again; only the model's tags are replaced, unless the bookmark
+/// was saved with `tag_mode` `replace`.
+async fn retag_bookmark(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Retag Bookmark Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let owned: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM bookmarks WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL)"
+    )
+    .bind(id)
+    .bind(user.id)
+    .fetch_one(&state.db)
+    .await
+    .map_err(db_error)?;
+    if !owned {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    jobs::enqueue(&state, &[id]).await.map_err(db_error)?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Drops the tags the model added, keeping the user's.
+async fn discard_ai_tags(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<BookmarkResponse>, StatusCode> {
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Discard AI Tags Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let updated = sqlx::query(
+        "WITH discarded AS (
+             DELETE FROM bookmark_tags bt USING bookmarks b
+             WHERE b.id = bt.bookmark_id AND b.id = $1 AND b.user_id = $2 AND b.deleted_at IS NULL
+                 AND bt.source = 'ai'
+         )
+         UPDATE bookmarks SET updated_at = now() WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL"
+    )
+    .bind(id)
+    .bind(user.id)
+    .execute(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    if updated.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    events::emit(&state, user.id, events::Event::Updated, id);
+
+    fetch_bookmark(&state.db, user.id, id)
+        .await
+        .map_err(db_error)?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct BookmarkContent {
+    markdown: String,
+    fetched_at: DateTime<Utc>,
+}
+
+async fn get_bookmark_content(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<BookmarkContent>, StatusCode> {
+    sqlx::query_as::<_, BookmarkContent>(
+        "SELECT c.markdown, c.fetched_at FROM bookmark_contents c
+         JOIN bookmarks b ON b.id = c.bookmark_id
+         WHERE c.bookmark_id = $1 AND b.user_id = $2 AND b.deleted_at IS NULL"
+    )
+    .bind(id)
+    .bind(user.id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| {
+        eprintln!("Get Bookmark Content Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .map(Json)
+    .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// The stored content as a page to read, or the markdown itself for `Accept: text/markdown`.
+async fn get_bookmark_reader(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let (markdown, title, language): (String, String, Option<String>) = sqlx::query_as(
+        "SELECT c.markdown, COALESCE(b.title, b.url), b.language FROM bookmark_contents c
+         JOIN bookmarks b ON b.id = c.bookmark_id
+         WHERE c.bookmark_id = $1 AND b.user_id = $2 AND b.deleted_at IS NULL"
+    )
+    .bind(id)
+    .bind(user.id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| {
+        eprintln!("Get Bookmark Reader Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let wants_markdown = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/markdown"));
+    if wants_markdown {
+        return Ok(([(header::CONTENT_TYPE, "text/markdown; charset=utf-8")], markdown).into_response());
+    }
+    let page = reader::render(&title, language.as_deref(), &markdown);
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+            (header::CONTENT_SECURITY_POLICY, "sandbox"),
+        ],
+        page,
+    )
+        .into_response())
+}
+
+/// Has the Wayback Machine capture the bookmark's page now, waiting for it to finish.
+async fn archive_externally(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<BookmarkResponse>, StatusCode> {
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Archive Externally Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let url: String = sqlx::query_scalar("SELECT url FROM bookmarks WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL")
+        .bind(id)
+        .bind(user.id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    save_to_wayback(&state, user.id, id, &url).await.map_err(|e| {
+        eprintln!("Archive Externally Error: {}", e);
+        StatusCode::BAD_GATEWAY
+    })?;
+
+    fetch_bookmark(&state.db, user.id, id)
+        .await
+        .map_err(db_error)?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// The most of a `Referer` kept with a visit.
+const MAX_REFERRER_LEN: usize = 2048;
+
+/// `GET /go/{id}`: counts a visit and sends the browser on to the bookmarked page, so links in a
+/// client can go through here to record which bookmarks get used. A plain link can't carry a
+/// Bearer token, so this only works for a browser logged in with a session, as in `/ui`.
+async fn visit_bookmark(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let referrer = headers
+        .get(header::REFERER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty() && value.len() <= MAX_REFERRER_LEN);
+    // Not an edit, so `updated_at` stays as it was
+    let url: Option<String> = sqlx::query_scalar(
+        "WITH visited AS (
+             UPDATE bookmarks SET visit_count = visit_count + 1, last_visited_at = now()
+             WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL
+             RETURNING id, url
+         ), logged AS (
+             INSERT INTO bookmark_visits (bookmark_id, referrer) SELECT id, $3 FROM visited
+         )
+         SELECT url FROM visited"
+    )
+    .bind(id)
+    .bind(user.id)
+    .bind(referrer)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| {
+        eprintln!("Visit Bookmark Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let url = url.ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok((StatusCode::FOUND, [(header::LOCATION, url)]).into_response())
+}
+
+/// The raw HTML snapshot from object storage, sandboxed so its scripts can't run on our origin.
+async fn get_bookmark_archive(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, StatusCode> {
+    let archive = state.archive.as_ref().ok_or(StatusCode::NOT_IMPLEMENTED)?;
+
+    let archived: Option<Option<String>> =
+        sqlx::query_scalar("SELECT archive_url FROM bookmarks WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL")
+            .bind(id)
+            .bind(user.id)
+            .fetch_optional(&state.db)
+            .await
+            .map_err(|e| {
+                eprintln!("Get Bookmark Archive Error: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    if archived.flatten().is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    archived_page(archive, id).await
+}
+
+/// Fetches a bookmark's snapshot and serves it with scripts and same-origin access disabled.
+async fn archived_page(archive: &archive::Archive, bookmark_id: Uuid) -> Result<Response, StatusCode> {
+    let page = archive.get_page(bookmark_id).await.map_err(|e| {
+        eprintln!("Get Bookmark Archive Error: {}", e);
+        StatusCode::BAD_GATEWAY
+    })?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+            (header::CONTENT_SECURITY_POLICY, "sandbox"),
+        ],
+        page,
+    )
+        .into_response())
+}
+
+async fn get_bookmark_screenshot(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, StatusCode> {
+    let (content_type, image): (String, Vec<u8>) = sqlx::query_as(
+        "SELECT s.content_type, s.image FROM bookmark_screenshots s
+         JOIN bookmarks b ON b.id = s.bookmark_id
+         WHERE s.bookmark_id = $1 AND b.user_id = $2 AND b.deleted_at IS NULL"
+    )
+    .bind(id)
+    .bind(user.id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| {
+        eprintln!("Get Bookmark Screenshot Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(([(header::CONTENT_TYPE, content_type)], image).into_response())
+}
+
+#[derive(Serialize, Deserialize)]
+struct FolderCandidate {
+    id: Uuid,
+    url: String,
+    title: Option<String>,
+    ai_summary: Option<String>,
+    tags: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct SuggestFoldersRequest {
+    bookmarks: Vec<FolderCandidate>,
+    folders: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct Suggestion {
+    bookmark_id: Uuid,
+    folder_name: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct SuggestFoldersResponse {
+    suggestions: Vec<Suggestion>,
+}
+
+async fn suggest_folders(
+    State(state): State<AppState>,
+    Extension(_user): Extension<CurrentUser>,
+    Json(payload): Json<SuggestFoldersRequest>,
+) -> Result<Json<SuggestFoldersResponse>, StatusCode> {
+    if payload.folders.is_empty() {
+        return Ok(Json(SuggestFoldersResponse { suggestions: vec![] }));
+    }
+
+    let prompt = format!(
+        "Folders: {:?}\n\nBookmarks: {}", 
+        payload.folders,
+        serde_json::to_string(&payload.bookmarks).unwrap()
+    );
+
+    let suggestions = {
+        let _permit = state.ai_limit.acquire().await.map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+        state.tagger.suggest_folders(&prompt).await
+    };
+    let suggestions = suggestions
+        .map_err(|e| {
+            eprintln!("Suggest Folders Error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_IMPLEMENTED)?;
+
+    Ok(Json(suggestions))
+}
+
+/// One bookmark; someone else's, or one in the trash, is `404`.
+async fn get_bookmark(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<BookmarkResponse>, StatusCode> {
+    let bookmark = fetch_bookmark(&state.db, user.id, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Get Bookmark Error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(bookmark))
+}
+
+/// Moves a bookmark to the trash; someone else's, or one already there, is `404`.
+async fn delete_bookmark(
     State(state): State<AppState>,
     Extension(user): Extension<CurrentUser>,
     Path(id): Path<Uuid>,
 ) -> Result<StatusCode, StatusCode> {
-    sqlx::query("DELETE FROM bookmarks WHERE id = $1 AND user_id = $2")
+    let deleted = sqlx::query("UPDATE bookmarks SET deleted_at = now(), updated_at = now() WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL")
         .bind(id)
         .bind(user.id)
         .execute(&state.db)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| {
+            eprintln!("Delete Bookmark Error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
 
+    if deleted.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    events::emit(&state, user.id, events::Event::Deleted, id);
     Ok(StatusCode::NO_CONTENT)
 }
 
+/// The bookmarks a bulk action applies to.
+#[derive(Deserialize)]
+struct BulkRequest {
+    /// These bookmarks
+    ids: Option<Vec<Uuid>>,
+    /// Or every bookmark these `GET /bookmarks` filters match
+    filter: Option<SearchParams>,
+    /// Only count what would be affected
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Serialize)]
+struct BulkResponse {
+    /// How many bookmarks were affected, or would be
+    count: usize,
+    dry_run: bool,
+}
+
+/// The filters of a bulk action, which has to set at least one: an empty filter would match
+/// everything, which is far more likely a mistake.
+fn push_bulk_filter(query: &mut QueryBuilder<Postgres>, filter: &SearchParams) -> Result<(), StatusCode> {
+    if !narrows(filter) || filter.semantic.is_some() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    push_list_filters(query, filter)
+}
+
+/// Whether a filter leaves anything out. `sort` and `order` only arrange the results, and a blank
+/// `q` searches for nothing in particular.
+fn narrows(filter: &SearchParams) -> bool {
+    serde_json::to_value(filter)
+        .ok()
+        .and_then(|value| {
+            value.as_object().map(|fields| {
+                fields.iter().any(|(name, value)| {
+                    !matches!(name.as_str(), "sort" | "order")
+                        && !value.is_null()
+                        && value.as_str().is_none_or(|text| !text.trim().is_empty())
+                })
+            })
+        })
+        .unwrap_or(false)
+}
+
+/// The query for the live bookmarks a bulk action applies to.
+fn bulk_selection<'a>(user_id: Uuid, payload: &'a BulkRequest) -> Result<QueryBuilder<'a, Postgres>, StatusCode> {
+    let mut query = QueryBuilder::<Postgres>::new("SELECT b.id FROM bookmarks b WHERE b.user_id = ");
+    query.push_bind(user_id);
+    match (&payload.ids, &payload.filter) {
+        (Some(ids), None) => {
+            query.push(" AND b.deleted_at IS NULL AND b.id = ANY(").push_bind(ids.clone()).push(")");
+        }
+        (None, Some(filter)) => push_bulk_filter(&mut query, filter)?,
+        _ => return Err(StatusCode::BAD_REQUEST),
+    }
+    Ok(query)
+}
+
+/// Moves a list of bookmarks, or everything matching a filter, to the trash at once.
+async fn bulk_delete_bookmarks(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Json(payload): Json<BulkRequest>,
+) -> Result<Json<BulkResponse>, StatusCode> {
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Bulk Delete Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let mut query = bulk_selection(user.id, &payload)?;
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    query.push(" FOR UPDATE");
+    let ids: Vec<Uuid> = query.build_query_scalar().fetch_all(&mut *tx).await.map_err(db_error)?;
+    if payload.dry_run {
+        return Ok(Json(BulkResponse { count: ids.len(), dry_run: true }));
+    }
+    sqlx::query("UPDATE bookmarks SET deleted_at = now(), updated_at = now() WHERE id = ANY($1)")
+        .bind(&ids)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    tx.commit().await.map_err(db_error)?;
+
+    for &id in &ids {
+        events::emit(&state, user.id, events::Event::Deleted, id);
+    }
+    Ok(Json(BulkResponse { count: ids.len(), dry_run: false }))
+}
+
+/// Queues a list of bookmarks, or everything matching a filter, to be fetched and tagged again,
+/// such as the ones left `untagged` or stuck `pending` after a lost job. Those already queued
+/// are left where they are.
+async fn bulk_reprocess_bookmarks(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Json(payload): Json<BulkRequest>,
+) -> Result<Json<BulkResponse>, StatusCode> {
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Bulk Reprocess Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let mut query = bulk_selection(user.id, &payload)?;
+    let ids: Vec<Uuid> = query.build_query_scalar().fetch_all(&state.db).await.map_err(db_error)?;
+    if !payload.dry_run {
+        jobs::enqueue(&state, &ids).await.map_err(db_error)?;
+    }
+    Ok(Json(BulkResponse { count: ids.len(), dry_run: payload.dry_run }))
+}
+
+async fn list_trash(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Result<Json<Vec<BookmarkResponse>>, StatusCode> {
+    let mut query = QueryBuilder::<Postgres>::new(BOOKMARK_SELECT);
+    query
+        .push(" WHERE b.user_id = ")
+        .push_bind(user.id)
+        .push(" AND b.deleted_at IS NOT NULL ORDER BY b.deleted_at DESC, b.id DESC");
+
+    query
+        .build_query_as()
+        .fetch_all(&state.db)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            eprintln!("List Trash Error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+async fn restore_bookmark(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<BookmarkResponse>, StatusCode> {
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Restore Bookmark Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let restored = sqlx::query(
+        "UPDATE bookmarks SET deleted_at = NULL, updated_at = now()
+         WHERE id = $1 AND user_id = $2 AND deleted_at IS NOT NULL"
+    )
+    .bind(id)
+    .bind(user.id)
+    .execute(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    if restored.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    events::emit(&state, user.id, events::Event::Updated, id);
+
+    fetch_bookmark(&state.db, user.id, id)
+        .await
+        .map_err(db_error)?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
 async fn health_check(State(state): State<AppState>) -> String {
     let row: (i32,) = sqlx::query_as::<_, (i32,)>("SELECT 1")
         .fetch_one(&state.db)
@@ -425,6 +2822,102 @@ async fn health_check(State(state): State<AppState>) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use axum_test::TestServer;
+
+    /// State backed by a lazy pool, for exercising routes that never reach the database.
+    fn test_state() -> AppState {
+        let db = PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/linkman")
+            .unwrap();
+        let openai = openai::Client::builder().api_key("sk-test").build().unwrap();
+        AppState {
+            db,
+            openai: Arc::new(openai),
+            tagger: Arc::new(tagging::Offline),
+            embedding_model: None,
+            archive: None,
+            screenshots: None,
+            resolve_canonical: true,
+            shaarli_api: false,
+            secure_cookies: false,
+            events: events::channel(),
+            rate_limiter: None,
+            metrics: Arc::new(metrics::Metrics::new(None)),
+            ai_check: None,
+            job_wakeup: Arc::new(Notify::new()),
+            fetcher: Arc::new(
+                fetcher::Fetcher::from_config(
+                    &config::FetchConfig::default(),
+                    retry::Policy::from_config(&config::RetryConfig::default()),
+                    1,
+                )
+                .unwrap(),
+            ),
+            webhook_client: reqwest::Client::new(),
+            credential_key: None,
+            wayback: None,
+            save_page_now: Arc::new(wayback::SavePageNow::from_config(&config::ArchiveConfig::default()).unwrap()),
+            wayback_every_bookmark: false,
+            change_threshold: config::RecrawlConfig::default().change_threshold,
+            ai_limit: Arc::new(Semaphore::new(1)),
+            slack: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_router_requires_auth() {
+        let server = TestServer::new(app(test_state()));
+        server.get("/").await.assert_text("Linkman API");
+        server
+            .get("/bookmarks")
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+        server.get("/healthz").await.assert_text("ok");
+    }
+
+    /// Runs against `DATABASE_URL` when it's set, and is skipped otherwise.
+    #[tokio::test]
+    async fn test_update_keeps_ai_tags() {
+        let Ok(url) = std::env::var("DATABASE_URL") else { return };
+        let db = PgPoolOptions::new().connect(&url).await.unwrap();
+        MIGRATOR.run(&db).await.unwrap();
+        let username = format!("test-{}", Uuid::new_v4());
+        let user_id: Uuid = sqlx::query_scalar("INSERT INTO users (username) VALUES ($1) RETURNING id")
+            .bind(&username)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        let id: Uuid = sqlx::query_scalar("INSERT INTO bookmarks (user_id, url) VALUES ($1, 'https://example.com') RETURNING id")
+            .bind(user_id)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        let mut tx = db.begin().await.unwrap();
+        attach_tags(&mut tx, user_id, id, &["rust".to_string(), "async".to_string()], TagSource::Ai).await.unwrap();
+        tx.commit().await.unwrap();
+
+        let state = AppState { db: db.clone(), ..test_state() };
+        let payload = UpdateBookmarkRequest {
+            url: None,
+            title: None,
+            notes: None,
+            tags: Some(vec!["rust".to_string(), "web".to_string()]),
+            is_public: None,
+        };
+        let updated = update_bookmark(State(state), Extension(CurrentUser { id: user_id, username }), Path(id), Json(payload)).await;
+        let sources: Vec<(String, TagSource)> = sqlx::query_as(
+            "SELECT t.name, bt.source FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id
+             WHERE bt.bookmark_id = $1 ORDER BY t.name"
+        )
+        .bind(id)
+        .fetch_all(&db)
+        .await
+        .unwrap();
+        sqlx::query("DELETE FROM users WHERE id = $1").bind(user_id).execute(&db).await.unwrap();
+
+        assert!(updated.is_ok());
+        assert_eq!(sources, vec![("rust".to_string(), TagSource::Ai), ("web".to_string(), TagSource::User)]);
+    }
 
     #[test]
     fn test_scrape_metadata() {
@@ -445,6 +2938,33 @@ mod tests {
         assert_eq!(meta["description"], "A test description");
         assert_eq!(meta["og:title"], "OG Title");
         assert!(meta.get("scraped_at").is_some());
+        assert_eq!(page_title(&meta).as_deref(), Some("OG Title"));
+
+        let meta = scrape_metadata("<title>\n  Spread\n  Out </title>");
+        assert_eq!(page_title(&meta).as_deref(), Some("Spread Out"));
+        assert_eq!(page_title(&scrape_metadata("<title> </title>")), None);
+    }
+
+    #[test]
+    fn test_page_markdown() {
+        let html = r#"
+            <html>
+            <head><title>Ignored</title><style>body { color: red; }</style></head>
+            <body>
+                <nav><a href="/">Home</a></nav>
+                <h1>Hello</h1>
+                <p>Some <strong>bold</strong> text.</p>
+                <script>alert("hi")</script>
+            </body>
+            </html>
+        "#;
+
+        let markdown = page_markdown(html);
+        assert!(markdown.contains("# Hello"));
+        assert!(markdown.contains("Some **bold** text."));
+        assert!(!markdown.contains("Home"));
+        assert!(!markdown.contains("alert"));
+        assert!(!markdown.contains("color: red"));
     }
 
     #[test]
@@ -453,5 +2973,122 @@ mod tests {
         let req: SyncBookmarkRequest = serde_json::from_str(json).unwrap();
         assert_eq!(req.url, "https://google.com");
         assert_eq!(req.title.unwrap(), "Google");
+        assert!(req.notes.is_none());
+    }
+
+    #[test]
+    fn test_required_scope() {
+        assert_eq!(required_scope(&Method::GET, "/bookmarks"), "read");
+        assert_eq!(required_scope(&Method::HEAD, "/bookmarks/sync"), "read");
+        assert_eq!(required_scope(&Method::POST, "/graphql"), "read");
+        assert_eq!(required_scope(&Method::POST, "/bookmarks/check"), "read");
+        assert_eq!(required_scope(&Method::POST, "/bookmarks/sync"), "write");
+        assert_eq!(required_scope(&Method::DELETE, "/bookmarks/1"), "write");
+    }
+
+    #[test]
+    fn test_key_is_full() {
+        let key = |scopes: &[&str]| ApiKey {
+            token: Uuid::nil(),
+            id: None,
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            tag_mode: TagMode::default(),
+        };
+        assert!(key(&["read", "write"]).is_full());
+        assert!(!key(&["write"]).is_full());
+        assert!(!key(&[]).is_full());
+    }
+
+    #[test]
+    fn test_narrows() {
+        let filter = |json: serde_json::Value| serde_json::from_value::<SearchParams>(json).unwrap();
+        assert!(!narrows(&filter(serde_json::json!({}))));
+        assert!(!narrows(&filter(serde_json::json!({"sort": "title", "order": "asc"}))));
+        assert!(!narrows(&filter(serde_json::json!({"q": ""}))));
+        assert!(!narrows(&filter(serde_json::json!({"q": "  ", "sort": "domain"}))));
+        assert!(narrows(&filter(serde_json::json!({"q": "rust"}))));
+        assert!(narrows(&filter(serde_json::json!({"unread": false}))));
+        assert!(narrows(&filter(serde_json::json!({"tag": "rust", "sort": "title"}))));
+    }
+
+    #[test]
+    fn test_tag_path() {
+        assert_eq!(tag_path("rust").as_deref(), Some("rust"));
+        assert_eq!(tag_path(" dev / rust /async/ ").as_deref(), Some("dev/rust/async"));
+        assert_eq!(tag_path("dev//rust").as_deref(), Some("dev/rust"));
+        assert_eq!(tag_path(" / "), None);
+        assert_eq!(tag_path(""), None);
+    }
+
+    #[test]
+    fn test_split_query() {
+        assert_eq!(split_query("rust  async").unwrap(), ("rust async".to_string(), vec![]));
+        assert_eq!(
+            split_query("site:lobste.rs rust Site:www.github.com").unwrap(),
+            ("rust".to_string(), vec!["lobste.rs".to_string(), "github.com".to_string()])
+        );
+        assert_eq!(split_query("").unwrap(), (String::new(), vec![]));
+        assert!(split_query("site: rust").is_err());
+    }
+
+    #[test]
+    fn test_text_cursor_roundtrip() {
+        let id = Uuid::new_v4();
+        for key in ["", "Rust_2024: what's new?", "日本語"] {
+            let cursor = encode_text_cursor(key, id);
+            assert!(cursor.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+            assert_eq!(decode_text_cursor(&cursor), Some((key.to_string(), id)));
+        }
+        assert_eq!(decode_text_cursor("not a cursor"), None);
+    }
+
+    #[test]
+    fn test_cursor_roundtrip() {
+        let created_at = DateTime::from_timestamp_micros(1_715_900_000_123_456).unwrap();
+        let id = Uuid::new_v4();
+        let cursor = encode_cursor(created_at, id);
+        assert_eq!(decode_cursor(&cursor), Some((created_at, id)));
+        assert_eq!(decode_cursor("garbage"), None);
+        assert_eq!(decode_cursor("123_not-a-uuid"), None);
+    }
+
+    #[test]
+    fn test_count_cursor_roundtrip() {
+        let id = Uuid::new_v4();
+        assert_eq!(decode_count_cursor(&encode_count_cursor(42, id)), Some((42, id)));
+        assert_eq!(decode_count_cursor("many_visits"), None);
+    }
+
+    /// `linkman-client` has its own copies of these; they have to agree on the JSON.
+    #[test]
+    fn test_client_types() {
+        let page = BookmarkPage { bookmarks: vec![BookmarkResponse::default()], next_cursor: Some("x".to_string()) };
+        let page: linkman_client::BookmarkPage = serde_json::from_value(serde_json::to_value(page).unwrap()).unwrap();
+        assert_eq!(page.bookmarks[0].processing_status, "");
+
+        let filter = linkman_client::Filter {
+            q: Some("rust".to_string()),
+            unread: Some(false),
+            collection: Some(Uuid::nil()),
+            created_after: Some(Utc::now()),
+            sort: Some(linkman_client::Sort::MostVisited),
+            order: Some(linkman_client::Order::Asc),
+            ..Default::default()
+        };
+        let params: SearchParams = serde_json::from_value(serde_json::to_value(&filter).unwrap()).unwrap();
+        assert_eq!(serde_json::to_value(&params).unwrap(), serde_json::to_value(&filter).unwrap());
+
+        let new = linkman_client::NewBookmark {
+            url: "https://example.com".to_string(),
+            tags: Some(vec!["rust".to_string()]),
+            tag_mode: Some(linkman_client::TagMode::Replace),
+            ..Default::default()
+        };
+        let request: SyncBookmarkRequest = serde_json::from_value(serde_json::to_value(new).unwrap()).unwrap();
+        assert_eq!(request.tag_mode, Some(TagMode::Replace));
+
+        let update = linkman_client::BookmarkUpdate { is_public: Some(true), ..Default::default() };
+        let request: UpdateBookmarkRequest = serde_json::from_value(serde_json::to_value(update).unwrap()).unwrap();
+        assert_eq!(request.is_public, Some(true));
     }
 }
@@ -0,0 +1,282 @@
+//! Bringing a Mastodon account's bookmarks, and optionally its favourites, into linkman.
+//!
+//! With `MASTODON_URL`, `MASTODON_TOKEN` and `MASTODON_SYNC_USER` set, the account's newest
+//! bookmarked posts are fetched every `MASTODON_SYNC_INTERVAL_HOURS` and saved for that linkman
+//! user, paging back until a page holds nothing new. Each post is bookmarked at its own address
+//! with its text, content warning, image descriptions and link preview as the bookmark's
+//! content, which fetching the post's page later leaves alone: the page is mostly Mastodon's app.
+//! The author and the post's time become the bookmark's, and the account, post id and what the
+//! post linked to are kept under `mastodon` in the page metadata. Unbookmarking a post on
+//! Mastodon leaves it in linkman.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use scraper::Html;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::{changes, config::MastodonConfig, events, jobs, normalize::normalize_url, page_markdown, AppState};
+
+/// The most Mastodon returns at once.
+const PAGE_SIZE: usize = 40;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const TITLE_CHARS: usize = 80;
+
+pub struct Config {
+    client: reqwest::Client,
+    instance: String,
+    token: String,
+    username: String,
+    favourites: bool,
+    interval: Duration,
+}
+
+impl Config {
+    pub fn from_config(config: &MastodonConfig) -> anyhow::Result<Option<Self>> {
+        let (Some(instance), Some(token), Some(username)) = (&config.url, &config.token, &config.user) else {
+            return Ok(None);
+        };
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent(format!("linkman/{}", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(Some(Config {
+            client,
+            instance: instance.trim_end_matches('/').to_string(),
+            token: token.clone(),
+            username: username.clone(),
+            favourites: config.favourites,
+            interval: Duration::from_secs(config.interval_hours * 60 * 60),
+        }))
+    }
+}
+
+pub async fn run(state: AppState, config: Config) {
+    let mut tick = tokio::time::interval(config.interval);
+    loop {
+        tick.tick().await;
+        let result = sync(&state, &config).await;
+        state.metrics.record_job("mastodon_sync", result.is_ok());
+        if let Err(e) = result {
+            eprintln!("Mastodon Sync Error: {}", e);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Status {
+    id: String,
+    created_at: DateTime<Utc>,
+    /// The post's page; some servers only give `uri`
+    url: Option<String>,
+    uri: String,
+    /// HTML
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    spoiler_text: String,
+    account: Account,
+    #[serde(default)]
+    media_attachments: Vec<Attachment>,
+    card: Option<Card>,
+}
+
+#[derive(Deserialize)]
+struct Account {
+    acct: String,
+    #[serde(default)]
+    display_name: String,
+}
+
+#[derive(Deserialize)]
+struct Attachment {
+    #[serde(rename = "type")]
+    kind: String,
+    url: Option<String>,
+    description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Card {
+    url: String,
+    #[serde(default)]
+    title: String,
+}
+
+impl Status {
+    fn text(&self) -> String {
+        let fragment = Html::parse_fragment(&self.content);
+        fragment.root_element().text().collect::<Vec<_>>().join(" ").split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    fn author(&self) -> &str {
+        if self.account.display_name.is_empty() { &self.account.acct } else { &self.account.display_name }
+    }
+
+    /// `Author: the start of the post…`
+    fn title(&self) -> String {
+        let text = if self.spoiler_text.is_empty() { self.text() } else { self.spoiler_text.clone() };
+        let mut title: String = text.chars().take(TITLE_CHARS).collect();
+        if text.chars().count() > TITLE_CHARS {
+            title.push('…');
+        }
+        format!("{}: {}", self.author(), title)
+    }
+
+    /// The post as the bookmark's content.
+    fn markdown(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.spoiler_text.is_empty() {
+            parts.push(format!("**CW: {}**", self.spoiler_text));
+        }
+        parts.push(page_markdown(&self.content).trim().to_string());
+        for media in &self.media_attachments {
+            let description = media.description.as_deref().unwrap_or_default();
+            match (&media.url, media.kind.as_str()) {
+                (Some(url), "image") => parts.push(format!("![{}]({})", description, url)),
+                (Some(url), kind) => parts.push(format!("[{}: {}]({})", kind, description, url)),
+                (None, _) => {}
+            }
+        }
+        if let Some(card) = &self.card {
+            let title = if card.title.is_empty() { &card.url } else { &card.title };
+            parts.push(format!("[{}]({})", title, card.url));
+        }
+        parts.retain(|part| !part.is_empty());
+        parts.join("\n\n")
+    }
+
+    /// What's kept under `mastodon` in the bookmark's page metadata.
+    fn metadata(&self) -> Value {
+        json!({
+            "id": self.id,
+            "account": self.account.acct,
+            "uri": self.uri,
+            "link": self.card.as_ref().map(|card| &card.url),
+        })
+    }
+}
+
+/// The `rel="next"` address in a `Link` header, where the next, older page is.
+fn next_link(header: &str) -> Option<String> {
+    header.split(',').find_map(|link| {
+        let (target, params) = link.split_once(';')?;
+        params.contains("rel=\"next\"").then(|| target.trim().trim_start_matches('<').trim_end_matches('>').to_string())
+    })
+}
+
+async fn sync(state: &AppState, config: &Config) -> anyhow::Result<()> {
+    let user_id = crate::user_id(&state.db, &config.username).await?;
+    let mut lists = vec!["bookmarks"];
+    if config.favourites {
+        lists.push("favourites");
+    }
+
+    let mut created = Vec::new();
+    for list in lists {
+        let mut next = Some(format!("{}/api/v1/{}?limit={}", config.instance, list, PAGE_SIZE));
+        while let Some(url) = next.take() {
+            let response = config.client.get(&url).bearer_auth(&config.token).send().await?.error_for_status()?;
+            next = response
+                .headers()
+                .get(reqwest::header::LINK)
+                .and_then(|link| link.to_str().ok())
+                .and_then(next_link);
+            let statuses: Vec<Status> = response.json().await?;
+
+            let new = save(state, user_id, &statuses).await?;
+            // Older pages were saved by an earlier sync
+            if new.is_empty() || statuses.len() < PAGE_SIZE {
+                next = None;
+            }
+            created.extend(new);
+        }
+    }
+
+    for id in &created {
+        events::emit(state, user_id, events::Event::Created, *id);
+    }
+    if !created.is_empty() {
+        println!("saved {} posts from Mastodon", created.len());
+    }
+    Ok(())
+}
+
+/// Bookmarks the posts the user doesn't have yet and queues them, returning the new bookmarks' ids.
+async fn save(state: &AppState, user_id: Uuid, statuses: &[Status]) -> anyhow::Result<Vec<Uuid>> {
+    let mut tx = state.db.begin().await?;
+    let mut created = Vec::new();
+    for status in statuses {
+        let Ok(url) = normalize_url(status.url.as_deref().unwrap_or(&status.uri)) else { continue };
+        let id: Option<Uuid> = sqlx::query_scalar(
+            "INSERT INTO bookmarks (user_id, url, title, author, published_at, image_url, site_meta)
+             VALUES ($1, $2, $3, $4, $5, $6, jsonb_build_object('mastodon', $7::jsonb))
+             ON CONFLICT (user_id, url) DO NOTHING
+             RETURNING id"
+        )
+        .bind(user_id)
+        .bind(&url)
+        .bind(status.title())
+        .bind(&status.account.acct)
+        .bind(status.created_at)
+        .bind(status.media_attachments.iter().find(|media| media.kind == "image").and_then(|media| media.url.as_ref()))
+        .bind(status.metadata())
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(id) = id else { continue };
+
+        let markdown = status.markdown();
+        sqlx::query("INSERT INTO bookmark_contents (bookmark_id, markdown, content_hash) VALUES ($1, $2, $3)")
+            .bind(id)
+            .bind(&markdown)
+            .bind(changes::hash(&markdown))
+            .execute(&mut *tx)
+            .await?;
+        created.push(id);
+    }
+    jobs::queue(&mut *tx, &created).await?;
+    tx.commit().await?;
+    state.job_wakeup.notify_one();
+    Ok(created)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status() {
+        let status: Status = serde_json::from_str(
+            r#"{
+                "id": "112233",
+                "created_at": "2024-06-01T12:00:00.000Z",
+                "url": "https://mastodon.social/@alice/112233",
+                "uri": "https://mastodon.social/users/alice/statuses/112233",
+                "content": "<p>Wrote up how we run <a href=\"https://mastodon.social/tags/postgres\">#<span>postgres</span></a> backups.</p><p>Feedback welcome!</p>",
+                "spoiler_text": "",
+                "account": { "acct": "alice", "display_name": "Alice" },
+                "media_attachments": [
+                    { "type": "image", "url": "https://files.example/diagram.png", "description": "Backup diagram" }
+                ],
+                "card": { "url": "https://alice.example/backups", "title": "Our backups" }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(status.title(), "Alice: Wrote up how we run # postgres backups. Feedback welcome!");
+        let markdown = status.markdown();
+        assert!(markdown.contains("Feedback welcome!"));
+        assert!(markdown.contains("![Backup diagram](https://files.example/diagram.png)"));
+        assert!(markdown.ends_with("[Our backups](https://alice.example/backups)"));
+        assert_eq!(status.metadata()["link"], "https://alice.example/backups");
+    }
+
+    #[test]
+    fn test_next_link() {
+        let header = "<https://mastodon.social/api/v1/bookmarks?limit=40&max_id=7>; rel=\"next\", \
+                      <https://mastodon.social/api/v1/bookmarks?limit=40&min_id=9>; rel=\"prev\"";
+        assert_eq!(next_link(header).as_deref(), Some("https://mastodon.social/api/v1/bookmarks?limit=40&max_id=7"));
+        assert_eq!(next_link("<https://mastodon.social/api/v1/bookmarks?min_id=9>; rel=\"prev\""), None);
+    }
+}
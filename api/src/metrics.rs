@@ -0,0 +1,244 @@
+//! Prometheus metrics at `GET /metrics`.
+//!
+//! Counts and times every request by matched route, counts background job runs and model calls by
+//! outcome along with the tokens they used, and reports the Postgres pool when scraped. Set
+//! `METRICS_TOKEN` to make scrapers send it as a bearer token. Everything is kept in memory, so
+//! counters start again from zero when the server restarts, as Prometheus expects.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use rig::completion::Usage;
+use sqlx::PgPool;
+
+use crate::{config::MetricsConfig, AppState};
+
+/// Upper bounds, in seconds, of the request latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+pub struct Metrics {
+    token: Option<String>,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// By method, route and status
+    requests: BTreeMap<(String, String, u16), u64>,
+    /// By method and route
+    latencies: BTreeMap<(String, String), Histogram>,
+    /// By job and whether it succeeded
+    jobs: BTreeMap<(&'static str, bool), u64>,
+    /// By call and whether it succeeded
+    ai_calls: BTreeMap<(&'static str, bool), u64>,
+    /// By call and direction
+    ai_tokens: BTreeMap<(&'static str, &'static str), u64>,
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// Observations per bucket, not cumulative; the last one is `+Inf`
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        let bucket = LATENCY_BUCKETS.iter().position(|&le| seconds <= le).unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+fn outcome(ok: bool) -> &'static str {
+    if ok { "success" } else { "failure" }
+}
+
+/// Label values are free text as far as the format is concerned.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+impl Metrics {
+    pub fn from_config(config: &MetricsConfig) -> Self {
+        Self::new(config.token.clone())
+    }
+
+    pub fn new(token: Option<String>) -> Self {
+        Metrics {
+            token,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    fn record_request(&self, method: &str, route: &str, status: StatusCode, elapsed: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner
+            .requests
+            .entry((method.to_string(), route.to_string(), status.as_u16()))
+            .or_default() += 1;
+        inner
+            .latencies
+            .entry((method.to_string(), route.to_string()))
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Counts one run of a background job, e.g. processing a bookmark or delivering a webhook.
+    pub fn record_job(&self, job: &'static str, ok: bool) {
+        *self.inner.lock().unwrap().jobs.entry((job, ok)).or_default() += 1;
+    }
+
+    /// Counts one call to the model; `usage` is what a successful call reported spending.
+    pub fn record_ai_call(&self, call: &'static str, ok: bool, usage: Option<&Usage>) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner.ai_calls.entry((call, ok)).or_default() += 1;
+        if let Some(usage) = usage {
+            *inner.ai_tokens.entry((call, "input")).or_default() += usage.input_tokens;
+            *inner.ai_tokens.entry((call, "output")).or_default() += usage.output_tokens;
+        }
+    }
+
+    /// The text exposition format, with the pool's current state read from `db`.
+    fn render(&self, db: &PgPool) -> String {
+        let inner = self.inner.lock().unwrap();
+        let mut out = String::new();
+
+        out.push_str("# HELP http_requests_total HTTP requests handled, by matched route.\n");
+        out.push_str("# TYPE http_requests_total counter\n");
+        for ((method, route, status), count) in &inner.requests {
+            let _ = writeln!(
+                out,
+                "http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                method,
+                escape(route),
+                status,
+                count
+            );
+        }
+
+        out.push_str("# HELP http_request_duration_seconds Time taken to answer HTTP requests.\n");
+        out.push_str("# TYPE http_request_duration_seconds histogram\n");
+        for ((method, route), histogram) in &inner.latencies {
+            let labels = format!("method=\"{}\",route=\"{}\"", method, escape(route));
+            let mut cumulative = 0;
+            for (i, count) in histogram.buckets.iter().enumerate() {
+                cumulative += count;
+                let le = LATENCY_BUCKETS.get(i).map_or("+Inf".to_string(), |le| le.to_string());
+                let _ = writeln!(out, "http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}", labels, le, cumulative);
+            }
+            let _ = writeln!(out, "http_request_duration_seconds_sum{{{}}} {}", labels, histogram.sum);
+            let _ = writeln!(out, "http_request_duration_seconds_count{{{}}} {}", labels, histogram.count);
+        }
+
+        out.push_str("# HELP background_jobs_total Background job runs, by outcome.\n");
+        out.push_str("# TYPE background_jobs_total counter\n");
+        for ((job, ok), count) in &inner.jobs {
+            let _ = writeln!(out, "background_jobs_total{{job=\"{}\",outcome=\"{}\"}} {}", job, outcome(*ok), count);
+        }
+
+        out.push_str("# HELP ai_requests_total Calls to the language and embedding models, by outcome.\n");
+        out.push_str("# TYPE ai_requests_total counter\n");
+        for ((call, ok), count) in &inner.ai_calls {
+            let _ = writeln!(out, "ai_requests_total{{call=\"{}\",outcome=\"{}\"}} {}", call, outcome(*ok), count);
+        }
+
+        out.push_str("# HELP ai_tokens_total Tokens the models reported using.\n");
+        out.push_str("# TYPE ai_tokens_total counter\n");
+        for ((call, direction), count) in &inner.ai_tokens {
+            let _ = writeln!(out, "ai_tokens_total{{call=\"{}\",direction=\"{}\"}} {}", call, direction, count);
+        }
+
+        let idle = db.num_idle() as u32;
+        out.push_str("# HELP db_pool_connections Open Postgres connections, by state.\n");
+        out.push_str("# TYPE db_pool_connections gauge\n");
+        let _ = writeln!(out, "db_pool_connections{{state=\"idle\"}} {}", idle);
+        let _ = writeln!(out, "db_pool_connections{{state=\"in_use\"}} {}", db.size().saturating_sub(idle));
+        out.push_str("# HELP db_pool_max_connections The most Postgres connections the pool will open.\n");
+        out.push_str("# TYPE db_pool_max_connections gauge\n");
+        let _ = writeln!(out, "db_pool_max_connections {}", db.options().get_max_connections());
+
+        out
+    }
+}
+
+/// Wraps every route, so requests turned away by authentication are counted too.
+pub async fn middleware(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched".to_string(), |path| path.as_str().to_string());
+    let method = req.method().to_string();
+
+    let started = Instant::now();
+    let res = next.run(req).await;
+    state.metrics.record_request(&method, &route, res.status(), started.elapsed());
+    res
+}
+
+pub async fn export(State(state): State<AppState>, headers: HeaderMap) -> Result<Response, StatusCode> {
+    if let Some(token) = &state.metrics.token {
+        let given = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        if given != Some(token.as_str()) {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(&state.db),
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_render() {
+        let metrics = Metrics::new(None);
+        metrics.record_request("GET", "/bookmarks/{id}", StatusCode::OK, Duration::from_millis(30));
+        metrics.record_request("GET", "/bookmarks/{id}", StatusCode::OK, Duration::from_secs(20));
+        metrics.record_job("bookmark_processing", false);
+        let mut usage = Usage::new();
+        usage.input_tokens = 100;
+        usage.output_tokens = 20;
+        metrics.record_ai_call("enrichment", true, Some(&usage));
+
+        let db = sqlx::postgres::PgPoolOptions::new()
+            .max_connections(3)
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap();
+        let out = metrics.render(&db);
+
+        for line in [
+            "http_requests_total{method=\"GET\",route=\"/bookmarks/{id}\",status=\"200\"} 2",
+            "http_request_duration_seconds_bucket{method=\"GET\",route=\"/bookmarks/{id}\",le=\"0.025\"} 0",
+            "http_request_duration_seconds_bucket{method=\"GET\",route=\"/bookmarks/{id}\",le=\"0.05\"} 1",
+            "http_request_duration_seconds_bucket{method=\"GET\",route=\"/bookmarks/{id}\",le=\"10\"} 1",
+            "http_request_duration_seconds_bucket{method=\"GET\",route=\"/bookmarks/{id}\",le=\"+Inf\"} 2",
+            "http_request_duration_seconds_count{method=\"GET\",route=\"/bookmarks/{id}\"} 2",
+            "background_jobs_total{job=\"bookmark_processing\",outcome=\"failure\"} 1",
+            "ai_requests_total{call=\"enrichment\",outcome=\"success\"} 1",
+            "ai_tokens_total{call=\"enrichment\",direction=\"input\"} 100",
+            "ai_tokens_total{call=\"enrichment\",direction=\"output\"} 20",
+            "db_pool_max_connections 3",
+        ] {
+            assert!(out.lines().any(|l| l == line), "missing {:?} in\n{}", line, out);
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Reading and writing the Netscape `bookmarks.html` format every browser exports.
+
+use chrono::DateTime;
+use scraper::{ElementRef, Html, Selector};
+
+use crate::import::{normalize_tag, ExternalBookmark};
+
+/// Parses an export, turning the enclosing folder path of each link into tags.
+///
+/// Links are returned in document order and may contain duplicates; callers dedupe.
+pub fn parse(html: &str) -> Vec<ExternalBookmark> {
+    let document = Html::parse_document(html);
+    let link_selector = Selector::parse("a[href]").unwrap();
+
+    document
+        .select(&link_selector)
+        .filter_map(|link| {
+            let attrs = link.value();
+            let url = attrs.attr("href")?.trim();
+            if !(url.starts_with("http://") || url.starts_with("https://")) {
+                // Skip javascript: bookmarklets, place: queries and the like
+                return None;
+            }
+
+            let title = link.text().collect::<String>().trim().to_string();
+            let mut tags = folder_path(link);
+            if let Some(explicit) = attrs.attr("tags") {
+                tags.extend(explicit.split(',').map(normalize_tag));
+            }
+            tags.retain(|t| !t.is_empty());
+            tags.dedup();
+
+            let added_at = attrs
+                .attr("add_date")
+                .and_then(|d| d.parse::<i64>().ok())
+                .and_then(|secs| DateTime::from_timestamp(secs, 0));
+
+            Some(ExternalBookmark {
+                url: url.to_string(),
+                title: (!title.is_empty()).then_some(title),
+                tags,
+                added_at,
+                description: description(link),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Folder names from the outermost folder down to the one holding `link`.
+fn folder_path(link: ElementRef) -> Vec<String> {
+    let mut path: Vec<String> = link
+        .ancestors()
+        .filter_map(ElementRef::wrap)
+        .filter(|el| el.value().name() == "dt")
+        .filter_map(|dt| {
+            dt.children()
+                .filter_map(ElementRef::wrap)
+                .find(|child| child.value().name() == "h3")
+        })
+        // The browser toolbar root isn't a folder anyone thinks of as a category
+        .filter(|h3| h3.value().attr("personal_toolbar_folder") != Some("true"))
+        .map(|h3| normalize_tag(&h3.text().collect::<String>()))
+        .collect();
+    path.reverse();
+    path
+}
+
+/// The `<DD>` text browsers put after a link's `<DT>`.
+fn description(link: ElementRef) -> Option<String> {
+    let dt = link.parent().and_then(ElementRef::wrap)?;
+    let dd = dt
+        .next_siblings()
+        .filter_map(ElementRef::wrap)
+        .next()
+        .filter(|el| el.value().name() == "dd")?;
+    let text = dd.text().collect::<String>().trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+/// Renders a flat export; tags go in the `TAGS` attribute understood by Firefox and Pinboard.
+pub fn render(bookmarks: &[ExternalBookmark]) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE NETSCAPE-Bookmark-file-1>\n\
+         <META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">\n\
+         <TITLE>Bookmarks</TITLE>\n\
+         <H1>Bookmarks</H1>\n\
+         <DL><p>\n",
+    );
+
+    for bookmark in bookmarks {
+        out.push_str("    <DT><A HREF=\"");
+        out.push_str(&escape(&bookmark.url));
+        out.push('"');
+        if let Some(added_at) = bookmark.added_at {
+            out.push_str(&format!(" ADD_DATE=\"{}\"", added_at.timestamp()));
+        }
+        if !bookmark.tags.is_empty() {
+            out.push_str(&format!(" TAGS=\"{}\"", escape(&bookmark.tags.join(","))));
+        }
+        out.push('>');
+        out.push_str(&escape(bookmark.title.as_deref().unwrap_or(&bookmark.url)));
+        out.push_str("</A>\n");
+        if let Some(description) = &bookmark.description {
+            out.push_str("    <DD>");
+            out.push_str(&escape(description));
+            out.push('\n');
+        }
+    }
+
+    out.push_str("</DL><p>\n");
+    out
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_folders_into_tags() {
+        let html = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks</H1>
+<DL><p>
+    <DT><H3 ADD_DATE="1700000000" PERSONAL_TOOLBAR_FOLDER="true">Bookmarks bar</H3>
+    <DL><p>
+        <DT><A HREF="https://rust-lang.org/" ADD_DATE="1700000001">Rust</A>
+        <DT><H3>Dev Tools</H3>
+        <DL><p>
+            <DT><A HREF="https://github.com/" TAGS="code,Git Hosting">GitHub</A>
+            <DT><A HREF="javascript:alert(1)">Bookmarklet</A>
+        </DL><p>
+    </DL><p>
+    <DT><A HREF="https://example.com/">Example</A>
+</DL><p>"#;
+
+        let bookmarks = parse(html);
+        assert_eq!(bookmarks.len(), 3);
+
+        assert_eq!(bookmarks[0].url, "https://rust-lang.org/");
+        assert_eq!(bookmarks[0].title.as_deref(), Some("Rust"));
+        assert!(bookmarks[0].tags.is_empty());
+        assert_eq!(bookmarks[0].added_at, DateTime::from_timestamp(1700000001, 0));
+
+        assert_eq!(bookmarks[1].url, "https://github.com/");
+        assert_eq!(bookmarks[1].tags, vec!["dev-tools", "code", "git-hosting"]);
+
+        assert_eq!(bookmarks[2].url, "https://example.com/");
+        assert!(bookmarks[2].tags.is_empty());
+    }
+
+    #[test]
+    fn test_render_roundtrip() {
+        let bookmarks = vec![
+            ExternalBookmark {
+                url: "https://example.com/?a=1&b=2".to_string(),
+                title: Some("Tom & Jerry <3".to_string()),
+                tags: vec!["cartoons".to_string(), "classic".to_string()],
+                added_at: DateTime::from_timestamp(1700000000, 0),
+                description: Some("A \"quoted\" summary".to_string()),
+                ..Default::default()
+            },
+            ExternalBookmark {
+                url: "https://rust-lang.org/".to_string(),
+                title: None,
+                ..Default::default()
+            },
+        ];
+
+        let html = render(&bookmarks);
+        assert!(html.starts_with("<!DOCTYPE NETSCAPE-Bookmark-file-1>"));
+
+        let mut parsed = parse(&html);
+        // Untitled bookmarks are exported with their URL as the link text
+        parsed[1].title = None;
+        assert_eq!(parsed, bookmarks);
+    }
+}
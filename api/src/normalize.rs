@@ -0,0 +1,125 @@
+//! URL canonicalization, so the same page saved from different links is one bookmark.
+
+use scraper::{Html, Selector};
+use url::Url;
+
+/// Query parameters that only identify where a click came from.
+const TRACKING_PARAMS: &[&str] = &[
+    "fbclid", "gclid", "dclid", "gbraid", "wbraid", "msclkid", "yclid", "igshid", "mc_cid",
+    "mc_eid", "_hsenc", "_hsmi", "mkt_tok", "oly_anon_id", "oly_enc_id", "vero_id",
+];
+
+fn is_tracking_param(name: &str) -> bool {
+    name.starts_with("utm_") || TRACKING_PARAMS.contains(&name)
+}
+
+/// Upgrades to https, drops tracking parameters and trailing slashes, and lets the `url`
+/// crate handle the rest (host case, default ports, percent-encoding).
+///
+/// URLs with an explicit port are left on http, since whatever listens there rarely speaks both.
+pub fn normalize_url(raw: &str) -> Result<String, url::ParseError> {
+    let mut url = Url::parse(raw.trim())?;
+    if url.scheme() == "http" && url.port().is_none() {
+        // Only fails for non-special schemes, which http isn't
+        let _ = url.set_scheme("https");
+    }
+
+    let kept: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| !is_tracking_param(name))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    if kept.is_empty() {
+        url.set_query(None);
+    } else if kept.len() < url.query_pairs().count() {
+        // Only re-encode when something was dropped; some servers care about exact query syntax
+        url.query_pairs_mut().clear().extend_pairs(kept);
+    }
+
+    if url.fragment() == Some("") {
+        url.set_fragment(None);
+    }
+
+    let path = url.path().to_string();
+    if path.len() > 1 && path.ends_with('/') {
+        url.set_path(path.trim_end_matches('/'));
+    }
+
+    Ok(url.into())
+}
+
+/// The page's `<link rel="canonical">`, normalized, if it points somewhere on the same host.
+///
+/// Canonical links to other hosts are usually syndication or misconfiguration, not the page's
+/// real address, so they're ignored.
+pub fn canonical_url(html_content: &str, page_url: &str) -> Option<String> {
+    let page = Url::parse(page_url).ok()?;
+    let document = Html::parse_document(html_content);
+    let selector = Selector::parse(r#"link[rel="canonical"][href]"#).unwrap();
+    let href = document.select(&selector).next()?.value().attr("href")?;
+
+    let canonical = page.join(href.trim()).ok()?;
+    if canonical.host_str() != page.host_str() {
+        return None;
+    }
+    normalize_url(canonical.as_str()).ok()
+}
+
+/// The site part of a `domain=` filter, as the `host` column has it: `lobste.rs`, `www.lobste.rs`
+/// and `https://lobste.rs/t/rust` are all `lobste.rs`.
+pub fn site(input: &str) -> Option<String> {
+    let input = input.trim();
+    let url = if input.contains("://") { Url::parse(input) } else { Url::parse(&format!("https://{}", input)) };
+    let host = url.ok()?.host_str()?.to_string();
+    let host = host.strip_prefix("www.").map(str::to_string).unwrap_or(host);
+    (!host.is_empty()).then_some(host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_url() {
+        let cases = [
+            ("http://example.com", "https://example.com/"),
+            ("https://example.com/", "https://example.com/"),
+            ("https://example.com?utm_source=x", "https://example.com/"),
+            ("HTTP://Example.COM:80/Docs/", "https://example.com/Docs"),
+            (
+                "https://example.com/a?id=1&utm_medium=email&fbclid=abc&page=2#",
+                "https://example.com/a?id=1&page=2",
+            ),
+            ("https://example.com/guide#install", "https://example.com/guide#install"),
+            ("http://localhost:8080/admin/", "http://localhost:8080/admin"),
+            ("https://example.com/search?q=a+b&flag", "https://example.com/search?q=a+b&flag"),
+        ];
+        for (raw, expected) in cases {
+            assert_eq!(normalize_url(raw).unwrap(), expected, "normalizing {}", raw);
+        }
+        assert!(normalize_url("not a url").is_err());
+    }
+
+    #[test]
+    fn test_canonical_url() {
+        let html = r#"<html><head><link rel="canonical" href="/posts/hello/?utm_source=rss"></head></html>"#;
+        assert_eq!(
+            canonical_url(html, "https://blog.example.com/p/123").as_deref(),
+            Some("https://blog.example.com/posts/hello")
+        );
+
+        let elsewhere = r#"<html><head><link rel="canonical" href="https://medium.com/hello"></head></html>"#;
+        assert_eq!(canonical_url(elsewhere, "https://blog.example.com/p/123"), None);
+        assert_eq!(canonical_url("<html></html>", "https://blog.example.com/"), None);
+    }
+
+    #[test]
+    fn test_site() {
+        assert_eq!(site("lobste.rs").as_deref(), Some("lobste.rs"));
+        assert_eq!(site(" WWW.Lobste.rs ").as_deref(), Some("lobste.rs"));
+        assert_eq!(site("https://lobste.rs/t/rust?page=2").as_deref(), Some("lobste.rs"));
+        assert_eq!(site("blog.example.com:8080").as_deref(), Some("blog.example.com"));
+        assert_eq!(site(""), None);
+        assert_eq!(site("not a site"), None);
+    }
+}
@@ -0,0 +1,98 @@
+//! Video and other embeddable pages described through oEmbed instead of their markup.
+//!
+//! Sites like YouTube build their pages in JavaScript, so scraping them gives the tagger little
+//! more than the site name. Their oEmbed endpoints answer with the real title, the channel and a
+//! thumbnail, and Vimeo's with the duration and description too. YouTube and Vimeo are known by
+//! address; any other page can point at its endpoint with a
+//! `<link rel="alternate" type="application/json+oembed">`.
+
+use std::time::Duration;
+
+use scraper::{Html, Selector};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use url::Url;
+
+use crate::fetcher::Fetcher;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The fields of an oEmbed response worth keeping; providers add whatever else they like.
+#[derive(Debug, Default, Deserialize)]
+pub struct Embed {
+    pub title: Option<String>,
+    pub author_name: Option<String>,
+    pub provider_name: Option<String>,
+    pub thumbnail_url: Option<String>,
+    /// Seconds; Vimeo sends it, YouTube doesn't
+    pub duration: Option<u64>,
+    pub description: Option<String>,
+}
+
+/// The oEmbed endpoint describing `page_url`, for a known provider or one the page declares.
+pub fn endpoint(page_url: &str, html_content: &str) -> Option<String> {
+    let page = Url::parse(page_url).ok()?;
+    let host = page.host_str()?.trim_start_matches("www.").trim_start_matches("m.");
+    let known = match host {
+        "youtube.com" | "youtu.be" | "music.youtube.com" => Some("https://www.youtube.com/oembed"),
+        "vimeo.com" | "player.vimeo.com" => Some("https://vimeo.com/api/oembed.json"),
+        _ => None,
+    };
+    if let Some(base) = known {
+        let mut endpoint = Url::parse(base).ok()?;
+        endpoint.query_pairs_mut().append_pair("url", page_url).append_pair("format", "json");
+        return Some(endpoint.into());
+    }
+
+    let document = Html::parse_document(html_content);
+    let selector = Selector::parse(r#"link[rel="alternate"][type="application/json+oembed"][href]"#).unwrap();
+    let href = document.select(&selector).next()?.value().attr("href")?;
+    Some(page.join(href.trim()).ok()?.into())
+}
+
+pub async fn fetch(fetcher: &Fetcher, endpoint: &str) -> anyhow::Result<Embed> {
+    let embed = tokio::time::timeout(TIMEOUT, async {
+        let fetched = fetcher.get(endpoint).await?;
+        fetched.response.error_for_status_ref()?;
+        anyhow::Ok(serde_json::from_slice(&fetched.bytes().await?)?)
+    });
+    embed.await?
+}
+
+/// Folds an oEmbed description into a page's scraped metadata. Its title and description are
+/// preferred to the page's, and the rest goes under `embed`.
+pub fn merge(site_meta: &mut Value, embed: Embed) {
+    if let Some(title) = embed.title.filter(|t| !t.trim().is_empty()) {
+        site_meta["og:title"] = json!(title);
+    }
+    if let Some(description) = embed.description.filter(|d| !d.trim().is_empty()) {
+        site_meta["og:description"] = json!(description.chars().take(1500).collect::<String>());
+    }
+    site_meta["embed"] = json!({
+        "provider": embed.provider_name,
+        "author": embed.author_name,
+        "thumbnail_url": embed.thumbnail_url,
+        "duration_seconds": embed.duration,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint() {
+        assert_eq!(
+            endpoint("https://www.youtube.com/watch?v=abc", "").as_deref(),
+            Some("https://www.youtube.com/oembed?url=https%3A%2F%2Fwww.youtube.com%2Fwatch%3Fv%3Dabc&format=json")
+        );
+        assert!(endpoint("https://vimeo.com/123", "").unwrap().starts_with("https://vimeo.com/api/oembed.json?url="));
+
+        let html = r#"<html><head><link rel="alternate" type="application/json+oembed" href="/oembed?id=7"></head></html>"#;
+        assert_eq!(
+            endpoint("https://videos.example.com/v/7", html).as_deref(),
+            Some("https://videos.example.com/oembed?id=7")
+        );
+        assert_eq!(endpoint("https://example.com/", "<html></html>"), None);
+    }
+}
@@ -0,0 +1,208 @@
+//! Omnivore's export archive: a zip of `metadata_*.json` lists of saved items, with each item's
+//! highlights in `highlights/SLUG.md`.
+//!
+//! Labels become tags, archived items count as read, and the description and thumbnail are kept
+//! as the bookmark's. Highlights go in the notes, each quote in Markdown with its annotation
+//! after it. A single metadata file can be imported on its own too. Items without a URL, such as
+//! uploaded files, are skipped, and the saved copies of pages aren't used; they're fetched again.
+
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+
+use chrono::DateTime;
+use serde::Deserialize;
+
+use crate::import::{normalize_tag, ExternalBookmark};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Item {
+    slug: Option<String>,
+    url: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    thumbnail: Option<String>,
+    /// `Archived` for read items, `Succeeded` for the rest
+    state: Option<String>,
+    #[serde(default)]
+    labels: Vec<Label>,
+    saved_at: Option<String>,
+    /// Only in exports made through the API
+    #[serde(default)]
+    highlights: Vec<Highlight>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Label {
+    Name(String),
+    Named { name: String },
+}
+
+#[derive(Deserialize)]
+struct Highlight {
+    quote: Option<String>,
+    annotation: Option<String>,
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/// Highlights as the notes will have them.
+fn highlight_notes(highlights: &[Highlight]) -> Option<String> {
+    let paragraphs: Vec<String> = highlights
+        .iter()
+        .filter_map(|highlight| {
+            let quote = non_empty(highlight.quote.clone()).map(|quote| {
+                quote.lines().map(|line| format!("> {}", line)).collect::<Vec<_>>().join("\n")
+            });
+            match (quote, non_empty(highlight.annotation.clone())) {
+                (Some(quote), Some(annotation)) => Some(format!("{}\n\n{}", quote, annotation)),
+                (quote, annotation) => quote.or(annotation),
+            }
+        })
+        .collect();
+    (!paragraphs.is_empty()).then(|| paragraphs.join("\n\n"))
+}
+
+/// A highlights file, without the back links Omnivore puts after each quote.
+fn markdown_notes(markdown: &str) -> Option<String> {
+    let notes: Vec<&str> = markdown
+        .lines()
+        .map(|line| line.find(" [⤴️](").map_or(line, |at| &line[..at]).trim_end())
+        .collect();
+    let notes = notes.join("\n").trim().to_string();
+    (!notes.is_empty()).then_some(notes)
+}
+
+fn bookmark(item: Item, highlights: &HashMap<String, String>) -> Option<ExternalBookmark> {
+    let url = non_empty(item.url)?;
+    let description = highlight_notes(&item.highlights).or_else(|| {
+        item.slug
+            .as_deref()
+            .and_then(|slug| highlights.get(slug))
+            .and_then(|markdown| markdown_notes(markdown))
+    });
+    Some(ExternalBookmark {
+        url,
+        title: non_empty(item.title),
+        tags: item
+            .labels
+            .into_iter()
+            .map(|label| match label {
+                Label::Name(name) | Label::Named { name } => normalize_tag(&name),
+            })
+            .filter(|tag| !tag.is_empty())
+            .collect(),
+        added_at: item
+            .saved_at
+            .as_deref()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .map(|t| t.to_utc()),
+        description,
+        archived: item.state.as_deref() == Some("Archived"),
+        excerpt: non_empty(item.description),
+        image_url: non_empty(item.thumbnail),
+        ..Default::default()
+    })
+}
+
+pub fn parse(content: &[u8]) -> anyhow::Result<Vec<ExternalBookmark>> {
+    if !content.starts_with(b"PK") {
+        let items: Vec<Item> = serde_json::from_slice(content)?;
+        return Ok(items.into_iter().filter_map(|item| bookmark(item, &HashMap::new())).collect());
+    }
+
+    let mut archive = zip::ZipArchive::new(Cursor::new(content))?;
+    let (mut items, mut highlights) = (Vec::new(), HashMap::new());
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let name = file.name()?.to_string();
+        let base = name.rsplit('/').next().unwrap_or_default();
+        if base.starts_with("metadata_") && base.ends_with(".json") {
+            let mut json = Vec::new();
+            file.read_to_end(&mut json)?;
+            items.extend(serde_json::from_slice::<Vec<Item>>(&json)?);
+        } else if let Some(slug) = name.contains("highlights/").then(|| base.strip_suffix(".md")).flatten() {
+            let mut markdown = String::new();
+            file.read_to_string(&mut markdown)?;
+            highlights.insert(slug.to_string(), markdown);
+        }
+    }
+    if items.is_empty() {
+        anyhow::bail!("Omnivore export has no metadata files");
+    }
+    Ok(items.into_iter().filter_map(|item| bookmark(item, &highlights)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    const METADATA: &str = r#"[
+        {
+            "id": "1f0e",
+            "slug": "why-rust-18f2",
+            "title": "Why Rust",
+            "description": "A case for it.",
+            "url": "https://example.com/why-rust",
+            "state": "Archived",
+            "thumbnail": "https://example.com/rust.png",
+            "labels": ["Programming", "to share"],
+            "savedAt": "2024-02-03T04:05:06.000Z"
+        },
+        {
+            "slug": "notes-9a1c",
+            "title": "An upload",
+            "url": "",
+            "state": "Succeeded",
+            "labels": []
+        }
+    ]"#;
+
+    #[test]
+    fn test_parse_archive() {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("metadata_0_to_2.json", options).unwrap();
+        zip.write_all(METADATA.as_bytes()).unwrap();
+        zip.start_file("highlights/why-rust-18f2.md", options).unwrap();
+        zip.write_all("> No data races [⤴️](https://omnivore.app/me/why-rust-18f2#a1)\n\nThe main point\n".as_bytes()).unwrap();
+        zip.start_file("content/why-rust-18f2.html", options).unwrap();
+        zip.write_all(b"<p>Rust is...</p>").unwrap();
+        let archive = zip.finish().unwrap().into_inner();
+
+        let bookmarks = parse(&archive).unwrap();
+        assert_eq!(bookmarks.len(), 1);
+        assert_eq!(bookmarks[0].title.as_deref(), Some("Why Rust"));
+        assert_eq!(bookmarks[0].tags, vec!["programming", "to-share"]);
+        assert_eq!(bookmarks[0].description.as_deref(), Some("> No data races\n\nThe main point"));
+        assert_eq!(bookmarks[0].excerpt.as_deref(), Some("A case for it."));
+        assert_eq!(bookmarks[0].image_url.as_deref(), Some("https://example.com/rust.png"));
+        assert_eq!(bookmarks[0].added_at, "2024-02-03T04:05:06Z".parse().ok());
+        assert!(bookmarks[0].archived);
+    }
+
+    #[test]
+    fn test_parse_metadata() {
+        let bookmarks = parse(METADATA.as_bytes()).unwrap();
+        assert_eq!(bookmarks.len(), 1);
+        assert_eq!(bookmarks[0].description, None);
+
+        let item = r#"[{
+            "url": "https://example.com/a",
+            "labels": [{ "name": "News" }],
+            "highlights": [
+                { "quote": "First line\nSecond line", "annotation": "" },
+                { "quote": null, "annotation": "Page note" }
+            ]
+        }]"#;
+        let bookmarks = parse(item.as_bytes()).unwrap();
+        assert_eq!(bookmarks[0].tags, vec!["news"]);
+        assert_eq!(bookmarks[0].description.as_deref(), Some("> First line\n> Second line\n\nPage note"));
+        assert!(!bookmarks[0].archived);
+    }
+}
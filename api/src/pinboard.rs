@@ -0,0 +1,431 @@
+//! A subset of the Pinboard v1 API, so clients written for Pinboard can sync with linkman.
+//!
+//! Only the JSON response format is supported. Clients authenticate with
+//! `?auth_token=username:TOKEN` (or a bare token) since that is all Pinboard clients know how to send.
+
+use std::collections::BTreeMap;
+
+use axum::{
+    extract::{Query, Request, State},
+    http::StatusCode,
+    middleware::{self, Next},
+    response::Response,
+    routing::get,
+    Extension, Json, Router,
+};
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::{Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::{
+    attach_tags, audit, authenticate, client_ip, events, jobs, normalize::normalize_url, rate_limit, replace_tags,
+    ApiKey, AppState, BookmarkResponse, CurrentUser, TagSource, BOOKMARK_SELECT,
+};
+
+pub fn router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/posts/add", get(add_post))
+        .route("/posts/get", get(get_posts))
+        .route("/posts/all", get(all_posts))
+        .route("/posts/update", get(last_update))
+        .route("/posts/delete", get(delete_post))
+        .route("/tags/get", get(get_tags))
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit::middleware))
+        .layer(middleware::from_fn_with_state(state, auth_middleware))
+}
+
+#[derive(Deserialize)]
+struct AuthParams {
+    auth_token: Option<String>,
+}
+
+async fn auth_middleware(
+    State(state): State<AppState>,
+    Query(params): Query<AuthParams>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let auth_token = params.auth_token.ok_or(StatusCode::UNAUTHORIZED)?;
+    let (username, token) = match auth_token.rsplit_once(':') {
+        Some((username, token)) => (Some(username), token),
+        None => (None, auth_token.as_str()),
+    };
+
+    let (user, key) = authenticate(&state, token, client_ip(&req)).await?;
+    if username.is_some_and(|name| name != user.username) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    // Everything is a GET here, so go by what the method does
+    let path = req.uri().path();
+    let scope = if path.ends_with("/posts/add") || path.ends_with("/posts/delete") { "write" } else { "read" };
+    if !key.allows(scope) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    req.extensions_mut().insert(user);
+    req.extensions_mut().insert(key);
+    if scope == "write" {
+        return Ok(audit::record(&state, req, next).await);
+    }
+    Ok(next.run(req).await)
+}
+
+fn result_code(code: &str) -> Json<Value> {
+    Json(json!({ "result_code": code }))
+}
+
+fn db_error(e: sqlx::Error) -> StatusCode {
+    eprintln!("Pinboard API Error: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Pinboard tags are space separated.
+fn split_tags(tags: Option<&str>) -> Vec<String> {
+    tags.unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect()
+}
+
+#[derive(Serialize)]
+struct Post {
+    href: String,
+    description: String,
+    extended: String,
+    meta: String,
+    hash: String,
+    time: String,
+    shared: &'static str,
+    toread: &'static str,
+    tags: String,
+}
+
+impl From<BookmarkResponse> for Post {
+    fn from(b: BookmarkResponse) -> Self {
+        Post {
+            description: b.title.unwrap_or_else(|| b.url.clone()),
+            href: b.url,
+            extended: b.notes.or(b.ai_summary).unwrap_or_default(),
+            meta: b.id.simple().to_string(),
+            hash: b.id.simple().to_string(),
+            time: pinboard_time(b.created_at),
+            shared: yes_no(b.is_public),
+            toread: yes_no(!b.is_read),
+            tags: b.tags.unwrap_or_default().join(" "),
+        }
+    }
+}
+
+fn yes_no(flag: bool) -> &'static str {
+    if flag { "yes" } else { "no" }
+}
+
+/// Pinboard's `yes`/`no` flags; anything else leaves the setting alone.
+fn parse_yes_no(value: Option<&str>) -> Option<bool> {
+    match value? {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+fn pinboard_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Accepts both the `2011-03-24` and `2011-03-24T19:02:07Z` forms Pinboard uses for `dt`.
+fn parse_dt(dt: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(dt)
+        .map(|t| t.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(dt, "%Y-%m-%d")
+                .ok()
+                .map(|d| d.and_hms_opt(0, 0, 0).unwrap().and_utc())
+        })
+}
+
+#[derive(Deserialize)]
+struct AddParams {
+    url: String,
+    description: Option<String>,
+    extended: Option<String>,
+    tags: Option<String>,
+    dt: Option<String>,
+    replace: Option<String>,
+    /// `yes` for unread
+    toread: Option<String>,
+    /// `yes` for public
+    shared: Option<String>,
+}
+
+/// Applies `toread` and `shared`, where they were given.
+async fn set_flags(tx: &mut sqlx::Transaction<'_, Postgres>, bookmark_id: Uuid, params: &AddParams) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE bookmarks SET
+             read_at = CASE WHEN $2 IS NULL THEN read_at WHEN $2 THEN NULL ELSE COALESCE(read_at, now()) END,
+             is_public = COALESCE($3, is_public)
+         WHERE id = $1"
+    )
+    .bind(bookmark_id)
+    .bind(parse_yes_no(params.toread.as_deref()))
+    .bind(parse_yes_no(params.shared.as_deref()))
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+async fn add_post(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Extension(key): Extension<ApiKey>,
+    Query(mut params): Query<AddParams>,
+) -> Result<Json<Value>, StatusCode> {
+    params.url = normalize_url(&params.url).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let tags = split_tags(params.tags.as_deref());
+    let added_at = params.dt.as_deref().and_then(parse_dt);
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+
+    let created: Option<Uuid> = sqlx::query_scalar(
+        "INSERT INTO bookmarks (user_id, url, title, notes, created_at, tag_mode)
+         VALUES ($1, $2, $3, $4, COALESCE($5, now()), $6)
+         ON CONFLICT (user_id, url) DO NOTHING
+         RETURNING id"
+    )
+    .bind(user.id)
+    .bind(&params.url)
+    .bind(&params.description)
+    .bind(&params.extended)
+    .bind(added_at)
+    .bind(key.tag_mode)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    if let Some(bookmark_id) = created {
+        attach_tags(&mut tx, user.id, bookmark_id, &tags, TagSource::User).await.map_err(db_error)?;
+        set_flags(&mut tx, bookmark_id, &params).await.map_err(db_error)?;
+        jobs::queue(&mut *tx, &[bookmark_id]).await.map_err(db_error)?;
+        tx.commit().await.map_err(db_error)?;
+        state.job_wakeup.notify_one();
+        events::emit(&state, user.id, events::Event::Created, bookmark_id);
+        return Ok(result_code("done"));
+    }
+
+    if params.replace.as_deref() == Some("no") {
+        return Ok(result_code("item already exists"));
+    }
+
+    let bookmark_id: Uuid = sqlx::query_scalar(
+        "UPDATE bookmarks SET title = COALESCE($1, title), title_from_page = title_from_page AND $1 IS NULL,
+             notes = COALESCE($2, notes), updated_at = now(),
+             deleted_at = NULL
+         WHERE user_id = $3 AND url = $4
+         RETURNING id"
+    )
+    .bind(&params.description)
+    .bind(&params.extended)
+    .bind(user.id)
+    .bind(&params.url)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    replace_tags(&mut tx, user.id, bookmark_id, &tags).await.map_err(db_error)?;
+    set_flags(&mut tx, bookmark_id, &params).await.map_err(db_error)?;
+
+    tx.commit().await.map_err(db_error)?;
+    events::emit(&state, user.id, events::Event::Updated, bookmark_id);
+    Ok(result_code("done"))
+}
+
+#[derive(Deserialize)]
+struct GetParams {
+    tag: Option<String>,
+    dt: Option<String>,
+    url: Option<String>,
+}
+
+/// Posts from a single day (the most recent one unless `dt` or `url` is given).
+async fn get_posts(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Query(mut params): Query<GetParams>,
+) -> Result<Json<Value>, StatusCode> {
+    params.url = params.url.map(|url| normalize_url(&url).unwrap_or(url));
+    let day = match params.dt.as_deref() {
+        Some(dt) => Some(parse_dt(dt).ok_or(StatusCode::BAD_REQUEST)?),
+        None if params.url.is_none() => sqlx::query_scalar(
+            "SELECT date_trunc('day', max(created_at)) FROM bookmarks WHERE user_id = $1 AND deleted_at IS NULL"
+        )
+        .bind(user.id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(db_error)?,
+        None => None,
+    };
+
+    let mut query = QueryBuilder::<Postgres>::new(BOOKMARK_SELECT);
+    query.push(" WHERE b.user_id = ").push_bind(user.id).push(" AND b.deleted_at IS NULL");
+    if let Some(url) = &params.url {
+        query.push(" AND b.url = ").push_bind(url);
+    }
+    if let Some(day) = day {
+        query
+            .push(" AND b.created_at >= date_trunc('day', ")
+            .push_bind(day)
+            .push(") AND b.created_at < date_trunc('day', ")
+            .push_bind(day)
+            .push(") + interval '1 day'");
+    }
+    push_tag_filter(&mut query, params.tag.as_deref());
+    query.push(" ORDER BY b.created_at DESC");
+
+    let posts = fetch_posts(&state, query).await?;
+    Ok(Json(json!({
+        "date": pinboard_time(day.unwrap_or_else(Utc::now)),
+        "user": user.username,
+        "posts": posts,
+    })))
+}
+
+#[derive(Deserialize)]
+struct AllParams {
+    tag: Option<String>,
+    start: Option<i64>,
+    results: Option<i64>,
+}
+
+async fn all_posts(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Query(params): Query<AllParams>,
+) -> Result<Json<Vec<Post>>, StatusCode> {
+    let mut query = QueryBuilder::<Postgres>::new(BOOKMARK_SELECT);
+    query.push(" WHERE b.user_id = ").push_bind(user.id).push(" AND b.deleted_at IS NULL");
+    push_tag_filter(&mut query, params.tag.as_deref());
+    query
+        .push(" ORDER BY b.created_at DESC, b.id DESC OFFSET ")
+        .push_bind(params.start.unwrap_or(0).max(0));
+    if let Some(results) = params.results {
+        query.push(" LIMIT ").push_bind(results.max(0));
+    }
+
+    Ok(Json(fetch_posts(&state, query).await?))
+}
+
+/// Clients poll this to decide whether they need to call `posts/all` again.
+async fn last_update(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Result<Json<Value>, StatusCode> {
+    let updated: Option<DateTime<Utc>> = sqlx::query_scalar(
+        "SELECT max(GREATEST(created_at, updated_at)) FROM bookmarks WHERE user_id = $1"
+    )
+    .bind(user.id)
+    .fetch_one(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    Ok(Json(json!({
+        "update_time": pinboard_time(updated.unwrap_or(DateTime::UNIX_EPOCH)),
+    })))
+}
+
+#[derive(Deserialize)]
+struct DeleteParams {
+    url: String,
+}
+
+async fn delete_post(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Query(params): Query<DeleteParams>,
+) -> Result<Json<Value>, StatusCode> {
+    let url = normalize_url(&params.url).unwrap_or(params.url);
+    let deleted: Option<Uuid> = sqlx::query_scalar(
+        "UPDATE bookmarks SET deleted_at = now(), updated_at = now() WHERE user_id = $1 AND url = $2 AND deleted_at IS NULL
+         RETURNING id"
+    )
+    .bind(user.id)
+    .bind(&url)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    match deleted {
+        Some(bookmark_id) => {
+            events::emit(&state, user.id, events::Event::Deleted, bookmark_id);
+            Ok(result_code("done"))
+        }
+        None => Ok(result_code("item not found")),
+    }
+}
+
+async fn get_tags(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Result<Json<BTreeMap<String, i64>>, StatusCode> {
+    let counts: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT t.name, count(bt.bookmark_id) FROM tags t
+         JOIN bookmark_tags bt ON bt.tag_id = t.id
+         JOIN bookmarks b ON b.id = bt.bookmark_id
+         WHERE t.user_id = $1 AND b.deleted_at IS NULL
+         GROUP BY t.name"
+    )
+    .bind(user.id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    Ok(Json(counts.into_iter().collect()))
+}
+
+/// Pinboard filters take up to three space-separated tags, all of which must match.
+fn push_tag_filter(query: &mut QueryBuilder<Postgres>, tags: Option<&str>) {
+    for tag in split_tags(tags).into_iter().take(3) {
+        query
+            .push(" AND EXISTS (SELECT 1 FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id WHERE bt.bookmark_id = b.id AND t.name = ")
+            .push_bind(tag)
+            .push(")");
+    }
+}
+
+async fn fetch_posts(state: &AppState, mut query: QueryBuilder<'_, Postgres>) -> Result<Vec<Post>, StatusCode> {
+    let bookmarks = query
+        .build_query_as::<BookmarkResponse>()
+        .fetch_all(&state.db)
+        .await
+        .map_err(db_error)?;
+    Ok(bookmarks.into_iter().map(Post::from).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dt() {
+        assert_eq!(
+            parse_dt("2011-03-24T19:02:07Z"),
+            DateTime::from_timestamp(1300993327, 0)
+        );
+        assert_eq!(parse_dt("2011-03-24"), DateTime::from_timestamp(1300924800, 0));
+        assert_eq!(parse_dt("yesterday"), None);
+    }
+
+    #[test]
+    fn test_flags() {
+        let post = Post::from(BookmarkResponse { is_public: true, ..Default::default() });
+        assert_eq!((post.shared, post.toread), ("yes", "yes"));
+        let post = Post::from(BookmarkResponse { is_read: true, ..Default::default() });
+        assert_eq!((post.shared, post.toread), ("no", "no"));
+
+        assert_eq!(parse_yes_no(Some("yes")), Some(true));
+        assert_eq!(parse_yes_no(Some("no")), Some(false));
+        assert_eq!(parse_yes_no(Some("maybe")), None);
+        assert_eq!(parse_yes_no(None), None);
+    }
+}
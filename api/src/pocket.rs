@@ -0,0 +1,145 @@
+//! Pocket exports, in both the legacy `ril_export.html` and the newer CSV flavour.
+
+use chrono::DateTime;
+use scraper::{ElementRef, Html, Selector};
+
+use crate::import::{normalize_tag, ExternalBookmark};
+
+/// Detects which flavour of export `content` is and parses it.
+pub fn parse(content: &str) -> anyhow::Result<Vec<ExternalBookmark>> {
+    if content.trim_start().starts_with('<') {
+        Ok(parse_html(content))
+    } else {
+        parse_csv(content)
+    }
+}
+
+/// The CSV export: `title,url,time_added,tags,status` with `|`-separated tags.
+///
+/// Columns are looked up by header name, so the optional `favorite` column some exports
+/// carry is picked up when present.
+fn parse_csv(content: &str) -> anyhow::Result<Vec<ExternalBookmark>> {
+    let mut reader = csv::Reader::from_reader(content.as_bytes());
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|h| h.trim().eq_ignore_ascii_case(name));
+    let (title, url, time_added, tags, status, favorite) = (
+        column("title"),
+        column("url").ok_or_else(|| anyhow::anyhow!("Pocket CSV is missing a url column"))?,
+        column("time_added"),
+        column("tags"),
+        column("status"),
+        column("favorite"),
+    );
+
+    let mut bookmarks = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let field = |idx: Option<usize>| {
+            idx.and_then(|i| record.get(i))
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+
+        let Some(url) = field(Some(url)) else { continue };
+        bookmarks.push(ExternalBookmark {
+            url: url.to_string(),
+            title: field(title).map(str::to_string),
+            tags: field(tags).map(|t| split_tags(t, '|')).unwrap_or_default(),
+            added_at: field(time_added)
+                .and_then(|t| t.parse().ok())
+                .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+            favorite: field(favorite).is_some_and(|f| f == "1" || f.eq_ignore_ascii_case("true")),
+            archived: field(status).is_some_and(|s| s.eq_ignore_ascii_case("archive")),
+            ..Default::default()
+        });
+    }
+    Ok(bookmarks)
+}
+
+/// The HTML export: one `<ul>` per `<h1>` section ("Unread List", "Read Archive").
+fn parse_html(content: &str) -> Vec<ExternalBookmark> {
+    let document = Html::parse_document(content);
+    let link_selector = Selector::parse("a[href]").unwrap();
+
+    document
+        .select(&link_selector)
+        .map(|link| {
+            let attrs = link.value();
+            let title = link.text().collect::<String>().trim().to_string();
+            ExternalBookmark {
+                url: attrs.attr("href").unwrap_or_default().trim().to_string(),
+                title: (!title.is_empty()).then_some(title),
+                tags: attrs.attr("tags").map(|t| split_tags(t, ',')).unwrap_or_default(),
+                added_at: attrs
+                    .attr("time_added")
+                    .and_then(|t| t.parse().ok())
+                    .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+                archived: section_heading(link).is_some_and(|h| h.to_lowercase().contains("archive")),
+                ..Default::default()
+            }
+        })
+        .filter(|b| !b.url.is_empty())
+        .collect()
+}
+
+/// Text of the `<h1>` immediately preceding the list holding `link`.
+fn section_heading(link: ElementRef) -> Option<String> {
+    let list = link
+        .ancestors()
+        .filter_map(ElementRef::wrap)
+        .find(|el| el.value().name() == "ul")?;
+    list.prev_siblings()
+        .filter_map(ElementRef::wrap)
+        .find(|el| el.value().name() == "h1")
+        .map(|h1| h1.text().collect())
+}
+
+fn split_tags(tags: &str, separator: char) -> Vec<String> {
+    tags.split(separator)
+        .map(normalize_tag)
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv() {
+        let csv = "title,url,time_added,cursor,tags,status\n\
+                   Rust,https://rust-lang.org/,1700000000,,lang|Systems Programming,unread\n\
+                   Old News,https://example.com/news,1600000000,,,archive\n";
+
+        let bookmarks = parse(csv).unwrap();
+        assert_eq!(bookmarks.len(), 2);
+        assert_eq!(bookmarks[0].title.as_deref(), Some("Rust"));
+        assert_eq!(bookmarks[0].tags, vec!["lang", "systems-programming"]);
+        assert_eq!(bookmarks[0].added_at, DateTime::from_timestamp(1700000000, 0));
+        assert!(!bookmarks[0].archived);
+        assert!(bookmarks[1].tags.is_empty());
+        assert!(bookmarks[1].archived);
+    }
+
+    #[test]
+    fn test_parse_html() {
+        let html = r#"<!DOCTYPE html>
+<html><head><title>Pocket Export</title></head><body>
+<h1>Unread</h1>
+<ul>
+<li><a href="https://rust-lang.org/" time_added="1700000000" tags="lang,rust">Rust</a></li>
+</ul>
+<h1>Read Archive</h1>
+<ul>
+<li><a href="https://example.com/news" time_added="1600000000" tags="">Old News</a></li>
+</ul>
+</body></html>"#;
+
+        let bookmarks = parse(html).unwrap();
+        assert_eq!(bookmarks.len(), 2);
+        assert_eq!(bookmarks[0].tags, vec!["lang", "rust"]);
+        assert!(!bookmarks[0].archived);
+        assert_eq!(bookmarks[1].url, "https://example.com/news");
+        assert!(bookmarks[1].archived);
+    }
+}
@@ -0,0 +1,88 @@
+//! A read-only link blog: the bookmarks a user has marked public, listed without authentication.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::{decode_cursor, encode_cursor, AppState, PageParams, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
+
+/// Only what the owner chose to publish; notes and read state stay private.
+#[derive(Serialize, sqlx::FromRow)]
+pub struct PublicBookmark {
+    #[serde(skip)]
+    id: Uuid,
+    url: String,
+    title: Option<String>,
+    ai_summary: Option<String>,
+    tags: Option<Vec<String>>,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct PublicProfile {
+    username: String,
+    bookmarks: Vec<PublicBookmark>,
+    next_cursor: Option<String>,
+}
+
+pub async fn profile(
+    State(state): State<AppState>,
+    Path(username): Path<String>,
+    Query(page): Query<PageParams>,
+) -> Result<Json<PublicProfile>, StatusCode> {
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Public Profile Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let limit = page.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let cursor = page
+        .cursor
+        .as_deref()
+        .map(|c| decode_cursor(c).ok_or(StatusCode::BAD_REQUEST))
+        .transpose()?;
+
+    let user_id: Uuid = sqlx::query_scalar("SELECT id FROM users WHERE username = $1")
+        .bind(&username)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut query = QueryBuilder::<Postgres>::new(
+        "SELECT b.id, b.url, b.title, b.ai_summary, b.created_at,
+         (SELECT array_agg(t.name ORDER BY t.name) FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id
+          WHERE bt.bookmark_id = b.id) as tags
+         FROM bookmarks b
+         WHERE b.is_public AND b.deleted_at IS NULL AND b.user_id = ",
+    );
+    query.push_bind(user_id);
+    if let Some((created_at, id)) = cursor {
+        query
+            .push(" AND (b.created_at, b.id) < (")
+            .push_bind(created_at)
+            .push(", ")
+            .push_bind(id)
+            .push(")");
+    }
+    query
+        .push(" ORDER BY b.created_at DESC, b.id DESC LIMIT ")
+        .push_bind(limit + 1);
+
+    let mut bookmarks: Vec<PublicBookmark> = query.build_query_as().fetch_all(&state.db).await.map_err(db_error)?;
+
+    let next_cursor = if bookmarks.len() as i64 > limit {
+        bookmarks.truncate(limit as usize);
+        bookmarks.last().map(|b| encode_cursor(b.created_at, b.id))
+    } else {
+        None
+    };
+
+    Ok(Json(PublicProfile { username, bookmarks, next_cursor }))
+}
@@ -0,0 +1,154 @@
+//! `GET /quick-add`, for bookmarklets, which can only navigate somewhere.
+//!
+//! `?url=` is bookmarked as `POST /bookmarks/sync` would, with an optional `title`, `notes` and
+//! comma-separated `tags`, and processing starts straight away. The token is a write-scoped API
+//! key passed as `?token=`, since a navigation can't carry a header; make one just for the
+//! bookmarklet so it can be revoked on its own. The response is a small page saying what was
+//! saved, or with `redirect=true` a redirect back to the bookmarked page. A bookmarklet is then
+//!
+//! ```text
+//! javascript:location.href='https://LINKMAN/quick-add?redirect=true&token=TOKEN&url='+encodeURIComponent(location.href)+'&title='+encodeURIComponent(document.title)
+//! ```
+
+use axum::{
+    extract::{Query, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::get,
+    Extension, Router,
+};
+use serde::Deserialize;
+
+use crate::{
+    audit, authenticate, client_ip, events, jobs, netscape::escape, normalize::normalize_url, rate_limit,
+    upsert_bookmark, ApiKey, AppState, CurrentUser, SyncBookmarkRequest,
+};
+
+pub fn router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/quick-add", get(quick_add))
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit::middleware))
+        .layer(middleware::from_fn_with_state(state, auth_middleware))
+}
+
+#[derive(Deserialize)]
+struct AuthParams {
+    token: Option<String>,
+}
+
+async fn auth_middleware(
+    State(state): State<AppState>,
+    Query(params): Query<AuthParams>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let token = params.token.ok_or(StatusCode::UNAUTHORIZED)?;
+    let (user, key) = authenticate(&state, &token, client_ip(&req)).await?;
+    // A GET, but it saves a bookmark
+    if !key.allows("write") {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    req.extensions_mut().insert(user);
+    req.extensions_mut().insert(key);
+    Ok(audit::record(&state, req, next).await)
+}
+
+#[derive(Deserialize)]
+struct QuickAddParams {
+    url: String,
+    title: Option<String>,
+    notes: Option<String>,
+    /// Comma-separated
+    tags: Option<String>,
+    #[serde(default)]
+    redirect: bool,
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+pub fn split_tags(tags: Option<&str>) -> Option<Vec<String>> {
+    let tags: Vec<String> = tags
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect();
+    (!tags.is_empty()).then_some(tags)
+}
+
+/// The confirmation page. It links back to the bookmarked page, which mustn't be told the
+/// address it came from: that has the token in it.
+fn confirmation(url: &str, title: Option<&str>, inserted: bool) -> String {
+    let heading = if inserted { "Saved to linkman" } else { "Already in linkman, updated" };
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><meta name=\"referrer\" content=\"no-referrer\">\
+         <meta name=\"viewport\" content=\"width=device-width\"><title>{heading}</title></head>\n\
+         <body><p>{heading}: <a href=\"{url}\">{title}</a></p></body></html>\n",
+        heading = heading,
+        url = escape(url),
+        title = escape(title.unwrap_or(url)),
+    )
+}
+
+async fn quick_add(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Extension(key): Extension<ApiKey>,
+    Query(params): Query<QuickAddParams>,
+) -> Result<Response, StatusCode> {
+    let url = normalize_url(&params.url).map_err(|_| StatusCode::BAD_REQUEST)?;
+    // It ends up in a link on linkman's own page, where a `javascript:` one would run
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let payload = SyncBookmarkRequest {
+        url: url.clone(),
+        title: non_empty(params.title),
+        notes: non_empty(params.notes),
+        tags: split_tags(params.tags.as_deref()),
+        tag_mode: None,
+    };
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Quick Add Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    let (bookmark_id, inserted) = upsert_bookmark(&mut tx, user.id, key.tag_mode, &url, &payload).await.map_err(db_error)?;
+    jobs::queue(&mut *tx, &[bookmark_id]).await.map_err(db_error)?;
+    tx.commit().await.map_err(db_error)?;
+    state.job_wakeup.notify_one();
+
+    let event = if inserted { events::Event::Created } else { events::Event::Updated };
+    events::emit(&state, user.id, event, bookmark_id);
+
+    let response = if params.redirect {
+        Redirect::to(&url).into_response()
+    } else {
+        Html(confirmation(&url, payload.title.as_deref(), inserted)).into_response()
+    };
+    Ok(([(header::REFERRER_POLICY, "no-referrer")], response).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_tags() {
+        assert_eq!(split_tags(Some(" rust, to read ,,")), Some(vec!["rust".to_string(), "to read".to_string()]));
+        assert_eq!(split_tags(Some(" , ")), None);
+        assert_eq!(split_tags(None), None);
+    }
+
+    #[test]
+    fn test_confirmation() {
+        let page = confirmation("https://example.com/?a=1&b=2", Some("<Title>"), true);
+        assert!(page.contains("Saved to linkman: <a href=\"https://example.com/?a=1&amp;b=2\">&lt;Title&gt;</a>"));
+        assert!(page.contains("content=\"no-referrer\""));
+    }
+}
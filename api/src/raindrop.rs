@@ -0,0 +1,94 @@
+//! Raindrop.io's CSV export, the one its backups include too.
+//!
+//! The columns are `id,title,note,excerpt,url,folder,tags,created,cover,highlights,favorite`.
+//! Folders become collections, nested ones written `Parent/Child`, except for "Unsorted", which
+//! is where Raindrop puts what isn't in any. The excerpt and cover become the bookmark's
+//! description and image, and highlights are kept in the notes after the note itself.
+
+use chrono::DateTime;
+
+use crate::import::{normalize_tag, ExternalBookmark};
+
+/// Raindrop's name for bookmarks in no collection.
+const UNSORTED: &str = "Unsorted";
+
+pub fn parse(content: &str) -> anyhow::Result<Vec<ExternalBookmark>> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(content.as_bytes());
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|h| h.trim().eq_ignore_ascii_case(name));
+    let (url, title, note, excerpt, folder, tags, created, cover, highlights, favorite) = (
+        column("url").ok_or_else(|| anyhow::anyhow!("Raindrop CSV is missing a url column"))?,
+        column("title"),
+        column("note"),
+        column("excerpt"),
+        column("folder"),
+        column("tags"),
+        column("created"),
+        column("cover"),
+        column("highlights"),
+        column("favorite"),
+    );
+
+    let mut bookmarks = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let field = |idx: Option<usize>| {
+            idx.and_then(|i| record.get(i))
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+
+        let Some(url) = field(Some(url)) else { continue };
+        let notes: Vec<&str> = [field(note), field(highlights)].into_iter().flatten().collect();
+        bookmarks.push(ExternalBookmark {
+            url: url.to_string(),
+            title: field(title).map(str::to_string),
+            tags: field(tags)
+                .map(|t| t.split(',').map(normalize_tag).filter(|t| !t.is_empty()).collect())
+                .unwrap_or_default(),
+            added_at: field(created)
+                .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+                .map(|t| t.to_utc()),
+            description: (!notes.is_empty()).then(|| notes.join("\n\n")),
+            favorite: field(favorite).is_some_and(|f| f.eq_ignore_ascii_case("true")),
+            excerpt: field(excerpt).map(str::to_string),
+            image_url: field(cover).map(str::to_string),
+            collection: field(folder)
+                .filter(|f| *f != UNSORTED)
+                .map(|f| f.split('/').map(str::trim).filter(|name| !name.is_empty()).map(str::to_string).collect())
+                .unwrap_or_default(),
+            ..Default::default()
+        });
+    }
+    Ok(bookmarks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let csv = "id,title,note,excerpt,url,folder,tags,created,cover,highlights,favorite\n\
+                   101,Tokio tutorial,Start here,Learn async Rust,https://tokio.rs/tokio/tutorial,Dev / Rust,\"async, Rust Lang\",2023-06-01T10:20:30.400Z,https://tokio.rs/img/card.png,\"Highlight: spawn tasks\",true\n\
+                   102,Someday,,,https://example.com/someday,Unsorted,,2023-06-02T00:00:00.000Z,,,false\n\
+                   103,Nowhere,,,,Dev,,,,,\n";
+        let bookmarks = parse(csv).unwrap();
+        assert_eq!(bookmarks.len(), 2);
+
+        assert_eq!(bookmarks[0].title.as_deref(), Some("Tokio tutorial"));
+        assert_eq!(bookmarks[0].collection, vec!["Dev", "Rust"]);
+        assert_eq!(bookmarks[0].tags, vec!["async", "rust-lang"]);
+        assert_eq!(bookmarks[0].excerpt.as_deref(), Some("Learn async Rust"));
+        assert_eq!(bookmarks[0].image_url.as_deref(), Some("https://tokio.rs/img/card.png"));
+        assert_eq!(bookmarks[0].description.as_deref(), Some("Start here\n\nHighlight: spawn tasks"));
+        assert_eq!(bookmarks[0].added_at, "2023-06-01T10:20:30.400Z".parse().ok());
+        assert!(bookmarks[0].favorite);
+
+        assert!(bookmarks[1].collection.is_empty());
+        assert_eq!(bookmarks[1].description, None);
+        assert!(!bookmarks[1].favorite);
+
+        assert!(parse("title,link\nA,https://example.com\n").is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! Per-API-key request limits.
+//!
+//! Each key gets a token bucket holding `RATE_LIMIT_PER_MINUTE` requests (default 300, `0`
+//! disables) that refills continuously, so short bursts are fine but a runaway client is held to
+//! its budget and told when to come back via `Retry-After`. Buckets live in memory, so each
+//! server process enforces the limit on its own, and are dropped once they've been idle long
+//! enough to refill.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use uuid::Uuid;
+
+use crate::{config::RateLimitConfig, ApiKey, AppState};
+
+/// How long an untouched bucket takes to fill up, after which it's no different from a new one.
+const REFILL: Duration = Duration::from_secs(60);
+
+pub struct RateLimiter {
+    per_minute: f64,
+    buckets: Mutex<Buckets>,
+}
+
+struct Buckets {
+    by_key: HashMap<Uuid, Bucket>,
+    swept_at: Instant,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn from_config(config: &RateLimitConfig) -> Option<Self> {
+        (config.per_minute > 0).then(|| Self::new(config.per_minute))
+    }
+
+    fn new(per_minute: u32) -> Self {
+        RateLimiter {
+            per_minute: per_minute as f64,
+            buckets: Mutex::new(Buckets { by_key: HashMap::new(), swept_at: Instant::now() }),
+        }
+    }
+
+    /// Takes one request from the key's bucket, or says how long until one is available.
+    fn check(&self, key: Uuid, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if now.saturating_duration_since(buckets.swept_at) >= REFILL {
+            buckets.by_key.retain(|_, bucket| now.saturating_duration_since(bucket.updated_at) < REFILL);
+            buckets.swept_at = now;
+        }
+        let bucket = buckets.by_key.entry(key).or_insert(Bucket {
+            tokens: self.per_minute,
+            updated_at: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_minute / 60.0).min(self.per_minute);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) * 60.0 / self.per_minute))
+        }
+    }
+}
+
+/// Runs behind authentication, which provides the key being limited.
+pub async fn middleware(
+    State(state): State<AppState>,
+    Extension(key): Extension<ApiKey>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if let Some(limiter) = &state.rate_limiter
+        && let Err(wait) = limiter.check(key.token, Instant::now())
+    {
+        // Retry-After is in whole seconds; rounding down would invite an immediate retry
+        let retry_after = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+        return (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, retry_after.to_string())]).into_response();
+    }
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let limiter = RateLimiter::new(2);
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let start = Instant::now();
+
+        assert!(limiter.check(a, start).is_ok());
+        assert!(limiter.check(a, start).is_ok());
+        assert_eq!(limiter.check(a, start), Err(Duration::from_secs(30)));
+        // Keys don't share a budget
+        assert!(limiter.check(b, start).is_ok());
+
+        assert!(limiter.check(a, start + Duration::from_secs(30)).is_ok());
+        assert!(limiter.check(a, start + Duration::from_secs(30)).is_err());
+    }
+
+    #[test]
+    fn test_idle_buckets_are_dropped() {
+        let limiter = RateLimiter::new(2);
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let start = Instant::now();
+
+        assert!(limiter.check(a, start).is_ok());
+        assert!(limiter.check(b, start + Duration::from_secs(30)).is_ok());
+        assert!(limiter.check(b, start + Duration::from_secs(61)).is_ok());
+        assert_eq!(limiter.buckets.lock().unwrap().by_key.len(), 1);
+    }
+}
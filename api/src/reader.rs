@@ -0,0 +1,63 @@
+//! A bookmark's stored content as a page to read, without going back to the site.
+//!
+//! The markdown kept from processing is rendered to plain HTML: headings, paragraphs, lists,
+//! links, images, code and tables, and nothing the page could have smuggled in with them. HTML in
+//! the markdown is shown as text, and links and images keep only `http`, `https`, `mailto` and
+//! relative addresses.
+
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+
+use crate::netscape::escape;
+
+/// `markdown` as a complete HTML document.
+pub fn render(title: &str, language: Option<&str>, markdown: &str) -> String {
+    let events = Parser::new_ext(markdown, Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH).map(|event| match event {
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        Event::Start(Tag::Link { link_type, dest_url, title, id }) => {
+            Event::Start(Tag::Link { link_type, dest_url: safe_url(dest_url), title, id })
+        }
+        Event::Start(Tag::Image { link_type, dest_url, title, id }) => {
+            Event::Start(Tag::Image { link_type, dest_url: safe_url(dest_url), title, id })
+        }
+        event => event,
+    });
+    let mut body = String::new();
+    html::push_html(&mut body, events);
+
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"{}\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>{}</title>\n</head>\n\
+         <body>\n<article>\n{}</article>\n</body>\n</html>\n",
+        escape(language.unwrap_or("und")),
+        escape(title),
+        body
+    )
+}
+
+/// `url`, or nothing if it has a scheme that could run script, like `javascript:`.
+fn safe_url(url: CowStr<'_>) -> CowStr<'_> {
+    let lower = url.to_ascii_lowercase();
+    let has_scheme = lower.find([':', '/', '?', '#']).is_some_and(|i| lower[i..].starts_with(':'));
+    let allowed = ["http:", "https:", "mailto:"].iter().any(|scheme| lower.starts_with(scheme));
+    if has_scheme && !allowed { CowStr::Borrowed("") } else { url }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let markdown = "# Borrowing\n\nSee [the book](https://doc.rust-lang.org/book/) and \
+                        [this](javascript:alert(1)).\n\n<script>alert(2)</script>\n\n![](JaVaScRiPt:x) [up](../index.html)";
+        let page = render("Borrowing <& co>", Some("en"), markdown);
+        assert!(page.contains("<html lang=\"en\">"));
+        assert!(page.contains("<title>Borrowing &lt;&amp; co&gt;</title>"));
+        assert!(page.contains("<h1>Borrowing</h1>"));
+        assert!(page.contains("<a href=\"https://doc.rust-lang.org/book/\">the book</a>"));
+        assert!(page.contains("<a href=\"\">this</a>"));
+        assert!(page.contains("&lt;script&gt;alert(2)&lt;/script&gt;"));
+        assert!(!page.to_ascii_lowercase().contains("javascript:"));
+        assert!(page.contains("<a href=\"../index.html\">up</a>"));
+    }
+}
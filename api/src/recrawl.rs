@@ -0,0 +1,104 @@
+//! Fetching bookmarked pages again to notice when they change.
+//!
+//! Every `RECRAWL_INTERVAL_HOURS` (off by default) each bookmark with stored content is fetched
+//! again and its content compared with what was kept, as `changes` describes. Nothing else about
+//! the bookmark is touched, and the model isn't asked to tag it again. Pages that are gone, or
+//! now only exist as a Wayback Machine snapshot, are left to the link checker, and synced Mastodon posts
+//! keep the content they came with.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::{sync::Semaphore, task::JoinSet};
+use uuid::Uuid;
+
+use crate::{changes, content, credentials, config::RecrawlConfig, AppState};
+
+const RECRAWL_CONCURRENCY: usize = 4;
+const RECRAWL_BATCH: i64 = 100;
+
+pub struct Config {
+    pub interval: Duration,
+}
+
+impl Config {
+    pub fn from_config(config: &RecrawlConfig) -> Option<Self> {
+        (config.interval_hours > 0).then(|| Config {
+            interval: Duration::from_secs(config.interval_hours * 60 * 60),
+        })
+    }
+}
+
+pub async fn run(state: AppState, config: Config) {
+    let mut tick = tokio::time::interval(Duration::from_secs(15 * 60).min(config.interval));
+    loop {
+        tick.tick().await;
+        let result = recrawl_due(&state, &config).await;
+        state.metrics.record_job("recrawl", result.is_ok());
+        if let Err(e) = result {
+            eprintln!("Recrawl Error: {}", e);
+        }
+    }
+}
+
+async fn recrawl_due(state: &AppState, config: &Config) -> anyhow::Result<()> {
+    loop {
+        let due: Vec<(Uuid, Uuid, String)> = sqlx::query_as(
+            "SELECT b.id, b.user_id, b.url FROM bookmarks b
+             JOIN bookmark_contents c ON c.bookmark_id = b.id
+             WHERE b.deleted_at IS NULL AND b.processing_status = 'done' AND NOT b.is_broken AND b.snapshot_url IS NULL
+               AND NOT COALESCE(b.site_meta ? 'mastodon', false)
+               AND COALESCE(b.recrawled_at, c.fetched_at) < now() - make_interval(secs => $1)
+             ORDER BY COALESCE(b.recrawled_at, c.fetched_at)
+             LIMIT $2"
+        )
+        .bind(config.interval.as_secs_f64())
+        .bind(RECRAWL_BATCH)
+        .fetch_all(&state.db)
+        .await?;
+
+        if due.is_empty() {
+            return Ok(());
+        }
+
+        let limit = Arc::new(Semaphore::new(RECRAWL_CONCURRENCY));
+        let mut tasks = JoinSet::new();
+        for (id, user_id, url) in due {
+            let permit = limit.clone().acquire_owned().await?;
+            let state = state.clone();
+            tasks.spawn(async move {
+                if let Err(e) = recrawl(&state, user_id, id, &url).await {
+                    eprintln!("Error recrawling bookmark {}: {}", id, e);
+                }
+                drop(permit);
+            });
+        }
+        tasks.join_all().await;
+    }
+}
+
+/// Fetches the page and stores its content if it's still there and still something with text.
+async fn recrawl(state: &AppState, user_id: Uuid, bookmark_id: Uuid, url: &str) -> anyhow::Result<()> {
+    // Whatever happens, it isn't due again until the next interval
+    sqlx::query("UPDATE bookmarks SET recrawled_at = now() WHERE id = $1")
+        .bind(bookmark_id)
+        .execute(&state.db)
+        .await?;
+
+    let host = url::Url::parse(url)?.host_str().unwrap_or_default().to_string();
+    let credential = credentials::for_host(state, user_id, &host).await?;
+    let download = crate::download(state.fetcher.get_as(url, credential.as_ref()).await?).await?;
+    if !download.status.is_success() {
+        return Ok(());
+    }
+    let text = match download.kind {
+        content::Kind::Html => Some(crate::page_markdown(&String::from_utf8_lossy(&download.body))),
+        content::Kind::Pdf => content::pdf_text(download.body).await,
+        content::Kind::Text => Some(String::from_utf8_lossy(&download.body).into_owned()),
+        _ => None,
+    };
+    if let Some(text) = text {
+        changes::store(state, user_id, bookmark_id, &text).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,171 @@
+//! Reddit saved items: `saved_posts.csv` or `saved_comments.csv` from Reddit's data export, or
+//! the listing `reddit.com/user/USERNAME/saved.json` returns.
+//!
+//! The export only has each item's permalink, which becomes the bookmark. The listing has more:
+//! a saved link post is bookmarked at the page it links to, with the thread as its discussion URL,
+//! and a saved comment at the page its thread links to, with the comment's own link as the
+//! discussion and its text in the notes. Text posts are their own thread.
+
+use chrono::DateTime;
+use serde::Deserialize;
+
+use crate::import::ExternalBookmark;
+
+const SITE: &str = "https://www.reddit.com";
+
+#[derive(Deserialize)]
+struct Listing {
+    data: ListingData,
+}
+
+#[derive(Deserialize)]
+struct ListingData {
+    children: Vec<Thing>,
+}
+
+#[derive(Deserialize)]
+struct Thing {
+    /// `t3` for posts, `t1` for comments
+    kind: String,
+    data: ThingData,
+}
+
+#[derive(Deserialize)]
+struct ThingData {
+    permalink: Option<String>,
+    created_utc: Option<f64>,
+    /// Posts
+    title: Option<String>,
+    url: Option<String>,
+    #[serde(default)]
+    is_self: bool,
+    /// Comments
+    link_title: Option<String>,
+    link_url: Option<String>,
+    body: Option<String>,
+}
+
+/// Permalinks come relative to the site in the API and whole in exports.
+fn absolute(permalink: &str) -> String {
+    if permalink.starts_with("http://") || permalink.starts_with("https://") {
+        permalink.to_string()
+    } else {
+        format!("{}{}", SITE, permalink)
+    }
+}
+
+fn bookmark(thing: Thing) -> Option<ExternalBookmark> {
+    let data = thing.data;
+    let thread = absolute(data.permalink.as_deref()?);
+    let (title, target, notes) = match thing.kind.as_str() {
+        "t3" => (data.title, data.url.filter(|_| !data.is_self), None),
+        "t1" => (data.link_title, data.link_url, data.body),
+        _ => return None,
+    };
+    let target = target.filter(|url| url.starts_with("http://") || url.starts_with("https://"));
+    Some(ExternalBookmark {
+        discussion_url: target.is_some().then(|| thread.clone()),
+        url: target.unwrap_or(thread),
+        title: title.map(|t| t.trim().to_string()).filter(|t| !t.is_empty()),
+        added_at: data.created_utc.and_then(|secs| DateTime::from_timestamp(secs as i64, 0)),
+        description: notes.map(|n| n.trim().to_string()).filter(|n| !n.is_empty()),
+        ..Default::default()
+    })
+}
+
+fn parse_export(content: &str) -> anyhow::Result<Vec<ExternalBookmark>> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(content.as_bytes());
+    let permalink = reader
+        .headers()?
+        .iter()
+        .position(|h| h.trim().eq_ignore_ascii_case("permalink"))
+        .ok_or_else(|| anyhow::anyhow!("Reddit export is missing a permalink column"))?;
+    let mut bookmarks = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        if let Some(link) = record.get(permalink).map(str::trim).filter(|l| !l.is_empty()) {
+            bookmarks.push(ExternalBookmark { url: absolute(link), ..Default::default() });
+        }
+    }
+    Ok(bookmarks)
+}
+
+pub fn parse(content: &str) -> anyhow::Result<Vec<ExternalBookmark>> {
+    let trimmed = content.trim_start();
+    if !(trimmed.starts_with('{') || trimmed.starts_with('[')) {
+        return parse_export(content);
+    }
+    // One listing, or the pages of one saved together
+    let listings: Vec<Listing> = if trimmed.starts_with('[') {
+        serde_json::from_str(content)?
+    } else {
+        vec![serde_json::from_str(content)?]
+    };
+    Ok(listings
+        .into_iter()
+        .flat_map(|listing| listing.data.children)
+        .filter_map(bookmark)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_listing() {
+        let listing = r#"{"kind": "Listing", "data": {"after": null, "children": [
+            {"kind": "t3", "data": {
+                "title": "Announcing Rust 1.80",
+                "url": "https://blog.rust-lang.org/2024/07/25/Rust-1.80.0.html",
+                "permalink": "/r/rust/comments/1ec1xyz/announcing_rust_180/",
+                "is_self": false,
+                "created_utc": 1721900000.0
+            }},
+            {"kind": "t3", "data": {
+                "title": "What are you working on?",
+                "url": "https://www.reddit.com/r/rust/comments/1ed2abc/what_are_you_working_on/",
+                "permalink": "/r/rust/comments/1ed2abc/what_are_you_working_on/",
+                "is_self": true,
+                "selftext": "Weekly thread"
+            }},
+            {"kind": "t1", "data": {
+                "link_title": "Async closures are stable",
+                "link_url": "https://example.com/async-closures",
+                "permalink": "/r/rust/comments/1ee3def/async_closures/lf0abcd/",
+                "body": "This is the part that matters.",
+                "created_utc": 1722000000.0
+            }}
+        ]}}"#;
+        let bookmarks = parse(listing).unwrap();
+        assert_eq!(bookmarks.len(), 3);
+
+        assert_eq!(bookmarks[0].url, "https://blog.rust-lang.org/2024/07/25/Rust-1.80.0.html");
+        assert_eq!(
+            bookmarks[0].discussion_url.as_deref(),
+            Some("https://www.reddit.com/r/rust/comments/1ec1xyz/announcing_rust_180/")
+        );
+        assert_eq!(bookmarks[0].added_at, DateTime::from_timestamp(1721900000, 0));
+
+        assert_eq!(bookmarks[1].url, "https://www.reddit.com/r/rust/comments/1ed2abc/what_are_you_working_on/");
+        assert_eq!(bookmarks[1].discussion_url, None);
+
+        assert_eq!(bookmarks[2].url, "https://example.com/async-closures");
+        assert_eq!(bookmarks[2].title.as_deref(), Some("Async closures are stable"));
+        assert_eq!(bookmarks[2].description.as_deref(), Some("This is the part that matters."));
+        assert_eq!(
+            bookmarks[2].discussion_url.as_deref(),
+            Some("https://www.reddit.com/r/rust/comments/1ee3def/async_closures/lf0abcd/")
+        );
+    }
+
+    #[test]
+    fn test_parse_export() {
+        let csv = "id,permalink\n1ec1xyz,https://www.reddit.com/r/rust/comments/1ec1xyz/announcing_rust_180/\n";
+        let bookmarks = parse(csv).unwrap();
+        assert_eq!(bookmarks[0].url, "https://www.reddit.com/r/rust/comments/1ec1xyz/announcing_rust_180/");
+        assert_eq!(bookmarks[0].discussion_url, None);
+
+        assert!(parse("id,url\n1,https://example.com\n").is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! Trying flaky fetches and model calls again before the job they're part of fails.
+//!
+//! A 503 from a site or the model endpoint is usually gone a moment later. Each fetch and model
+//! call gets `RETRY_ATTEMPTS` tries, waiting `RETRY_BASE_DELAY_MS`, doubled after every failed
+//! try up to `RETRY_MAX_DELAY_MS`, and, with `RETRY_JITTER`, a random part of that. Only failures
+//! in the `RETRY_ON` classes are tried again. The last failure goes back to the job queue, which
+//! counts it as one attempt and retries the whole job on its own, much longer, schedule.
+
+use std::future::Future;
+use std::time::Duration;
+
+use rig::completion::CompletionError;
+use rig::extractor::ExtractionError;
+use rig::http_client;
+
+use crate::config::{RetryClass, RetryConfig};
+use crate::fetcher;
+
+#[derive(Debug, Clone)]
+pub struct Policy {
+    attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+    retry_on: Vec<RetryClass>,
+}
+
+/// A failure that may be worth another try.
+pub trait Retryable: std::fmt::Display {
+    /// Which `RetryClass` the failure falls in, if any.
+    fn class(&self) -> Option<RetryClass>;
+}
+
+/// The class of an HTTP response status: 429 and the 5xx errors.
+pub fn status_class(status: u16) -> Option<RetryClass> {
+    match status {
+        429 => Some(RetryClass::RateLimited),
+        500..=599 => Some(RetryClass::ServerError),
+        _ => None,
+    }
+}
+
+impl Retryable for reqwest::Error {
+    fn class(&self) -> Option<RetryClass> {
+        // Refused by the fetcher itself, which will refuse it again
+        if fetcher::cause(self).is_some() {
+            None
+        } else if self.is_timeout() {
+            Some(RetryClass::Timeout)
+        } else if self.is_connect() {
+            Some(RetryClass::Connect)
+        } else {
+            status_class(self.status()?.as_u16())
+        }
+    }
+}
+
+impl Retryable for ExtractionError {
+    fn class(&self) -> Option<RetryClass> {
+        let ExtractionError::CompletionError(CompletionError::HttpError(e)) = self else {
+            return None;
+        };
+        match e {
+            http_client::Error::InvalidStatusCode(status) | http_client::Error::InvalidStatusCodeWithMessage(status, _) => {
+                status_class(status.as_u16())
+            }
+            http_client::Error::Instance(e) => e.downcast_ref::<reqwest::Error>()?.class(),
+            _ => None,
+        }
+    }
+}
+
+impl Policy {
+    pub fn from_config(config: &RetryConfig) -> Self {
+        Policy {
+            attempts: config.attempts.max(1),
+            base_delay: Duration::from_millis(config.base_delay_ms),
+            max_delay: Duration::from_millis(config.max_delay_ms),
+            jitter: config.jitter,
+            retry_on: config.retry_on.clone(),
+        }
+    }
+
+    /// Runs `call` until it succeeds, fails in a way not worth retrying, or runs out of tries.
+    /// `what` names the call in the log.
+    pub async fn run<T, E, F, Fut>(&self, what: &str, mut call: F) -> Result<T, E>
+    where
+        E: Retryable,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut tries = 1;
+        loop {
+            let e = match call().await {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+            let retryable = e.class().is_some_and(|class| self.retry_on.contains(&class));
+            if !retryable || tries >= self.attempts {
+                return Err(e);
+            }
+            let delay = self.delay(tries);
+            eprintln!("Retrying {} in {}ms (try {} of {}): {}", what, delay.as_millis(), tries + 1, self.attempts, e);
+            tokio::time::sleep(delay).await;
+            tries += 1;
+        }
+    }
+
+    /// How long to wait after the `tries`th failed try.
+    fn delay(&self, tries: u32) -> Duration {
+        let delay = self.base_delay.saturating_mul(2u32.saturating_pow(tries - 1)).min(self.max_delay);
+        if self.jitter {
+            delay.mul_f64(rand::random_range(0.0..=1.0))
+        } else {
+            delay
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let config = RetryConfig { jitter: false, ..RetryConfig::default() };
+        let policy = Policy::from_config(&config);
+        assert_eq!(policy.delay(1), Duration::from_millis(500));
+        assert_eq!(policy.delay(3), Duration::from_millis(2000));
+        assert_eq!(policy.delay(40), Duration::from_millis(10_000));
+
+        let jittered = Policy { jitter: true, ..policy };
+        assert!(jittered.delay(2) <= Duration::from_millis(1000));
+
+        assert_eq!(status_class(503), Some(RetryClass::ServerError));
+        assert_eq!(status_class(429), Some(RetryClass::RateLimited));
+        assert_eq!(status_class(404), None);
+    }
+}
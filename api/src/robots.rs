@@ -0,0 +1,127 @@
+//! Reading robots.txt, as described by RFC 9309.
+//!
+//! Only the rules for our own product token are kept, or the `*` group's if nothing names us. The
+//! longest matching rule decides, with `Allow` winning a tie, and `*` and `$` work as wildcards.
+
+/// What one site's robots.txt lets us fetch.
+#[derive(Debug, Default)]
+pub struct Robots {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+impl Robots {
+    /// Everything allowed, for sites without a robots.txt.
+    pub fn allow_all() -> Robots {
+        Robots::default()
+    }
+
+    /// The rules in `txt` that apply to `agent`, a product token such as `linkman`.
+    pub fn parse(txt: &str, agent: &str) -> Robots {
+        let agent = agent.to_ascii_lowercase();
+        let mut groups: Vec<(Vec<String>, Vec<Rule>)> = Vec::new();
+        let mut in_agents = false;
+
+        for line in txt.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    // Consecutive user-agent lines share the group that follows them
+                    if !in_agents {
+                        groups.push((Vec::new(), Vec::new()));
+                    }
+                    in_agents = true;
+                    if let Some((agents, _)) = groups.last_mut() {
+                        agents.push(value.to_ascii_lowercase());
+                    }
+                }
+                key @ ("allow" | "disallow") => {
+                    in_agents = false;
+                    // An empty Disallow allows everything, which is what no rule does anyway
+                    if let Some((_, rules)) = groups.last_mut()
+                        && !value.is_empty()
+                    {
+                        rules.push(Rule { allow: key == "allow", pattern: value.to_string() });
+                    }
+                }
+                _ => in_agents = false,
+            }
+        }
+
+        let named = |name: &str| groups.iter().any(|(agents, _)| agents.iter().any(|a| a == name));
+        let wanted = if named(&agent) { agent } else { "*".to_string() };
+        let rules = groups
+            .into_iter()
+            .filter(|(agents, _)| agents.contains(&wanted))
+            .flat_map(|(_, rules)| rules)
+            .collect();
+        Robots { rules }
+    }
+
+    /// Whether `path`, with any query string, may be fetched.
+    pub fn is_allowed(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|rule| matches(&rule.pattern, path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+}
+
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = path.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_robots() {
+        let txt = "User-agent: *\n\
+                   Disallow: /\n\
+                   \n\
+                   User-agent: Googlebot\n\
+                   User-agent: linkman\n\
+                   Disallow: /private/ # members only\n\
+                   Allow: /private/public-*\n\
+                   Disallow: /*.pdf$\n";
+        let robots = Robots::parse(txt, "linkman");
+        assert!(robots.is_allowed("/blog/post"));
+        assert!(!robots.is_allowed("/private/notes"));
+        assert!(robots.is_allowed("/private/public-notes"));
+        assert!(!robots.is_allowed("/files/report.pdf"));
+        assert!(robots.is_allowed("/files/report.pdf?download=1"));
+
+        let others = Robots::parse(txt, "otherbot");
+        assert!(!others.is_allowed("/blog/post"));
+        assert!(Robots::allow_all().is_allowed("/anything"));
+    }
+}
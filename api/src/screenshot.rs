@@ -0,0 +1,87 @@
+//! Thumbnails of bookmarked pages from an external headless-browser service.
+//!
+//! `SCREENSHOT_SERVICE_URL` is a URL template where `{url}` is replaced by the percent-encoded
+//! page address, e.g. `http://screenshots:3000/capture?url={url}&width=1280`. The service is
+//! expected to answer a GET with the image itself.
+
+use std::time::Duration;
+
+use axum::body::Bytes;
+
+use crate::config::ScreenshotConfig;
+
+/// Screenshots bigger than this are almost certainly full-page captures, not thumbnails.
+const MAX_SCREENSHOT_BYTES: usize = 5 * 1024 * 1024;
+
+pub struct ScreenshotService {
+    template: String,
+    client: reqwest::Client,
+}
+
+pub struct Screenshot {
+    pub content_type: String,
+    pub image: Bytes,
+}
+
+impl ScreenshotService {
+    pub fn from_config(config: &ScreenshotConfig) -> anyhow::Result<Option<Self>> {
+        let Some(template) = config.service_url.clone() else {
+            return Ok(None);
+        };
+
+        let client = reqwest::Client::builder()
+            // Rendering a page is slow, but a wedged browser shouldn't hold a worker forever
+            .timeout(Duration::from_secs(60))
+            .build()?;
+
+        Ok(Some(ScreenshotService { template, client }))
+    }
+
+    fn request_url(&self, page_url: &str) -> String {
+        let encoded: String = url::form_urlencoded::byte_serialize(page_url.as_bytes()).collect();
+        self.template.replace("{url}", &encoded)
+    }
+
+    pub async fn capture(&self, page_url: &str) -> anyhow::Result<Screenshot> {
+        let res = self
+            .client
+            .get(self.request_url(page_url))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let content_type = res
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        if !content_type.starts_with("image/") {
+            anyhow::bail!("screenshot service returned {:?} instead of an image", content_type);
+        }
+
+        let image = res.bytes().await?;
+        if image.len() > MAX_SCREENSHOT_BYTES {
+            anyhow::bail!("screenshot is {} bytes, over the {} byte limit", image.len(), MAX_SCREENSHOT_BYTES);
+        }
+
+        Ok(Screenshot { content_type, image })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_url() {
+        let service = ScreenshotService {
+            template: "http://shots:3000/capture?url={url}&width=1280".to_string(),
+            client: reqwest::Client::new(),
+        };
+        assert_eq!(
+            service.request_url("https://example.com/a?b=c&d=e"),
+            "http://shots:3000/capture?url=https%3A%2F%2Fexample.com%2Fa%3Fb%3Dc%26d%3De&width=1280"
+        );
+    }
+}
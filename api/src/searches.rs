@@ -0,0 +1,123 @@
+//! Saved searches: a named set of search filters that can be re-run later, e.g. as a smart folder.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json as DbJson, Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::{push_list_filters, search_page, AppState, BookmarkPage, CurrentUser, PageParams, SearchParams};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/searches", get(list_searches).post(create_search))
+        .route("/searches/{id}", delete(delete_search))
+        .route("/searches/{id}/results", get(search_results))
+}
+
+fn db_error(e: sqlx::Error) -> StatusCode {
+    match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => StatusCode::CONFLICT,
+        e => {
+            eprintln!("Saved Searches Error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct SavedSearch {
+    id: Uuid,
+    name: String,
+    params: DbJson<SearchParams>,
+    created_at: DateTime<Utc>,
+}
+
+async fn list_searches(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Result<Json<Vec<SavedSearch>>, StatusCode> {
+    sqlx::query_as::<_, SavedSearch>(
+        "SELECT id, name, params, created_at FROM saved_searches WHERE user_id = $1 ORDER BY name"
+    )
+    .bind(user.id)
+    .fetch_all(&state.db)
+    .await
+    .map(Json)
+    .map_err(db_error)
+}
+
+#[derive(Deserialize)]
+struct CreateSearchRequest {
+    name: String,
+    #[serde(default)]
+    params: SearchParams,
+}
+
+async fn create_search(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Json(payload): Json<CreateSearchRequest>,
+) -> Result<(StatusCode, Json<SavedSearch>), StatusCode> {
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    // Refuse filters that would fail every time the search is run
+    push_list_filters(&mut QueryBuilder::<Postgres>::new(""), &payload.params)?;
+
+    let search = sqlx::query_as::<_, SavedSearch>(
+        "INSERT INTO saved_searches (user_id, name, params) VALUES ($1, $2, $3)
+         RETURNING id, name, params, created_at"
+    )
+    .bind(user.id)
+    .bind(name)
+    .bind(DbJson(&payload.params))
+    .fetch_one(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    Ok((StatusCode::CREATED, Json(search)))
+}
+
+async fn delete_search(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = sqlx::query("DELETE FROM saved_searches WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user.id)
+        .execute(&state.db)
+        .await
+        .map_err(db_error)?;
+
+    if deleted.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Runs a saved search, paginated like `GET /bookmarks`.
+async fn search_results(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+    Query(page): Query<PageParams>,
+) -> Result<Json<BookmarkPage>, StatusCode> {
+    let DbJson(params): DbJson<SearchParams> =
+        sqlx::query_scalar("SELECT params FROM saved_searches WHERE id = $1 AND user_id = $2")
+            .bind(id)
+            .bind(user.id)
+            .fetch_optional(&state.db)
+            .await
+            .map_err(db_error)?
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+    search_page(&state, &user, &params, &page).await.map(Json)
+}
@@ -0,0 +1,572 @@
+//! Shaarli: importing its exports, and the parts of its REST API (v1) its mobile apps and
+//! bookmarklets use.
+//!
+//! Shaarli exports bookmarks as Netscape HTML, which is read as any browser's is. Its API's
+//! `GET /links?limit=all` list can be imported too, keeping link descriptions as notes.
+//!
+//! The API is off unless `shaarli_api` is set, and is served per user under
+//! `/shaarli/USERNAME/api/v1`, since Shaarli is single-user and its clients only know an instance
+//! URL. Clients sign a short-lived JWT with the instance's "API secret"; here that's any of the
+//! user's API tokens, and the write scope is needed to change anything. Shaarli's `description`
+//! is the bookmark's notes and `private` is the opposite of public. Ids are UUIDs rather than
+//! Shaarli's numbers, and there are no short URLs, so `shorturl` is empty.
+
+use std::collections::HashSet;
+
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::Response,
+    routing::get,
+    Extension, Json, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, SecondsFormat, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{json, Value};
+use sha2::Sha512;
+use sqlx::{Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::{
+    audit, authenticate, client_ip, events, import::{normalize_tag, ExternalBookmark}, jobs, netscape,
+    normalize::normalize_url, rate_limit, replace_tags, required_scope, upsert_bookmark, ApiKey, AppState,
+    CurrentUser, SyncBookmarkRequest,
+};
+
+/// How old a token may be, as Shaarli allows.
+const TOKEN_LIFETIME_SECS: i64 = 9 * 60;
+/// How far ahead of the server's clock a client's may be.
+const CLOCK_SKEW_SECS: i64 = 60;
+/// Shaarli's page size when a client doesn't ask for one.
+const DEFAULT_LIMIT: i64 = 20;
+const DEFAULT_TAG_LIMIT: i64 = 100;
+
+/// A link as Shaarli's API has it, both in lists it returns and in what clients send.
+#[derive(Deserialize)]
+struct ExportedLink {
+    url: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    created: Option<String>,
+}
+
+/// Reads either export: Netscape HTML, or the JSON array the API lists links as.
+pub fn parse(content: &str) -> anyhow::Result<Vec<ExternalBookmark>> {
+    if content.trim_start().starts_with('<') {
+        return Ok(netscape::parse(content));
+    }
+    let links: Vec<ExportedLink> = serde_json::from_str(content)?;
+    Ok(links
+        .into_iter()
+        .filter_map(|link| {
+            let url = link.url.map(|u| u.trim().to_string()).filter(|u| !u.is_empty())?;
+            Some(ExternalBookmark {
+                url,
+                title: link.title.map(|t| t.trim().to_string()).filter(|t| !t.is_empty()),
+                tags: link.tags.iter().map(|t| normalize_tag(t)).filter(|t| !t.is_empty()).collect(),
+                added_at: link
+                    .created
+                    .as_deref()
+                    .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+                    .map(|t| t.to_utc()),
+                description: link.description.map(|d| d.trim().to_string()).filter(|d| !d.is_empty()),
+                ..Default::default()
+            })
+        })
+        .collect())
+}
+
+pub fn router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/info", get(info))
+        .route("/links", get(list_links).post(create_link))
+        .route("/links/{id}", get(get_link).put(update_link).delete(delete_link))
+        .route("/tags", get(list_tags))
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit::middleware))
+        .layer(middleware::from_fn_with_state(state, auth_middleware))
+}
+
+/// Whether `jwt` is an HS512 token signed with `secret`, issued no more than nine minutes before
+/// `now`.
+fn verify_jwt(jwt: &str, secret: &str, now: i64) -> bool {
+    let mut parts = jwt.split('.');
+    let (Some(header), Some(payload), Some(signature), None) = (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return false;
+    };
+    let decode = |part: &str| URL_SAFE_NO_PAD.decode(part.trim_end_matches('=')).ok();
+    let json = |part: &str| decode(part).and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok());
+
+    if json(header).is_none_or(|header| header["alg"] != "HS512") {
+        return false;
+    }
+    let Some(signature) = decode(signature) else { return false };
+    let mut mac = Hmac::<Sha512>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("{}.{}", header, payload).as_bytes());
+    if mac.verify_slice(&signature).is_err() {
+        return false;
+    }
+
+    json(payload)
+        .and_then(|claims| claims["iat"].as_i64())
+        .is_some_and(|iat| iat <= now + CLOCK_SKEW_SECS && now - iat <= TOKEN_LIFETIME_SECS)
+}
+
+#[derive(Deserialize)]
+struct UserPath {
+    username: String,
+}
+
+async fn auth_middleware(
+    State(state): State<AppState>,
+    Path(path): Path<UserPath>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let jwt = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    // The token only signs the JWT, so each of the user's is tried in turn
+    let tokens: Vec<Uuid> = sqlx::query_scalar(
+        "SELECT t.token FROM api_tokens t JOIN users u ON u.id = t.user_id
+         WHERE u.username = $1 AND t.revoked_at IS NULL AND (t.expires_at IS NULL OR t.expires_at > now())"
+    )
+    .bind(&path.username)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+    let now = Utc::now().timestamp();
+    let token = tokens
+        .into_iter()
+        .find(|token| verify_jwt(jwt, &token.to_string(), now))
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let (user, key) = authenticate(&state, &token.to_string(), client_ip(&req)).await?;
+
+    let scope = required_scope(req.method(), req.uri().path());
+    if !key.allows(scope) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    req.extensions_mut().insert(user);
+    req.extensions_mut().insert(key);
+    if scope == "write" {
+        return Ok(audit::record(&state, req, next).await);
+    }
+    Ok(next.run(req).await)
+}
+
+fn db_error(e: sqlx::Error) -> StatusCode {
+    eprintln!("Shaarli API Error: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Times as Shaarli writes them, `2015-05-05T12:34:56+00:00`.
+fn atom<S: Serializer>(time: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&time.to_rfc3339_opts(SecondsFormat::Secs, false))
+}
+
+/// Shaarli's `updated` is an empty string for links never edited.
+fn atom_or_empty<S: Serializer>(time: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error> {
+    match time {
+        Some(time) => atom(time, serializer),
+        None => serializer.serialize_str(""),
+    }
+}
+
+/// A bookmark in Shaarli's shape.
+#[derive(Serialize, sqlx::FromRow)]
+struct Link {
+    id: Uuid,
+    url: String,
+    shorturl: String,
+    title: String,
+    description: String,
+    tags: Vec<String>,
+    private: bool,
+    #[serde(serialize_with = "atom")]
+    created: DateTime<Utc>,
+    #[serde(serialize_with = "atom_or_empty")]
+    updated: Option<DateTime<Utc>>,
+}
+
+const LINK_SELECT: &str = "SELECT b.id, b.url, '' AS shorturl, COALESCE(b.title, b.url) AS title,
+     COALESCE(b.notes, '') AS description,
+     COALESCE((SELECT array_agg(t.name ORDER BY t.name) FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id
+      WHERE bt.bookmark_id = b.id), '{}') AS tags,
+     NOT b.is_public AS private, b.created_at AS created, b.updated_at AS updated
+     FROM bookmarks b";
+
+/// Shaarli's `all`, `private` and `public`.
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Visibility {
+    #[default]
+    All,
+    Private,
+    Public,
+}
+
+/// A page size, or `all`.
+fn limit(value: Option<&str>, default: i64) -> Result<Option<i64>, StatusCode> {
+    match value {
+        None => Ok(Some(default)),
+        Some("all") => Ok(None),
+        Some(n) => n.parse::<i64>().map(|n| Some(n.max(1))).map_err(|_| StatusCode::BAD_REQUEST),
+    }
+}
+
+fn push_limit(query: &mut QueryBuilder<Postgres>, limit: Option<i64>, offset: Option<i64>) {
+    if let Some(limit) = limit {
+        query.push(" LIMIT ").push_bind(limit);
+    }
+    query.push(" OFFSET ").push_bind(offset.unwrap_or(0).max(0));
+}
+
+fn push_visibility(query: &mut QueryBuilder<Postgres>, visibility: Visibility) {
+    match visibility {
+        Visibility::All => {}
+        Visibility::Private => {
+            query.push(" AND NOT b.is_public");
+        }
+        Visibility::Public => {
+            query.push(" AND b.is_public");
+        }
+    }
+}
+
+async fn info(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Result<Json<Value>, StatusCode> {
+    let (total, private): (i64, i64) = sqlx::query_as(
+        "SELECT count(*), count(*) FILTER (WHERE NOT is_public) FROM bookmarks WHERE user_id = $1 AND deleted_at IS NULL"
+    )
+    .bind(user.id)
+    .fetch_one(&state.db)
+    .await
+    .map_err(db_error)?;
+    Ok(Json(json!({
+        "global_counter": total,
+        "private_counter": private,
+        "settings": {
+            "title": format!("{}'s links", user.username),
+            "header_link": "",
+            "timezone": "UTC",
+            "enabled_plugins": [],
+            "default_private_links": true,
+        },
+    })))
+}
+
+#[derive(Deserialize)]
+struct ListParams {
+    offset: Option<i64>,
+    limit: Option<String>,
+    searchterm: Option<String>,
+    /// Space-separated; every one must be on the link
+    searchtags: Option<String>,
+    #[serde(default)]
+    visibility: Visibility,
+}
+
+async fn list_links(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Query(params): Query<ListParams>,
+) -> Result<Json<Vec<Link>>, StatusCode> {
+    let limit = limit(params.limit.as_deref(), DEFAULT_LIMIT)?;
+    let mut query = QueryBuilder::<Postgres>::new(LINK_SELECT);
+    query.push(" WHERE b.user_id = ").push_bind(user.id).push(" AND b.deleted_at IS NULL");
+    push_visibility(&mut query, params.visibility);
+    for word in params.searchterm.as_deref().unwrap_or_default().split_whitespace() {
+        let pattern = format!("%{}%", word);
+        query
+            .push(" AND (b.url ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR b.title ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR b.notes ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR EXISTS (SELECT 1 FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id WHERE bt.bookmark_id = b.id AND t.name ILIKE ")
+            .push_bind(pattern)
+            .push("))");
+    }
+    for tag in params.searchtags.as_deref().unwrap_or_default().split_whitespace() {
+        query
+            .push(" AND EXISTS (SELECT 1 FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id WHERE bt.bookmark_id = b.id AND lower(t.name) = lower(")
+            .push_bind(tag.to_string())
+            .push("))");
+    }
+    query.push(" ORDER BY b.created_at DESC, b.id DESC");
+    push_limit(&mut query, limit, params.offset);
+    let links = query.build_query_as().fetch_all(&state.db).await.map_err(db_error)?;
+    Ok(Json(links))
+}
+
+async fn fetch_link(state: &AppState, user_id: Uuid, id: Uuid) -> Result<Link, StatusCode> {
+    QueryBuilder::<Postgres>::new(LINK_SELECT)
+        .push(" WHERE b.id = ")
+        .push_bind(id)
+        .push(" AND b.user_id = ")
+        .push_bind(user_id)
+        .push(" AND b.deleted_at IS NULL")
+        .build_query_as()
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[derive(Deserialize)]
+struct LinkPath {
+    id: Uuid,
+}
+
+async fn get_link(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(path): Path<LinkPath>,
+) -> Result<Json<Link>, StatusCode> {
+    fetch_link(&state, user.id, path.id).await.map(Json)
+}
+
+/// What clients send to create or edit a link.
+#[derive(Deserialize)]
+struct LinkRequest {
+    url: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    tags: Option<Vec<String>>,
+    private: Option<bool>,
+}
+
+/// Shaarli tags have no spaces, so clients may send several in one.
+fn tags(names: &[String]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    names
+        .iter()
+        .flat_map(|name| name.split_whitespace())
+        .filter(|name| seen.insert(name.to_string()))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Like Shaarli, saving a URL that's already bookmarked is a conflict, answered with that link.
+async fn create_link(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Extension(key): Extension<ApiKey>,
+    Json(payload): Json<LinkRequest>,
+) -> Result<(StatusCode, Json<Link>), StatusCode> {
+    let url = payload.url.as_deref().ok_or(StatusCode::BAD_REQUEST)?;
+    let url = normalize_url(url).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let existing: Option<Uuid> = sqlx::query_scalar(
+        "SELECT id FROM bookmarks WHERE user_id = $1 AND url = $2 AND deleted_at IS NULL"
+    )
+    .bind(user.id)
+    .bind(&url)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(db_error)?;
+    if let Some(id) = existing {
+        return Ok((StatusCode::CONFLICT, Json(fetch_link(&state, user.id, id).await?)));
+    }
+
+    let request = SyncBookmarkRequest {
+        url: url.clone(),
+        title: payload.title.clone().filter(|title| !title.is_empty()),
+        notes: payload.description.clone().filter(|notes| !notes.is_empty()),
+        tags: payload.tags.as_deref().map(tags),
+        tag_mode: None,
+    };
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    let (bookmark_id, inserted) = upsert_bookmark(&mut tx, user.id, key.tag_mode, &url, &request).await.map_err(db_error)?;
+    // Shaarli's default for new links is private
+    sqlx::query("UPDATE bookmarks SET is_public = $2 WHERE id = $1")
+        .bind(bookmark_id)
+        .bind(!payload.private.unwrap_or(true))
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    if inserted {
+        jobs::queue(&mut *tx, &[bookmark_id]).await.map_err(db_error)?;
+    }
+    tx.commit().await.map_err(db_error)?;
+    state.job_wakeup.notify_one();
+
+    let event = if inserted { events::Event::Created } else { events::Event::Updated };
+    events::emit(&state, user.id, event, bookmark_id);
+    Ok((StatusCode::CREATED, Json(fetch_link(&state, user.id, bookmark_id).await?)))
+}
+
+/// Whatever is given is changed; Shaarli's clients send the whole link anyway.
+async fn update_link(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(path): Path<LinkPath>,
+    Json(payload): Json<LinkRequest>,
+) -> Result<Json<Link>, StatusCode> {
+    let url = match payload.url.as_deref() {
+        Some(url) => Some(normalize_url(url).map_err(|_| StatusCode::BAD_REQUEST)?),
+        None => None,
+    };
+
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    let updated: Option<Uuid> = sqlx::query_scalar(
+        "UPDATE bookmarks SET url = COALESCE($3, url),
+             title = CASE WHEN $4 IS NULL OR $4 = '' THEN title ELSE $4 END,
+             title_from_page = title_from_page AND ($4 IS NULL OR $4 = ''),
+             notes = CASE WHEN $5 IS NULL THEN notes ELSE NULLIF($5, '') END,
+             is_public = COALESCE(NOT $6, is_public),
+             updated_at = now()
+         WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL
+         RETURNING id"
+    )
+    .bind(path.id)
+    .bind(user.id)
+    .bind(&url)
+    .bind(&payload.title)
+    .bind(&payload.description)
+    .bind(payload.private)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => StatusCode::CONFLICT,
+        e => db_error(e),
+    })?;
+    if updated.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    if let Some(names) = &payload.tags {
+        replace_tags(&mut tx, user.id, path.id, &tags(names)).await.map_err(db_error)?;
+    }
+    tx.commit().await.map_err(db_error)?;
+
+    events::emit(&state, user.id, events::Event::Updated, path.id);
+    fetch_link(&state, user.id, path.id).await.map(Json)
+}
+
+/// Moves the bookmark to the trash, where the rest of linkman can still restore it.
+async fn delete_link(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(path): Path<LinkPath>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = sqlx::query(
+        "UPDATE bookmarks SET deleted_at = now(), updated_at = now() WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL"
+    )
+    .bind(path.id)
+    .bind(user.id)
+    .execute(&state.db)
+    .await
+    .map_err(db_error)?;
+    if deleted.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    events::emit(&state, user.id, events::Event::Deleted, path.id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct Tag {
+    name: String,
+    occurrences: i64,
+}
+
+#[derive(Deserialize)]
+struct TagParams {
+    offset: Option<i64>,
+    limit: Option<String>,
+    #[serde(default)]
+    visibility: Visibility,
+}
+
+/// Tags on the user's links, the most used first.
+async fn list_tags(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Query(params): Query<TagParams>,
+) -> Result<Json<Vec<Tag>>, StatusCode> {
+    let limit = limit(params.limit.as_deref(), DEFAULT_TAG_LIMIT)?;
+    let mut query = QueryBuilder::<Postgres>::new(
+        "SELECT t.name, count(*) AS occurrences FROM tags t
+         JOIN bookmark_tags bt ON bt.tag_id = t.id JOIN bookmarks b ON b.id = bt.bookmark_id
+         WHERE t.user_id = "
+    );
+    query.push_bind(user.id).push(" AND b.deleted_at IS NULL");
+    push_visibility(&mut query, params.visibility);
+    query.push(" GROUP BY t.name ORDER BY occurrences DESC, t.name");
+    push_limit(&mut query, limit, params.offset);
+    let tags = query.build_query_as().fetch_all(&state.db).await.map_err(db_error)?;
+    Ok(Json(tags))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign(header: &str, claims: &str, secret: &str) -> String {
+        let signed = format!("{}.{}", URL_SAFE_NO_PAD.encode(header), URL_SAFE_NO_PAD.encode(claims));
+        let mut mac = Hmac::<Sha512>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(signed.as_bytes());
+        format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes()))
+    }
+
+    #[test]
+    fn test_verify_jwt() {
+        let header = r#"{"typ":"JWT","alg":"HS512"}"#;
+        let jwt = sign(header, r#"{"iat":1700000000}"#, "secret");
+        assert!(verify_jwt(&jwt, "secret", 1700000000));
+        assert!(verify_jwt(&jwt, "secret", 1700000000 + TOKEN_LIFETIME_SECS));
+        assert!(!verify_jwt(&jwt, "secret", 1700000000 + TOKEN_LIFETIME_SECS + 1));
+        assert!(!verify_jwt(&jwt, "secret", 1700000000 - CLOCK_SKEW_SECS - 1));
+        assert!(!verify_jwt(&jwt, "other", 1700000000));
+
+        assert!(!verify_jwt(&sign(r#"{"alg":"none"}"#, r#"{"iat":1700000000}"#, "secret"), "secret", 1700000000));
+        assert!(!verify_jwt(&sign(header, "{}", "secret"), "secret", 1700000000));
+        assert!(!verify_jwt("not.a-jwt", "secret", 1700000000));
+    }
+
+    #[test]
+    fn test_parse_api_links() {
+        let links = r#"[
+            {
+                "id": 3,
+                "url": "https://shaarli.readthedocs.io/",
+                "shorturl": "abc123",
+                "title": "Shaarli docs",
+                "description": "How to *self-host* it",
+                "tags": ["docs", "Self-Hosting"],
+                "private": true,
+                "created": "2024-01-02T03:04:05+01:00",
+                "updated": ""
+            },
+            { "id": 4, "url": "", "title": "Note", "tags": [] }
+        ]"#;
+        let bookmarks = parse(links).unwrap();
+        assert_eq!(bookmarks.len(), 1);
+        assert_eq!(bookmarks[0].title.as_deref(), Some("Shaarli docs"));
+        assert_eq!(bookmarks[0].tags, vec!["docs", "self-hosting"]);
+        assert_eq!(bookmarks[0].description.as_deref(), Some("How to *self-host* it"));
+        assert_eq!(bookmarks[0].added_at, "2024-01-02T02:04:05Z".parse().ok());
+
+        let html = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<DL><p>
+<DT><A HREF="https://example.com/" ADD_DATE="1700000000" PRIVATE="0" TAGS="rust,web">Example</A>
+<DD>Notes here
+</DL><p>"#;
+        let bookmarks = parse(html).unwrap();
+        assert_eq!(bookmarks[0].tags, vec!["rust", "web"]);
+
+        assert_eq!(tags(&["a b".to_string(), "b".to_string()]), vec!["a", "b"]);
+    }
+}
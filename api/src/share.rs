@@ -0,0 +1,133 @@
+//! Public, unauthenticated links to a single bookmark.
+//!
+//! Anyone with the link sees the bookmark, its summary and the stored page text, but not the
+//! owner's notes. Revoking deletes the token, so an old link can't come back to life.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Response,
+    routing::post,
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{archived_page, AppState, CurrentUser};
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/bookmarks/{id}/share", post(share_bookmark).delete(revoke_share))
+}
+
+fn db_error(e: sqlx::Error) -> StatusCode {
+    eprintln!("Share Error: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+#[derive(Serialize)]
+struct ShareLink {
+    token: Uuid,
+    url: String,
+}
+
+/// Returns the bookmark's share link, creating one the first time.
+async fn share_bookmark(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ShareLink>, StatusCode> {
+    let token: Uuid = sqlx::query_scalar(
+        "INSERT INTO bookmark_shares (bookmark_id)
+         SELECT id FROM bookmarks WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL
+         ON CONFLICT (bookmark_id) DO UPDATE SET bookmark_id = EXCLUDED.bookmark_id
+         RETURNING token"
+    )
+    .bind(id)
+    .bind(user.id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(db_error)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(ShareLink {
+        token,
+        url: format!("/share/{}", token),
+    }))
+}
+
+async fn revoke_share(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let revoked = sqlx::query(
+        "DELETE FROM bookmark_shares s USING bookmarks b
+         WHERE s.bookmark_id = b.id AND b.id = $1 AND b.user_id = $2"
+    )
+    .bind(id)
+    .bind(user.id)
+    .execute(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    if revoked.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct SharedBookmark {
+    url: String,
+    title: Option<String>,
+    ai_summary: Option<String>,
+    tags: Option<Vec<String>>,
+    created_at: DateTime<Utc>,
+    /// Markdown of the page as it was when saved
+    content: Option<String>,
+    /// Whether `/share/{token}/archive` has a snapshot to serve
+    archived: bool,
+}
+
+pub async fn shared_bookmark(
+    State(state): State<AppState>,
+    Path(token): Path<Uuid>,
+) -> Result<Json<SharedBookmark>, StatusCode> {
+    sqlx::query_as::<_, SharedBookmark>(
+        "SELECT b.url, b.title, b.ai_summary, b.created_at,
+         (SELECT array_agg(t.name ORDER BY t.name) FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id
+          WHERE bt.bookmark_id = b.id) as tags,
+         c.markdown as content,
+         b.archive_url IS NOT NULL as archived
+         FROM bookmark_shares s
+         JOIN bookmarks b ON b.id = s.bookmark_id
+         LEFT JOIN bookmark_contents c ON c.bookmark_id = b.id
+         WHERE s.token = $1 AND b.deleted_at IS NULL"
+    )
+    .bind(token)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(db_error)?
+    .map(Json)
+    .ok_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn shared_archive(
+    State(state): State<AppState>,
+    Path(token): Path<Uuid>,
+) -> Result<Response, StatusCode> {
+    let archive = state.archive.as_ref().ok_or(StatusCode::NOT_IMPLEMENTED)?;
+
+    let bookmark_id: Uuid = sqlx::query_scalar(
+        "SELECT b.id FROM bookmark_shares s JOIN bookmarks b ON b.id = s.bookmark_id
+         WHERE s.token = $1 AND b.deleted_at IS NULL AND b.archive_url IS NOT NULL"
+    )
+    .bind(token)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(db_error)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    archived_page(archive, bookmark_id).await
+}
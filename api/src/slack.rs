@@ -0,0 +1,435 @@
+//! A Slack app for teams sharing a linkman instance: the `/linkman` slash command and link
+//! unfurls.
+//!
+//! With `SLACK_SIGNING_SECRET` set, `POST /slack/commands` takes the slash command and
+//! `POST /slack/events` the app's event subscription; anything not signed with the secret in the
+//! last five minutes is turned away. Each person connects their own linkman account once with
+//! `/linkman connect API_KEY`, using a write-scoped key made for Slack, which the command's
+//! reply never repeats. `/linkman save URL [tags]` then bookmarks the URL as `POST
+//! /bookmarks/sync` would, with comma-separated tags. Revoking the key disconnects them, as does
+//! `/linkman disconnect`.
+//!
+//! With `SLACK_BOT_TOKEN` as well, a `link_shared` event for a link the person sharing it has
+//! saved is answered with `chat.unfurl`: the bookmark's title, summary and tags, for everyone in
+//! the channel to see. Links they haven't saved are left to Slack's own previews.
+
+use std::time::Duration;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::post,
+    Form, Json, Router,
+};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::{
+    authenticate, config::SlackConfig, events, jobs, normalize::normalize_url, quick_add::split_tags, upsert_bookmark,
+    AppState, SyncBookmarkRequest,
+};
+
+/// How old a request's timestamp can be, in seconds, before it's taken for a replay.
+const MAX_AGE_SECS: i64 = 5 * 60;
+const BODY_LIMIT: usize = 64 * 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const SUMMARY_CHARS: usize = 300;
+
+pub struct Slack {
+    client: reqwest::Client,
+    signing_secret: String,
+    bot_token: Option<String>,
+    api_url: String,
+}
+
+impl Slack {
+    pub fn from_config(config: &SlackConfig) -> anyhow::Result<Option<Self>> {
+        let Some(signing_secret) = &config.signing_secret else {
+            return Ok(None);
+        };
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent(format!("linkman/{}", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(Some(Slack {
+            client,
+            signing_secret: signing_secret.clone(),
+            bot_token: config.bot_token.clone(),
+            api_url: config.api_url.trim_end_matches('/').to_string(),
+        }))
+    }
+}
+
+pub fn router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/slack/commands", post(command))
+        .route("/slack/events", post(event))
+        .layer(middleware::from_fn_with_state(state, verify_middleware))
+}
+
+/// Whether `signature` is Slack's `v0=` HMAC of the timestamp and body, sent within
+/// `MAX_AGE_SECS` of `now`.
+fn verify(secret: &str, timestamp: &str, body: &[u8], signature: &str, now: i64) -> bool {
+    let Ok(sent) = timestamp.parse::<i64>() else { return false };
+    if (now - sent).abs() > MAX_AGE_SECS {
+        return false;
+    }
+    let Some(Ok(signature)) = signature.strip_prefix("v0=").map(hex::decode) else { return false };
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(b"v0:");
+    mac.update(timestamp.as_bytes());
+    mac.update(b":");
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Checks the signature over the raw body, then hands the body on for the handler to parse.
+async fn verify_middleware(State(state): State<AppState>, req: Request, next: Next) -> Result<Response, StatusCode> {
+    let slack = state.slack.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    let (parts, body) = req.into_parts();
+    let body = axum::body::to_bytes(body, BODY_LIMIT).await.map_err(|_| StatusCode::PAYLOAD_TOO_LARGE)?;
+    let header = |name: &str| parts.headers.get(name).and_then(|value| value.to_str().ok()).unwrap_or_default();
+    let timestamp = header("x-slack-request-timestamp");
+    let signature = header("x-slack-signature");
+    if !verify(&slack.signing_secret, timestamp, &body, signature, chrono::Utc::now().timestamp()) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(next.run(Request::from_parts(parts, Body::from(body))).await)
+}
+
+/// What the text after `/linkman` asks for.
+#[derive(Debug, PartialEq)]
+enum Action<'a> {
+    Help,
+    Connect(&'a str),
+    Disconnect,
+    Save { url: String, tags: Option<Vec<String>> },
+}
+
+fn parse(text: &str) -> Action<'_> {
+    let text = text.trim();
+    let (verb, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let rest = rest.trim();
+    match verb {
+        "connect" if !rest.is_empty() => Action::Connect(rest),
+        "disconnect" => Action::Disconnect,
+        "save" if !rest.is_empty() => {
+            let (url, tags) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            Action::Save { url: unwrap_link(url), tags: split_tags(Some(tags)) }
+        }
+        _ => Action::Help,
+    }
+}
+
+/// The URL in a link as Slack sends it, `<https://example.com/?a=1&amp;b=2|example.com>`.
+fn unwrap_link(text: &str) -> String {
+    let link = text.strip_prefix('<').and_then(|text| text.strip_suffix('>')).unwrap_or(text);
+    let url = link.split_once('|').map_or(link, |(url, _)| url);
+    url.replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&")
+}
+
+/// Text shown as it is in a Slack message, without `<` starting a link.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+const HELP: &str = "`/linkman connect API_KEY` links your linkman account, using a write-scoped API key \
+                    made for Slack\n`/linkman save URL [tag, tag]` bookmarks a link\n\
+                    `/linkman disconnect` forgets your account";
+
+#[derive(Deserialize)]
+struct SlashCommand {
+    team_id: String,
+    user_id: String,
+    #[serde(default)]
+    text: String,
+}
+
+/// Only the person who ran the command sees the reply.
+fn reply(text: impl Into<String>) -> Json<Value> {
+    Json(json!({ "response_type": "ephemeral", "text": text.into() }))
+}
+
+fn db_error(e: sqlx::Error) -> StatusCode {
+    eprintln!("Slack Error: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// The token of the API key the Slack user connected with, if they did.
+async fn connected_key(state: &AppState, team_id: &str, slack_user_id: &str) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT t.token FROM slack_users s JOIN api_tokens t ON t.id = s.key_id
+         WHERE s.team_id = $1 AND s.slack_user_id = $2"
+    )
+    .bind(team_id)
+    .bind(slack_user_id)
+    .fetch_optional(&state.db)
+    .await
+}
+
+async fn command(State(state): State<AppState>, Form(command): Form<SlashCommand>) -> Result<Json<Value>, StatusCode> {
+    match parse(&command.text) {
+        Action::Help => Ok(reply(HELP)),
+        Action::Connect(token) => {
+            let (user, key) = match authenticate(&state, token, None).await {
+                Ok(found) => found,
+                Err(StatusCode::UNAUTHORIZED) => return Ok(reply("That isn't a live linkman API key.")),
+                Err(status) => return Err(status),
+            };
+            if !key.allows("write") {
+                return Ok(reply("That API key can't save bookmarks; make one with the write scope."));
+            }
+            sqlx::query(
+                "INSERT INTO slack_users (team_id, slack_user_id, key_id) VALUES ($1, $2, $3)
+                 ON CONFLICT (team_id, slack_user_id) DO UPDATE SET key_id = EXCLUDED.key_id, created_at = now()"
+            )
+            .bind(&command.team_id)
+            .bind(&command.user_id)
+            .bind(key.id)
+            .execute(&state.db)
+            .await
+            .map_err(db_error)?;
+            Ok(reply(format!("Connected to linkman as {}.", escape(&user.username))))
+        }
+        Action::Disconnect => {
+            sqlx::query("DELETE FROM slack_users WHERE team_id = $1 AND slack_user_id = $2")
+                .bind(&command.team_id)
+                .bind(&command.user_id)
+                .execute(&state.db)
+                .await
+                .map_err(db_error)?;
+            Ok(reply("Disconnected from linkman."))
+        }
+        Action::Save { url, tags } => {
+            let Some(token) = connected_key(&state, &command.team_id, &command.user_id).await.map_err(db_error)? else {
+                return Ok(reply("Connect your linkman account first: `/linkman connect API_KEY`"));
+            };
+            let (user, key) = match authenticate(&state, &token.to_string(), None).await {
+                Ok(found) => found,
+                Err(StatusCode::UNAUTHORIZED) => {
+                    return Ok(reply("The API key you connected with has been revoked or has expired; connect a new one."));
+                }
+                Err(status) => return Err(status),
+            };
+            let Ok(url) = normalize_url(&url) else {
+                return Ok(reply(format!("{} isn't a URL that can be bookmarked.", escape(&url))));
+            };
+            let payload = SyncBookmarkRequest { url: url.clone(), title: None, notes: None, tags, tag_mode: None };
+            let mut tx = state.db.begin().await.map_err(db_error)?;
+            let (bookmark_id, inserted) =
+                upsert_bookmark(&mut tx, user.id, key.tag_mode, &url, &payload).await.map_err(db_error)?;
+            jobs::queue(&mut *tx, &[bookmark_id]).await.map_err(db_error)?;
+            tx.commit().await.map_err(db_error)?;
+            state.job_wakeup.notify_one();
+
+            let event = if inserted { events::Event::Created } else { events::Event::Updated };
+            events::emit(&state, user.id, event, bookmark_id);
+
+            if inserted {
+                Ok(reply(format!("Saved {}; it's being fetched and tagged.", escape(&url))))
+            } else {
+                Ok(reply(format!("{} was already saved; updated it.", escape(&url))))
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Envelope {
+    /// Sent once, when the request URL is set in the app's settings
+    UrlVerification { challenge: String },
+    EventCallback { team_id: String, event: Value },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct LinkShared {
+    user: String,
+    /// Where the message with the links is; a link typed in the message composer has
+    /// `unfurl_id` and `source` instead
+    channel: Option<String>,
+    message_ts: Option<String>,
+    unfurl_id: Option<String>,
+    source: Option<String>,
+    links: Vec<SharedLink>,
+}
+
+#[derive(Deserialize)]
+struct SharedLink {
+    url: String,
+}
+
+/// Slack wants an answer within three seconds, so unfurling happens after it has one.
+async fn event(State(state): State<AppState>, body: Bytes) -> Result<Response, StatusCode> {
+    let envelope: Envelope = serde_json::from_slice(&body).map_err(|_| StatusCode::BAD_REQUEST)?;
+    match envelope {
+        Envelope::UrlVerification { challenge } => Ok(Json(json!({ "challenge": challenge })).into_response()),
+        Envelope::EventCallback { team_id, event } if event["type"] == "link_shared" => {
+            let shared: LinkShared = serde_json::from_value(event).map_err(|_| StatusCode::BAD_REQUEST)?;
+            tokio::spawn(async move {
+                if let Err(e) = unfurl(&state, &team_id, shared).await {
+                    eprintln!("Slack Unfurl Error: {}", e);
+                }
+            });
+            Ok(StatusCode::OK.into_response())
+        }
+        Envelope::EventCallback { .. } | Envelope::Other => Ok(StatusCode::OK.into_response()),
+    }
+}
+
+/// How a saved bookmark shows up under the message that shared its link.
+fn attachment(url: &str, title: Option<&str>, summary: Option<&str>, tags: &[String]) -> Value {
+    let mut attachment = Map::new();
+    attachment.insert("title".to_string(), json!(title.unwrap_or(url)));
+    attachment.insert("title_link".to_string(), json!(url));
+    if let Some(summary) = summary {
+        let mut text: String = summary.chars().take(SUMMARY_CHARS).collect();
+        if summary.chars().count() > SUMMARY_CHARS {
+            text.push('…');
+        }
+        attachment.insert("text".to_string(), json!(text));
+    }
+    let footer = if tags.is_empty() { "linkman".to_string() } else { format!("linkman · {}", tags.join(", ")) };
+    attachment.insert("footer".to_string(), json!(footer));
+    Value::Object(attachment)
+}
+
+#[derive(sqlx::FromRow)]
+struct Saved {
+    url: String,
+    title: Option<String>,
+    ai_summary: Option<String>,
+    tags: Vec<String>,
+}
+
+async fn unfurl(state: &AppState, team_id: &str, shared: LinkShared) -> anyhow::Result<()> {
+    let Some(slack) = &state.slack else { return Ok(()) };
+    let Some(bot_token) = &slack.bot_token else { return Ok(()) };
+    let Some(token) = connected_key(state, team_id, &shared.user).await? else { return Ok(()) };
+    let (user, key) = match authenticate(state, &token.to_string(), None).await {
+        Ok(found) => found,
+        Err(StatusCode::UNAUTHORIZED) => return Ok(()),
+        Err(status) => anyhow::bail!("authenticating the connected key: {}", status),
+    };
+    if !key.allows("read") {
+        return Ok(());
+    }
+
+    // Slack's URL for each link, by the URL it would be saved under
+    let links: Vec<(String, String)> = shared
+        .links
+        .into_iter()
+        .filter_map(|link| Some((normalize_url(&link.url).ok()?, link.url)))
+        .collect();
+    let wanted: Vec<&String> = links.iter().map(|(normalized, _)| normalized).collect();
+    let saved: Vec<Saved> = sqlx::query_as(
+        "SELECT b.url, b.title, b.ai_summary, COALESCE((SELECT array_agg(t.name ORDER BY t.name) FROM bookmark_tags bt
+             JOIN tags t ON bt.tag_id = t.id WHERE bt.bookmark_id = b.id), '{}') AS tags
+         FROM bookmarks b
+         WHERE b.user_id = $1 AND b.deleted_at IS NULL AND b.url = ANY($2)"
+    )
+    .bind(user.id)
+    .bind(&wanted)
+    .fetch_all(&state.db)
+    .await?;
+
+    let mut unfurls = Map::new();
+    for bookmark in &saved {
+        for (_, shared_url) in links.iter().filter(|(normalized, _)| *normalized == bookmark.url) {
+            let unfurl = attachment(shared_url, bookmark.title.as_deref(), bookmark.ai_summary.as_deref(), &bookmark.tags);
+            unfurls.insert(shared_url.clone(), unfurl);
+        }
+    }
+    if unfurls.is_empty() {
+        return Ok(());
+    }
+
+    let mut request = json!({ "unfurls": unfurls });
+    match (shared.channel, shared.message_ts, shared.unfurl_id, shared.source) {
+        (Some(channel), Some(ts), _, _) => {
+            request["channel"] = json!(channel);
+            request["ts"] = json!(ts);
+        }
+        (_, _, Some(unfurl_id), Some(source)) => {
+            request["unfurl_id"] = json!(unfurl_id);
+            request["source"] = json!(source);
+        }
+        _ => return Ok(()),
+    }
+    let response: Value = slack
+        .client
+        .post(format!("{}/chat.unfurl", slack.api_url))
+        .bearer_auth(bot_token)
+        .json(&request)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    // Slack answers 200 either way
+    if response["ok"] != true {
+        anyhow::bail!("chat.unfurl: {}", response["error"]);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        // The example in Slack's "Verifying requests from Slack"
+        let secret = "8f742231b10e8888abcd99yyyzzz85a5";
+        let body = b"token=xyzz0WbapA4vBCDEFasx0q6G&team_id=T1DC2JH3J&team_domain=testteamnow&channel_id=G8PSS9T3V&channel_name=foobar&user_id=U2CERLKJA&user_name=roadrunner&command=%2Fwebhook-collect&text=&response_url=https%3A%2F%2Fhooks.slack.com%2Fcommands%2FT1DC2JH3J%2F397700885554%2F96rGlfmibIGlgcZRskXaIFfN&trigger_id=398738663015.47445629121.803a0bc887a14d10d2c447fce8b6703c";
+        let signature = "v0=a2114d57b48eac39b9ad189dd8316235a7b4a8d21a10bd27519666489c69b503";
+        assert!(verify(secret, "1531420618", body, signature, 1531420618 + 60));
+        // Too old, sent at another time, a different body or secret, or not signed at all
+        assert!(!verify(secret, "1531420618", body, signature, 1531420618 + MAX_AGE_SECS + 1));
+        assert!(!verify(secret, "1531420619", body, signature, 1531420618));
+        assert!(!verify(secret, "1531420618", b"token=other", signature, 1531420618));
+        assert!(!verify("another secret", "1531420618", body, signature, 1531420618));
+        assert!(!verify(secret, "1531420618", body, "", 1531420618));
+        assert!(!verify(secret, "", body, signature, 1531420618));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse(" save <https://example.com/?a=1&amp;b=2|example.com> rust, to read "),
+            Action::Save {
+                url: "https://example.com/?a=1&b=2".to_string(),
+                tags: Some(vec!["rust".to_string(), "to read".to_string()]),
+            }
+        );
+        assert_eq!(parse("save https://example.com/"), Action::Save { url: "https://example.com/".to_string(), tags: None });
+        assert_eq!(parse("connect  0e4f…"), Action::Connect("0e4f…"));
+        assert_eq!(parse("disconnect"), Action::Disconnect);
+        assert_eq!(parse("save"), Action::Help);
+        assert_eq!(parse("connect"), Action::Help);
+        assert_eq!(parse(""), Action::Help);
+    }
+
+    #[test]
+    fn test_attachment() {
+        let tags = vec!["rust".to_string(), "release".to_string()];
+        assert_eq!(
+            attachment("https://blog.rust-lang.org/", Some("Rust Blog"), None, &tags),
+            json!({ "title": "Rust Blog", "title_link": "https://blog.rust-lang.org/", "footer": "linkman · rust, release" })
+        );
+        let summary = "a".repeat(SUMMARY_CHARS + 1);
+        let long = attachment("https://example.com/", None, Some(&summary), &[]);
+        assert_eq!(long["title"], "https://example.com/");
+        assert_eq!(long["text"].as_str().unwrap().chars().count(), SUMMARY_CHARS + 1);
+        assert!(long["text"].as_str().unwrap().ends_with('…'));
+        assert_eq!(long["footer"], "linkman");
+    }
+}
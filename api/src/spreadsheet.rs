@@ -0,0 +1,130 @@
+//! Reading and writing bookmarks as CSV, for spreadsheets and whatever else keeps lists in them.
+//!
+//! Exports have the columns `url,title,tags,created_at,notes`, with tags separated by commas
+//! inside their cell. Imports find those columns by header name, ignoring case, unless a
+//! `columns` mapping like `url=Link,tags=Labels` points them at other headers. Only `url` is
+//! required. Tags may be separated by commas, semicolons or `|`, and `created_at` may be an RFC
+//! 3339 time, a plain `YYYY-MM-DD` date or Unix seconds.
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::import::{normalize_tag, ExternalBookmark};
+
+const COLUMNS: [&str; 5] = ["url", "title", "tags", "created_at", "notes"];
+
+/// Which header each of `COLUMNS` is read from.
+fn mapping(columns: Option<&str>) -> anyhow::Result<Vec<(&'static str, String)>> {
+    let mut mapping: Vec<(&'static str, String)> = COLUMNS.iter().map(|c| (*c, c.to_string())).collect();
+    for pair in columns.unwrap_or_default().split(',').filter(|p| !p.trim().is_empty()) {
+        let (field, header) = pair
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("column mapping {:?} should look like field=Header", pair))?;
+        let Some(entry) = mapping.iter_mut().find(|(f, _)| *f == field.trim()) else {
+            anyhow::bail!("unknown column {:?}, expected one of {}", field.trim(), COLUMNS.join(", "));
+        };
+        entry.1 = header.trim().to_string();
+    }
+    Ok(mapping)
+}
+
+pub fn parse(content: &str, columns: Option<&str>) -> anyhow::Result<Vec<ExternalBookmark>> {
+    let mapping = mapping(columns)?;
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(content.as_bytes());
+    let headers = reader.headers()?.clone();
+    let column = |field: &str| {
+        let (_, name) = mapping.iter().find(|(f, _)| *f == field)?;
+        headers.iter().position(|h| h.trim().eq_ignore_ascii_case(name))
+    };
+    let (url, title, tags, created_at, notes) = (
+        column("url").ok_or_else(|| anyhow::anyhow!("CSV is missing a url column"))?,
+        column("title"),
+        column("tags"),
+        column("created_at"),
+        column("notes"),
+    );
+
+    let mut bookmarks = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let field = |idx: Option<usize>| {
+            idx.and_then(|i| record.get(i))
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+
+        let Some(url) = field(Some(url)) else { continue };
+        bookmarks.push(ExternalBookmark {
+            url: url.to_string(),
+            title: field(title).map(str::to_string),
+            tags: field(tags)
+                .map(|t| t.split([',', ';', '|']).map(normalize_tag).filter(|t| !t.is_empty()).collect())
+                .unwrap_or_default(),
+            added_at: field(created_at).and_then(parse_time),
+            description: field(notes).map(str::to_string),
+            ..Default::default()
+        });
+    }
+    Ok(bookmarks)
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.to_utc());
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Some(date.and_hms_opt(0, 0, 0)?.and_utc());
+    }
+    DateTime::from_timestamp(value.parse().ok()?, 0)
+}
+
+pub fn render(bookmarks: &[ExternalBookmark]) -> anyhow::Result<String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(COLUMNS)?;
+    for bookmark in bookmarks {
+        writer.write_record([
+            bookmark.url.as_str(),
+            bookmark.title.as_deref().unwrap_or_default(),
+            &bookmark.tags.join(", "),
+            &bookmark.added_at.map(|t| t.to_rfc3339()).unwrap_or_default(),
+            bookmark.description.as_deref().unwrap_or_default(),
+        ])?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let bookmarks = vec![ExternalBookmark {
+            url: "https://rust-lang.org/".to_string(),
+            title: Some("Rust, the language".to_string()),
+            tags: vec!["lang".to_string(), "dev/rust".to_string()],
+            added_at: DateTime::from_timestamp(1700000000, 0),
+            description: Some("Says \"hello\"".to_string()),
+            ..Default::default()
+        }];
+        let csv = render(&bookmarks).unwrap();
+        assert!(csv.starts_with("url,title,tags,created_at,notes\n"));
+        assert_eq!(parse(&csv, None).unwrap(), bookmarks);
+    }
+
+    #[test]
+    fn test_parse_mapped() {
+        let csv = "Name,Link,Labels,Saved\n\
+                   Rust,https://rust-lang.org/,Systems Programming; lang,2023-11-14\n\
+                   ,,,\n\
+                   News,https://example.com/news,,1600000000\n";
+        let bookmarks = parse(csv, Some("url=Link, title=Name,tags=labels,created_at=Saved")).unwrap();
+        assert_eq!(bookmarks.len(), 2);
+        assert_eq!(bookmarks[0].title.as_deref(), Some("Rust"));
+        assert_eq!(bookmarks[0].tags, vec!["systems-programming", "lang"]);
+        assert_eq!(bookmarks[0].added_at, "2023-11-14T00:00:00Z".parse().ok());
+        assert_eq!(bookmarks[1].added_at, DateTime::from_timestamp(1600000000, 0));
+
+        assert!(parse(csv, None).is_err());
+        assert!(parse(csv, Some("link=Link")).is_err());
+    }
+}
@@ -0,0 +1,908 @@
+//! The SQLite backend, for a single-user install that would rather not run Postgres.
+//!
+//! With `DATABASE_URL=sqlite://linkman.db` the bookmarks live in that file, with the schema in
+//! `migrations_sqlite`, and the server answers the core of the API from here: registering
+//! devices, saving, listing, searching, editing and trashing bookmarks, and listing tags. Saved
+//! pages are fetched and tagged in the background, in the server process rather than through the
+//! job queue, and whatever was still pending when it stopped is picked up on the next start.
+//! Everything else, like collections, archives, semantic search, imports and the integrations,
+//! needs Postgres.
+//!
+//! The queries are SQLite's own. A bookmark's tags are gathered with `json_group_array` instead
+//! of `array_agg` and compared with `json_each` instead of `UNNEST`, search is `LIKE`, which
+//! already ignores case for ASCII, and `LIKE` needs its escape character spelled out.
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::Response,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Utc};
+use sqlx::migrate::Migrator;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+use sqlx::{QueryBuilder, Sqlite, SqlitePool, Transaction};
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+use crate::{
+    config, content, details, fetcher::Fetcher, health, metrics, normalize, retry, tagging::{self, Tagger},
+    tags::{escape_like, ListTagsParams, TagCount, DEFAULT_SUGGESTIONS, MAX_SUGGESTIONS},
+    ApiKey, BookmarkPage, BookmarkResponse, CurrentUser, Download, Order, PageParams, RegisterRequest,
+    RegisterResponse, SearchParams, Sort, SyncBookmarkRequest, TagMode, TagSource, UpdateBookmarkRequest,
+    DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE, SCOPES,
+};
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations_sqlite");
+
+#[derive(Clone)]
+pub struct SqliteState {
+    db: SqlitePool,
+    fetcher: Arc<Fetcher>,
+    tagger: Arc<dyn Tagger>,
+    /// Bookmarks being processed at once
+    processing: Arc<Semaphore>,
+}
+
+/// Whether `DATABASE_URL` names a SQLite database rather than Postgres.
+pub fn is_url(url: &str) -> bool {
+    url.starts_with("sqlite:")
+}
+
+/// Opens the database, creating the file if it isn't there yet, and brings its schema up to date.
+pub async fn connect(url: &str, max_connections: u32) -> anyhow::Result<SqlitePool> {
+    let options = SqliteConnectOptions::from_str(url)?
+        .create_if_missing(true)
+        .foreign_keys(true)
+        // Readers don't wait on the background processing's writes
+        .journal_mode(SqliteJournalMode::Wal);
+    let db = SqlitePoolOptions::new().max_connections(max_connections).connect_with(options).await?;
+    MIGRATOR.run(&db).await?;
+    Ok(db)
+}
+
+/// `linkman serve` on a SQLite database.
+pub async fn serve(config: &config::Config) -> anyhow::Result<()> {
+    let retry = retry::Policy::from_config(&config.retry);
+    let metrics = Arc::new(metrics::Metrics::from_config(&config.metrics));
+    let state = SqliteState {
+        db: connect(&config.database.url, config.database.max_connections).await?,
+        fetcher: Arc::new(Fetcher::from_config(&config.fetch, retry.clone(), config.jobs.fetch_concurrency)?),
+        tagger: tagging::from_config(&config.ai, &config.tagging, retry, metrics)?,
+        processing: Arc::new(Semaphore::new(config.jobs.concurrency)),
+    };
+    resume(&state).await?;
+    crate::listen(router(state), config).await
+}
+
+pub fn router(state: SqliteState) -> Router {
+    let api_routes = Router::new()
+        .route("/bookmarks", get(list_bookmarks))
+        .route("/bookmarks/sync", post(sync_bookmark))
+        .route("/bookmarks/search", get(search_bookmarks))
+        .route("/bookmarks/{id}", get(get_bookmark).patch(update_bookmark).delete(delete_bookmark))
+        .route("/tags", get(list_tags))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth_middleware));
+
+    Router::new()
+        .route("/", get(crate::hello))
+        .route("/healthz", get(health::healthz))
+        .route("/admin/register", post(register_user))
+        .merge(api_routes)
+        .with_state(state)
+}
+
+fn db_error(e: sqlx::Error) -> StatusCode {
+    match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => StatusCode::CONFLICT,
+        e => {
+            eprintln!("SQLite Error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Times are written with microseconds at most, as the `(created_at, id)` cursors hold them.
+fn now() -> DateTime<Utc> {
+    DateTime::from_timestamp_micros(Utc::now().timestamp_micros()).unwrap_or_default()
+}
+
+/// The bearer token in `Authorization`; there are no login sessions on this backend.
+fn bearer(headers: &HeaderMap) -> Option<Uuid> {
+    let header = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    Uuid::parse_str(header.strip_prefix("Bearer ")?).ok()
+}
+
+async fn auth_middleware(State(state): State<SqliteState>, mut req: Request, next: Next) -> Result<Response, StatusCode> {
+    let token = bearer(req.headers()).ok_or(StatusCode::UNAUTHORIZED)?;
+    let (user, key) = authenticate(&state.db, token).await?;
+    if !key.allows(crate::required_scope(req.method(), req.uri().path())) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    req.extensions_mut().insert(user);
+    req.extensions_mut().insert(key);
+    Ok(next.run(req).await)
+}
+
+/// Resolves a token to its owner and records the use on it.
+async fn authenticate(db: &SqlitePool, token: Uuid) -> Result<(CurrentUser, ApiKey), StatusCode> {
+    let now = now();
+    let (id, username, key_id, scopes, tag_mode): (Uuid, String, Uuid, String, TagMode) = sqlx::query_as(
+        "SELECT u.id, u.username, t.id, t.scopes, t.tag_mode FROM api_tokens t JOIN users u ON u.id = t.user_id
+         WHERE t.token = $1 AND (t.expires_at IS NULL OR t.expires_at > $2)"
+    )
+    .bind(token)
+    .bind(now)
+    .fetch_optional(db)
+    .await
+    .map_err(db_error)?
+    .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    sqlx::query("UPDATE api_tokens SET last_used_at = $1 WHERE token = $2")
+        .bind(now)
+        .bind(token)
+        .execute(db)
+        .await
+        .map_err(db_error)?;
+
+    let scopes = serde_json::from_str(&scopes).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok((CurrentUser { id, username }, ApiKey { token, id: Some(key_id), scopes, tag_mode }))
+}
+
+/// Creates the account on first use and a token for the device. There are no passwords on this
+/// backend, so another device for an account that already exists is registered with a token the
+/// account already has, holding every scope.
+async fn register_user(
+    State(state): State<SqliteState>,
+    headers: HeaderMap,
+    Json(payload): Json<RegisterRequest>,
+) -> Result<Json<RegisterResponse>, StatusCode> {
+    let scopes = payload.scopes.unwrap_or_else(|| SCOPES.map(String::from).to_vec());
+    if scopes.is_empty() || !scopes.iter().all(|s| SCOPES.contains(&s.as_str())) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let existing: Option<Uuid> = sqlx::query_scalar("SELECT id FROM users WHERE username = $1")
+        .bind(&payload.username)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)?;
+    if let Some(id) = existing {
+        let (user, key) = authenticate(&state.db, bearer(&headers).ok_or(StatusCode::UNAUTHORIZED)?).await?;
+        if user.id != id || !key.is_full() {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    let now = now();
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    let user_id = match existing {
+        Some(id) => id,
+        None => {
+            let id = Uuid::new_v4();
+            sqlx::query("INSERT INTO users (id, username, created_at) VALUES ($1, $2, $3)")
+                .bind(id)
+                .bind(&payload.username)
+                .bind(now)
+                .execute(&mut *tx)
+                .await
+                .map_err(db_error)?;
+            id
+        }
+    };
+
+    let token = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO api_tokens (token, id, user_id, device_name, scopes, expires_at, created_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)"
+    )
+    .bind(token)
+    .bind(Uuid::new_v4())
+    .bind(user_id)
+    .bind(&payload.device_name)
+    .bind(serde_json::to_string(&scopes).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?)
+    .bind(payload.expires_at)
+    .bind(now)
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    tx.commit().await.map_err(db_error)?;
+    Ok(Json(RegisterResponse { token }))
+}
+
+/// A bookmark as this schema stores it, tags as JSON arrays.
+#[derive(sqlx::FromRow)]
+struct BookmarkRow {
+    id: Uuid,
+    url: String,
+    host: Option<String>,
+    title: Option<String>,
+    title_from_page: bool,
+    notes: Option<String>,
+    description: Option<String>,
+    ai_summary: Option<String>,
+    content_type: Option<String>,
+    processing_status: String,
+    error_message: Option<String>,
+    is_public: bool,
+    is_favorite: bool,
+    read_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    deleted_at: Option<DateTime<Utc>>,
+    tags: String,
+    user_tags: String,
+    ai_tags: String,
+}
+
+/// Tag names from `json_group_array`; none at all is `null`, as `array_agg` gives Postgres.
+fn tag_names(json: &str) -> Option<Vec<String>> {
+    let names: Vec<String> = serde_json::from_str(json).unwrap_or_default();
+    (!names.is_empty()).then_some(names)
+}
+
+impl From<BookmarkRow> for BookmarkResponse {
+    fn from(row: BookmarkRow) -> Self {
+        BookmarkResponse {
+            id: row.id,
+            url: row.url,
+            title: row.title,
+            title_from_page: row.title_from_page,
+            notes: row.notes,
+            description: row.description,
+            ai_summary: row.ai_summary,
+            content_type: row.content_type,
+            tags: tag_names(&row.tags),
+            user_tags: tag_names(&row.user_tags),
+            ai_tags: tag_names(&row.ai_tags),
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+            domain: row.host,
+            processing_status: row.processing_status,
+            error_message: row.error_message,
+            deleted_at: row.deleted_at,
+            is_read: row.read_at.is_some(),
+            read_at: row.read_at,
+            is_favorite: row.is_favorite,
+            is_public: row.is_public,
+            ..Default::default()
+        }
+    }
+}
+
+const BOOKMARK_SELECT: &str = "SELECT b.id, b.url, b.host, b.title, b.title_from_page, b.notes, b.description, b.ai_summary,
+     b.content_type, b.processing_status, b.error_message, b.is_public, b.is_favorite, b.read_at, b.created_at,
+     b.updated_at, b.deleted_at,
+     (SELECT json_group_array(t.name ORDER BY t.name) FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id
+      WHERE bt.bookmark_id = b.id) as tags,
+     (SELECT json_group_array(t.name ORDER BY t.name) FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id
+      WHERE bt.bookmark_id = b.id AND bt.source = 'user') as user_tags,
+     (SELECT json_group_array(t.name ORDER BY t.name) FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id
+      WHERE bt.bookmark_id = b.id AND bt.source = 'ai') as ai_tags
+     FROM bookmarks b";
+
+async fn fetch_bookmark<'e>(
+    executor: impl sqlx::SqliteExecutor<'e>,
+    user_id: Uuid,
+    id: Uuid,
+) -> Result<Option<BookmarkResponse>, sqlx::Error> {
+    let mut query = QueryBuilder::<Sqlite>::new(BOOKMARK_SELECT);
+    query
+        .push(" WHERE b.user_id = ")
+        .push_bind(user_id)
+        .push(" AND b.id = ")
+        .push_bind(id)
+        .push(" AND b.deleted_at IS NULL");
+    let row = query.build_query_as::<BookmarkRow>().fetch_optional(executor).await?;
+    Ok(row.map(BookmarkResponse::from))
+}
+
+/// The `GET /bookmarks` filters, as far as this schema has what they filter on. The rest, like
+/// `lang` or `collection`, are `400` rather than ignored; `semantic` is `501`, as it is on
+/// Postgres without an embedding model.
+fn push_list_filters(query: &mut QueryBuilder<Sqlite>, params: &SearchParams) -> Result<(), StatusCode> {
+    if params.semantic.is_some() {
+        return Err(StatusCode::NOT_IMPLEMENTED);
+    }
+    if params.lang.is_some() || params.collection.is_some() || params.sort.is_some_and(|sort| sort != Sort::CreatedAt) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    query.push(" AND b.deleted_at IS NULL");
+    let (q, sites) = crate::split_query(params.q.as_deref().unwrap_or_default())?;
+    if !sites.is_empty() {
+        query.push(" AND b.host IN (");
+        let mut hosts = query.separated(", ");
+        for site in sites {
+            hosts.push_bind(site);
+        }
+        query.push(")");
+    }
+    if let Some(domain) = params.domain.as_deref() {
+        let site = normalize::site(domain).ok_or(StatusCode::BAD_REQUEST)?;
+        query.push(" AND b.host = ").push_bind(site);
+    }
+    if !q.is_empty() {
+        let pattern = format!("%{}%", escape_like(&q));
+        query
+            .push(" AND (b.url LIKE ")
+            .push_bind(pattern.clone())
+            .push(" ESCAPE '\\' OR b.title LIKE ")
+            .push_bind(pattern.clone())
+            .push(" ESCAPE '\\' OR b.notes LIKE ")
+            .push_bind(pattern.clone())
+            .push(" ESCAPE '\\' OR b.ai_summary LIKE ")
+            .push_bind(pattern.clone())
+            .push(" ESCAPE '\\' OR EXISTS (SELECT 1 FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id WHERE bt.bookmark_id = b.id AND t.name LIKE ")
+            .push_bind(pattern)
+            .push(" ESCAPE '\\'))");
+    }
+    // There's no link checker here, so a `status` can only be a processing one
+    for status in [&params.status, &params.processing].into_iter().flatten() {
+        if !matches!(status.as_str(), "pending" | "fetching" | "tagging" | "done" | "failed") {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        query.push(" AND b.processing_status = ").push_bind(status.clone());
+    }
+    if let Some(untagged) = params.untagged {
+        query
+            .push(if untagged { " AND NOT" } else { " AND" })
+            .push(" EXISTS (SELECT 1 FROM bookmark_tags bt WHERE bt.bookmark_id = b.id)");
+    }
+    match params.unread {
+        None => {}
+        Some(true) => {
+            query.push(" AND b.read_at IS NULL");
+        }
+        Some(false) => {
+            query.push(" AND b.read_at IS NOT NULL");
+        }
+    }
+    if let Some(favorite) = params.favorite {
+        query.push(" AND b.is_favorite = ").push_bind(favorite);
+    }
+    if let Some(created_before) = params.created_before {
+        query.push(" AND b.created_at < ").push_bind(created_before);
+    }
+    if let Some(created_after) = params.created_after {
+        query.push(" AND b.created_at >= ").push_bind(created_after);
+    }
+    for (tag, source) in [
+        (&params.tag, None),
+        (&params.user_tag, Some(TagSource::User)),
+        (&params.ai_tag, Some(TagSource::Ai)),
+    ] {
+        if let Some(tag) = tag.as_deref() {
+            let tag = crate::tag_path(tag).ok_or(StatusCode::BAD_REQUEST)?;
+            let prefix = format!("{}/", tag);
+            query
+                .push(" AND EXISTS (SELECT 1 FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id WHERE bt.bookmark_id = b.id AND (t.name = ")
+                .push_bind(tag)
+                .push(" OR substr(t.name, 1, ")
+                .push_bind(prefix.chars().count() as i64)
+                .push(") = ")
+                .push_bind(prefix)
+                .push(")");
+            if let Some(source) = source {
+                query.push(" AND bt.source = ").push_bind(source);
+            }
+            query.push(")");
+        }
+    }
+    Ok(())
+}
+
+/// One page of bookmarks, newest first unless `order=asc`.
+async fn list_bookmarks(
+    State(state): State<SqliteState>,
+    Extension(user): Extension<CurrentUser>,
+    Query(page): Query<PageParams>,
+    Query(params): Query<SearchParams>,
+) -> Result<Json<BookmarkPage>, StatusCode> {
+    let limit = page.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let (direction, after) = match params.order.unwrap_or(Order::Desc) {
+        Order::Asc => ("ASC", ">"),
+        Order::Desc => ("DESC", "<"),
+    };
+
+    let mut query = QueryBuilder::<Sqlite>::new(BOOKMARK_SELECT);
+    query.push(" WHERE b.user_id = ").push_bind(user.id);
+    push_list_filters(&mut query, &params)?;
+    if let Some(cursor) = page.cursor.as_deref() {
+        let (created_at, id) = crate::decode_cursor(cursor).ok_or(StatusCode::BAD_REQUEST)?;
+        query
+            .push(format!(" AND (b.created_at, b.id) {} (", after))
+            .push_bind(created_at)
+            .push(", ")
+            .push_bind(id)
+            .push(")");
+    }
+    // Fetch one extra row to find out whether another page exists
+    query
+        .push(format!(" ORDER BY b.created_at {dir}, b.id {dir} LIMIT ", dir = direction))
+        .push_bind(limit + 1);
+
+    let mut bookmarks: Vec<BookmarkResponse> = query
+        .build_query_as::<BookmarkRow>()
+        .fetch_all(&state.db)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(BookmarkResponse::from)
+        .collect();
+
+    let next_cursor = if bookmarks.len() as i64 > limit {
+        bookmarks.truncate(limit as usize);
+        bookmarks.last().map(|b| Sort::CreatedAt.cursor(b))
+    } else {
+        None
+    };
+    Ok(Json(BookmarkPage { bookmarks, next_cursor }))
+}
+
+/// Unpaginated search, as `GET /bookmarks/search` is on Postgres.
+async fn search_bookmarks(
+    State(state): State<SqliteState>,
+    Extension(user): Extension<CurrentUser>,
+    Query(params): Query<SearchParams>,
+) -> Result<Json<Vec<BookmarkResponse>>, StatusCode> {
+    let mut query = QueryBuilder::<Sqlite>::new(BOOKMARK_SELECT);
+    query.push(" WHERE b.user_id = ").push_bind(user.id);
+    push_list_filters(&mut query, &params)?;
+    query.push(" ORDER BY b.created_at DESC, b.id DESC");
+
+    let rows = query.build_query_as::<BookmarkRow>().fetch_all(&state.db).await.map_err(db_error)?;
+    Ok(Json(rows.into_iter().map(BookmarkResponse::from).collect()))
+}
+
+/// Saves a bookmark and answers with it: `201 Created` for a new one, `200 OK` when the URL was
+/// already saved and has been updated. Either way the page is fetched and tagged again.
+async fn sync_bookmark(
+    State(state): State<SqliteState>,
+    Extension(user): Extension<CurrentUser>,
+    Extension(key): Extension<ApiKey>,
+    Json(payload): Json<SyncBookmarkRequest>,
+) -> Result<(StatusCode, Json<BookmarkResponse>), StatusCode> {
+    let url = normalize::normalize_url(&payload.url).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+
+    // Only a row this statement inserted has never been updated
+    let (bookmark_id, inserted): (Uuid, bool) = sqlx::query_as(
+        "INSERT INTO bookmarks (id, user_id, url, host, title, notes, tag_mode, created_at, updated_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $8)
+         ON CONFLICT (user_id, url) DO UPDATE SET title = COALESCE(excluded.title, bookmarks.title),
+             title_from_page = bookmarks.title_from_page AND excluded.title IS NULL,
+             notes = COALESCE(excluded.notes, bookmarks.notes), tag_mode = excluded.tag_mode,
+             processing_status = 'pending', error_message = NULL, updated_at = $8, deleted_at = NULL
+         RETURNING id, created_at = updated_at"
+    )
+    .bind(Uuid::new_v4())
+    .bind(user.id)
+    .bind(&url)
+    .bind(normalize::site(&url))
+    .bind(&payload.title)
+    .bind(&payload.notes)
+    .bind(payload.tag_mode.unwrap_or(key.tag_mode))
+    .bind(now())
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    if let Some(tags) = &payload.tags {
+        attach_tags(&mut tx, user.id, bookmark_id, tags, TagSource::User).await.map_err(db_error)?;
+    }
+    let bookmark = fetch_bookmark(&mut *tx, user.id, bookmark_id)
+        .await
+        .map_err(db_error)?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit().await.map_err(db_error)?;
+    process(&state, bookmark_id);
+
+    let status = if inserted { StatusCode::CREATED } else { StatusCode::OK };
+    Ok((status, Json(bookmark)))
+}
+
+/// One bookmark; someone else's, or one in the trash, is `404`.
+async fn get_bookmark(
+    State(state): State<SqliteState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<BookmarkResponse>, StatusCode> {
+    let bookmark = fetch_bookmark(&state.db, user.id, id).await.map_err(db_error)?.ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(bookmark))
+}
+
+async fn update_bookmark(
+    State(state): State<SqliteState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdateBookmarkRequest>,
+) -> Result<Json<BookmarkResponse>, StatusCode> {
+    let url = payload
+        .url
+        .as_deref()
+        .map(normalize::normalize_url)
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    let updated = sqlx::query(
+        "UPDATE bookmarks SET url = COALESCE($1, url), host = COALESCE($2, host), title = COALESCE($3, title),
+             title_from_page = title_from_page AND $3 IS NULL, notes = COALESCE($4, notes),
+             is_public = COALESCE($5, is_public), updated_at = $6
+         WHERE id = $7 AND user_id = $8 AND deleted_at IS NULL"
+    )
+    .bind(&url)
+    .bind(url.as_deref().and_then(normalize::site))
+    .bind(&payload.title)
+    .bind(&payload.notes)
+    .bind(payload.is_public)
+    .bind(now())
+    .bind(id)
+    .bind(user.id)
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    if updated.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    if let Some(tags) = &payload.tags {
+        replace_tags(&mut tx, user.id, id, tags).await.map_err(db_error)?;
+    }
+
+    let bookmark = fetch_bookmark(&mut *tx, user.id, id)
+        .await
+        .map_err(db_error)?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit().await.map_err(db_error)?;
+    Ok(Json(bookmark))
+}
+
+/// Moves a bookmark to the trash; someone else's, or one already there, is `404`.
+async fn delete_bookmark(
+    State(state): State<SqliteState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let now = now();
+    let deleted = sqlx::query(
+        "UPDATE bookmarks SET deleted_at = $1, updated_at = $1 WHERE id = $2 AND user_id = $3 AND deleted_at IS NULL"
+    )
+    .bind(now)
+    .bind(id)
+    .bind(user.id)
+    .execute(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    if deleted.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Tags in use, most used first, optionally only those starting with `prefix` (case-insensitive).
+async fn list_tags(
+    State(state): State<SqliteState>,
+    Extension(user): Extension<CurrentUser>,
+    Query(params): Query<ListTagsParams>,
+) -> Result<Json<Vec<TagCount>>, StatusCode> {
+    let limit = params.limit.unwrap_or(DEFAULT_SUGGESTIONS).clamp(1, MAX_SUGGESTIONS);
+    let pattern = format!("{}%", escape_like(&params.prefix.unwrap_or_default().trim().to_lowercase()));
+
+    let tags = sqlx::query_as::<_, TagCount>(
+        "SELECT t.name, count(*) as count FROM tags t
+         JOIN bookmark_tags bt ON bt.tag_id = t.id
+         JOIN bookmarks b ON b.id = bt.bookmark_id
+         WHERE t.user_id = $1 AND lower(t.name) LIKE $2 ESCAPE '\\' AND b.deleted_at IS NULL
+         GROUP BY t.name
+         ORDER BY count DESC, t.name
+         LIMIT $3"
+    )
+    .bind(user.id)
+    .bind(pattern)
+    .bind(limit)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+    Ok(Json(tags))
+}
+
+/// Links `tags` to a bookmark, creating any tags the user doesn't have yet. A tag the user adds
+/// becomes theirs even if the model put it there first.
+async fn attach_tags(
+    tx: &mut Transaction<'_, Sqlite>,
+    user_id: Uuid,
+    bookmark_id: Uuid,
+    tags: &[String],
+    source: TagSource,
+) -> Result<(), sqlx::Error> {
+    for tag_name in tags.iter().filter_map(|t| crate::tag_path(t)) {
+        let tag_id: i64 = sqlx::query_scalar(
+            "INSERT INTO tags (user_id, name) VALUES ($1, $2)
+             ON CONFLICT (user_id, name) DO UPDATE SET name = excluded.name
+             RETURNING id"
+        )
+        .bind(user_id)
+        .bind(tag_name)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        sqlx::query(
+            "INSERT INTO bookmark_tags (bookmark_id, tag_id, source) VALUES ($1, $2, $3)
+             ON CONFLICT (bookmark_id, tag_id) DO UPDATE SET source = 'user' WHERE excluded.source = 'user'"
+        )
+        .bind(bookmark_id)
+        .bind(tag_id)
+        .bind(source)
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Makes a bookmark's tags exactly `names`. Tags it keeps stay as they were, whoever added them.
+async fn replace_tags(
+    tx: &mut Transaction<'_, Sqlite>,
+    user_id: Uuid,
+    bookmark_id: Uuid,
+    names: &[String],
+) -> Result<(), sqlx::Error> {
+    let names: Vec<String> = names.iter().filter_map(|name| crate::tag_path(name)).collect();
+    let kept: Vec<String> = sqlx::query_scalar(
+        "SELECT t.name FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id
+         WHERE bt.bookmark_id = $1 AND t.name IN (SELECT value FROM json_each($2))"
+    )
+    .bind(bookmark_id)
+    .bind(serde_json::Value::from(names.clone()))
+    .fetch_all(&mut **tx)
+    .await?;
+
+    sqlx::query(
+        "DELETE FROM bookmark_tags WHERE bookmark_id = $1
+             AND tag_id NOT IN (SELECT id FROM tags WHERE user_id = $2 AND name IN (SELECT value FROM json_each($3)))"
+    )
+    .bind(bookmark_id)
+    .bind(user_id)
+    .bind(serde_json::Value::from(names.clone()))
+    .execute(&mut **tx)
+    .await?;
+
+    let added: Vec<String> = names.into_iter().filter(|name| !kept.contains(name)).collect();
+    attach_tags(tx, user_id, bookmark_id, &added, TagSource::User).await
+}
+
+/// Picks up the bookmarks a restart interrupted, and those saved while the server was down.
+async fn resume(state: &SqliteState) -> Result<(), sqlx::Error> {
+    let pending: Vec<Uuid> = sqlx::query_scalar(
+        "SELECT id FROM bookmarks WHERE processing_status NOT IN ('done', 'failed') AND deleted_at IS NULL"
+    )
+    .fetch_all(&state.db)
+    .await?;
+    for id in pending {
+        process(state, id);
+    }
+    Ok(())
+}
+
+/// Fetches and tags a bookmark in the background. There's no retrying, unlike the job queue: a
+/// bookmark that fails says why, and saving it again tries again.
+fn process(state: &SqliteState, bookmark_id: Uuid) {
+    let state = state.clone();
+    tokio::spawn(async move {
+        let Ok(_permit) = state.processing.acquire().await else { return };
+        if let Err(e) = process_bookmark(&state, bookmark_id).await {
+            eprintln!("Error processing bookmark {}: {}", bookmark_id, e);
+            let failed = sqlx::query("UPDATE bookmarks SET processing_status = 'failed', error_message = $1 WHERE id = $2")
+                .bind(e.to_string())
+                .bind(bookmark_id)
+                .execute(&state.db)
+                .await;
+            if let Err(e) = failed {
+                eprintln!("Error recording failure of bookmark {}: {}", bookmark_id, e);
+            }
+        }
+    });
+}
+
+async fn set_processing_status(db: &SqlitePool, bookmark_id: Uuid, status: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE bookmarks SET processing_status = $1 WHERE id = $2")
+        .bind(status)
+        .bind(bookmark_id)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+/// The Postgres pipeline's fetching and tagging, without what needs its schema: archives,
+/// screenshots, page history, embeddings and the tag vocabulary.
+async fn process_bookmark(state: &SqliteState, bookmark_id: Uuid) -> anyhow::Result<()> {
+    let Some((user_id, url)): Option<(Uuid, String)> =
+        sqlx::query_as("SELECT user_id, url FROM bookmarks WHERE id = $1 AND deleted_at IS NULL")
+            .bind(bookmark_id)
+            .fetch_optional(&state.db)
+            .await?
+    else {
+        return Ok(());
+    };
+
+    set_processing_status(&state.db, bookmark_id, "fetching").await?;
+    let Download { kind, content_type, length, body, .. } = crate::download(state.fetcher.get(&url).await?).await?;
+    let page = (kind == content::Kind::Html).then(|| String::from_utf8_lossy(&body).into_owned());
+    let site_meta = match kind {
+        content::Kind::Html => crate::scrape_metadata(page.as_deref().unwrap_or_default()),
+        content::Kind::Pdf | content::Kind::Text => {
+            let mut meta = content::metadata(kind, content_type.as_deref(), &url, length, &[]);
+            let text = match kind {
+                content::Kind::Pdf => content::pdf_text(body).await,
+                _ => Some(String::from_utf8_lossy(&body).into_owned()),
+            };
+            if let Some(text) = &text {
+                content::add_excerpt(&mut meta, text);
+            }
+            meta
+        }
+        _ => content::metadata(kind, content_type.as_deref(), &url, length, &body),
+    };
+    let description = page.as_deref().and_then(|page| details::extract(page, &url, &site_meta).description);
+
+    // Only a title the user didn't give is replaced with the page's
+    sqlx::query(
+        "UPDATE bookmarks SET title = CASE WHEN title IS NULL OR title_from_page THEN COALESCE($1, title) ELSE title END,
+             title_from_page = title_from_page OR (title IS NULL AND $1 IS NOT NULL),
+             description = COALESCE(description, $2), content_type = $3, updated_at = $4
+         WHERE id = $5"
+    )
+    .bind(crate::page_title(&site_meta))
+    .bind(description)
+    .bind(&content_type)
+    .bind(now())
+    .bind(bookmark_id)
+    .execute(&state.db)
+    .await?;
+
+    set_processing_status(&state.db, bookmark_id, "tagging").await?;
+    let enrichment = state.tagger.tag(&url, &site_meta.to_string(), &[]).await?;
+
+    let mut tx = state.db.begin().await?;
+    if let Some(enrichment) = &enrichment {
+        // Merging keeps the user's tags and swaps out the model's from last time
+        sqlx::query(
+            "DELETE FROM bookmark_tags WHERE bookmark_id = $1
+                 AND (source = 'ai' OR (SELECT tag_mode FROM bookmarks WHERE id = $1) = 'replace')"
+        )
+        .bind(bookmark_id)
+        .execute(&mut *tx)
+        .await?;
+        attach_tags(&mut tx, user_id, bookmark_id, &enrichment.tags, TagSource::Ai).await?;
+    }
+    sqlx::query(
+        "UPDATE bookmarks SET ai_summary = COALESCE($1, ai_summary), processing_status = 'done',
+             error_message = NULL, updated_at = $2
+         WHERE id = $3"
+    )
+    .bind(enrichment.as_ref().map(|e| &e.summary))
+    .bind(now())
+    .bind(bookmark_id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum_test::TestServer;
+    use serde_json::{json, Value};
+
+    /// Against a fresh in-memory database. Processing is held up, so bookmarks stay `pending`
+    /// instead of being fetched.
+    async fn test_server() -> TestServer {
+        let state = SqliteState {
+            db: connect("sqlite::memory:", 1).await.unwrap(),
+            fetcher: Arc::new(
+                Fetcher::from_config(
+                    &config::FetchConfig::default(),
+                    retry::Policy::from_config(&config::RetryConfig::default()),
+                    1,
+                )
+                .unwrap(),
+            ),
+            tagger: Arc::new(tagging::Offline),
+            processing: Arc::new(Semaphore::new(0)),
+        };
+        TestServer::new(router(state))
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_backend() {
+        let server = test_server().await;
+        server.get("/bookmarks").await.assert_status(StatusCode::UNAUTHORIZED);
+
+        let register = json!({ "username": "a", "device_name": "laptop" });
+        let token = server.post("/admin/register").json(&register).await.json::<Value>()["token"].clone();
+        let auth = format!("Bearer {}", token.as_str().unwrap());
+        // A second device needs a token the account already has
+        server.post("/admin/register").json(&register).await.assert_status(StatusCode::UNAUTHORIZED);
+        server
+            .post("/admin/register")
+            .authorization(&auth)
+            .json(&register)
+            .await
+            .assert_status_ok();
+
+        let saved = server
+            .post("/bookmarks/sync")
+            .authorization(&auth)
+            .json(&json!({ "url": "https://www.rust-lang.org/learn", "title": "Learn Rust", "tags": ["dev/rust", "docs"] }))
+            .await;
+        saved.assert_status(StatusCode::CREATED);
+        let saved = saved.json::<Value>();
+        assert_eq!(saved["tags"], json!(["dev/rust", "docs"]));
+        assert_eq!(saved["domain"], "rust-lang.org");
+        assert_eq!(saved["processing_status"], "pending");
+        server
+            .post("/bookmarks/sync")
+            .authorization(&auth)
+            .json(&json!({ "url": "https://www.rust-lang.org/learn", "tags": ["docs"] }))
+            .await
+            .assert_status_ok();
+        server
+            .post("/bookmarks/sync")
+            .authorization(&auth)
+            .json(&json!({ "url": "https://lobste.rs/" }))
+            .await
+            .assert_status(StatusCode::CREATED);
+
+        let page = server.get("/bookmarks?limit=1").authorization(&auth).await.json::<Value>();
+        assert_eq!(page["bookmarks"][0]["url"], "https://lobste.rs/");
+        let cursor = page["next_cursor"].as_str().unwrap();
+        let page = server.get(&format!("/bookmarks?limit=1&cursor={}", cursor)).authorization(&auth).await.json::<Value>();
+        assert_eq!(page["bookmarks"][0]["title"], "Learn Rust");
+        assert_eq!(page["next_cursor"], Value::Null);
+
+        let titles = |results: Value| -> Vec<String> {
+            results.as_array().unwrap().iter().map(|b| b["url"].as_str().unwrap().to_string()).collect()
+        };
+        for (query, found) in [
+            ("q=LEARN", vec!["https://www.rust-lang.org/learn"]),
+            ("q=site:lobste.rs", vec!["https://lobste.rs/"]),
+            ("tag=dev", vec!["https://www.rust-lang.org/learn"]),
+            ("tag=de", vec![]),
+            ("q=100%25", vec![]),
+            ("untagged=true", vec!["https://lobste.rs/"]),
+        ] {
+            let results = server.get(&format!("/bookmarks/search?{}", query)).authorization(&auth).await;
+            assert_eq!(titles(results.json()), found, "{}", query);
+        }
+        server.get("/bookmarks?lang=en").authorization(&auth).await.assert_status_bad_request();
+        server
+            .get("/bookmarks?semantic=rust")
+            .authorization(&auth)
+            .await
+            .assert_status(StatusCode::NOT_IMPLEMENTED);
+
+        let id = saved["id"].as_str().unwrap();
+        let updated = server
+            .patch(&format!("/bookmarks/{}", id))
+            .authorization(&auth)
+            .json(&json!({ "tags": ["docs", "learning"] }))
+            .await
+            .json::<Value>();
+        assert_eq!(updated["tags"], json!(["docs", "learning"]));
+
+        let tags = server.get("/tags?prefix=LEA").authorization(&auth).await.json::<Value>();
+        assert_eq!(tags, json!([{ "name": "learning", "count": 1 }]));
+
+        server.delete(&format!("/bookmarks/{}", id)).authorization(&auth).await.assert_status(StatusCode::NO_CONTENT);
+        server.get(&format!("/bookmarks/{}", id)).authorization(&auth).await.assert_status_not_found();
+    }
+}
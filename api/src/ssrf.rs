@@ -0,0 +1,215 @@
+//! Keeping fetches of user-supplied URLs off the server's own network.
+//!
+//! Without this a bookmark for `http://169.254.169.254/` or `http://localhost:5432` would have
+//! the server fetch, archive and show its owner whatever answers there. Every host a fetch
+//! connects to, redirects included, is resolved here first, and loopback, private, link-local
+//! and other non-public addresses are refused unless `FETCH_ALLOWED_NETWORKS` lists them, as are
+//! IPv6 addresses that carry one, such as NAT64 and 6to4. The addresses checked are the ones
+//! connected to, so a name can't resolve to a public address for the check and a private one for
+//! the fetch.
+//!
+//! The proxies the fetcher is configured with may be on a private network themselves. Their host
+//! names are exempt when reqwest looks them up to connect to the proxy, but a bookmark can't
+//! name one as its own host, and a proxy's address doesn't open up that address to direct
+//! fetches.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+
+use ipnet::IpNet;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use url::{Host, Url};
+
+use crate::fetcher::FetchError;
+
+/// Which non-public hosts and addresses may be fetched anyway.
+#[derive(Debug, Default)]
+pub struct Guard {
+    networks: Vec<IpNet>,
+    hosts: Vec<String>,
+    /// Hosts of the configured proxies
+    proxies: Vec<String>,
+}
+
+impl Guard {
+    /// From `FETCH_ALLOWED_NETWORKS` entries: networks like `10.1.0.0/16`, single addresses, or
+    /// host names allowed whatever they resolve to.
+    pub fn from_config(allowed: &[String]) -> Guard {
+        let mut guard = Guard::default();
+        for entry in allowed {
+            if let Ok(network) = entry.parse::<IpNet>() {
+                guard.networks.push(network);
+            } else if let Ok(ip) = entry.parse::<IpAddr>() {
+                guard.networks.push(IpNet::from(ip));
+            } else {
+                guard.hosts.push(entry.to_ascii_lowercase());
+            }
+        }
+        guard
+    }
+
+    /// Lets the lookups of the proxies at `hosts` through, and nothing else on them.
+    pub fn with_proxies(mut self, hosts: impl IntoIterator<Item = String>) -> Guard {
+        self.proxies.extend(hosts.into_iter().map(|host| host.to_ascii_lowercase()));
+        self
+    }
+
+    fn allows(&self, ip: IpAddr) -> bool {
+        is_public(ip) || self.networks.iter().any(|network| network.contains(&ip))
+    }
+
+    /// Refuses `url` up front if its host is a non-public address, for hosts that are never
+    /// looked up because they're already one.
+    pub fn check_url(&self, url: &Url) -> Result<(), FetchError> {
+        let ip = match url.host() {
+            Some(Host::Ipv4(ip)) => IpAddr::V4(ip),
+            Some(Host::Ipv6(ip)) => IpAddr::V6(ip),
+            // Its lookup would be let through as the proxy's
+            Some(Host::Domain(host)) if self.is_proxy(host) && !self.is_allowed_host(host) => {
+                return Err(FetchError::PrivateAddress(host.to_string()));
+            }
+            _ => return Ok(()),
+        };
+        match self.allows(ip) {
+            true => Ok(()),
+            false => Err(FetchError::PrivateAddress(ip.to_string())),
+        }
+    }
+
+    fn is_allowed_host(&self, host: &str) -> bool {
+        self.hosts.iter().any(|allowed| host.eq_ignore_ascii_case(allowed))
+    }
+
+    fn is_proxy(&self, host: &str) -> bool {
+        self.proxies.iter().any(|proxy| host.eq_ignore_ascii_case(proxy))
+    }
+
+    /// The addresses `host` resolves to, or an error if any of them is off limits.
+    pub async fn lookup(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, Box<dyn std::error::Error + Send + Sync>> {
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port)).await?.collect();
+        if self.is_allowed_host(host) || self.is_proxy(host) {
+            return Ok(addrs);
+        }
+        // One bad address fails the lot, rather than leaving which one gets used to chance
+        if let Some(addr) = addrs.iter().find(|addr| !self.allows(addr.ip())) {
+            return Err(Box::new(FetchError::PrivateAddress(format!("{} ({})", host, addr.ip()))));
+        }
+        Ok(addrs)
+    }
+}
+
+/// Whether `ip` is on the public internet.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match embedded_v4(ip) {
+            Some(ip) => is_public_v4(ip),
+            None => {
+                let local_nat64 = ip.segments()[..3] == [0x64, 0xff9b, 1];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+                    || local_nat64)
+            }
+        },
+    }
+}
+
+/// The IPv4 address an IPv6 one stands for, which is where a connection to it ends up: mapped and
+/// compatible (`::ffff:a.b.c.d`, `::a.b.c.d`), NAT64 (`64:ff9b::a.b.c.d`) and 6to4 (`2002:ab:cd::`).
+fn embedded_v4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let [a, b, c, d, e, f, g, h] = ip.segments();
+    let v4 = |high: u16, low: u16| Some(Ipv4Addr::from((u32::from(high) << 16) | u32::from(low)));
+    match (a, b, c, d, e, f) {
+        (0, 0, 0, 0, 0, 0 | 0xffff) => v4(g, h),
+        (0x64, 0xff9b, 0, 0, 0, 0) => v4(g, h),
+        (0x2002, ..) => v4(b, c),
+        _ => None,
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    let shared = a == 100 && b & 0xc0 == 64;
+    let benchmarking = a == 198 && b & 0xfe == 18;
+    // 240.0.0.0/4, broadcast included
+    let reserved = a >= 240;
+    !(ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || a == 0
+        || shared
+        || benchmarking
+        || reserved)
+}
+
+/// The fetcher's DNS resolver, so redirects and retries are checked as much as the first request.
+pub struct Resolver(pub Arc<Guard>);
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let guard = self.0.clone();
+        Box::pin(async move {
+            // reqwest puts the port back itself
+            let addrs = guard.lookup(name.as_str(), 0).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guard() {
+        let guard = Guard::from_config(&[]);
+        for url in [
+            "http://127.0.0.1:5432/",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://10.0.0.1/",
+            "http://100.64.0.1/",
+            "http://[::1]/",
+            "http://[::ffff:192.168.0.1]/",
+            "http://[fd00::1]/",
+            "http://[::]/",
+            "http://[::7f00:1]/",
+            "http://[64:ff9b::7f00:1]/",
+            "http://[64:ff9b::a9fe:a9fe]/",
+            "http://[64:ff9b:1::1]/",
+            "http://[2002:7f00:1::]/",
+            "http://[2002:c0a8:1::1]/",
+            "http://198.18.0.1/",
+            "http://198.19.255.255/",
+            "http://240.0.0.1/",
+            "http://255.255.255.255/",
+        ] {
+            assert!(guard.check_url(&Url::parse(url).unwrap()).is_err(), "{}", url);
+        }
+        for url in [
+            "http://93.184.215.14/",
+            "http://198.20.0.1/",
+            "http://[64:ff9b::5db8:d70e]/",
+            "http://[2002:5db8:d70e::1]/",
+            "http://[2606:4700::1111]/",
+        ] {
+            assert!(guard.check_url(&Url::parse(url).unwrap()).is_ok(), "{}", url);
+        }
+        assert!(guard.check_url(&Url::parse("https://example.com/").unwrap()).is_ok());
+
+        let guard = Guard::from_config(&["10.1.0.0/16".to_string(), "::1".to_string(), "nas.lan".to_string()]);
+        assert!(guard.check_url(&Url::parse("http://10.1.2.3/").unwrap()).is_ok());
+        assert!(guard.check_url(&Url::parse("http://10.2.0.1/").unwrap()).is_err());
+        assert!(guard.check_url(&Url::parse("http://[::1]:8080/").unwrap()).is_ok());
+        assert_eq!(guard.hosts, ["nas.lan"]);
+
+        let guard = Guard::from_config(&[]).with_proxies(["127.0.0.1".to_string(), "Tor.LAN".to_string()]);
+        assert!(guard.check_url(&Url::parse("http://127.0.0.1:5432/").unwrap()).is_err());
+        assert!(guard.check_url(&Url::parse("http://tor.lan:9050/").unwrap()).is_err());
+        assert!(guard.is_proxy("tor.lan"));
+    }
+}
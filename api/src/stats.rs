@@ -0,0 +1,157 @@
+//! `GET /stats`: the shape of a user's library in one request, for dashboards.
+//!
+//! Counts are of live bookmarks; storage includes the trash, which takes up space until it's
+//! purged. Archived copies saved before their size was recorded count in `archived_pages` but
+//! not in `archive_bytes`.
+
+use axum::{extract::State, http::StatusCode, routing::get, Extension, Json, Router};
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::{AppState, CurrentUser};
+
+/// How far back the weekly histogram goes.
+const WEEKS: i32 = 52;
+const TOP_TAGS: i64 = 20;
+const TOP_DOMAINS: i64 = 20;
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/stats", get(library_stats))
+}
+
+fn db_error(e: sqlx::Error) -> StatusCode {
+    eprintln!("Stats Error: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct Counts {
+    total: i64,
+    unread: i64,
+    favorites: i64,
+    broken: i64,
+    untagged: i64,
+    failed: i64,
+    trashed: i64,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct Week {
+    /// The Monday the week starts on
+    week: NaiveDate,
+    count: i64,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct TagCount {
+    name: String,
+    count: i64,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct DomainCount {
+    domain: String,
+    count: i64,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct Storage {
+    archived_pages: i64,
+    archive_bytes: i64,
+    /// Page content kept in the database, earlier versions included
+    content_bytes: i64,
+    screenshot_bytes: i64,
+}
+
+#[derive(Serialize)]
+struct LibraryStats {
+    #[serde(flatten)]
+    counts: Counts,
+    /// Bookmarks saved each week, oldest first, with empty weeks included
+    per_week: Vec<Week>,
+    top_tags: Vec<TagCount>,
+    top_domains: Vec<DomainCount>,
+    storage: Storage,
+}
+
+async fn library_stats(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Result<Json<LibraryStats>, StatusCode> {
+    // One snapshot, so the numbers add up with each other
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+    let counts: Counts = sqlx::query_as(
+        "SELECT count(*) FILTER (WHERE deleted_at IS NULL) AS total,
+             count(*) FILTER (WHERE deleted_at IS NULL AND read_at IS NULL) AS unread,
+             count(*) FILTER (WHERE deleted_at IS NULL AND is_favorite) AS favorites,
+             count(*) FILTER (WHERE deleted_at IS NULL AND is_broken) AS broken,
+             count(*) FILTER (WHERE deleted_at IS NULL
+                 AND NOT EXISTS (SELECT 1 FROM bookmark_tags bt WHERE bt.bookmark_id = b.id)) AS untagged,
+             count(*) FILTER (WHERE deleted_at IS NULL AND processing_status = 'failed') AS failed,
+             count(*) FILTER (WHERE deleted_at IS NOT NULL) AS trashed
+         FROM bookmarks b WHERE user_id = $1"
+    )
+    .bind(user.id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    let per_week: Vec<Week> = sqlx::query_as(
+        "SELECT w.week::date AS week, count(b.id) AS count
+         FROM generate_series(date_trunc('week', now()) - make_interval(weeks => $2 - 1), date_trunc('week', now()), '1 week') AS w(week)
+         LEFT JOIN bookmarks b ON b.user_id = $1 AND b.deleted_at IS NULL AND date_trunc('week', b.created_at) = w.week
+         GROUP BY w.week ORDER BY w.week"
+    )
+    .bind(user.id)
+    .bind(WEEKS)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    let top_tags: Vec<TagCount> = sqlx::query_as(
+        "SELECT t.name, count(*) AS count FROM tags t
+         JOIN bookmark_tags bt ON bt.tag_id = t.id
+         JOIN bookmarks b ON b.id = bt.bookmark_id
+         WHERE t.user_id = $1 AND b.deleted_at IS NULL
+         GROUP BY t.name ORDER BY count DESC, t.name LIMIT $2"
+    )
+    .bind(user.id)
+    .bind(TOP_TAGS)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    let top_domains: Vec<DomainCount> = sqlx::query_as(
+        "SELECT host AS domain, count(*) AS count FROM bookmarks
+         WHERE user_id = $1 AND deleted_at IS NULL AND host IS NOT NULL
+         GROUP BY host ORDER BY count DESC, host LIMIT $2"
+    )
+    .bind(user.id)
+    .bind(TOP_DOMAINS)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    let storage: Storage = sqlx::query_as(
+        "SELECT (SELECT count(*) FROM bookmarks WHERE user_id = $1 AND archive_url IS NOT NULL) AS archived_pages,
+             (SELECT COALESCE(sum(archive_bytes), 0)::bigint FROM bookmarks WHERE user_id = $1) AS archive_bytes,
+             (SELECT COALESCE(sum(octet_length(c.markdown)), 0)::bigint FROM bookmark_contents c
+                  JOIN bookmarks b ON b.id = c.bookmark_id WHERE b.user_id = $1)
+             + (SELECT COALESCE(sum(octet_length(v.markdown)), 0)::bigint FROM bookmark_content_versions v
+                  JOIN bookmarks b ON b.id = v.bookmark_id WHERE b.user_id = $1) AS content_bytes,
+             (SELECT COALESCE(sum(octet_length(s.image)), 0)::bigint FROM bookmark_screenshots s
+                  JOIN bookmarks b ON b.id = s.bookmark_id WHERE b.user_id = $1) AS screenshot_bytes"
+    )
+    .bind(user.id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    tx.commit().await.map_err(db_error)?;
+
+    Ok(Json(LibraryStats { counts, per_week, top_tags, top_domains, storage }))
+}
@@ -0,0 +1,377 @@
+//! Suggested collections, found by clustering the embeddings of bookmarks that aren't in one.
+//!
+//! An admin starts a run with `POST /admin/suggestions/collections`, for one `?user=` or everyone.
+//! Each user's newest uncollected bookmarks with an embedding are grouped by k-means on cosine
+//! similarity, and every group of at least five is named by the model, or after its most common
+//! tag when there's no model to ask. A run replaces the user's earlier suggestions.
+//! `GET /suggestions/collections` lists them, `POST /suggestions/collections/{id}/accept` files
+//! the bookmarks into a new top-level collection of that name, or the one already called that,
+//! and `DELETE /suggestions/collections/{id}` dismisses one.
+
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get, post},
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Utc};
+use pgvector::Vector;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    collections::{fetch_collection, Collection},
+    AppState, CurrentUser,
+};
+
+/// The most bookmarks clustered for one user, the newest.
+const MAX_BOOKMARKS: i64 = 5000;
+const MIN_CLUSTER_SIZE: usize = 5;
+const MAX_CLUSTERS: usize = 20;
+const ITERATIONS: usize = 25;
+/// Bookmarks listed when asking the model for a name.
+const NAME_SAMPLE: usize = 30;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/suggestions/collections", get(list_suggestions))
+        .route("/suggestions/collections/{id}", delete(dismiss_suggestion))
+        .route("/suggestions/collections/{id}/accept", post(accept_suggestion))
+}
+
+fn db_error(e: sqlx::Error) -> StatusCode {
+    match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => StatusCode::CONFLICT,
+        e => {
+            eprintln!("Collection Suggestions Error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn normalized(vector: &[f32]) -> Vec<f32> {
+    let norm = dot(vector, vector).sqrt();
+    if norm == 0.0 { vector.to_vec() } else { vector.iter().map(|x| x / norm).collect() }
+}
+
+fn nearest(centres: &[Vec<f32>], vector: &[f32]) -> usize {
+    (0..centres.len())
+        .max_by(|&a, &b| dot(&centres[a], vector).total_cmp(&dot(&centres[b], vector)))
+        .unwrap_or_default()
+}
+
+/// How many groups to look for among `n` bookmarks.
+fn cluster_count(n: usize) -> usize {
+    ((n as f64 / 2.0).sqrt().round() as usize).clamp(2, MAX_CLUSTERS).min(n / MIN_CLUSTER_SIZE)
+}
+
+/// k-means over unit vectors, returning the cluster each one ends up in. The centres start
+/// spread out, and the same input always gives the same clusters: the first vector, then each
+/// time the one least like any centre so far.
+fn kmeans(vectors: &[Vec<f32>], k: usize) -> Vec<usize> {
+    let mut centres = vec![vectors[0].clone()];
+    while centres.len() < k {
+        let likeness = |v: &Vec<f32>| centres.iter().map(|c| dot(c, v)).fold(f32::MIN, f32::max);
+        let farthest = vectors.iter().min_by(|a, b| likeness(a).total_cmp(&likeness(b))).unwrap();
+        centres.push(farthest.clone());
+    }
+
+    let mut assignment: Vec<usize> = Vec::new();
+    for _ in 0..ITERATIONS {
+        let next: Vec<usize> = vectors.iter().map(|v| nearest(&centres, v)).collect();
+        if next == assignment {
+            break;
+        }
+        assignment = next;
+        for (cluster, centre) in centres.iter_mut().enumerate() {
+            let mut sum = vec![0.0; centre.len()];
+            for (vector, _) in vectors.iter().zip(&assignment).filter(|(_, c)| **c == cluster) {
+                sum.iter_mut().zip(vector).for_each(|(s, x)| *s += x);
+            }
+            // An emptied cluster keeps its centre, and stays empty
+            if sum.iter().any(|s| *s != 0.0) {
+                *centre = normalized(&sum);
+            }
+        }
+    }
+    assignment
+}
+
+/// The tag most of the bookmarks share, alphabetically first among equals.
+fn most_common_tag<'a>(tags: impl Iterator<Item = &'a [String]>) -> Option<String> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for tag in tags.flatten() {
+        *counts.entry(tag).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by(|(a, x), (b, y)| x.cmp(y).then_with(|| b.cmp(a)))
+        .map(|(tag, _)| tag.to_string())
+}
+
+#[derive(sqlx::FromRow)]
+struct Candidate {
+    id: Uuid,
+    url: String,
+    title: Option<String>,
+    embedding: Vector,
+    tags: Option<Vec<String>>,
+}
+
+impl Candidate {
+    fn tags(&self) -> &[String] {
+        self.tags.as_deref().unwrap_or_default()
+    }
+}
+
+/// What the model is given to name a group.
+fn name_prompt(existing: &[String], members: &[&Candidate]) -> String {
+    let mut prompt = String::new();
+    if !existing.is_empty() {
+        prompt.push_str(&format!("Existing folders:\n{}\n\n", existing.join("\n")));
+    }
+    prompt.push_str("Bookmarks:");
+    for member in members.iter().take(NAME_SAMPLE) {
+        prompt.push_str(&format!("\n- {}", member.title.as_deref().unwrap_or(&member.url)));
+        if !member.tags().is_empty() {
+            prompt.push_str(&format!(" [{}]", member.tags().join(", ")));
+        }
+    }
+    prompt
+}
+
+/// Clusters the user's uncollected bookmarks and stores the named groups as their suggestions,
+/// returning how many there are.
+pub async fn suggest(state: &AppState, user_id: Uuid) -> anyhow::Result<usize> {
+    let mut candidates: Vec<Candidate> = sqlx::query_as(
+        "SELECT b.id, b.url, b.title, b.embedding,
+             (SELECT array_agg(t.name ORDER BY t.name) FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id
+              WHERE bt.bookmark_id = b.id) AS tags
+         FROM bookmarks b
+         WHERE b.user_id = $1 AND b.deleted_at IS NULL AND b.embedding IS NOT NULL
+           AND NOT EXISTS (SELECT 1 FROM collection_bookmarks cb WHERE cb.bookmark_id = b.id)
+         ORDER BY b.created_at DESC, b.id DESC LIMIT $2"
+    )
+    .bind(user_id)
+    .bind(MAX_BOOKMARKS)
+    .fetch_all(&state.db)
+    .await?;
+    // Vectors from an earlier embedding model can't be compared with the current ones
+    if let Some(dimensions) = candidates.first().map(|c| c.embedding.as_slice().len()) {
+        candidates.retain(|c| c.embedding.as_slice().len() == dimensions);
+    }
+
+    let mut groups: Vec<Vec<&Candidate>> = Vec::new();
+    if candidates.len() >= 2 * MIN_CLUSTER_SIZE {
+        let vectors: Vec<Vec<f32>> = candidates.iter().map(|c| normalized(c.embedding.as_slice())).collect();
+        let k = cluster_count(vectors.len());
+        let assignment = tokio::task::spawn_blocking(move || kmeans(&vectors, k)).await?;
+        groups = vec![Vec::new(); k];
+        for (candidate, cluster) in candidates.iter().zip(assignment) {
+            groups[cluster].push(candidate);
+        }
+        groups.retain(|group| group.len() >= MIN_CLUSTER_SIZE);
+        groups.sort_by_key(|group| std::cmp::Reverse(group.len()));
+    }
+
+    let existing: Vec<String> =
+        sqlx::query_scalar("SELECT name FROM collections WHERE user_id = $1 AND parent_id IS NULL ORDER BY name")
+            .bind(user_id)
+            .fetch_all(&state.db)
+            .await?;
+    let mut named: Vec<(String, Vec<Uuid>)> = Vec::new();
+    for group in groups {
+        let name = match state.tagger.name_collection(&name_prompt(&existing, &group)).await? {
+            Some(answer) => Some(answer.name.trim().to_string()).filter(|name| !name.is_empty()),
+            None => most_common_tag(group.iter().map(|c| c.tags())),
+        };
+        let Some(name) = name else { continue };
+        let ids = group.iter().map(|c| c.id);
+        // Two groups given the same name become one suggestion
+        match named.iter_mut().find(|(other, _)| other.eq_ignore_ascii_case(&name)) {
+            Some((_, members)) => members.extend(ids),
+            None => named.push((name, ids.collect())),
+        }
+    }
+
+    let mut tx = state.db.begin().await?;
+    sqlx::query("DELETE FROM collection_suggestions WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    for (name, ids) in &named {
+        sqlx::query("INSERT INTO collection_suggestions (user_id, name, bookmark_ids) VALUES ($1, $2, $3)")
+            .bind(user_id)
+            .bind(name)
+            .bind(ids)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(named.len())
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct SuggestedBookmark {
+    id: Uuid,
+    url: String,
+    title: Option<String>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct Suggestion {
+    id: Uuid,
+    name: String,
+    #[serde(skip)]
+    bookmark_ids: Vec<Uuid>,
+    /// Those still uncollected and out of the trash
+    #[sqlx(skip)]
+    bookmarks: Vec<SuggestedBookmark>,
+    created_at: DateTime<Utc>,
+}
+
+/// The user's suggestions, biggest first.
+async fn list_suggestions(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Result<Json<Vec<Suggestion>>, StatusCode> {
+    let mut suggestions: Vec<Suggestion> = sqlx::query_as(
+        "SELECT id, name, bookmark_ids, created_at FROM collection_suggestions
+         WHERE user_id = $1 ORDER BY cardinality(bookmark_ids) DESC, name"
+    )
+    .bind(user.id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    for suggestion in &mut suggestions {
+        suggestion.bookmarks = sqlx::query_as(
+            "SELECT b.id, b.url, b.title FROM bookmarks b
+             WHERE b.user_id = $1 AND b.id = ANY($2) AND b.deleted_at IS NULL
+               AND NOT EXISTS (SELECT 1 FROM collection_bookmarks cb WHERE cb.bookmark_id = b.id)
+             ORDER BY b.created_at DESC, b.id DESC"
+        )
+        .bind(user.id)
+        .bind(&suggestion.bookmark_ids)
+        .fetch_all(&state.db)
+        .await
+        .map_err(db_error)?;
+    }
+    Ok(Json(suggestions))
+}
+
+/// Files the suggestion's bookmarks into the collection it names, making the collection unless
+/// there's already a top-level one called that.
+async fn accept_suggestion(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<(StatusCode, Json<Collection>), StatusCode> {
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    let suggestion: Option<(String, Vec<Uuid>)> = sqlx::query_as(
+        "DELETE FROM collection_suggestions WHERE id = $1 AND user_id = $2 RETURNING name, bookmark_ids"
+    )
+    .bind(id)
+    .bind(user.id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    let (name, ids) = suggestion.ok_or(StatusCode::NOT_FOUND)?;
+
+    let existing: Option<Uuid> =
+        sqlx::query_scalar("SELECT id FROM collections WHERE user_id = $1 AND parent_id IS NULL AND name = $2")
+            .bind(user.id)
+            .bind(&name)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(db_error)?;
+    let (collection_id, status) = match existing {
+        Some(collection_id) => (collection_id, StatusCode::OK),
+        None => {
+            let collection_id: Uuid =
+                sqlx::query_scalar("INSERT INTO collections (user_id, name) VALUES ($1, $2) RETURNING id")
+                    .bind(user.id)
+                    .bind(&name)
+                    .fetch_one(&mut *tx)
+                    .await
+                    .map_err(db_error)?;
+            (collection_id, StatusCode::CREATED)
+        }
+    };
+    sqlx::query(
+        "INSERT INTO collection_bookmarks (collection_id, bookmark_id)
+         SELECT $1, id FROM bookmarks WHERE user_id = $2 AND id = ANY($3) AND deleted_at IS NULL
+         ON CONFLICT DO NOTHING"
+    )
+    .bind(collection_id)
+    .bind(user.id)
+    .bind(&ids)
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    tx.commit().await.map_err(db_error)?;
+
+    Ok((status, Json(fetch_collection(&state, user.id, collection_id).await?)))
+}
+
+async fn dismiss_suggestion(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = sqlx::query("DELETE FROM collection_suggestions WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user.id)
+        .execute(&state.db)
+        .await
+        .map_err(db_error)?;
+
+    if deleted.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kmeans() {
+        let vectors: Vec<Vec<f32>> = [[1.0, 0.1], [0.9, 0.2], [1.0, 0.0], [0.1, 1.0], [0.0, 0.9], [0.2, 1.0]]
+            .iter()
+            .map(|v| normalized(v))
+            .collect();
+        let assignment = kmeans(&vectors, 2);
+        assert_eq!(assignment[0], assignment[1]);
+        assert_eq!(assignment[0], assignment[2]);
+        assert_eq!(assignment[3], assignment[4]);
+        assert_eq!(assignment[3], assignment[5]);
+        assert_ne!(assignment[0], assignment[3]);
+    }
+
+    #[test]
+    fn test_cluster_count() {
+        assert_eq!(cluster_count(10), 2);
+        assert_eq!(cluster_count(200), 10);
+        assert_eq!(cluster_count(5000), MAX_CLUSTERS);
+    }
+
+    #[test]
+    fn test_most_common_tag() {
+        let tags = [
+            vec!["rust".to_string(), "async".to_string()],
+            vec!["async".to_string()],
+            vec!["rust".to_string()],
+            vec![],
+        ];
+        assert_eq!(most_common_tag(tags.iter().map(Vec::as_slice)), Some("async".to_string()));
+        assert_eq!(most_common_tag(std::iter::empty()), None);
+    }
+}
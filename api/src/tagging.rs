@@ -0,0 +1,343 @@
+//! Summarizing and tagging pages, sorting bookmarks into folders, naming suggested collections
+//! and writing weekly roundups, with whichever model provider is configured.
+//!
+//! `ai.provider` picks the implementation: `openai` for OpenAI and the many servers that copy its
+//! API (llama.cpp, vLLM, LM Studio), `ollama` for Ollama's own API, `anthropic`, or `none` to
+//! save and fetch bookmarks without calling a model at all. Embeddings still go through the
+//! OpenAI-compatible client in `embeddings`.
+//!
+//! Users with a tag vocabulary get it listed in the prompt, and whatever the model answers is
+//! mapped onto it afterwards: exact and plural matches first, then the closest entry by edit
+//! distance, with tags that resemble nothing in the list dropped.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use rig::client::CompletionClient;
+use rig::completion::{CompletionError, CompletionModel, CompletionRequest, CompletionResponse};
+use rig::extractor::ExtractorBuilder;
+use rig::providers::{anthropic, ollama, openai};
+use rig::streaming::StreamingCompletionResponse;
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::config::{AiConfig, Provider, TaggingConfig};
+use crate::metrics::Metrics;
+use crate::retry::Policy;
+use crate::SuggestFoldersResponse;
+
+const SUGGEST_FOLDERS_PREAMBLE: &str = "You are a semantic classification agent. Your sole task is to analyze the input text and select the single most appropriate folder from the provided list.\n\nRules:\n\nOutput exactly one folder path from the list for each bookmark. Do not create new folders.\n\nBase your selection on the closest match to the input's primary domain, technology, or intent.\n\nExtract only what is explicitly stated or strongly implied.";
+
+const ROUNDUP_PREAMBLE: &str = "You are a reading assistant. Your task is to write a short roundup of the bookmarks someone saved this week, from the titles, sites, tags and summaries provided.\n\nRules:\n\nWrite the roundup as one or two plain paragraphs addressed to the reader, saying what they saved and how the pieces relate.\n\nList the common themes as a few words each, most prominent first, and only themes at least two bookmarks share.\n\nExtract only what is explicitly stated or strongly implied.";
+
+const NAME_COLLECTION_PREAMBLE: &str = "You are a librarian. Your task is to name a folder for the group of bookmarks provided, which were found to be about the same thing.\n\nRules:\n\nOutput one short name of one to four words, in title case, that covers most of the bookmarks.\n\nIf one of the existing folder names fits the group, output it exactly.\n\nExtract only what is explicitly stated or strongly implied.";
+
+/// How alike, from 0 to 1, a tag must be to a vocabulary entry to be mapped onto it.
+const SIMILARITY_THRESHOLD: f64 = 0.8;
+
+/// Enough for a summary and tags, or folders for a few hundred bookmarks.
+const ANTHROPIC_MAX_TOKENS: u64 = 4096;
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Enrichment {
+    /// Two or three sentences on what the page is about
+    pub summary: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct CollectionName {
+    /// One to four words
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Roundup {
+    /// A paragraph or two on what was saved and how it fits together
+    pub summary: String,
+    /// What several of the bookmarks had in common, a few words each
+    pub themes: Vec<String>,
+}
+
+/// A model that can describe pages. Every method answers `None` when there's no model to ask.
+pub trait Tagger: Send + Sync {
+    /// A summary and at most `max_tags` tags for the page at `url`, given its scraped metadata,
+    /// drawn from `vocabulary` unless it's empty.
+    fn tag<'a>(
+        &'a self,
+        url: &'a str,
+        content: &'a str,
+        vocabulary: &'a [String],
+    ) -> BoxFuture<'a, anyhow::Result<Option<Enrichment>>>;
+
+    /// Picks a folder for each bookmark described in `prompt`.
+    fn suggest_folders<'a>(&'a self, prompt: &'a str) -> BoxFuture<'a, anyhow::Result<Option<SuggestFoldersResponse>>>;
+
+    /// Names a collection for the group of bookmarks described in `prompt`.
+    fn name_collection<'a>(&'a self, prompt: &'a str) -> BoxFuture<'a, anyhow::Result<Option<CollectionName>>>;
+
+    /// Writes up the week's bookmarks described in `prompt`.
+    fn roundup<'a>(&'a self, prompt: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Roundup>>>;
+}
+
+pub fn from_config(
+    ai: &AiConfig,
+    tagging: &TaggingConfig,
+    retry: Policy,
+    metrics: Arc<Metrics>,
+) -> anyhow::Result<Arc<dyn Tagger>> {
+    let tagging = tagging.clone();
+    Ok(match ai.provider {
+        Provider::OpenAi => {
+            let client = openai::Client::builder().api_key(&ai.api_key).base_url(ai.api_base()).build()?;
+            Arc::new(RigTagger {
+                model: client.completion_model(&ai.model),
+                // Keeps Qwen-style local models from spending the request thinking
+                params: Some(json!({ "enable_thinking": false })),
+                max_tokens: None,
+                tagging,
+                retry,
+                metrics,
+            })
+        }
+        Provider::Ollama => {
+            let client = ollama::Client::builder()
+                .api_key(ollama::OllamaApiKey::from(ai.api_key.as_str()))
+                .base_url(ai.api_base())
+                .build()?;
+            Arc::new(RigTagger {
+                model: client.completion_model(&ai.model),
+                params: None,
+                max_tokens: None,
+                tagging,
+                retry,
+                metrics,
+            })
+        }
+        Provider::Anthropic => {
+            let client = anthropic::Client::builder().api_key(&ai.api_key).base_url(ai.api_base()).build()?;
+            Arc::new(RigTagger {
+                model: client.completion_model(&ai.model),
+                params: None,
+                // Anthropic insists on a limit
+                max_tokens: Some(ANTHROPIC_MAX_TOKENS),
+                tagging,
+                retry,
+                metrics,
+            })
+        }
+        Provider::None => Arc::new(Offline),
+    })
+}
+
+/// Any of rig's providers, asked for structured output through its extractor.
+struct RigTagger<M> {
+    model: M,
+    /// Extra request fields only this provider understands
+    params: Option<Value>,
+    max_tokens: Option<u64>,
+    tagging: TaggingConfig,
+    retry: Policy,
+    metrics: Arc<Metrics>,
+}
+
+impl<M: CompletionModel + 'static> RigTagger<M> {
+    async fn extract<T, N>(&self, call: &'static str, model: N, preamble: &str, prompt: &str) -> anyhow::Result<T>
+    where
+        T: JsonSchema + DeserializeOwned + Serialize + Send + Sync + 'static,
+        N: CompletionModel + 'static,
+    {
+        let mut builder = ExtractorBuilder::<N, T>::new(model).preamble(preamble);
+        if let Some(params) = &self.params {
+            builder = builder.additional_params(params.clone());
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            builder = builder.max_tokens(max_tokens);
+        }
+        let extractor = builder.build();
+        let extracted = self
+            .retry
+            .run(call, || async {
+                let extracted = extractor.extract_with_usage(prompt).await;
+                self.metrics.record_ai_call(call, extracted.is_ok(), extracted.as_ref().ok().map(|r| &r.usage));
+                extracted
+            })
+            .await;
+        Ok(extracted.map_err(|e| anyhow::anyhow!("Rig extraction error: {}", e))?.data)
+    }
+}
+
+impl<M: CompletionModel + 'static> Tagger for RigTagger<M> {
+    fn tag<'a>(
+        &'a self,
+        url: &'a str,
+        content: &'a str,
+        vocabulary: &'a [String],
+    ) -> BoxFuture<'a, anyhow::Result<Option<Enrichment>>> {
+        Box::pin(async move {
+            let tagging = &self.tagging;
+            let model = WithTemperature { model: self.model.clone(), temperature: tagging.temperature };
+            let mut preamble = tagging.render(&tagging.system_prompt, url, content);
+            if let Some(language) = &tagging.language {
+                preamble.push_str(&format!("\n\nWrite the summary and tags in {}, whatever language the page is in.", language));
+            }
+            if !vocabulary.is_empty() {
+                preamble.push_str("\n\nOnly use tags from this list, and fewer tags if not enough of them fit:\n");
+                preamble.push_str(&vocabulary.join("\n"));
+            }
+            let prompt = tagging.render(&tagging.prompt, url, content);
+            let mut enrichment: Enrichment = self.extract("enrichment", model, &preamble, &prompt).await?;
+            if !vocabulary.is_empty() {
+                enrichment.tags = constrain(&enrichment.tags, vocabulary);
+            }
+            enrichment.tags.truncate(tagging.max_tags);
+            Ok(Some(enrichment))
+        })
+    }
+
+    fn suggest_folders<'a>(&'a self, prompt: &'a str) -> BoxFuture<'a, anyhow::Result<Option<SuggestFoldersResponse>>> {
+        Box::pin(async move {
+            let model = self.model.clone();
+            Ok(Some(self.extract("suggest_folders", model, SUGGEST_FOLDERS_PREAMBLE, prompt).await?))
+        })
+    }
+
+    fn name_collection<'a>(&'a self, prompt: &'a str) -> BoxFuture<'a, anyhow::Result<Option<CollectionName>>> {
+        Box::pin(async move {
+            let model = self.model.clone();
+            let mut preamble = NAME_COLLECTION_PREAMBLE.to_string();
+            if let Some(language) = &self.tagging.language {
+                preamble.push_str(&format!("\n\nWrite the name in {}.", language));
+            }
+            Ok(Some(self.extract("name_collection", model, &preamble, prompt).await?))
+        })
+    }
+
+    fn roundup<'a>(&'a self, prompt: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Roundup>>> {
+        Box::pin(async move {
+            let model = WithTemperature { model: self.model.clone(), temperature: self.tagging.temperature };
+            let mut preamble = ROUNDUP_PREAMBLE.to_string();
+            if let Some(language) = &self.tagging.language {
+                preamble.push_str(&format!("\n\nWrite the roundup and themes in {}.", language));
+            }
+            Ok(Some(self.extract("roundup", model, &preamble, prompt).await?))
+        })
+    }
+}
+
+fn normalize(tag: &str) -> String {
+    tag.trim().to_lowercase().replace([' ', '_'], "-")
+}
+
+/// The vocabulary entry a tag from the model stands for, if any is close enough. A nested entry
+/// such as `dev/rust` also matches on its last segment.
+fn vocabulary_match<'v>(tag: &str, vocabulary: &'v [String]) -> Option<&'v str> {
+    let wanted = normalize(tag);
+    let mut best: Option<(f64, &str)> = None;
+    for entry in vocabulary {
+        let full = normalize(entry);
+        let leaf = full.rsplit('/').next().unwrap_or_default().to_string();
+        for candidate in [&full, &leaf] {
+            let plural = |a: &str, b: &str| a.strip_suffix('s') == Some(b);
+            if *candidate == wanted || plural(candidate, &wanted) || plural(&wanted, candidate) {
+                return Some(entry);
+            }
+            let score = strsim::normalized_levenshtein(&wanted, candidate);
+            if score >= SIMILARITY_THRESHOLD && best.is_none_or(|(best, _)| score > best) {
+                best = Some((score, entry));
+            }
+        }
+    }
+    best.map(|(_, entry)| entry)
+}
+
+/// `tags` mapped onto `vocabulary`, in order, without duplicates.
+fn constrain(tags: &[String], vocabulary: &[String]) -> Vec<String> {
+    let mut kept: Vec<String> = Vec::new();
+    for entry in tags.iter().filter_map(|tag| vocabulary_match(tag, vocabulary)) {
+        if !kept.iter().any(|k| k == entry) {
+            kept.push(entry.to_string());
+        }
+    }
+    kept
+}
+
+/// `ai.provider = "none"`: bookmarks are still fetched and archived, just never tagged.
+pub struct Offline;
+
+impl Tagger for Offline {
+    fn tag<'a>(
+        &'a self,
+        _url: &'a str,
+        _content: &'a str,
+        _vocabulary: &'a [String],
+    ) -> BoxFuture<'a, anyhow::Result<Option<Enrichment>>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    fn suggest_folders<'a>(&'a self, _prompt: &'a str) -> BoxFuture<'a, anyhow::Result<Option<SuggestFoldersResponse>>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    fn name_collection<'a>(&'a self, _prompt: &'a str) -> BoxFuture<'a, anyhow::Result<Option<CollectionName>>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    fn roundup<'a>(&'a self, _prompt: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Roundup>>> {
+        Box::pin(async { Ok(None) })
+    }
+}
+
+/// Sets a default temperature on every request, which rig's extractor has no option for.
+#[derive(Clone)]
+struct WithTemperature<M> {
+    model: M,
+    temperature: Option<f64>,
+}
+
+impl<M: CompletionModel> CompletionModel for WithTemperature<M> {
+    type Response = M::Response;
+    type StreamingResponse = M::StreamingResponse;
+    type Client = M::Client;
+
+    fn make(client: &Self::Client, model: impl Into<String>) -> Self {
+        WithTemperature { model: M::make(client, model), temperature: None }
+    }
+
+    fn completion(
+        &self,
+        mut request: CompletionRequest,
+    ) -> impl Future<Output = Result<CompletionResponse<Self::Response>, CompletionError>> + Send {
+        request.temperature = request.temperature.or(self.temperature);
+        self.model.completion(request)
+    }
+
+    fn stream(
+        &self,
+        mut request: CompletionRequest,
+    ) -> impl Future<Output = Result<StreamingCompletionResponse<Self::StreamingResponse>, CompletionError>> + Send {
+        request.temperature = request.temperature.or(self.temperature);
+        self.model.stream(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constrain() {
+        let vocabulary: Vec<String> = ["dev/rust", "web-development", "databases", "async"]
+            .iter()
+            .map(|t| t.to_string())
+            .collect();
+        let tags: Vec<String> = ["Rust", "web development", "database", "asynk", "cooking", "rust"]
+            .iter()
+            .map(|t| t.to_string())
+            .collect();
+        assert_eq!(constrain(&tags, &vocabulary), vec!["dev/rust", "web-development", "databases", "async"]);
+        assert_eq!(vocabulary_match("javascript", &vocabulary), None);
+    }
+}
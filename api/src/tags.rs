@@ -0,0 +1,379 @@
+//! Browsing a user's tag vocabulary, and cleaning it up after the AI has had its way with it.
+
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{delete, get, post},
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{tag_path, AppState, CurrentUser};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/tags", get(list_tags))
+        .route("/tags/stats", get(tag_stats))
+        .route("/tags/rename", post(rename_tag))
+        .route("/tags/merge", post(merge_tags))
+        .route("/tags/vocabulary", get(get_vocabulary).put(replace_vocabulary).post(extend_vocabulary))
+        // Wildcard so nested tags like `dev/rust` can be addressed
+        .route("/tags/{*tag}", delete(delete_tag))
+}
+
+fn db_error(e: sqlx::Error) -> StatusCode {
+    match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => StatusCode::CONFLICT,
+        e => {
+            eprintln!("Tags Error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Marks the bookmarks carrying the tags matched by the appended condition (on `t`, with the user
+/// as `$1`) as changed, so delta sync picks up the retagging.
+const TOUCH_TAGGED: &str = "UPDATE bookmarks SET updated_at = now() WHERE id IN (
+    SELECT bt.bookmark_id FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id WHERE t.user_id = $1 AND ";
+
+pub const DEFAULT_SUGGESTIONS: i64 = 10;
+pub const MAX_SUGGESTIONS: i64 = 100;
+
+#[derive(Deserialize)]
+pub struct ListTagsParams {
+    pub prefix: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct TagCount {
+    name: String,
+    count: i64,
+}
+
+/// `LIKE` treats `%` and `_` as wildcards; a prefix the user typed should match literally.
+pub fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// Tags in use, most used first, optionally only those starting with `prefix` (case-insensitive).
+async fn list_tags(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Query(params): Query<ListTagsParams>,
+) -> Result<Json<Vec<TagCount>>, StatusCode> {
+    let limit = params.limit.unwrap_or(DEFAULT_SUGGESTIONS).clamp(1, MAX_SUGGESTIONS);
+    let pattern = format!("{}%", escape_like(&params.prefix.unwrap_or_default().trim().to_lowercase()));
+
+    sqlx::query_as::<_, TagCount>(
+        "SELECT t.name, count(*) as count FROM tags t
+         JOIN bookmark_tags bt ON bt.tag_id = t.id
+         JOIN bookmarks b ON b.id = bt.bookmark_id
+         WHERE t.user_id = $1 AND lower(t.name) LIKE $2 AND b.deleted_at IS NULL
+         GROUP BY t.name
+         ORDER BY count DESC, t.name
+         LIMIT $3"
+    )
+    .bind(user.id)
+    .bind(pattern)
+    .bind(limit)
+    .fetch_all(&state.db)
+    .await
+    .map(Json)
+    .map_err(db_error)
+}
+
+/// How many co-occurring tags to report per tag.
+const RELATED_TAGS: i64 = 5;
+
+#[derive(Serialize, sqlx::FromRow)]
+struct TagStats {
+    name: String,
+    count: i64,
+    first_used: DateTime<Utc>,
+    last_used: DateTime<Utc>,
+    #[sqlx(skip)]
+    related: Vec<TagCount>,
+}
+
+/// Every tag in use with when it was first and last applied (by bookmark save time) and the
+/// tags that most often appear alongside it.
+async fn tag_stats(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Result<Json<Vec<TagStats>>, StatusCode> {
+    const USAGE: &str = "WITH usage AS (
+         SELECT t.name, bt.bookmark_id, b.created_at FROM tags t
+         JOIN bookmark_tags bt ON bt.tag_id = t.id
+         JOIN bookmarks b ON b.id = bt.bookmark_id
+         WHERE t.user_id = $1 AND b.deleted_at IS NULL
+     )";
+
+    let mut stats: Vec<TagStats> = sqlx::query_as(&format!(
+        "{} SELECT name, count(*) as count, min(created_at) as first_used, max(created_at) as last_used
+         FROM usage GROUP BY name ORDER BY count DESC, name",
+        USAGE
+    ))
+    .bind(user.id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    let pairs: Vec<(String, String, i64)> = sqlx::query_as(&format!(
+        "{} SELECT tag, related, count FROM (
+             SELECT a.name as tag, c.name as related, count(*) as count,
+                    row_number() OVER (PARTITION BY a.name ORDER BY count(*) DESC, c.name) as rank
+             FROM usage a JOIN usage c ON c.bookmark_id = a.bookmark_id AND c.name <> a.name
+             GROUP BY a.name, c.name
+         ) ranked
+         WHERE rank <= $2
+         ORDER BY tag, rank",
+        USAGE
+    ))
+    .bind(user.id)
+    .bind(RELATED_TAGS)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    let mut related: HashMap<String, Vec<TagCount>> = HashMap::new();
+    for (tag, name, count) in pairs {
+        related.entry(tag).or_default().push(TagCount { name, count });
+    }
+    for tag in &mut stats {
+        tag.related = related.remove(&tag.name).unwrap_or_default();
+    }
+
+    Ok(Json(stats))
+}
+
+#[derive(Deserialize)]
+struct RenameRequest {
+    from: String,
+    to: String,
+}
+
+/// Renames a tag and everything nested under it, so `dev` → `code` also turns `dev/rust`
+/// into `code/rust`. Renaming onto a tag that already exists is a conflict; merge instead.
+async fn rename_tag(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Json(payload): Json<RenameRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let from = tag_path(&payload.from).ok_or(StatusCode::BAD_REQUEST)?;
+    let to = tag_path(&payload.to).ok_or(StatusCode::BAD_REQUEST)?;
+    if from == to {
+        return Ok(StatusCode::NO_CONTENT);
+    }
+
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+
+    sqlx::query(&format!("{} (t.name = $2 OR starts_with(t.name, $2 || '/')))", TOUCH_TAGGED))
+        .bind(user.id)
+        .bind(&from)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+    let renamed = sqlx::query(
+        "UPDATE tags SET name = $1 || substr(name, length($2) + 1)
+         WHERE user_id = $3 AND (name = $2 OR starts_with(name, $2 || '/'))"
+    )
+    .bind(&to)
+    .bind(&from)
+    .bind(user.id)
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    if renamed.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    tx.commit().await.map_err(db_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+struct MergeRequest {
+    from: Vec<String>,
+    into: String,
+}
+
+/// Retags every bookmark carrying any of `from` with `into` and removes the `from` tags.
+async fn merge_tags(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Json(payload): Json<MergeRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let into = tag_path(&payload.into).ok_or(StatusCode::BAD_REQUEST)?;
+    let from: Vec<String> = payload
+        .from
+        .iter()
+        .filter_map(|t| tag_path(t))
+        .filter(|t| *t != into)
+        .collect();
+    if from.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+
+    let into_id: Uuid = sqlx::query_scalar(
+        "INSERT INTO tags (user_id, name) VALUES ($1, $2)
+         ON CONFLICT (user_id, name) DO UPDATE SET name = EXCLUDED.name
+         RETURNING id"
+    )
+    .bind(user.id)
+    .bind(&into)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    sqlx::query(
+        "INSERT INTO bookmark_tags (bookmark_id, tag_id)
+         SELECT bt.bookmark_id, $1 FROM bookmark_tags bt JOIN tags t ON bt.tag_id = t.id
+         WHERE t.user_id = $2 AND t.name = ANY($3)
+         ON CONFLICT DO NOTHING"
+    )
+    .bind(into_id)
+    .bind(user.id)
+    .bind(&from)
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    sqlx::query(&format!("{} t.name = ANY($2))", TOUCH_TAGGED))
+        .bind(user.id)
+        .bind(&from)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+    let removed = sqlx::query("DELETE FROM tags WHERE user_id = $1 AND name = ANY($2)")
+        .bind(user.id)
+        .bind(&from)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+    if removed.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    tx.commit().await.map_err(db_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Serialize, Deserialize)]
+struct Vocabulary {
+    tags: Vec<String>,
+}
+
+/// The tags the AI is limited to; empty means it may make up its own.
+async fn get_vocabulary(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Result<Json<Vocabulary>, StatusCode> {
+    let tags = sqlx::query_scalar("SELECT name FROM tag_vocabulary WHERE user_id = $1 ORDER BY name")
+        .bind(user.id)
+        .fetch_all(&state.db)
+        .await
+        .map_err(db_error)?;
+    Ok(Json(Vocabulary { tags }))
+}
+
+async fn add_to_vocabulary(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: Uuid,
+    tags: &[String],
+) -> Result<(), StatusCode> {
+    let tags = tags.iter().map(|t| tag_path(t)).collect::<Option<Vec<_>>>().ok_or(StatusCode::BAD_REQUEST)?;
+    sqlx::query(
+        "INSERT INTO tag_vocabulary (user_id, name) SELECT $1, * FROM UNNEST($2::text[])
+         ON CONFLICT DO NOTHING"
+    )
+    .bind(user_id)
+    .bind(&tags)
+    .execute(&mut **tx)
+    .await
+    .map_err(db_error)?;
+    Ok(())
+}
+
+/// Sets the whole vocabulary; an empty list lets the AI tag freely again.
+async fn replace_vocabulary(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Json(payload): Json<Vocabulary>,
+) -> Result<Json<Vocabulary>, StatusCode> {
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    sqlx::query("DELETE FROM tag_vocabulary WHERE user_id = $1")
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    add_to_vocabulary(&mut tx, user.id, &payload.tags).await?;
+    tx.commit().await.map_err(db_error)?;
+    get_vocabulary(State(state), Extension(user)).await
+}
+
+/// Adds tags to the vocabulary, keeping the ones already there.
+async fn extend_vocabulary(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Json(payload): Json<Vocabulary>,
+) -> Result<Json<Vocabulary>, StatusCode> {
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    add_to_vocabulary(&mut tx, user.id, &payload.tags).await?;
+    tx.commit().await.map_err(db_error)?;
+    get_vocabulary(State(state), Extension(user)).await
+}
+
+/// Removes a tag from every bookmark. Tags nested under it are left alone.
+async fn delete_tag(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(tag): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let tag = tag_path(&tag).ok_or(StatusCode::BAD_REQUEST)?;
+
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+
+    sqlx::query(&format!("{} t.name = $2)", TOUCH_TAGGED))
+        .bind(user.id)
+        .bind(&tag)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+    let deleted = sqlx::query("DELETE FROM tags WHERE user_id = $1 AND name = $2")
+        .bind(user.id)
+        .bind(&tag)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+    if deleted.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    tx.commit().await.map_err(db_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_like() {
+        assert_eq!(escape_like("rust"), "rust");
+        assert_eq!(escape_like("100%_done"), "100\\%\\_done");
+        assert_eq!(escape_like(r"a\b"), r"a\\b");
+    }
+}
@@ -0,0 +1,64 @@
+//! HTTPS without a reverse proxy.
+//!
+//! With `tls.cert` and `tls.key` set (PEM files; the certificate may include the chain) the API
+//! is served over TLS on the usual listen address. Sending the process `SIGHUP` re-reads both
+//! files, so renewed certificates take effect without dropping connections; if the new files
+//! don't load, the old certificate stays in use.
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+
+use crate::config::TlsConfig;
+
+pub struct Tls {
+    cert: PathBuf,
+    key: PathBuf,
+}
+
+impl Tls {
+    pub fn from_config(config: &TlsConfig) -> Option<Self> {
+        Some(Tls {
+            cert: PathBuf::from(config.cert.as_ref()?),
+            key: PathBuf::from(config.key.as_ref()?),
+        })
+    }
+
+    pub async fn serve(self, addr: SocketAddr, app: Router) -> anyhow::Result<()> {
+        // Other dependencies pull in ring as well, so rustls can't pick a provider by itself
+        let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+        let rustls = RustlsConfig::from_pem_file(&self.cert, &self.key)
+            .await
+            .map_err(|e| anyhow::anyhow!("Can't load TLS certificate: {}", e))?;
+
+        #[cfg(unix)]
+        tokio::spawn(reload_on_hangup(rustls.clone(), self));
+
+        println!("listening on https://{}", addr);
+        axum_server::bind_rustls(addr, rustls)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+async fn reload_on_hangup(rustls: RustlsConfig, tls: Tls) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            eprintln!("Can't listen for SIGHUP, TLS certificates won't be reloaded: {}", e);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        match rustls.reload_from_pem_file(&tls.cert, &tls.key).await {
+            Ok(()) => println!("reloaded TLS certificate from {}", tls.cert.display()),
+            Err(e) => eprintln!("TLS Reload Error: {}", e),
+        }
+    }
+}
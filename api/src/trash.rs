@@ -0,0 +1,62 @@
+//! Permanent removal of trashed bookmarks.
+//!
+//! Deleted bookmarks stay restorable for `TRASH_RETENTION_DAYS` (default 30, `0` keeps them
+//! forever) and are then deleted for good, along with their archived snapshot.
+
+use std::time::Duration;
+
+use uuid::Uuid;
+
+use crate::{config::TrashConfig, AppState};
+
+pub struct Config {
+    pub retention_days: i32,
+}
+
+impl Config {
+    pub fn from_config(config: &TrashConfig) -> Option<Self> {
+        (config.retention_days > 0).then_some(Config {
+            retention_days: config.retention_days,
+        })
+    }
+}
+
+pub async fn run(state: AppState, config: Config) {
+    let mut tick = tokio::time::interval(Duration::from_secs(60 * 60));
+    loop {
+        tick.tick().await;
+        let result = purge(&state, &config).await;
+        state.metrics.record_job("trash_purge", result.is_ok());
+        match result {
+            Ok(0) => {}
+            Ok(purged) => println!("purged {} bookmarks from the trash", purged),
+            Err(e) => eprintln!("Trash Purge Error: {}", e),
+        }
+    }
+}
+
+async fn purge(state: &AppState, config: &Config) -> anyhow::Result<usize> {
+    // Tombstones let delta sync report the purge to clients that missed the trashing
+    let purged: Vec<(Uuid, bool)> = sqlx::query_as(
+        "WITH purged AS (
+             DELETE FROM bookmarks WHERE deleted_at < now() - make_interval(days => $1)
+             RETURNING id, user_id, archive_url IS NOT NULL as archived
+         ), tombstones AS (
+             INSERT INTO bookmark_tombstones (bookmark_id, user_id) SELECT id, user_id FROM purged
+         )
+         SELECT id, archived FROM purged"
+    )
+    .bind(config.retention_days)
+    .fetch_all(&state.db)
+    .await?;
+
+    if let Some(archive) = &state.archive {
+        for (id, _) in purged.iter().filter(|(_, archived)| *archived) {
+            if let Err(e) = archive.delete_page(*id).await {
+                eprintln!("Error deleting archived page for bookmark {}: {}", id, e);
+            }
+        }
+    }
+
+    Ok(purged.len())
+}
@@ -0,0 +1,359 @@
+//! A small web UI at `/ui`, for using linkman from a browser without another frontend.
+//!
+//! Pages are rendered on the server and need no JavaScript: the bookmark list takes the same
+//! filters as `GET /bookmarks`, with a search box and tag links, and each bookmark's page shows
+//! what was kept of it, links to the reader view and the archived copy, and has forms to retag
+//! it, mark it read, favorite it or move it to the trash. It signs in with a password, as
+//! `/auth/login` does, and the session cookie is the only credential it takes. That cookie is
+//! `SameSite=Lax`, and form posts from another origin are refused, so another site can't submit
+//! these forms as the user.
+
+use axum::{
+    extract::{Path, Query, RawQuery, Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Extension, Form, Json, Router,
+};
+use serde::Deserialize;
+use url::form_urlencoded;
+use uuid::Uuid;
+
+use crate::{
+    accounts, audit, delete_bookmark, fetch_bookmark, netscape::escape, quick_add::split_tags, rate_limit, search_page,
+    toggle_favorite, toggle_read, update_bookmark, AppState, BookmarkResponse, CurrentUser, PageParams, SearchParams,
+    UpdateBookmarkRequest,
+};
+
+/// Pages load no scripts, images or frames, and can't be framed themselves.
+const CONTENT_SECURITY_POLICY: &str = "default-src 'none'; style-src 'unsafe-inline'; form-action 'self'; frame-ancestors 'none'";
+
+const STYLE: &str = "body{font:16px/1.5 system-ui,sans-serif;max-width:50rem;margin:0 auto;padding:0 1rem;color:#222}\
+    header{display:flex;gap:1rem;align-items:center;flex-wrap:wrap;padding:1rem 0;border-bottom:1px solid #ddd}\
+    header form{margin:0}ul.bookmarks{list-style:none;padding:0}ul.bookmarks li{padding:.6rem 0;border-bottom:1px solid #eee}\
+    .meta,.url{color:#666;font-size:.9em;overflow-wrap:anywhere}.tags a{margin-right:.5rem}\
+    .actions{display:flex;gap:.5rem;flex-wrap:wrap}.actions form{margin:0}.error{color:#b00}";
+
+pub fn router(state: AppState) -> Router<AppState> {
+    let pages = Router::new()
+        .route("/ui", get(list_page))
+        .route("/ui/bookmarks/{id}", get(bookmark_page))
+        .route("/ui/bookmarks/{id}/tags", post(set_tags))
+        .route("/ui/bookmarks/{id}/read", post(read))
+        .route("/ui/bookmarks/{id}/favorite", post(favorite))
+        .route("/ui/bookmarks/{id}/delete", post(delete))
+        .route("/ui/logout", post(logout))
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit::middleware))
+        .layer(middleware::from_fn_with_state(state, session_middleware));
+    Router::new().route("/ui/login", get(login_page).post(login)).merge(pages)
+}
+
+/// Sends anyone without a session to the login page.
+async fn session_middleware(State(state): State<AppState>, mut req: Request, next: Next) -> Result<Response, StatusCode> {
+    let (user, key) = match accounts::authenticate_session(&state, req.headers()).await {
+        Ok(session) => session,
+        Err(StatusCode::UNAUTHORIZED) => return Ok(Redirect::to("/ui/login").into_response()),
+        Err(status) => return Err(status),
+    };
+
+    let writes = req.method() == Method::POST;
+    if writes && accounts::cross_site(req.headers()) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    req.extensions_mut().insert(user);
+    req.extensions_mut().insert(key);
+    if writes {
+        return Ok(audit::record(&state, req, next).await);
+    }
+    Ok(next.run(req).await)
+}
+
+/// A whole page; `user` adds the search box and navigation.
+fn page(title: &str, user: Option<&CurrentUser>, search: Option<&str>, body: &str) -> Response {
+    let header = match user {
+        Some(user) => format!(
+            "<header><a href=\"/ui\"><strong>linkman</strong></a>\
+             <form action=\"/ui\" method=\"get\"><input type=\"search\" name=\"q\" placeholder=\"Search\" value=\"{}\"></form>\
+             <a href=\"/ui?unread=true\">Unread</a><a href=\"/ui?favorite=true\">Favorites</a>\
+             <form action=\"/ui/logout\" method=\"post\"><button>Log out {}</button></form></header>\n",
+            escape(search.unwrap_or_default()),
+            escape(&user.username),
+        ),
+        None => "<header><strong>linkman</strong></header>\n".to_string(),
+    };
+    let html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\"><head><meta charset=\"utf-8\">\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+         <title>{} · linkman</title><style>{}</style></head>\n<body>\n{}<main>\n{}</main>\n</body></html>\n",
+        escape(title),
+        STYLE,
+        header,
+        body
+    );
+    ([(header::CONTENT_SECURITY_POLICY, CONTENT_SECURITY_POLICY)], Html(html)).into_response()
+}
+
+/// `/ui` with a tag's bookmarks.
+fn tag_link(tag: &str) -> String {
+    format!(
+        "<a href=\"/ui?tag={}\">#{}</a>",
+        form_urlencoded::byte_serialize(tag.as_bytes()).collect::<String>(),
+        escape(tag)
+    )
+}
+
+fn tag_links(bookmark: &BookmarkResponse) -> String {
+    bookmark.tags.as_deref().unwrap_or_default().iter().map(|tag| tag_link(tag)).collect()
+}
+
+/// The bookmark as the list shows it. Its own URL is text, never a link: it could be a
+/// `javascript:` one; opening it goes through `/go/{id}`.
+fn list_item(bookmark: &BookmarkResponse) -> String {
+    let mut meta = vec![bookmark.created_at.format("%b %-d, %Y").to_string()];
+    if let Some(domain) = &bookmark.domain {
+        meta.insert(0, escape(domain));
+    }
+    if !bookmark.is_read {
+        meta.push("unread".to_string());
+    }
+    if bookmark.is_favorite {
+        meta.push("★".to_string());
+    }
+    format!(
+        "<li><a href=\"/ui/bookmarks/{}\">{}</a><div class=\"meta\">{}</div><div class=\"tags\">{}</div></li>\n",
+        bookmark.id,
+        escape(bookmark.title.as_deref().unwrap_or(&bookmark.url)),
+        meta.join(" · "),
+        tag_links(bookmark)
+    )
+}
+
+/// The same listing from `cursor` on.
+fn with_cursor(query: Option<&str>, cursor: &str) -> String {
+    let mut next = form_urlencoded::Serializer::new(String::new());
+    for (name, value) in form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+        if name != "cursor" {
+            next.append_pair(&name, &value);
+        }
+    }
+    next.append_pair("cursor", cursor);
+    format!("/ui?{}", next.finish())
+}
+
+async fn list_page(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Query(paging): Query<PageParams>,
+    Query(params): Query<SearchParams>,
+    RawQuery(query): RawQuery,
+) -> Result<Response, StatusCode> {
+    let results = search_page(&state, &user, &params, &paging).await?;
+
+    let mut body = String::new();
+    if let Some(tag) = &params.tag {
+        body.push_str(&format!("<h1>#{}</h1>\n", escape(tag)));
+    }
+    if results.bookmarks.is_empty() {
+        body.push_str("<p>Nothing here.</p>\n");
+    } else {
+        body.push_str("<ul class=\"bookmarks\">\n");
+        body.extend(results.bookmarks.iter().map(list_item));
+        body.push_str("</ul>\n");
+    }
+    if let Some(cursor) = &results.next_cursor {
+        body.push_str(&format!("<p><a href=\"{}\">More →</a></p>\n", escape(&with_cursor(query.as_deref(), cursor))));
+    }
+    Ok(page("Bookmarks", Some(&user), params.q.as_deref(), &body))
+}
+
+/// A button that posts to `action`.
+fn button(action: &str, label: &str) -> String {
+    format!("<form method=\"post\" action=\"{}\"><button>{}</button></form>", action, label)
+}
+
+async fn bookmark_page(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, StatusCode> {
+    let db_error = |e: sqlx::Error| {
+        eprintln!("UI Bookmark Error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let bookmark = fetch_bookmark(&state.db, user.id, id).await.map_err(db_error)?.ok_or(StatusCode::NOT_FOUND)?;
+    let has_content: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM bookmark_contents WHERE bookmark_id = $1)")
+        .bind(id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(db_error)?;
+
+    let title = bookmark.title.as_deref().unwrap_or(&bookmark.url);
+    let mut body = format!("<h1>{}</h1>\n<p class=\"url\">{}</p>\n", escape(title), escape(&bookmark.url));
+
+    let mut links = vec![format!("<a href=\"/go/{}\">Open</a>", id)];
+    if has_content {
+        links.push(format!("<a href=\"/bookmarks/{}/reader\">Read here</a>", id));
+    }
+    if bookmark.archived_at.is_some() {
+        links.push(format!("<a href=\"/bookmarks/{}/archive\">Archived copy</a>", id));
+    }
+    body.push_str(&format!("<p>{}</p>\n", links.join(" · ")));
+
+    for (heading, text) in [
+        ("Summary", &bookmark.ai_summary),
+        ("Description", &bookmark.description),
+        ("Notes", &bookmark.notes),
+    ] {
+        if let Some(text) = text.as_deref().filter(|text| !text.trim().is_empty()) {
+            body.push_str(&format!("<h2>{}</h2>\n<p>{}</p>\n", heading, escape(text)));
+        }
+    }
+    if bookmark.processing_status == "failed" {
+        body.push_str(&format!(
+            "<p class=\"error\">Processing failed: {}</p>\n",
+            escape(bookmark.error_message.as_deref().unwrap_or("unknown error"))
+        ));
+    }
+
+    let tags = bookmark.tags.as_deref().unwrap_or_default();
+    body.push_str(&format!(
+        "<h2>Tags</h2>\n<p class=\"tags\">{}</p>\n\
+         <form method=\"post\" action=\"/ui/bookmarks/{}/tags\">\
+         <input name=\"tags\" size=\"40\" value=\"{}\" aria-label=\"Tags, comma-separated\"> <button>Save tags</button></form>\n",
+        tag_links(&bookmark),
+        id,
+        escape(&tags.join(", "))
+    ));
+
+    body.push_str(&format!(
+        "<p class=\"meta\">Saved {}{}</p>\n<div class=\"actions\">{}{}{}</div>\n",
+        bookmark.created_at.format("%b %-d, %Y"),
+        if bookmark.visit_count > 0 { format!(" · opened {} times", bookmark.visit_count) } else { String::new() },
+        button(&format!("/ui/bookmarks/{}/read", id), if bookmark.is_read { "Mark unread" } else { "Mark read" }),
+        button(&format!("/ui/bookmarks/{}/favorite", id), if bookmark.is_favorite { "Unfavorite" } else { "Favorite" }),
+        button(&format!("/ui/bookmarks/{}/delete", id), "Move to trash"),
+    ));
+    Ok(page(title, Some(&user), None, &body))
+}
+
+fn back_to(id: Uuid) -> Response {
+    Redirect::to(&format!("/ui/bookmarks/{}", id)).into_response()
+}
+
+#[derive(Deserialize)]
+struct TagsForm {
+    /// Comma-separated; replaces every tag the bookmark had
+    tags: String,
+}
+
+async fn set_tags(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+    Form(form): Form<TagsForm>,
+) -> Result<Response, StatusCode> {
+    let update = UpdateBookmarkRequest {
+        url: None,
+        title: None,
+        notes: None,
+        tags: Some(split_tags(Some(&form.tags)).unwrap_or_default()),
+        is_public: None,
+    };
+    let _ = update_bookmark(State(state), Extension(user), Path(id), Json(update)).await?;
+    Ok(back_to(id))
+}
+
+async fn read(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, StatusCode> {
+    let _ = toggle_read(State(state), Extension(user), Path(id)).await?;
+    Ok(back_to(id))
+}
+
+async fn favorite(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, StatusCode> {
+    let _ = toggle_favorite(State(state), Extension(user), Path(id)).await?;
+    Ok(back_to(id))
+}
+
+async fn delete(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, StatusCode> {
+    delete_bookmark(State(state), Extension(user), Path(id)).await?;
+    Ok(Redirect::to("/ui").into_response())
+}
+
+fn login_form(error: Option<&str>) -> String {
+    format!(
+        "<h1>Log in</h1>\n{}<form method=\"post\" action=\"/ui/login\">\
+         <p><label>Username <input name=\"username\" autocomplete=\"username\" required></label></p>\
+         <p><label>Password <input name=\"password\" type=\"password\" autocomplete=\"current-password\" required></label></p>\
+         <p><button>Log in</button></p></form>\n\
+         <p class=\"meta\">Accounts made with an API token need a password first: <code>PUT /auth/password</code>.</p>\n",
+        error.map(|error| format!("<p class=\"error\">{}</p>\n", escape(error))).unwrap_or_default()
+    )
+}
+
+async fn login_page() -> Response {
+    page("Log in", None, None, &login_form(None))
+}
+
+#[derive(Deserialize)]
+struct LoginForm {
+    username: String,
+    password: String,
+}
+
+async fn login(State(state): State<AppState>, Form(form): Form<LoginForm>) -> Result<Response, StatusCode> {
+    let user = match accounts::check_password(&state, &form.username, form.password).await {
+        Ok(user) => user,
+        Err(StatusCode::UNAUTHORIZED) => {
+            let mut response = page("Log in", None, None, &login_form(Some("Wrong username or password.")));
+            *response.status_mut() = StatusCode::UNAUTHORIZED;
+            return Ok(response);
+        }
+        Err(status) => return Err(status),
+    };
+    let cookie = accounts::new_session(&state, user.id).await?;
+    Ok(([(header::SET_COOKIE, cookie)], Redirect::to("/ui")).into_response())
+}
+
+async fn logout(State(state): State<AppState>, headers: HeaderMap) -> Result<Response, StatusCode> {
+    let cookie = accounts::end_session(&state, &headers).await?;
+    Ok(([(header::SET_COOKIE, cookie)], Redirect::to("/ui/login")).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_cursor() {
+        assert_eq!(with_cursor(Some("tag=dev%2Frust&cursor=old&q=a+b"), "new_1"), "/ui?tag=dev%2Frust&q=a+b&cursor=new_1");
+        assert_eq!(with_cursor(None, "new_1"), "/ui?cursor=new_1");
+    }
+
+    #[test]
+    fn test_list_item() {
+        let bookmark = BookmarkResponse {
+            url: "javascript:alert(1)".to_string(),
+            title: Some("<b>Hi</b>".to_string()),
+            tags: Some(vec!["c++".to_string()]),
+            is_read: true,
+            ..Default::default()
+        };
+        let item = list_item(&bookmark);
+        assert!(item.contains("&lt;b&gt;Hi&lt;/b&gt;"));
+        assert!(!item.contains("javascript:"));
+        assert!(item.contains("<a href=\"/ui?tag=c%2B%2B\">#c++</a>"));
+        assert!(!item.contains("unread"));
+    }
+}
@@ -0,0 +1,133 @@
+//! Wallabag's JSON export: an array of entries with their tags, annotations and flags.
+//!
+//! Archived entries count as read. Each annotation becomes a paragraph of the notes, its quote
+//! first when it has one. The stored copy of the page isn't used; it's fetched again instead.
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::import::{normalize_tag, ExternalBookmark};
+
+#[derive(Deserialize)]
+struct Entry {
+    url: Option<String>,
+    title: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    created_at: Option<String>,
+    /// `1`/`0` in some versions, `true`/`false` in others
+    #[serde(default)]
+    is_archived: Value,
+    #[serde(default)]
+    is_starred: Value,
+    #[serde(default)]
+    annotations: Vec<Annotation>,
+}
+
+#[derive(Deserialize)]
+struct Annotation {
+    text: Option<String>,
+    quote: Option<String>,
+}
+
+fn truthy(flag: &Value) -> bool {
+    flag.as_bool().unwrap_or_else(|| flag.as_i64().is_some_and(|n| n != 0))
+}
+
+/// Notes made of an entry's annotations, or none if it has none.
+fn notes(annotations: &[Annotation]) -> Option<String> {
+    let paragraphs: Vec<String> = annotations
+        .iter()
+        .filter_map(|annotation| {
+            let quote = annotation.quote.as_deref().map(str::trim).filter(|q| !q.is_empty());
+            let text = annotation.text.as_deref().map(str::trim).filter(|t| !t.is_empty());
+            match (quote, text) {
+                (Some(quote), Some(text)) => Some(format!("> {}\n\n{}", quote, text)),
+                (Some(quote), None) => Some(format!("> {}", quote)),
+                (None, Some(text)) => Some(text.to_string()),
+                (None, None) => None,
+            }
+        })
+        .collect();
+    (!paragraphs.is_empty()).then(|| paragraphs.join("\n\n"))
+}
+
+/// Wallabag writes times like `2019-04-26T14:27:50+02:00`, and older versions without the colon.
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .or_else(|_| DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%z"))
+        .map(|time| time.to_utc())
+        .ok()
+}
+
+pub fn parse(content: &str) -> anyhow::Result<Vec<ExternalBookmark>> {
+    let entries: Vec<Entry> = serde_json::from_str(content)?;
+    Ok(entries
+        .into_iter()
+        .filter_map(|entry| {
+            let url = entry.url.map(|u| u.trim().to_string()).filter(|u| !u.is_empty())?;
+            Some(ExternalBookmark {
+                url,
+                title: entry.title.map(|t| t.trim().to_string()).filter(|t| !t.is_empty()),
+                tags: entry.tags.iter().map(|t| normalize_tag(t)).filter(|t| !t.is_empty()).collect(),
+                added_at: entry.created_at.as_deref().and_then(parse_time),
+                description: notes(&entry.annotations),
+                favorite: truthy(&entry.is_starred),
+                archived: truthy(&entry.is_archived),
+                ..Default::default()
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let export = r#"[
+            {
+                "id": 12,
+                "url": "https://blog.rust-lang.org/2024/05/02/Rust-1.78.0.html",
+                "title": "Announcing Rust 1.78.0",
+                "tags": ["Rust Releases", "dev"],
+                "created_at": "2024-05-03T09:10:11+02:00",
+                "is_archived": 1,
+                "is_starred": 0,
+                "content": "<p>The Rust team is happy to announce...</p>",
+                "annotations": [
+                    { "text": "Worth upgrading for this", "quote": "diagnostic attributes" },
+                    { "text": "", "quote": "deny lints" }
+                ]
+            },
+            {
+                "url": "https://example.com/later",
+                "title": "",
+                "created_at": "2019-04-26T14:27:50+0200",
+                "is_archived": false,
+                "is_starred": true
+            },
+            { "title": "No address" }
+        ]"#;
+        let bookmarks = parse(export).unwrap();
+        assert_eq!(bookmarks.len(), 2);
+
+        assert_eq!(bookmarks[0].title.as_deref(), Some("Announcing Rust 1.78.0"));
+        assert_eq!(bookmarks[0].tags, vec!["rust-releases", "dev"]);
+        assert_eq!(bookmarks[0].added_at, "2024-05-03T07:10:11Z".parse().ok());
+        assert_eq!(
+            bookmarks[0].description.as_deref(),
+            Some("> diagnostic attributes\n\nWorth upgrading for this\n\n> deny lints")
+        );
+        assert!(bookmarks[0].archived && !bookmarks[0].favorite);
+
+        assert_eq!(bookmarks[1].title, None);
+        assert_eq!(bookmarks[1].description, None);
+        assert_eq!(bookmarks[1].added_at, "2019-04-26T12:27:50Z".parse().ok());
+        assert!(!bookmarks[1].archived && bookmarks[1].favorite);
+
+        assert!(parse("{}").is_err());
+    }
+}
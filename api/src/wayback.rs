@@ -0,0 +1,189 @@
+//! The Internet Archive's copies of bookmarked pages.
+//!
+//! When a bookmark's page answers 404 or 410, or doesn't answer in time, the Wayback Machine's
+//! availability API is asked for its closest snapshot, and that is processed instead. The
+//! snapshot is fetched with `id_` in its address, which serves the page as it was captured
+//! rather than wrapped in the archive's toolbar. `WAYBACK_FALLBACK=false` turns this off, for
+//! anyone who'd rather not have their bookmarks looked up there.
+//!
+//! The other way round, Save Page Now has the archive capture a page while it's still there, on
+//! request or, with `ARCHIVE_TO_WAYBACK`, for every bookmark. A capture takes the archive a while,
+//! so its status is checked every few seconds until it's done.
+
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use reqwest::{header, Client, RequestBuilder};
+use serde_json::Value;
+use url::Url;
+
+use crate::config::{ArchiveConfig, FetchConfig};
+use crate::fetcher::Fetcher;
+
+/// How often a capture's status is checked, and how many times before giving up on it.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const MAX_POLLS: u32 = 36;
+
+pub struct Wayback {
+    api: String,
+}
+
+/// A capture of a page.
+#[derive(Debug, PartialEq)]
+pub struct Snapshot {
+    /// The snapshot's page on web.archive.org, for people to look at
+    pub url: String,
+    /// The same capture without the archive's toolbar, for processing
+    pub raw_url: String,
+    pub captured_at: DateTime<Utc>,
+}
+
+impl Wayback {
+    pub fn from_config(config: &FetchConfig) -> Option<Self> {
+        config.wayback_fallback.then(|| Wayback { api: config.wayback_api.clone() })
+    }
+
+    /// The snapshot of `page_url` closest to now, if there is one.
+    pub async fn closest(&self, fetcher: &Fetcher, page_url: &str) -> anyhow::Result<Option<Snapshot>> {
+        let mut api = Url::parse(&self.api)?;
+        api.query_pairs_mut().append_pair("url", page_url);
+        let fetched = fetcher.get(api.as_str()).await?;
+        fetched.response.error_for_status_ref()?;
+        let availability: Value = serde_json::from_slice(&fetched.bytes().await?)?;
+        Ok(parse(&availability))
+    }
+}
+
+/// Save Page Now, which captures a page when asked.
+pub struct SavePageNow {
+    endpoint: String,
+    /// Where the captures end up, e.g. `https://web.archive.org`
+    origin: String,
+    /// `LOW access:secret`, with an archive.org account's keys
+    authorization: Option<String>,
+}
+
+impl SavePageNow {
+    pub fn from_config(config: &ArchiveConfig) -> anyhow::Result<Self> {
+        let endpoint = Url::parse(&config.wayback_save_url)?;
+        let authorization = match (&config.wayback_access_key, &config.wayback_secret_key) {
+            (Some(access), Some(secret)) => Some(format!("LOW {}:{}", access, secret)),
+            _ => None,
+        };
+        Ok(SavePageNow {
+            endpoint: endpoint.as_str().trim_end_matches('/').to_string(),
+            origin: endpoint.origin().ascii_serialization(),
+            authorization,
+        })
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        let request = request.header(header::ACCEPT, "application/json");
+        match &self.authorization {
+            Some(authorization) => request.header(header::AUTHORIZATION, authorization),
+            None => request,
+        }
+    }
+
+    /// Has the archive capture `page_url` and waits for it, returning the capture's address.
+    pub async fn save(&self, client: &Client, page_url: &str) -> anyhow::Result<String> {
+        let form = url::form_urlencoded::Serializer::new(String::new()).append_pair("url", page_url).finish();
+        let started: Value = self
+            .authorize(client.post(&self.endpoint))
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let Some(job_id) = started["job_id"].as_str() else {
+            anyhow::bail!("Save Page Now didn't start capturing {}: {}", page_url, reason(&started));
+        };
+
+        let status_url = format!("{}/status/{}", self.endpoint, job_id);
+        for _ in 0..MAX_POLLS {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            let status: Value = self.authorize(client.get(&status_url)).send().await?.error_for_status()?.json().await?;
+            match status["status"].as_str() {
+                Some("pending") => continue,
+                Some("success") => {
+                    return capture_url(&self.origin, &status)
+                        .ok_or_else(|| anyhow::anyhow!("Save Page Now captured {} but didn't say where", page_url));
+                }
+                _ => anyhow::bail!("Save Page Now couldn't capture {}: {}", page_url, reason(&status)),
+            }
+        }
+        anyhow::bail!("Save Page Now was still capturing {} after {}s", page_url, (POLL_INTERVAL * MAX_POLLS).as_secs())
+    }
+}
+
+/// Why Save Page Now refused or failed.
+fn reason(response: &Value) -> &str {
+    response["message"].as_str().or(response["status_ext"].as_str()).unwrap_or("no reason given")
+}
+
+/// The address of a finished capture, from its status.
+fn capture_url(origin: &str, status: &Value) -> Option<String> {
+    Some(format!("{}/web/{}/{}", origin, status["timestamp"].as_str()?, status["original_url"].as_str()?))
+}
+
+fn parse(availability: &Value) -> Option<Snapshot> {
+    let closest = &availability["archived_snapshots"]["closest"];
+    if closest["available"] != true || closest["status"].as_str().is_some_and(|status| !status.starts_with('2')) {
+        return None;
+    }
+    let timestamp = closest["timestamp"].as_str()?;
+    let captured_at = NaiveDateTime::parse_from_str(timestamp, "%Y%m%d%H%M%S").ok()?.and_utc();
+    let url = closest["url"].as_str()?.to_string();
+    let raw_url = url.replacen(&format!("/web/{}/", timestamp), &format!("/web/{}id_/", timestamp), 1);
+    Some(Snapshot { url, raw_url, captured_at })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse() {
+        let availability = json!({
+            "url": "example.com/gone",
+            "archived_snapshots": {
+                "closest": {
+                    "status": "200",
+                    "available": true,
+                    "url": "http://web.archive.org/web/20230919044612/https://example.com/gone",
+                    "timestamp": "20230919044612"
+                }
+            }
+        });
+        assert_eq!(
+            parse(&availability),
+            Some(Snapshot {
+                url: "http://web.archive.org/web/20230919044612/https://example.com/gone".to_string(),
+                raw_url: "http://web.archive.org/web/20230919044612id_/https://example.com/gone".to_string(),
+                captured_at: "2023-09-19T04:46:12Z".parse().unwrap(),
+            })
+        );
+        assert_eq!(parse(&json!({ "url": "example.com/never", "archived_snapshots": {} })), None);
+    }
+
+    #[test]
+    fn test_capture_url() {
+        let save = SavePageNow::from_config(&ArchiveConfig::default()).unwrap();
+        let status = json!({
+            "status": "success",
+            "job_id": "spn2-9c1f0e3c8d",
+            "original_url": "https://example.com/post",
+            "timestamp": "20240601120000"
+        });
+        assert_eq!(
+            capture_url(&save.origin, &status).as_deref(),
+            Some("https://web.archive.org/web/20240601120000/https://example.com/post")
+        );
+        assert_eq!(capture_url(&save.origin, &json!({ "status": "success" })), None);
+        assert_eq!(reason(&json!({ "status": "error", "message": "Too many captures." })), "Too many captures.");
+        assert_eq!(save.endpoint, "https://web.archive.org/save");
+    }
+}
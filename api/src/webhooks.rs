@@ -0,0 +1,353 @@
+//! Outbound webhooks on bookmark events.
+//!
+//! Each delivery is a JSON `POST` signed with the webhook's secret: the
+//! `X-Linkman-Signature` header is `sha256=` followed by the hex HMAC-SHA256 of the body.
+//! Deliveries wait in `webhook_deliveries` for the job worker, which retries failed ones with the
+//! same backoff as bookmark jobs, so a restart doesn't drop them. Every delivery is logged for
+//! `GET /webhooks/{id}/deliveries`.
+//!
+//! A webhook can't point at the server's own network, any more than a bookmark can be fetched
+//! from it: the URL is checked when the webhook is made, and every delivery and redirect goes
+//! through the `ssrf` guard.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use sqlx::{Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::{
+    events::Event,
+    fetcher::FetchError,
+    jobs,
+    ssrf::{self, Guard},
+    AppState, BookmarkResponse, CurrentUser, BOOKMARK_SELECT,
+};
+
+const MAX_ATTEMPTS: i32 = 5;
+const TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_REDIRECTS: usize = 5;
+const LOCK_TIMEOUT: &str = "5 minutes";
+const DELIVERY_LOG: i64 = 50;
+
+/// The client every delivery goes through, held to the same limits as page fetches.
+pub fn client(guard: Arc<Guard>) -> reqwest::Result<reqwest::Client> {
+    let redirect_guard = guard.clone();
+    let redirects = reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() > MAX_REDIRECTS {
+            attempt.error(FetchError::TooManyRedirects(MAX_REDIRECTS))
+        } else if let Err(e) = redirect_guard.check_url(attempt.url()) {
+            attempt.error(e)
+        } else {
+            attempt.follow()
+        }
+    });
+    reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .user_agent(format!("linkman/{}", env!("CARGO_PKG_VERSION")))
+        .redirect(redirects)
+        .dns_resolver(Arc::new(ssrf::Resolver(guard)))
+        .build()
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/webhooks", get(list_webhooks).post(create_webhook))
+        .route("/webhooks/{id}", delete(delete_webhook))
+        .route("/webhooks/{id}/deliveries", get(list_deliveries))
+}
+
+fn db_error(e: sqlx::Error) -> StatusCode {
+    eprintln!("Webhooks Error: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct Webhook {
+    id: Uuid,
+    url: String,
+    events: Vec<String>,
+    created_at: DateTime<Utc>,
+}
+
+async fn list_webhooks(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Result<Json<Vec<Webhook>>, StatusCode> {
+    sqlx::query_as::<_, Webhook>("SELECT id, url, events, created_at FROM webhooks WHERE user_id = $1 ORDER BY created_at")
+        .bind(user.id)
+        .fetch_all(&state.db)
+        .await
+        .map(Json)
+        .map_err(db_error)
+}
+
+#[derive(Deserialize)]
+struct CreateWebhookRequest {
+    url: String,
+    /// Event names to deliver; all of them when empty or omitted
+    #[serde(default)]
+    events: Vec<String>,
+    /// Generated when omitted
+    secret: Option<String>,
+}
+
+/// The secret is only ever returned here, so the caller can verify signatures.
+#[derive(Serialize)]
+struct CreatedWebhook {
+    #[serde(flatten)]
+    webhook: Webhook,
+    secret: String,
+}
+
+async fn create_webhook(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Json(payload): Json<CreateWebhookRequest>,
+) -> Result<(StatusCode, Json<CreatedWebhook>), StatusCode> {
+    let url = url::Url::parse(payload.url.trim()).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    // Deliveries are checked too, in case the name is pointed somewhere else later
+    let guard = state.fetcher.guard();
+    guard.check_url(&url).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if let Some(url::Host::Domain(host)) = url.host() {
+        guard.lookup(host, url.port_or_known_default().unwrap_or_default()).await.map_err(|_| StatusCode::BAD_REQUEST)?;
+    }
+    if !payload.events.iter().all(|e| Event::ALL.iter().any(|known| known.name() == e)) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let secret = payload
+        .secret
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| Uuid::new_v4().simple().to_string());
+
+    let webhook = sqlx::query_as::<_, Webhook>(
+        "INSERT INTO webhooks (user_id, url, secret, events) VALUES ($1, $2, $3, $4)
+         RETURNING id, url, events, created_at"
+    )
+    .bind(user.id)
+    .bind(url.as_str())
+    .bind(&secret)
+    .bind(&payload.events)
+    .fetch_one(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    Ok((StatusCode::CREATED, Json(CreatedWebhook { webhook, secret })))
+}
+
+async fn delete_webhook(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = sqlx::query("DELETE FROM webhooks WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user.id)
+        .execute(&state.db)
+        .await
+        .map_err(db_error)?;
+
+    if deleted.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct Delivery {
+    id: Uuid,
+    event: String,
+    payload: Value,
+    attempts: i32,
+    status_code: Option<i32>,
+    error: Option<String>,
+    created_at: DateTime<Utc>,
+    delivered_at: Option<DateTime<Utc>>,
+}
+
+/// The most recent deliveries for a webhook, newest first.
+async fn list_deliveries(
+    State(state): State<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<Delivery>>, StatusCode> {
+    let owned: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM webhooks WHERE id = $1 AND user_id = $2)")
+        .bind(id)
+        .bind(user.id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(db_error)?;
+    if !owned {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    sqlx::query_as::<_, Delivery>(
+        "SELECT id, event, payload, attempts, status_code, error, created_at, delivered_at
+         FROM webhook_deliveries WHERE webhook_id = $1
+         ORDER BY created_at DESC LIMIT $2"
+    )
+    .bind(id)
+    .bind(DELIVERY_LOG)
+    .fetch_all(&state.db)
+    .await
+    .map(Json)
+    .map_err(db_error)
+}
+
+/// Queues an event for delivery to the user's matching webhooks.
+pub async fn dispatch(state: &AppState, user_id: Uuid, event: Event, bookmark_id: Uuid) -> anyhow::Result<()> {
+    let hooks: Vec<Uuid> = sqlx::query_scalar(
+        "SELECT id FROM webhooks
+         WHERE user_id = $1 AND (cardinality(events) = 0 OR $2 = ANY(events))"
+    )
+    .bind(user_id)
+    .bind(event.name())
+    .fetch_all(&state.db)
+    .await?;
+    if hooks.is_empty() {
+        return Ok(());
+    }
+
+    // Deleted bookmarks are still in the trash, so this finds them too
+    let mut query = QueryBuilder::<Postgres>::new(BOOKMARK_SELECT);
+    query.push(" WHERE b.id = ").push_bind(bookmark_id);
+    let Some(bookmark) = query.build_query_as::<BookmarkResponse>().fetch_optional(&state.db).await? else {
+        return Ok(());
+    };
+
+    let payload = json!({
+        "event": event.name(),
+        "occurred_at": Utc::now(),
+        "bookmark": bookmark,
+    });
+
+    sqlx::query(
+        "INSERT INTO webhook_deliveries (webhook_id, event, payload, next_attempt_at)
+         SELECT id, $2, $3, now() FROM UNNEST($1::uuid[]) AS id"
+    )
+    .bind(&hooks)
+    .bind(event.name())
+    .bind(&payload)
+    .execute(&state.db)
+    .await?;
+    state.job_wakeup.notify_one();
+    Ok(())
+}
+
+/// A delivery due another attempt, claimed by a job worker.
+pub struct Claimed {
+    id: Uuid,
+    event: String,
+    payload: Value,
+    attempts: i32,
+    url: String,
+    secret: String,
+}
+
+pub async fn claim(state: &AppState) -> Result<Option<Claimed>, sqlx::Error> {
+    let row: Option<(Uuid, String, Value, i32, String, String)> = sqlx::query_as(&format!(
+        "UPDATE webhook_deliveries d SET attempts = d.attempts + 1, locked_at = now()
+         FROM webhooks w
+         WHERE w.id = d.webhook_id AND d.id = (
+             SELECT id FROM webhook_deliveries
+             WHERE next_attempt_at <= now() AND (locked_at IS NULL OR locked_at < now() - interval '{}')
+             ORDER BY next_attempt_at
+             LIMIT 1
+             FOR UPDATE SKIP LOCKED
+         )
+         RETURNING d.id, d.event, d.payload, d.attempts, w.url, w.secret",
+        LOCK_TIMEOUT
+    ))
+    .fetch_optional(&state.db)
+    .await?;
+
+    Ok(row.map(|(id, event, payload, attempts, url, secret)| Claimed { id, event, payload, attempts, url, secret }))
+}
+
+fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Makes one attempt at a claimed delivery, and schedules the next if it failed and there are
+/// attempts left.
+pub async fn deliver(state: &AppState, delivery: Claimed) -> Result<(), sqlx::Error> {
+    let body = delivery.payload.to_string();
+    let result = match url::Url::parse(&delivery.url) {
+        // Addresses are never looked up, so the resolver can't turn them down
+        Ok(url) => match state.fetcher.guard().check_url(&url) {
+            Ok(()) => state
+                .webhook_client
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header("X-Linkman-Event", &delivery.event)
+                .header("X-Linkman-Delivery", delivery.id.to_string())
+                .header("X-Linkman-Signature", sign(&delivery.secret, &body))
+                .body(body)
+                .send()
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        },
+        Err(e) => Err(e.to_string()),
+    };
+    let (status, error) = match result {
+        Ok(res) if res.status().is_success() => (Some(res.status()), None),
+        Ok(res) => (Some(res.status()), Some(format!("HTTP {}", res.status()))),
+        Err(e) => (None, Some(e)),
+    };
+    let retry = error.is_some() && delivery.attempts < MAX_ATTEMPTS;
+    if !retry {
+        state.metrics.record_job("webhook_delivery", error.is_none());
+    }
+
+    sqlx::query(
+        "UPDATE webhook_deliveries SET status_code = $1, error = $2, locked_at = NULL,
+             delivered_at = CASE WHEN $2 IS NULL THEN now() END,
+             next_attempt_at = CASE WHEN $3 THEN now() + make_interval(secs => $4) END
+         WHERE id = $5"
+    )
+    .bind(status.map(|s| s.as_u16() as i32))
+    .bind(&error)
+    .bind(retry)
+    .bind(jobs::retry_delay(delivery.attempts) as f64)
+    .bind(delivery.id)
+    .execute(&state.db)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        assert_eq!(
+            sign("key", "The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_client_refuses_private_hosts() {
+        let client = client(Arc::new(Guard::default())).unwrap();
+        let error = client.post("http://localhost:9/").send().await.unwrap_err();
+        assert!(matches!(crate::fetcher::cause(&error), Some(FetchError::PrivateAddress(_))), "{:?}", error);
+    }
+}